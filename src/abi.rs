@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{info, warn};
+use mongodb::bson::{Bson, DateTime, Document};
+use serde::Serialize;
+use serde_json::Value;
+use web3::ethabi::{Contract as EthContract, RawLog, Token};
+use web3::types::{Log, H160};
+
+use crate::mongo::collections::contract::{ContractMetadata, MetadataSource};
+use crate::mongo::collections::contract_event::ContractEvent;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::{Address, Block};
+use crate::mongo::Database;
+
+/// Function call decoded with the ABI attached to the called contract.
+#[derive(Serialize, Debug, Clone)]
+pub struct DecodedCall {
+    pub address: Address,
+    pub contract: String,
+    pub function: String,
+    pub params: Document,
+}
+
+/// Parsed ABIs of the contracts with verified metadata, by lowercase 0x address.
+#[derive(Clone, Default)]
+pub struct AbiRegistry {
+    contracts: HashMap<Address, (String, EthContract)>,
+}
+
+fn to_string<T: Serialize>(value: &T) -> String {
+    web3::helpers::to_string(value).replace('\"', "")
+}
+
+/// Converts a decoded ABI value to BSON. Addresses are 0x prefixed, integers hex encoded without
+/// prefix like the amounts of the other collections.
+pub fn token_to_bson(token: &Token) -> Bson {
+    match token {
+        Token::Address(address) => Bson::String(format!("{:?}", address)),
+        Token::Uint(value) | Token::Int(value) => Bson::String(format!("{:x}", value)),
+        Token::Bool(value) => Bson::Boolean(*value),
+        Token::String(value) => Bson::String(value.to_owned()),
+        Token::Bytes(value) | Token::FixedBytes(value) => {
+            Bson::String(f!("0x{}", hex::encode(value)))
+        }
+        Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+            Bson::Array(tokens.iter().map(token_to_bson).collect())
+        }
+    }
+}
+
+pub(crate) fn params_document<'a>(
+    params: impl Iterator<Item = (&'a String, &'a Token)>,
+) -> Document {
+    let mut document = Document::new();
+    for (index, (name, value)) in params.enumerate() {
+        let name = match name.is_empty() {
+            true => f!("param{index}"),
+            false => name.to_owned(),
+        };
+        document.insert(name, token_to_bson(value));
+    }
+    document
+}
+
+impl AbiRegistry {
+    pub async fn load(database: &Database) -> AbiRegistry {
+        AbiRegistry::from_contracts(database.contracts.with_abi().await)
+    }
+
+    pub fn from_contracts(contracts: Vec<ContractMetadata>) -> AbiRegistry {
+        let mut registry = AbiRegistry::default();
+
+        for contract in contracts {
+            let abi = match contract.abi {
+                None => continue,
+                Some(abi) => abi,
+            };
+
+            match EthContract::load(abi.as_bytes()) {
+                Ok(parsed) => {
+                    registry
+                        .contracts
+                        .insert(contract.address.to_lowercase(), (contract.name, parsed));
+                }
+                Err(err) => warn!(
+                    "[WARN] Ignoring invalid ABI of contract {}: {err}",
+                    contract.address
+                ),
+            }
+        }
+
+        registry
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contracts.is_empty()
+    }
+
+    pub fn decode_input(&self, to: &H160, input: &[u8]) -> Option<DecodedCall> {
+        let address = format!("{:?}", to);
+        let (name, contract) = self.contracts.get(&address)?;
+
+        if input.len() < 4 {
+            return None;
+        }
+
+        let function = contract
+            .functions()
+            .find(|function| function.short_signature() == input[..4])?;
+        let values = function.decode_input(&input[4..]).ok()?;
+
+        Some(DecodedCall {
+            contract: name.to_owned(),
+            function: function.name.to_owned(),
+            params: params_document(function.inputs.iter().map(|p| &p.name).zip(values.iter())),
+            address,
+        })
+    }
+
+    pub fn decode_log(
+        &self,
+        log: &Log,
+        block: Block,
+        timestamp: DateTime,
+    ) -> Option<ContractEvent> {
+        let address = format!("{:?}", log.address);
+        let (name, contract) = self.contracts.get(&address)?;
+        let topic = log.topics.first()?;
+
+        let event = contract
+            .events()
+            .find(|event| !event.anonymous && event.signature() == *topic)?;
+        let parsed = event
+            .parse_log(RawLog {
+                topics: log.topics.to_owned(),
+                data: log.data.0.to_owned(),
+            })
+            .ok()?;
+
+        let transaction_id = to_string(&log.transaction_hash);
+        let log_index = to_string(&log.log_index);
+
+        Some(ContractEvent {
+            contract: name.to_owned(),
+            event: event.name.to_owned(),
+            params: params_document(parsed.params.iter().map(|p| (&p.name, &p.value))),
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+            address,
+        })
+    }
+}
+
+/// Reads an ABI file: either a plain ABI array or a build artifact with an `abi` field.
+fn read_abi(path: &Path) -> String {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read ABI {}: {err}", path.display()));
+    let value: Value = serde_json::from_str(&content)
+        .unwrap_or_else(|err| panic!("Failed to parse ABI {}: {err}", path.display()));
+
+    let abi = match value.get("abi") {
+        Some(abi) => abi.to_owned(),
+        None => value,
+    };
+
+    let abi = abi.to_string();
+    EthContract::load(abi.as_bytes())
+        .unwrap_or_else(|err| panic!("Invalid ABI {}: {err}", path.display()));
+    abi
+}
+
+/// Attaches verified source metadata to a contract by hand.
+pub async fn add_contract_metadata(
+    database: &Database,
+    address: &str,
+    name: &str,
+    abi: &Path,
+    compiler: Option<String>,
+) {
+    let address =
+        H160::from_str(address).unwrap_or_else(|_| panic!("Invalid contract address {address}"));
+
+    database
+        .contracts
+        .store(&ContractMetadata {
+            address: format!("{:?}", address),
+            name: name.to_owned(),
+            abi: Some(read_abi(abi)),
+            compiler,
+            metadata_source: Some(MetadataSource::Manual),
+            updated_at: DateTime::now(),
+        })
+        .await
+        .expect("Failed to store contract metadata!");
+
+    println!("Stored metadata of {name} ({:?})", address);
+}
+
+fn sourcify_metadata(path: &Path) -> Option<ContractMetadata> {
+    let address = path.parent()?.file_name()?.to_str()?;
+    let address = H160::from_str(address).ok()?;
+
+    let content = fs::read_to_string(path).ok()?;
+    let metadata: Value = serde_json::from_str(&content).ok()?;
+
+    let abi = metadata.pointer("/output/abi")?.to_string();
+    EthContract::load(abi.as_bytes()).ok()?;
+
+    let name = metadata
+        .pointer("/settings/compilationTarget")
+        .and_then(|target| target.as_object())
+        .and_then(|target| target.values().next())
+        .and_then(|name| name.as_str())
+        .unwrap_or_default()
+        .to_owned();
+
+    Some(ContractMetadata {
+        address: format!("{:?}", address),
+        name,
+        abi: Some(abi),
+        compiler: metadata
+            .pointer("/compiler/version")
+            .and_then(|version| version.as_str())
+            .map(|version| version.to_owned()),
+        metadata_source: Some(MetadataSource::Sourcify),
+        updated_at: DateTime::now(),
+    })
+}
+
+fn find_metadata_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("Failed to read directory {}: {err}", dir.display()));
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_metadata_files(&path, files);
+        } else if path.file_name().and_then(|name| name.to_str()) == Some("metadata.json") {
+            files.push(path);
+        }
+    }
+}
+
+/// Imports the metadata of a Sourcify style archive, laid out as
+/// `<dir>/**/<address>/metadata.json`.
+pub async fn import_sourcify(database: &Database, dir: &Path) {
+    let mut files = vec![];
+    find_metadata_files(dir, &mut files);
+
+    let mut imported = 0;
+    for file in files {
+        let contract = match sourcify_metadata(&file) {
+            None => {
+                warn!("[WARN] Skipping unreadable metadata {}", file.display());
+                continue;
+            }
+            Some(contract) => contract,
+        };
+
+        info!(
+            "[INFO] Importing metadata of {} ({})",
+            contract.name, contract.address
+        );
+        database
+            .contracts
+            .store(&contract)
+            .await
+            .expect("Failed to store contract metadata!");
+        imported += 1;
+    }
+
+    println!("Imported metadata of {} contracts", imported);
+}
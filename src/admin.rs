@@ -0,0 +1,41 @@
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::mongo::Database;
+
+/// Settings key holding the SHA-256 hash of the admin token.
+const ADMIN_TOKEN_KEY: &str = "admin.token_sha256";
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Panics unless `token` matches the admin token stored in the settings. Guards the commands that
+/// drop or delete data so a mistyped flag on a deployment can't trigger them.
+pub async fn authorize(database: &Database, token: Option<&str>, action: &str) {
+    let stored = match database.settings.get(ADMIN_TOKEN_KEY).await {
+        None => panic!("{action} requires an admin token, set one with `set-admin-token` first"),
+        Some(settings) => settings.value,
+    };
+
+    match token {
+        None => panic!("{action} is an admin command, pass --admin-token"),
+        Some(token) if hash(token) != stored => panic!("Invalid admin token for {action}"),
+        Some(_) => info!("[INFO] Authorized {action}"),
+    }
+}
+
+/// Stores the hash of a new admin token. Replacing an existing token requires it as `current`.
+pub async fn set_token(database: &Database, token: &str, current: Option<&str>) {
+    if database.settings.get(ADMIN_TOKEN_KEY).await.is_some() {
+        authorize(database, current, "set-admin-token").await;
+    }
+
+    database
+        .settings
+        .set(ADMIN_TOKEN_KEY, hash(token))
+        .await
+        .expect("Failed to store admin token!");
+
+    println!("Admin token updated");
+}
@@ -0,0 +1,577 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use log::info;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use serde::Serialize;
+use web3::types::U256;
+
+use crate::mongo::collections::axie_sale::Sale;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::transaction_pool::Pool;
+use crate::mongo::collections::wallet::Wallet;
+use crate::mongo::collections::wallet_transfer::{TransferDirection, WalletTransfer};
+use crate::mongo::collections::{Address, Block};
+use crate::mongo::Database;
+use crate::ronin::{ContractType, Ronin};
+use crate::{spam, tiering};
+
+pub const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+const WALLET_BATCH_SIZE: usize = 1000;
+
+/// Disjoint set over wallet addresses. The lowest address of a set is its root, which makes
+/// cluster ids stable across runs over the same data.
+#[derive(Default)]
+struct Clusters {
+    parent: HashMap<Address, Address>,
+}
+
+impl Clusters {
+    fn find(&mut self, address: &str) -> Address {
+        let mut root = address.to_owned();
+        while let Some(parent) = self.parent.get(&root) {
+            if *parent == root {
+                break;
+            }
+            root = parent.to_owned();
+        }
+        self.parent
+            .entry(root.to_owned())
+            .or_insert_with(|| root.to_owned());
+
+        let mut current = address.to_owned();
+        while current != root {
+            current = self
+                .parent
+                .insert(current, root.to_owned())
+                .unwrap_or_else(|| root.to_owned());
+        }
+
+        root
+    }
+
+    fn union(&mut self, a: &str, b: &str) {
+        let a = self.find(a);
+        let b = self.find(b);
+
+        match a.cmp(&b) {
+            std::cmp::Ordering::Less => self.parent.insert(b, a),
+            std::cmp::Ordering::Greater => self.parent.insert(a, b),
+            std::cmp::Ordering::Equal => None,
+        };
+    }
+}
+
+/// Filter on the `block` field of a collection for an inclusive range. A `to_block` of 0 leaves
+/// the range open ended.
+pub fn block_range_filter(from_block: Block, to_block: Block) -> Document {
+    let mut range = doc! {"$gte": from_block as i64};
+    if to_block > 0 {
+        range.insert("$lte", to_block as i64);
+    }
+    doc! {"block": range}
+}
+
+/// Clusters wallets with two heuristics over the indexed transfers and writes the cluster id onto
+/// the wallet documents:
+///
+/// * funding source: a wallet belongs to the cluster of the wallet that sent it its first ERC20
+///   transfer.
+/// * co-spending: wallets sending tokens within the same transaction belong to the same cluster.
+///
+/// Addresses taking part in more than `max_funder_fanout` fundings or co-spends are treated as
+/// services (exchanges, routers, bridges) and ignored, as are the tracked token contracts.
+pub async fn cluster_wallets(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    max_funder_fanout: usize,
+) {
+    let options = FindOptions::builder().sort(doc! {"block": 1i64}).build();
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_transfers.collection.clone(),
+        block_range_filter(from_block, to_block),
+        options,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query erc transfers!");
+
+    let excluded: HashSet<String> = Ronin::contract_list()
+        .keys()
+        .map(|address| address.to_string())
+        .chain([ZERO_ADDRESS.to_string()])
+        .collect();
+
+    let mut funders: HashMap<Address, Address> = HashMap::new();
+    let mut fanout: HashMap<Address, usize> = HashMap::new();
+    let mut co_spends: Vec<Vec<Address>> = vec![];
+    let mut activity: HashMap<Address, usize> = HashMap::new();
+
+    let mut current_block: Block = 0;
+    let mut block_spenders: HashMap<String, HashSet<Address>> = HashMap::new();
+    let mut processed: u64 = 0;
+
+    let mut flush_block = |block_spenders: &mut HashMap<String, HashSet<Address>>| {
+        for (_, spenders) in block_spenders.drain() {
+            if spenders.len() > 1 {
+                for spender in spenders.iter() {
+                    *activity.entry(spender.to_owned()).or_default() += 1;
+                }
+                co_spends.push(spenders.into_iter().collect());
+            }
+        }
+    };
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc transfers!")
+    {
+        let transfer: ERCTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc transfer!");
+
+        if transfer.block != current_block {
+            flush_block(&mut block_spenders);
+            current_block = transfer.block;
+        }
+
+        processed += 1;
+        if processed.rem_euclid(1_000_000) == 0 {
+            info!(
+                "[INFO] Clustering: {} transfers read, at block {}",
+                processed, current_block
+            );
+        }
+
+        if excluded.contains(&transfer.from) || excluded.contains(&transfer.to) {
+            continue;
+        }
+
+        block_spenders
+            .entry(transfer.transaction_id.to_owned())
+            .or_default()
+            .insert(transfer.from.to_owned());
+
+        if transfer.erc == ContractType::ERC20 && !funders.contains_key(&transfer.to) {
+            *fanout.entry(transfer.from.to_owned()).or_default() += 1;
+            funders.insert(transfer.to, transfer.from);
+        }
+    }
+    flush_block(&mut block_spenders);
+
+    let mut clusters = Clusters::default();
+
+    for (wallet, funder) in funders.iter() {
+        if fanout.get(funder).copied().unwrap_or(0) <= max_funder_fanout {
+            clusters.union(wallet, funder);
+        }
+    }
+
+    for spenders in co_spends.iter() {
+        let spenders: Vec<&Address> = spenders
+            .iter()
+            .filter(|s| activity.get(*s).copied().unwrap_or(0) <= max_funder_fanout)
+            .collect();
+
+        for pair in spenders.windows(2) {
+            clusters.union(pair[0], pair[1]);
+        }
+    }
+
+    let addresses: Vec<Address> = clusters.parent.keys().cloned().collect();
+    let mut members: HashMap<Address, Vec<Address>> = HashMap::new();
+    for address in addresses {
+        let root = clusters.find(&address);
+        members.entry(root).or_default().push(address);
+    }
+
+    let mut wallet_pool: Pool<Wallet> = database.wallets.get_pool();
+    let mut cluster_num = 0;
+    let mut wallet_num = 0;
+
+    for (cluster, wallets) in members.iter().filter(|(_, w)| w.len() > 1) {
+        cluster_num += 1;
+        wallet_num += wallets.len();
+
+        for wallet in wallets {
+            wallet_pool.update(database.wallets.set_cluster(wallet, cluster));
+
+            if wallet_pool.len() >= WALLET_BATCH_SIZE {
+                wallet_pool
+                    .commit(true)
+                    .await
+                    .expect("Failed to update wallet clusters");
+            }
+        }
+    }
+
+    wallet_pool
+        .commit(true)
+        .await
+        .expect("Failed to update wallet clusters");
+
+    println!(
+        "Clustered {} wallets into {} clusters from {} transfers",
+        wallet_num, cluster_num, processed
+    );
+}
+
+#[derive(Default)]
+struct DepositCandidate {
+    depositors: HashSet<Address>,
+    sweeps: HashMap<Address, usize>,
+    other_outflows: usize,
+}
+
+/// Flags likely exchange deposit addresses: wallets that receive ERC20 transfers from at least
+/// `min_depositors` distinct senders and only ever send tokens on to one of the given exchange
+/// `hot_wallets`. The hot wallet receiving most sweeps is stored as `deposit_for` on the wallet.
+pub async fn detect_deposit_addresses(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    hot_wallets: &[Address],
+    min_depositors: usize,
+) {
+    let hot_wallets: HashSet<Address> = hot_wallets
+        .iter()
+        .map(|address| address.to_lowercase())
+        .collect();
+
+    let mut filter = block_range_filter(from_block, to_block);
+    filter.insert("erc", ContractType::ERC20.as_str());
+    spam::exclude(&mut filter);
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_transfers.collection.clone(),
+        filter,
+        None,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query erc transfers!");
+
+    let mut candidates: HashMap<Address, DepositCandidate> = HashMap::new();
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc transfers!")
+    {
+        let transfer: ERCTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc transfer!");
+
+        if transfer.from == ZERO_ADDRESS || hot_wallets.contains(&transfer.from) {
+            continue;
+        }
+
+        let sender = candidates.entry(transfer.from.to_owned()).or_default();
+        if hot_wallets.contains(&transfer.to) {
+            *sender.sweeps.entry(transfer.to.to_owned()).or_default() += 1;
+        } else {
+            sender.other_outflows += 1;
+        }
+
+        if !hot_wallets.contains(&transfer.to) {
+            candidates
+                .entry(transfer.to)
+                .or_default()
+                .depositors
+                .insert(transfer.from);
+        }
+    }
+
+    let mut wallet_pool: Pool<Wallet> = database.wallets.get_pool();
+    let mut deposit_num = 0;
+
+    for (address, candidate) in candidates.iter() {
+        if candidate.depositors.len() < min_depositors || candidate.other_outflows > 0 {
+            continue;
+        }
+
+        let hot_wallet = match candidate.sweeps.iter().max_by_key(|(_, sweeps)| **sweeps) {
+            None => continue,
+            Some((hot_wallet, _)) => hot_wallet,
+        };
+
+        deposit_num += 1;
+        wallet_pool.update(database.wallets.set_deposit_for(address, hot_wallet));
+
+        if wallet_pool.len() >= WALLET_BATCH_SIZE {
+            wallet_pool
+                .commit(true)
+                .await
+                .expect("Failed to update deposit addresses");
+        }
+    }
+
+    wallet_pool
+        .commit(true)
+        .await
+        .expect("Failed to update deposit addresses");
+
+    println!(
+        "Flagged {} deposit addresses of {} hot wallets",
+        deposit_num,
+        hot_wallets.len()
+    );
+}
+
+/// Transfers between a wallet and one counterparty in one token. Volumes are hex encoded raw
+/// amounts like the transfer values.
+#[derive(Serialize, Default)]
+pub struct CounterpartyToken {
+    pub token: Address,
+    pub transfers: u64,
+    pub sent: String,
+    pub received: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct Counterparty {
+    pub address: Address,
+    pub transfers: u64,
+    pub tokens: Vec<CounterpartyToken>,
+}
+
+/// Top counterparties of `wallet` by transfer count within a block range, with the volume per
+/// token in both directions. Reads the `wallet_transfers` view, so it is a single index scan over
+/// (wallet, block).
+pub async fn top_counterparties(
+    database: &Database,
+    wallet: &str,
+    from_block: Block,
+    to_block: Block,
+    limit: usize,
+) -> Vec<Counterparty> {
+    let mut filter = block_range_filter(from_block, to_block);
+    filter.insert("wallet", wallet.to_lowercase());
+    spam::exclude(&mut filter);
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.wallet_transfers.collection.clone(),
+        filter,
+        None,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query wallet transfers!");
+
+    let mut edges: HashMap<Address, HashMap<Address, (u64, U256, U256)>> = HashMap::new();
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read wallet transfers!")
+    {
+        let transfer: WalletTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize wallet transfer!");
+        let value = U256::from_str_radix(&transfer.value, 16).unwrap_or_default();
+
+        let edge = edges
+            .entry(transfer.counterparty)
+            .or_default()
+            .entry(transfer.token)
+            .or_default();
+        edge.0 += 1;
+        match transfer.direction {
+            TransferDirection::Out => edge.1 = edge.1.saturating_add(value),
+            TransferDirection::In => edge.2 = edge.2.saturating_add(value),
+        }
+    }
+
+    let mut counterparties: Vec<Counterparty> = edges
+        .into_iter()
+        .map(|(address, tokens)| {
+            let mut tokens: Vec<CounterpartyToken> = tokens
+                .into_iter()
+                .map(|(token, (transfers, sent, received))| CounterpartyToken {
+                    token,
+                    transfers,
+                    sent: format!("{:x}", sent),
+                    received: format!("{:x}", received),
+                })
+                .collect();
+            tokens.sort_by_key(|t| std::cmp::Reverse(t.transfers));
+
+            Counterparty {
+                address,
+                transfers: tokens.iter().map(|t| t.transfers).sum(),
+                tokens,
+            }
+        })
+        .collect();
+
+    counterparties.sort_by(|a, b| {
+        b.transfers
+            .cmp(&a.transfers)
+            .then_with(|| a.address.cmp(&b.address))
+    });
+    counterparties.truncate(limit);
+    counterparties
+}
+
+/// Wallets holding at least `min_amount` (raw amount) of the ERC20 `token` during the whole block
+/// range: from the end of block `from_block - 1` through the end of `to_block`. Balances are
+/// replayed from the indexed transfers, so the token must have been indexed from its deployment.
+pub async fn token_holders(
+    database: &Database,
+    token: &str,
+    min_amount: U256,
+    from_block: Block,
+    to_block: Block,
+) -> BTreeSet<Address> {
+    let mut filter = block_range_filter(0, to_block);
+    filter.insert("token", token);
+    filter.insert("erc", ContractType::ERC20.as_str());
+
+    let options = FindOptions::builder().sort(doc! {"block": 1i64}).build();
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_transfers.collection.clone(),
+        filter,
+        options,
+        (0, to_block),
+    )
+    .await
+    .expect("Failed to query erc transfers!");
+
+    let mut balances: HashMap<Address, U256> = HashMap::new();
+    let mut holders: Option<BTreeSet<Address>> = None;
+    let mut touched: HashSet<Address> = HashSet::new();
+    let mut current_block: Block = 0;
+
+    let holding = |balances: &HashMap<Address, U256>, wallet: &Address| {
+        balances.get(wallet).copied().unwrap_or_default() >= min_amount
+    };
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc transfers!")
+    {
+        let transfer: ERCTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc transfer!");
+
+        if transfer.block != current_block {
+            // Balances are compared at block boundaries, intra block movements don't count.
+            if let Some(holders) = holders.as_mut() {
+                holders.retain(|wallet| !touched.contains(wallet) || holding(&balances, wallet));
+            }
+            touched.clear();
+            current_block = transfer.block;
+        }
+
+        if holders.is_none() && transfer.block >= from_block {
+            holders = Some(
+                balances
+                    .keys()
+                    .filter(|wallet| holding(&balances, wallet))
+                    .cloned()
+                    .collect(),
+            );
+        }
+
+        let value = U256::from_str_radix(&transfer.value_or_token_id, 16).unwrap_or_default();
+        let from = balances.entry(transfer.from.to_owned()).or_default();
+        *from = from.saturating_sub(value);
+        let to = balances.entry(transfer.to.to_owned()).or_default();
+        *to = to.saturating_add(value);
+
+        touched.insert(transfer.from);
+        touched.insert(transfer.to);
+    }
+
+    let mut holders = holders.unwrap_or_else(|| {
+        balances
+            .keys()
+            .filter(|wallet| holding(&balances, wallet))
+            .cloned()
+            .collect()
+    });
+    holders.retain(|wallet| {
+        wallet != ZERO_ADDRESS && (!touched.contains(wallet) || holding(&balances, wallet))
+    });
+    holders
+}
+
+/// Wallets that bought a token of `collection` within the block range.
+pub async fn collection_buyers(
+    database: &Database,
+    collection: &str,
+    from_block: Block,
+    to_block: Block,
+) -> BTreeSet<Address> {
+    let mut filter = block_range_filter(from_block, to_block);
+    filter.insert("token", collection);
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_sales.collection.clone(),
+        filter,
+        None,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query erc sales!");
+
+    let mut buyers: BTreeSet<Address> = BTreeSet::new();
+
+    while cursor.advance().await.expect("Failed to read erc sales!") {
+        let sale: Sale = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc sale!");
+        buyers.insert(sale.buyer);
+    }
+
+    buyers
+}
+
+/// Wallets qualifying for an airdrop within the block range: holders of at least `min_amount`
+/// whole tokens of `hold_token` and buyers from any of the `bought_from` collections. With
+/// `match_any` a wallet qualifies by meeting one of the given criteria, otherwise it has to meet
+/// all of them.
+pub async fn airdrop_wallets(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    hold_token: Option<&str>,
+    min_amount: u64,
+    bought_from: &[Address],
+    match_any: bool,
+) -> Vec<Address> {
+    let mut matches: Vec<BTreeSet<Address>> = vec![];
+
+    if let Some(token) = hold_token {
+        let (token, min_amount) = Ronin::erc20_amount(token, min_amount)
+            .unwrap_or_else(|| panic!("Unknown ERC20 token: {token}"));
+
+        matches.push(token_holders(database, &token, min_amount, from_block, to_block).await);
+    }
+
+    for collection in bought_from {
+        matches.push(
+            collection_buyers(database, &collection.to_lowercase(), from_block, to_block).await,
+        );
+    }
+
+    let mut matches = matches.into_iter();
+    let first = matches.next().unwrap_or_default();
+
+    matches
+        .fold(first, |wallets, other| match match_any {
+            true => wallets.union(&other).cloned().collect(),
+            false => wallets.intersection(&other).cloned().collect(),
+        })
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,70 @@
+use std::future::Future;
+
+use log::info;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOneOptions;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::mongo::collections::query_cache::CachedQuery;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+
+/// Block of the latest indexed transaction.
+pub async fn indexed_head(database: &Database) -> Block {
+    database
+        .transactions
+        .collection
+        .find_one(
+            None,
+            FindOneOptions::builder()
+                .sort(doc! {"block": -1i64})
+                .build(),
+        )
+        .await
+        .expect("Failed to query transactions!")
+        .map(|tx| tx.block)
+        .unwrap_or_default()
+}
+
+/// Returns the cached result of `key` while the indexer has advanced less than `cache_blocks`
+/// blocks since it was computed, otherwise runs `query` and caches its result. A `cache_blocks` of
+/// 0 disables the cache.
+pub async fn cached<T, F, Fut>(database: &Database, cache_blocks: u64, key: &str, query: F) -> Value
+where
+    T: Serialize,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = T>,
+{
+    let to_json = |result: T| serde_json::to_value(result).expect("Failed to serialize result!");
+
+    if cache_blocks == 0 {
+        return to_json(query().await);
+    }
+
+    let head = indexed_head(database).await;
+
+    if let Some(cached) = database.query_cache.get(key).await {
+        if head < cached.block + cache_blocks {
+            if let Ok(value) = mongodb::bson::from_bson(cached.value) {
+                info!("[INFO] Using cached {key} of block {}", cached.block);
+                return value;
+            }
+        }
+    }
+
+    let value = to_json(query().await);
+
+    database
+        .query_cache
+        .store(&CachedQuery {
+            key: key.to_string(),
+            block: head,
+            value: mongodb::bson::to_bson(&value).expect("Failed to convert result!"),
+            created_at: DateTime::now(),
+        })
+        .await
+        .expect("Failed to store cached query!");
+
+    value
+}
@@ -1,10 +1,172 @@
-use clap::Parser;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
 use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use mongodb::options::Acknowledgment;
+
+use crate::mongo::collections::storage_profile::StorageProfile;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Compact,
+    Json,
+}
+
+/// File format of `export`
+#[derive(ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// What to do with a block the provider still returns as null after the retries
+#[derive(ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MissingBlockPolicy {
+    /// Stop the indexer
+    Fail,
+    /// Record the block in missing_blocks and continue with the next one
+    Skip,
+    /// Load the block from --fallback-web3-hostname, skip it if that fails too
+    Fallback,
+}
+
+/// Block at which streaming stops: a fixed height or the chain head, re-evaluated while streaming
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StopBlock {
+    Latest,
+    Number(u64),
+}
+
+impl FromStr for StopBlock {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "latest" => Ok(StopBlock::Latest),
+            number => number
+                .parse::<u64>()
+                .map(StopBlock::Number)
+                .map_err(|_| f!("Invalid stop block {number}: expected a block number or latest")),
+        }
+    }
+}
+
+/// Destination of the decoded documents: "mongo" or "file:<dir>" for newline delimited JSON files
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Sink {
+    Mongo,
+    File(PathBuf),
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            None if value == "mongo" => Ok(Sink::Mongo),
+            Some(("file", dir)) if !dir.is_empty() => Ok(Sink::File(PathBuf::from(dir))),
+            _ => Err(f!("Invalid sink {value}: expected mongo or file:<dir>")),
+        }
+    }
+}
+
+/// UTC date ("2022-08-01") or RFC 3339 timestamp ("2022-08-01T12:00:00Z")
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Date(pub mongodb::bson::DateTime);
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let timestamp = match value.contains('T') {
+            true => value.to_string(),
+            false => f!("{value}T00:00:00Z"),
+        };
+
+        mongodb::bson::DateTime::parse_rfc3339_str(&timestamp)
+            .map(Date)
+            .map_err(|_| f!("Invalid date {value}: expected YYYY-MM-DD or an RFC 3339 timestamp"))
+    }
+}
+
+/// Write concern of one collection, see `--write-concern`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionWriteConcern {
+    /// Collection name, "*" for all collections without their own write concern
+    pub collection: String,
+    pub w: Acknowledgment,
+    pub journal: bool,
+}
+
+impl FromStr for CollectionWriteConcern {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (collection, concern) = value.split_once('=').ok_or_else(|| {
+            f!("Invalid write concern {value}: expected <collection>=<w>[+journal]")
+        })?;
+        let (w, journal) = match concern.strip_suffix("+journal") {
+            Some(w) => (w, true),
+            None => (concern, false),
+        };
+        let w = match w.parse::<u32>() {
+            Ok(nodes) => Acknowledgment::Nodes(nodes),
+            Err(_) if w == "majority" => Acknowledgment::Majority,
+            Err(_) => {
+                return Err(f!(
+                    "Invalid write concern {w}: expected majority or a node count"
+                ))
+            }
+        };
+
+        Ok(CollectionWriteConcern {
+            collection: collection.to_string(),
+            w,
+            journal,
+        })
+    }
+}
+
+/// Minimum amount in whole tokens for an ERC20 transfer to be recorded as whale transfer
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WhaleThreshold {
+    /// Token address or contract name
+    pub token: String,
+    pub amount: u64,
+}
+
+impl FromStr for WhaleThreshold {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (token, amount) = value
+            .split_once('=')
+            .ok_or_else(|| f!("Invalid whale threshold {value}: expected <token>=<amount>"))?;
+
+        Ok(WhaleThreshold {
+            token: token.to_lowercase(),
+            amount: amount
+                .parse::<u64>()
+                .map_err(|_| f!("Invalid whale threshold amount {amount}"))?,
+        })
+    }
+}
 
 /// Better Ronin blockchain indexer
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(author = "wehmoen#0001", version, about, long_about = None)]
+#[clap(args_override_self = true)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// TOML file with options of the command line, e.g. `db_uri = "mongodb://.."` or
+    /// `feature_governance = false`. Options given on the command line override the file
+    #[clap(long, value_parser)]
+    pub config: Option<PathBuf>,
     /// MongoDB connection URL
     #[clap(
         short = 'u',
@@ -16,17 +178,140 @@ pub struct Args {
     /// MongoDB database name
     #[clap(short = 'd', long, value_parser, default_value = "roninchain")]
     pub db_name: String,
+    /// MongoDB connection URL of the cold database `tier` moves old blocks to, read by the
+    /// analysis, export, explorer and stats commands for blocks below the cold cutoff (defaults to
+    /// --db-uri)
+    #[clap(long, value_parser, global = true)]
+    pub cold_db_uri: Option<String>,
+    /// TOML or JSON file with the tracked contracts, replaces the built in contract list
+    #[clap(long, value_parser)]
+    pub contracts: Option<std::path::PathBuf>,
+    /// TOML or JSON file with additional events to decode into their own collections
+    #[clap(long, value_parser)]
+    pub events: Option<std::path::PathBuf>,
+    /// Axie equipment contract emitting ItemEquipped/ItemUnequipped, charms and runes are not
+    /// tracked without it
+    #[clap(long, value_parser)]
+    pub equipment_contract: Option<String>,
+    /// Multisig contract emitting the governance Submission, Confirmation, Revocation and
+    /// Execution events, can be repeated. Governance events are not indexed without one
+    #[clap(long = "governance-contract", value_parser)]
+    pub governance_contracts: Vec<String>,
     /// Web3 Websocket Host
     #[clap(short = 'w', long, value_parser, default_value = "ws://localhost:8546")]
     pub web3_hostname: String,
-    /// Replay - Drops the entire database and starts reindexing the chain from block 0
+    /// Web3 host to load blocks from that the primary host returns as null
+    #[clap(long, value_parser)]
+    pub fallback_web3_hostname: Option<String>,
+    /// Number of retries for blocks the provider returns as null or fails to load
+    #[clap(long, value_parser, default_value_t = 3)]
+    pub missing_block_retries: u32,
+    /// Blocks and receipts loaded ahead of the block being decoded and written, 0 to load each
+    /// block once it is processed
+    #[clap(long, value_parser, default_value_t = 4)]
+    pub prefetch_blocks: usize,
+    /// Maximum number of RPC requests per second to all providers together, shared by all
+    /// workers. 0 for no limit
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub rpc_max_qps: u32,
+    /// Maximum number of RPC requests per second of a single logs mode worker, on top of
+    /// --rpc-max-qps. 0 for no limit
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub rpc_worker_qps: u32,
+    /// Number of retries for receipts and eth_getLogs requests that fail. Blocks whose receipts
+    /// still fail are recorded in failed_blocks and skipped
+    #[clap(long, value_parser, default_value_t = 5)]
+    pub rpc_retries: u32,
+    /// Delay before the first retry of an RPC call in milliseconds, doubled per retry
+    #[clap(long, value_parser, default_value_t = 500)]
+    pub retry_backoff_ms: u64,
+    /// Upper bound of the retry delay in milliseconds
+    #[clap(long, value_parser, default_value_t = 30_000)]
+    pub retry_max_backoff_ms: u64,
+    /// Fraction by which the retry delay randomly varies, between 0 and 1
+    #[clap(long, value_parser, default_value_t = 0.25)]
+    pub retry_jitter: f64,
+    /// Behavior for blocks still missing after the retries
+    #[clap(long, value_enum, default_value_t = MissingBlockPolicy::Skip)]
+    pub missing_block_policy: MissingBlockPolicy,
+    /// Maximum number of blocks rolled back when the parent hash of a block does not match the
+    /// stored previous block. The documents of the orphaned blocks are moved to tombstones. Requires
+    /// the blocks feature, 0 disables the check
+    #[clap(long, value_parser, default_value_t = 64)]
+    pub reorg_depth: u64,
+    /// Distance in blocks to the chain head the stream stops at, so blocks that may still be
+    /// reorganized are not indexed
+    #[clap(long, value_parser, default_value_t = 50)]
+    pub reorg_offset: u64,
+    /// Replay - Drops the entire database and starts reindexing the chain from block 0. Requires
+    /// --admin-token
     #[clap(short = 'r', long, value_parser, default_value_t = false)]
     pub replay: bool,
+    /// Admin token required by replay and tier, see `set-admin-token`
+    #[clap(long, value_parser)]
+    pub admin_token: Option<String>,
+    /// Only stream while holding the leader lease, for several replicas in follow mode. Standbys
+    /// take over once the lease of the leader expires
+    #[clap(long, value_parser, default_value_t = false)]
+    pub leader_election: bool,
+    /// Seconds the leader lease stays valid without being renewed
+    #[clap(long, value_parser, default_value_t = 15)]
+    pub lease_ttl: u64,
+    /// Name of this replica in the leader lease (defaults to $HOSTNAME and the process id)
+    #[clap(long, value_parser)]
+    pub instance_id: Option<String>,
+    /// Shadow mode - Write the decoder collections to `<name>_v<version>` next to the live ones,
+    /// to backfill a new decoder version before promoting it with `promote-shadow`. Requires
+    /// --logs-mode
+    #[clap(long, value_parser)]
+    pub shadow_version: Option<u32>,
+    /// Address of the HTTP status server with /healthz and /status, e.g. 0.0.0.0:8080
+    #[clap(long, value_parser)]
+    pub status_addr: Option<std::net::SocketAddr>,
+    /// NATS server to publish committed transactions, transfers and sales to, e.g.
+    /// nats://127.0.0.1:4222
+    #[clap(long, value_parser)]
+    pub nats_url: Option<String>,
+    /// Subject prefix of the published documents, followed by the collection name
+    #[clap(long, value_parser, default_value = "fri")]
+    pub nats_subject_prefix: String,
+    /// Only publish the documents of a collection matching a rule, as <collection>=<rule>, e.g.
+    /// "erc_transfers=token == WETH and value_or_token_id >= 100 WETH". Can be repeated
+    #[clap(long = "nats-filter", value_parser)]
+    pub nats_filters: Vec<String>,
+    /// Where the stream writes decoded documents: mongo, or file:<dir> to append them as
+    /// newline delimited JSON per collection for debugging or a later bulk load. The start block is
+    /// still resumed from MongoDB, pass --start-date or --start-block for a fresh range
+    #[clap(long, value_parser, default_value = "mongo")]
+    pub sink: Sink,
+    /// Wait for the node to finish syncing instead of exiting
+    #[clap(long, value_parser, default_value_t = false)]
+    pub wait_for_sync: bool,
+    /// Log output format
+    #[clap(long, value_enum, default_value = "pretty")]
+    pub log_format: LogFormat,
     /// Show logs for empty blocks
     #[clap(short = 'l', long, value_parser, default_value_t = false)]
     pub empty_logs: bool,
+    /// Reuse cached analysis results until the indexer has advanced this many blocks (0 disables
+    /// the cache)
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub cache_blocks: u64,
+    /// Keep transfers of tokens flagged as spam airdrops in analyses and exports
+    #[clap(long, value_parser, default_value_t = false)]
+    pub include_spam: bool,
+    /// Number of runtime worker threads (0 starts one per core)
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub worker_threads: usize,
+    /// Pin each runtime worker thread to its own core, round robin over the cores the process may
+    /// run on (Linux only)
+    #[clap(long, value_parser, default_value_t = false)]
+    pub pin_workers: bool,
+    /// Interval in seconds between writes to the metrics collection (0 disables)
+    #[clap(long, value_parser, default_value_t = 60)]
+    pub metrics_interval: u64,
     /// Debug mode
-    #[clap(short = 'd', long, value_parser, default_value_t = false)]
+    #[clap(long, value_parser, default_value_t = false)]
     pub debug: bool,
     /// Debug start block
     #[clap(short = 's', long, value_parser, default_value_t = 0)]
@@ -34,23 +319,617 @@ pub struct Args {
     /// Debug stop block
     #[clap(short = 'e', long, value_parser, default_value_t = 0)]
     pub debug_stop_block: u64,
+    /// Start block. Without it the stream resumes after the last committed block and logs mode
+    /// after its last scanned block
+    #[clap(long, value_parser, conflicts_with_all = &["start-date", "from-head"])]
+    pub start_block: Option<u64>,
+    /// Stop block: a block number or "latest" to keep following the chain head
+    #[clap(long, value_parser)]
+    pub stop_block: Option<StopBlock>,
+    /// Start indexing, or analyzing with a subcommand, at the first block of this date
+    #[clap(long, value_parser)]
+    pub start_date: Option<Date>,
+    /// Start indexing this many blocks behind the current chain head, for a quick catch-up run
+    #[clap(long, value_parser, conflicts_with = "start-date")]
+    pub from_head: Option<u64>,
+    /// Stop indexing, or analyzing with a subcommand, before the first block of this date
+    #[clap(long, value_parser)]
+    pub stop_date: Option<Date>,
+    /// Interval in seconds for re-evaluating "--stop-block latest"
+    #[clap(long, value_parser, default_value_t = 600)]
+    pub stop_block_refresh: u64,
+    /// Storage profile of the database, only applies to the initial sync. Without it the profile
+    /// of the database is used, full for a new one. Compact omits derivable fields and shortens
+    /// rarely read field names to reduce the document size
+    #[clap(long, value_enum)]
+    pub storage_profile: Option<StorageProfile>,
+    /// Logs mode - Index ERC transfers, sales and decoded events from eth_getLogs over block
+    /// ranges instead of loading every transaction and receipt. Transactions, wallet updates, RON
+    /// balances and token discovery are not available in this mode
+    #[clap(long, value_parser, default_value_t = false)]
+    pub logs_mode: bool,
+    /// Number of blocks per eth_getLogs request in logs mode
+    #[clap(long, value_parser, default_value_t = 2000)]
+    pub logs_range: u64,
+    /// Number of blocks per checkpointed chunk in logs mode. The progress of every chunk is stored,
+    /// a restart only indexes what is left of unfinished chunks
+    #[clap(long, value_parser, default_value_t = 100_000)]
+    pub chunk_blocks: u64,
+    /// Number of chunks indexed concurrently in logs mode. ERC20 balance reconciliation only runs
+    /// with a single worker
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub logs_workers: usize,
+    /// File the completion report of a logs mode backfill is written to as JSON. The report is
+    /// also kept in the `logs_mode.report` setting
+    #[clap(long, value_parser)]
+    pub report: Option<std::path::PathBuf>,
     /// Disable logging of wallet updates
-    #[clap(short = 'w', long, value_parser, default_value_t = true)]
+    #[clap(long, value_parser, default_value_t = true)]
     pub debug_disable_wallet_updates: bool,
     /// Feature: ERC Transfers
     #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
     pub feature_erc_transfers: bool,
+    /// Feature: Per wallet transfer view (wallet_transfers)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_wallet_transfers: bool,
+    /// Feature: Register token contracts missing from the contract list in `tokens` and index their
+    /// transfers
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_contract_discovery: bool,
+    /// Deployer address whose token deployments are tracked as official tokens, can be repeated
+    #[clap(long = "official-deployer", value_parser)]
+    pub official_deployers: Vec<String>,
+    /// Record ERC20 transfers of at least <amount> whole tokens as whale transfers, e.g.
+    /// "WETH=100" or "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5=100". Can be repeated
+    #[clap(long = "whale-threshold", value_parser)]
+    pub whale_thresholds: Vec<WhaleThreshold>,
+    /// Write concern of a collection as <collection>=<w>[+journal], w being majority or a node
+    /// count, e.g. "erc721_sales=majority+journal" or "erc_transfers=1" for a fast backfill. The
+    /// collection * sets the default of the others, otherwise the server default applies. Can be
+    /// repeated
+    #[clap(long = "write-concern", value_parser)]
+    pub write_concerns: Vec<CollectionWriteConcern>,
+    /// Insert the documents of the blocks of the stream in a MongoDB transaction, so a crash never
+    /// leaves them partially inserted. The transaction covers the collections the blocks are
+    /// decoded into, not the collections derived from them (balances, RON balances, NFT owners,
+    /// ERC1155 supply, wallet and whale transfers, price history, equipment) nor the stream
+    /// progress, which are written after it. Only available on replica sets and sharded clusters,
+    /// standalone servers are written without
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub atomic_commits: bool,
+    /// Blocks the stream collects before committing them together, fewer round trips for sparse
+    /// historical ranges. Blocks within --reorg-depth of the stop block are committed one by one
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub flush_blocks: u64,
+    /// Pending documents and updates that commit the collected blocks before --flush-blocks is
+    /// reached
+    #[clap(long, value_parser, default_value_t = 10_000)]
+    pub flush_documents: usize,
+    /// Upserts sent to MongoDB per unordered bulk update, e.g. of the wallet updates of a block
+    #[clap(long, value_parser, default_value_t = 1000)]
+    pub update_batch_size: usize,
+    /// Feature: Native RON balances from transaction values and gas fees
+    #[clap(long, value_parser, default_value_t = false, action = clap::ArgAction::Set)]
+    pub feature_ron_balances: bool,
+    /// Feature: Current ERC20 balance per wallet and token (balances)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_erc20_balances: bool,
+    /// Interval in blocks between checks of the changed ERC20 balances against balanceOf (0
+    /// disables)
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub balance_reconcile_interval: u64,
+    /// Feature: Native RON transfers from transaction values (native_transfers)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_native_transfers: bool,
+    /// Feature: ERC 1155 supply per token id
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_erc1155_supply: bool,
+    /// Feature: Current owner per ERC721 and ERC1155 token id (nft_owners)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_nft_owners: bool,
+    /// Feature: Sale price history per NFT (price_history)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_price_history: bool,
+    /// Feature: Katana pair reserves from Sync events
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_pair_reserves: bool,
+    /// Feature: Multisig/governance proposal events of the --governance-contract contracts
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_governance: bool,
+    /// Feature: RON/AXS staking and delegation events
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_staking: bool,
+    /// Feature: Charm and rune loadout per Axie (equipment_events, axie_equipment), requires
+    /// --equipment-contract
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_axie_equipment: bool,
+    /// Feature: Record malformed transaction receipts in receipt_anomalies
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_receipt_checks: bool,
     /// Feature: ERC 721 Sales
     #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
     pub feature_erc_721_sales: bool,
-    /// Feature: Transactions
+    /// Feature: Transactions. Stored without status, gas used and created contract when no other
+    /// enabled feature needs the receipts, which are then not loaded
     #[clap(long, value_parser, default_value_t = true,  action = clap::ArgAction::Set)]
     pub feature_transactions: bool,
+    /// Feature: Block headers with hashes, timestamps, miner and gas used (blocks)
+    #[clap(long, value_parser, default_value_t = true, action = clap::ArgAction::Set)]
+    pub feature_blocks: bool,
     /// Feature: Wallet Updates
     #[clap(long, value_parser, default_value_t = false, action = clap::ArgAction::Set)]
     pub feature_wallet_updates: bool,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Cluster wallets by funding source and co-spending over the indexed transfers
+    ClusterWallets {
+        /// First block to analyze
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to analyze (0 analyzes up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Ignore funders that funded more wallets than this (exchanges, bridges, faucets)
+        #[clap(long, value_parser, default_value_t = 50)]
+        max_funder_fanout: usize,
+    },
+    /// Flag tokens sent as unsolicited airdrops to many wallets as spam and mark their transfers
+    ClassifySpam {
+        /// First block to analyze
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to analyze (0 analyzes up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Minimum number of wallets receiving the identical transfer from one sender
+        #[clap(long, value_parser, default_value_t = 1000)]
+        min_recipients: usize,
+        /// Flag a token only if fewer than this share of its recipients moved it on
+        #[clap(long, value_parser, default_value_t = 0.01)]
+        max_active_ratio: f64,
+    },
+    /// Report transfers and sales whose transaction or block is not stored, as left behind by
+    /// partially committed blocks. Checks the transactions and blocks that are enabled as features
+    CheckIntegrity {
+        /// First block to check
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to check (0 checks up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// References resolved per query
+        #[clap(long, value_parser, default_value_t = 1000)]
+        batch_size: usize,
+        /// Index the blocks holding orphans again
+        #[clap(long, value_parser, default_value_t = false)]
+        repair: bool,
+    },
+    /// Flag likely exchange deposit addresses that sweep their inflows to known hot wallets
+    DetectDepositAddresses {
+        /// First block to analyze
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to analyze (0 analyzes up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Exchange hot wallet address, can be repeated
+        #[clap(long = "hot-wallet", value_parser, required = true)]
+        hot_wallets: Vec<String>,
+        /// Minimum number of distinct depositors for a deposit address
+        #[clap(long, value_parser, default_value_t = 5)]
+        min_depositors: usize,
+    },
+    /// Print the top counterparties of a wallet as JSON
+    Counterparties {
+        /// Wallet address
+        #[clap(long, value_parser)]
+        wallet: String,
+        /// First block to include
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to include (0 includes everything up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Number of counterparties to return
+        #[clap(long, value_parser, default_value_t = 25)]
+        limit: usize,
+    },
+    /// Print the wallets qualifying for an airdrop as JSON
+    AirdropWallets {
+        /// First block of the eligibility window
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block of the eligibility window (0 includes everything up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// ERC20 token (address or contract name) that had to be held during the whole window
+        #[clap(long, value_parser)]
+        hold_token: Option<String>,
+        /// Minimum balance of --hold-token in whole tokens
+        #[clap(long, value_parser, default_value_t = 1)]
+        min_amount: u64,
+        /// NFT collection address a wallet bought from within the window, can be repeated
+        #[clap(long = "bought-from", value_parser)]
+        bought_from: Vec<String>,
+        /// Qualify wallets meeting any criterion instead of all of them
+        #[clap(long, value_parser, default_value_t = false)]
+        match_any: bool,
+    },
+    /// Attach verified source metadata to a contract. Its ABI is used to decode the inputs and
+    /// events of the contract
+    AddContractMetadata {
+        /// Contract address
+        #[clap(long, value_parser)]
+        address: String,
+        /// Contract name
+        #[clap(long, value_parser)]
+        name: String,
+        /// ABI JSON file, a plain ABI array or a build artifact with an "abi" field
+        #[clap(long, value_parser)]
+        abi: std::path::PathBuf,
+        /// Compiler version
+        #[clap(long, value_parser)]
+        compiler: Option<String>,
+    },
+    /// Import contract metadata from a Sourcify style archive (<dir>/**/<address>/metadata.json)
+    ImportSourcify {
+        /// Archive directory
+        #[clap(value_parser)]
+        dir: std::path::PathBuf,
+    },
+    /// Print a transaction with its decoded transfers, sales and logs as JSON
+    Transaction {
+        /// Transaction hash
+        #[clap(value_parser)]
+        hash: String,
+    },
+    /// Roll completed days up into per token daily metrics (token_daily), marketplace
+    /// revenue (marketplace_fees) and the net worth of watchlisted wallets (net_worth)
+    Rollup {
+        /// Marketplace treasury address, ERC20 transfers into it count as protocol revenue
+        #[clap(long, value_parser)]
+        treasury: Option<String>,
+    },
+    /// Add wallets to or remove them from the net worth watchlist and print it
+    Watchlist {
+        /// Wallet to add, can be repeated
+        #[clap(long, value_parser)]
+        add: Vec<String>,
+        /// Wallet to remove, can be repeated
+        #[clap(long, value_parser)]
+        remove: Vec<String>,
+    },
+    /// Print the daily net worth series of a watchlisted wallet as JSON
+    Portfolio {
+        /// Wallet address
+        #[clap(value_parser)]
+        wallet: String,
+    },
+    /// Record blocks and their decoded documents as regression test fixtures
+    CaptureFixtures {
+        /// Block numbers to capture
+        #[clap(value_parser, required = true)]
+        blocks: Vec<u64>,
+        /// Fixture directory
+        #[clap(long, value_parser, default_value = "fixtures")]
+        dir: std::path::PathBuf,
+    },
+    /// Print the block number and timestamp of a date or block as JSON
+    BlockLookup {
+        /// Find the first block at or after this date
+        #[clap(
+            long,
+            value_parser,
+            conflicts_with = "block",
+            required_unless_present = "block"
+        )]
+        date: Option<Date>,
+        /// Find the timestamp of this block
+        #[clap(long, value_parser)]
+        block: Option<u64>,
+    },
+    /// Fetch the tokenURI/uri metadata of the NFTs in nft_owners into token_metadata
+    FetchMetadata {
+        /// Only fetch the metadata of this token contract
+        #[clap(long, value_parser)]
+        token: Option<String>,
+        /// Maximum number of tokens to fetch
+        #[clap(long, value_parser, default_value_t = 1000)]
+        limit: usize,
+        /// IPFS gateway used for ipfs:// URIs
+        #[clap(long, value_parser, default_value = "https://ipfs.io")]
+        ipfs_gateway: String,
+        /// Fetch tokens whose previous fetch failed again
+        #[clap(long, value_parser, default_value_t = false)]
+        retry_failed: bool,
+    },
+    /// Export the transactions, transfers and sales of a list of wallets as JSON lines
+    ExportCohort {
+        /// File with one wallet address per line
+        #[clap(long, value_parser)]
+        wallets: std::path::PathBuf,
+        /// JSON lines file to write
+        #[clap(long, value_parser)]
+        out: std::path::PathBuf,
+        /// First block to export
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to export (0 exports up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Wallets per query
+        #[clap(long, value_parser, default_value_t = 1000)]
+        batch_size: usize,
+    },
+    /// Export a collection to CSV or Parquet files partitioned by block range, e.g. for Spark or
+    /// DuckDB
+    Export {
+        /// Collection to export
+        #[clap(long, value_parser)]
+        collection: String,
+        /// First block to export
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to export (0 exports up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// File format
+        #[clap(long, value_enum, default_value = "parquet")]
+        format: ExportFormat,
+        /// Directory the files are written to
+        #[clap(long, value_parser)]
+        out: std::path::PathBuf,
+        /// Blocks per file, 0 writes a single file and also exports collections without a block
+        #[clap(long, value_parser, default_value_t = 100_000)]
+        partition_blocks: u64,
+    },
+    /// Bulk insert the NDJSON files of --sink file:<dir> or the Parquet files of export into the
+    /// collections they are named after, skipping documents that are already present. Requires
+    /// --admin-token
+    Import {
+        /// Directory of the files, or a single file
+        #[clap(long, value_parser)]
+        path: std::path::PathBuf,
+        /// Documents inserted per batch
+        #[clap(long, value_parser, default_value_t = 1000)]
+        batch_size: usize,
+        /// Import files again that were already imported unchanged
+        #[clap(long, value_parser, default_value_t = false)]
+        force: bool,
+    },
+    /// Print the sale price history of an NFT as JSON
+    PriceHistory {
+        /// Token contract address
+        #[clap(long, value_parser)]
+        token: String,
+        /// Token id, decimal or 0x prefixed hex
+        #[clap(long, value_parser)]
+        token_id: String,
+    },
+    /// Print the charms and runes currently equipped to an Axie as JSON
+    AxieEquipment {
+        /// Axie id, decimal or 0x prefixed hex
+        #[clap(long, value_parser)]
+        axie_id: String,
+    },
+    /// Benchmark --web3-hostname and --fallback-web3-hostname: receipts per second, eth_getLogs
+    /// throughput and range limit, eth_getBlockReceipts and batch support. Prints the recommended
+    /// indexing mode and concurrency as JSON and stores the profile for the indexer
+    Probe {
+        /// Number of blocks below the head to load receipts for
+        #[clap(long, value_parser, default_value_t = 20)]
+        sample_blocks: u64,
+        /// Highest number of blocks in flight to try
+        #[clap(long, value_parser, default_value_t = 8)]
+        max_concurrency: usize,
+    },
+    /// Decode the logs stored in the decode_failures collection again
+    RetryDecodeFailures,
+    /// Index the blocks recorded in failed_blocks again, removing those that succeed
+    RetryFailedBlocks,
+    /// Index the blocks the stream marked pending in block_status but never completed again, as
+    /// left behind by a crash during a commit. Documents that were already stored are applied to
+    /// the derived collections (balances, owners, supply, price history, ...) whose update the
+    /// crash cut off, never twice
+    RepairBlocks,
+    /// Add the native number fields to the transactions, transfers and sales written by older
+    /// releases. Requires --admin-token
+    Migrate,
+    /// Delete the documents rolled back in reorgs from tombstones. Requires --admin-token
+    PruneTombstones {
+        /// Keep the tombstones of the last days
+        #[clap(long, value_parser, default_value_t = 30)]
+        older_than_days: u64,
+    },
+    /// Move block ranges older than the newest --keep-blocks blocks to a cold database. Requires
+    /// --admin-token
+    Tier {
+        /// MongoDB database name of the cold database
+        #[clap(long, value_parser, default_value = "roninchain_cold")]
+        cold_db_name: String,
+        /// Number of most recent indexed blocks to keep in the hot database
+        #[clap(long, value_parser, default_value_t = 1_000_000)]
+        keep_blocks: u64,
+        /// Documents moved per batch
+        #[clap(long, value_parser, default_value_t = 10_000)]
+        batch_size: i64,
+    },
+    /// Delete the documents of a block range from every collection keyed by block, take them back
+    /// out of the balances, owners, supply and price history and reset the checkpoints to its
+    /// start, so the range is indexed again. Blocks moved to cold storage by tier can't be rolled
+    /// back. Requires --admin-token
+    Rollback {
+        /// First block to delete
+        #[clap(long, value_parser)]
+        from_block: u64,
+        /// Last block to delete
+        #[clap(long, value_parser)]
+        to_block: u64,
+    },
+    /// Replace the decoder collections with those of a shadow version, keeping the current ones as
+    /// `<name>_pre_v<version>`. Requires --admin-token
+    PromoteShadow {
+        /// Shadow version to promote
+        #[clap(long, value_parser)]
+        version: u32,
+        /// Promote even if the shadow has not caught up with the live pipeline
+        #[clap(long, value_parser, default_value_t = false)]
+        force: bool,
+    },
+    /// Print transfers per token, sales per collection, active wallets per day, the largest blocks
+    /// and the suppressed duplicates per collection as tables
+    Stats {
+        /// First block to include
+        #[clap(long, value_parser, default_value_t = 0)]
+        from_block: u64,
+        /// Last block to include (0 includes everything up to the latest indexed block)
+        #[clap(long, value_parser, default_value_t = 0)]
+        to_block: u64,
+        /// Number of tokens, collections and blocks to list
+        #[clap(long, value_parser, default_value_t = 10)]
+        limit: i64,
+        /// Number of days of active wallets to list, latest first
+        #[clap(long, value_parser, default_value_t = 30)]
+        days: i64,
+        /// Print the stats as JSON
+        #[clap(long, value_parser, default_value_t = false)]
+        json: bool,
+    },
+    /// Print the last indexed block, chain head, lag, document counts per collection and the
+    /// features the stream last ran with
+    Status {
+        /// Print the report as JSON
+        #[clap(long, value_parser, default_value_t = false)]
+        json: bool,
+    },
+    /// Set the admin token of the destructive commands, replacing it requires the current one as
+    /// --admin-token
+    SetAdminToken {
+        /// New admin token
+        #[clap(long, value_parser)]
+        token: String,
+    },
+}
+
+impl Args {
+    /// State of the `--feature-*` toggles by feature name.
+    pub fn features(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("erc_transfers", self.feature_erc_transfers),
+            ("wallet_transfers", self.feature_wallet_transfers),
+            ("contract_discovery", self.feature_contract_discovery),
+            ("ron_balances", self.feature_ron_balances),
+            ("erc20_balances", self.feature_erc20_balances),
+            ("native_transfers", self.feature_native_transfers),
+            ("erc1155_supply", self.feature_erc1155_supply),
+            ("nft_owners", self.feature_nft_owners),
+            ("price_history", self.feature_price_history),
+            ("pair_reserves", self.feature_pair_reserves),
+            ("governance", self.feature_governance),
+            ("staking", self.feature_staking),
+            ("axie_equipment", self.feature_axie_equipment),
+            ("receipt_checks", self.feature_receipt_checks),
+            ("erc_721_sales", self.feature_erc_721_sales),
+            ("transactions", self.feature_transactions),
+            ("blocks", self.feature_blocks),
+            ("wallet_updates", self.feature_wallet_updates),
+        ]
+    }
+
+    /// Block to start at instead of resuming: `--start-block`, or `--debug-start-block` in debug
+    /// mode.
+    pub fn first_block(&self) -> Option<u64> {
+        self.start_block
+            .or_else(|| Some(self.debug_start_block).filter(|block| self.debug && *block > 0))
+    }
+}
+
+/// Path given as `--config <file>` or `--config=<file>`.
+fn config_path(argv: &[OsString]) -> Option<PathBuf> {
+    let mut argv = argv.iter().map(|arg| arg.to_string_lossy());
+
+    while let Some(arg) = argv.next() {
+        if arg == "--" {
+            return None;
+        }
+        if arg == "--config" {
+            return argv.next().map(|path| PathBuf::from(path.as_ref()));
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    None
+}
+
+fn config_value(key: &str, value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.to_owned(),
+        serde_json::Value::Number(value) => value.to_string(),
+        serde_json::Value::Bool(value) => value.to_string(),
+        _ => panic!("Invalid value for {key} in config file: {value}"),
+    }
+}
+
+/// Options of a config file as command line arguments. Keys are the long names of the options,
+/// with dashes or underscores. Arrays repeat the option.
+fn config_args(path: &Path) -> Vec<OsString> {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read config file {}: {err}", path.display()));
+    let options: serde_json::Map<String, serde_json::Value> = toml_edit::de::from_str(&content)
+        .unwrap_or_else(|err| panic!("Failed to parse config file {}: {err}", path.display()));
+
+    let command = Args::command();
+    let mut args = vec![];
+
+    for (key, value) in options {
+        let name = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()) && name != "config")
+            .unwrap_or_else(|| panic!("Unknown option {key} in config file {}", path.display()));
+
+        let values = match value {
+            serde_json::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match (arg.get_action(), &value) {
+                (ArgAction::SetTrue, serde_json::Value::Bool(true)) => {
+                    args.push(OsString::from(f!("--{name}")))
+                }
+                (ArgAction::SetTrue, serde_json::Value::Bool(false)) => {}
+                _ => args.push(OsString::from(f!(
+                    "--{name}={}",
+                    config_value(&key, &value)
+                ))),
+            }
+        }
+    }
+
+    args
+}
+
+/// The defaults of every option without a subcommand, for embedders building the arguments in code
+/// instead of parsing a command line.
+impl Default for Args {
+    fn default() -> Self {
+        Args::parse_from(["fri"])
+    }
+}
+
+/// Parses the command line. The options of `--config` are placed in front of it, so options given
+/// on the command line override those of the file.
 pub fn parse() -> Args {
-    Args::parse()
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+
+    if let Some(path) = config_path(&argv) {
+        let options = config_args(&path);
+        argv.splice(1..1, options);
+    }
+
+    Args::parse_from(argv)
 }
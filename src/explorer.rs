@@ -0,0 +1,195 @@
+use std::str::FromStr;
+
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use web3::types::{Log, TransactionId, H256};
+
+use crate::abi::{AbiRegistry, DecodedCall};
+use crate::mongo::collections::auction::Auction;
+use crate::mongo::collections::axie_sale::Sale;
+use crate::mongo::collections::contract_event::ContractEvent;
+use crate::mongo::collections::decode_failure::DecodeFailure;
+use crate::mongo::collections::equipment_event::EquipmentEvent;
+use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::governance_event::GovernanceEvent;
+use crate::mongo::collections::order::OrderEvent;
+use crate::mongo::collections::pair_reserve::PairReserve;
+use crate::mongo::collections::staking_event::StakingEvent;
+use crate::mongo::collections::transaction::Transaction;
+use crate::mongo::Database;
+use crate::ronin::Ronin;
+use crate::tiering;
+
+/// Everything indexed for one transaction, in the shape a block explorer page needs. `logs` are
+/// the raw receipt logs from the node, `input` the call decoded with the ABI of the called contract
+/// when it has verified metadata. Internal calls are not part of the bundle since the
+/// indexer does not trace transactions.
+#[derive(Serialize)]
+pub struct TransactionBundle {
+    pub transaction: Option<Transaction>,
+    pub input: Option<DecodedCall>,
+    pub erc_transfers: Vec<ERCTransfer>,
+    pub erc1155_transfers: Vec<ERC1155Transfer>,
+    pub sales: Vec<Sale>,
+    pub auctions: Vec<Auction>,
+    pub orders: Vec<OrderEvent>,
+    pub pair_reserves: Vec<PairReserve>,
+    pub governance_events: Vec<GovernanceEvent>,
+    pub staking_events: Vec<StakingEvent>,
+    pub equipment_events: Vec<EquipmentEvent>,
+    pub decode_failures: Vec<DecodeFailure>,
+    pub contract_events: Vec<ContractEvent>,
+    pub logs: Vec<Log>,
+}
+
+/// Documents of `collection` matching `filter`, from the hot and cold database.
+async fn find_all<T>(
+    database: &Database,
+    collection: impl Fn(&Database) -> Collection<T>,
+    filter: Document,
+) -> Vec<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let name = collection(database).name().to_string();
+    let mut cursor = tiering::find(database, collection, filter, None, (0, 0))
+        .await
+        .unwrap_or_else(|_| panic!("Failed to query {name}"));
+
+    let mut documents = vec![];
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|_| panic!("Failed to read {name}"))
+    {
+        documents.push(
+            cursor
+                .deserialize_current()
+                .unwrap_or_else(|_| panic!("Failed to deserialize {name}")),
+        );
+    }
+    documents
+}
+
+pub async fn transaction_bundle(
+    database: &Database,
+    ronin: &Ronin,
+    hash: &str,
+) -> TransactionBundle {
+    let hash = hash.to_lowercase();
+    let by_transaction_id = doc! {"transaction_id": &hash};
+
+    let logs = match H256::from_str(&hash) {
+        Ok(tx_hash) => ronin
+            .provider
+            .eth()
+            .transaction_receipt(tx_hash)
+            .await
+            .expect("Failed to retrieve transaction receipt!")
+            .map(|receipt| receipt.logs)
+            .unwrap_or_default(),
+        Err(_) => vec![],
+    };
+
+    let tx = match H256::from_str(&hash) {
+        Ok(tx_hash) => ronin
+            .provider
+            .eth()
+            .transaction(TransactionId::Hash(tx_hash))
+            .await
+            .expect("Failed to retrieve transaction!"),
+        Err(_) => None,
+    };
+
+    let input = match tx {
+        Some(tx) => match tx.to {
+            Some(to) => AbiRegistry::load(database)
+                .await
+                .decode_input(&to, &tx.input.0),
+            None => None,
+        },
+        None => None,
+    };
+
+    TransactionBundle {
+        transaction: tiering::find_one(
+            database,
+            |db| db.transactions.collection.clone(),
+            doc! {"hash": &hash},
+            None,
+        )
+        .await
+        .expect("Failed to query transactions!"),
+        input,
+        erc_transfers: find_all(
+            database,
+            |db| db.erc_transfers.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        erc1155_transfers: find_all(
+            database,
+            |db| db.erc1155_transfers.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        sales: find_all(
+            database,
+            |db| db.erc_sales.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        auctions: find_all(
+            database,
+            |db| db.auctions.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        orders: find_all(
+            database,
+            |db| db.orders.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        pair_reserves: find_all(
+            database,
+            |db| db.pair_reserves.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        governance_events: find_all(
+            database,
+            |db| db.governance_events.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        staking_events: find_all(
+            database,
+            |db| db.staking_events.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        equipment_events: find_all(
+            database,
+            |db| db.equipment_events.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        decode_failures: find_all(
+            database,
+            |db| db.decode_failures.collection.clone(),
+            by_transaction_id.clone(),
+        )
+        .await,
+        contract_events: find_all(
+            database,
+            |db| db.contract_events.collection.clone(),
+            by_transaction_id,
+        )
+        .await,
+        logs,
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::info;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Collection;
+use parquet::basic::{Compression, ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::analysis::block_range_filter;
+use crate::cli_args::ExportFormat;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+use crate::spam;
+use crate::tiering;
+use crate::tiering::latest_block;
+
+/// Rows buffered per Parquet row group.
+const ROW_GROUP_ROWS: usize = 100_000;
+
+/// Cohort wallets mapped to the batch they are queried in.
+type Cohort = HashMap<String, usize>;
+
+fn read_wallets(path: &Path) -> Vec<String> {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read wallets {}: {err}", path.display()));
+
+    let mut wallets: Vec<String> = content
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    wallets.sort();
+    wallets.dedup();
+    wallets
+}
+
+struct CohortExport<'a, W: Write> {
+    batches: Vec<&'a [String]>,
+    cohort: Cohort,
+    from_block: Block,
+    to_block: Block,
+    out: W,
+}
+
+impl<'a, W: Write> CohortExport<'a, W> {
+    /// Writes the documents of `collection` with a cohort wallet on either side as JSON lines
+    /// tagged with `kind`. A document between wallets of two batches is only written by the
+    /// earlier batch.
+    async fn collection<T>(
+        &mut self,
+        database: &Database,
+        collection: impl Fn(&Database) -> Collection<T>,
+        kind: &str,
+        (from_field, to_field): (&str, &str),
+        parties: fn(&T) -> (&str, &str),
+    ) -> u64
+    where
+        T: DeserializeOwned + Serialize + Unpin + Send + Sync,
+    {
+        let mut exported = 0;
+
+        for (index, batch) in self.batches.iter().enumerate() {
+            let mut filter = block_range_filter(self.from_block, self.to_block);
+            spam::exclude(&mut filter);
+            filter.insert(
+                "$or",
+                vec![
+                    doc! {from_field: {"$in": batch.to_vec()}},
+                    doc! {to_field: {"$in": batch.to_vec()}},
+                ],
+            );
+
+            let mut cursor = tiering::find(
+                database,
+                &collection,
+                filter,
+                None,
+                (self.from_block, self.to_block),
+            )
+            .await
+            .unwrap_or_else(|_| panic!("Failed to query {kind}"));
+
+            while cursor
+                .advance()
+                .await
+                .unwrap_or_else(|_| panic!("Failed to read {kind}"))
+            {
+                let document: T = cursor
+                    .deserialize_current()
+                    .unwrap_or_else(|_| panic!("Failed to deserialize {kind}"));
+
+                let (from, to) = parties(&document);
+                let first_batch = [from, to]
+                    .iter()
+                    .filter_map(|wallet| self.cohort.get(*wallet))
+                    .min()
+                    .copied();
+                if first_batch != Some(index) {
+                    continue;
+                }
+
+                let mut line =
+                    serde_json::to_value(&document).expect("Failed to serialize document");
+                line["kind"] = kind.into();
+                writeln!(self.out, "{line}").expect("Failed to write export");
+                exported += 1;
+            }
+
+            info!(
+                "[INFO] Exported {kind} of batch {}/{}",
+                index + 1,
+                self.batches.len()
+            );
+        }
+
+        exported
+    }
+}
+
+/// Exports the transactions, ERC20/721 and ERC1155 transfers and sales of the wallets listed in
+/// `wallets` (one address per line) to `out` as JSON lines with a `kind` field. Wallets are queried
+/// `batch_size` at a time.
+pub async fn export_cohort(
+    database: &Database,
+    wallets: &Path,
+    out: &Path,
+    from_block: Block,
+    to_block: Block,
+    batch_size: usize,
+) {
+    let wallets = read_wallets(wallets);
+    let batches: Vec<&[String]> = wallets.chunks(batch_size.max(1)).collect();
+    let cohort: Cohort = batches
+        .iter()
+        .enumerate()
+        .flat_map(|(index, batch)| batch.iter().map(move |wallet| (wallet.to_owned(), index)))
+        .collect();
+
+    let file =
+        File::create(out).unwrap_or_else(|err| panic!("Failed to create {}: {err}", out.display()));
+    let mut export = CohortExport {
+        batches,
+        cohort,
+        from_block,
+        to_block,
+        out: BufWriter::new(file),
+    };
+
+    let transactions = export
+        .collection(
+            database,
+            |db| db.transactions.collection.clone(),
+            "transaction",
+            ("from", "to"),
+            |tx| (&tx.from, &tx.to),
+        )
+        .await;
+    let transfers = export
+        .collection(
+            database,
+            |db| db.erc_transfers.collection.clone(),
+            "erc_transfer",
+            ("from", "to"),
+            |transfer| (&transfer.from, &transfer.to),
+        )
+        .await;
+    let erc1155_transfers = export
+        .collection(
+            database,
+            |db| db.erc1155_transfers.collection.clone(),
+            "erc1155_transfer",
+            ("from", "to"),
+            |transfer| (&transfer.from, &transfer.to),
+        )
+        .await;
+    let sales = export
+        .collection(
+            database,
+            |db| db.erc_sales.collection.clone(),
+            "sale",
+            ("seller", "buyer"),
+            |sale| (&sale.seller, &sale.buyer),
+        )
+        .await;
+
+    export.out.flush().expect("Failed to write export");
+
+    println!(
+        "Exported {transactions} transactions, {transfers} erc transfers, {erc1155_transfers} erc1155 transfers and {sales} sales of {} wallets",
+        wallets.len()
+    );
+}
+
+/// Column type of a tabular export, inferred from the first document of a file. Nested documents,
+/// arrays and other BSON types are written as relaxed extended JSON text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ColumnKind {
+    Int,
+    Double,
+    Bool,
+    Text,
+}
+
+impl ColumnKind {
+    fn of(value: &Bson) -> ColumnKind {
+        match value {
+            Bson::Int32(_) | Bson::Int64(_) => ColumnKind::Int,
+            Bson::Double(_) => ColumnKind::Double,
+            Bson::Boolean(_) => ColumnKind::Bool,
+            _ => ColumnKind::Text,
+        }
+    }
+}
+
+fn text(value: &Bson) -> String {
+    match value {
+        Bson::String(value) => value.to_owned(),
+        Bson::DateTime(date) => date.try_to_rfc3339_string().unwrap_or_default(),
+        Bson::ObjectId(id) => id.to_hex(),
+        value => value.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
+enum TableWriter {
+    Csv(csv::Writer<File>),
+    Parquet {
+        writer: SerializedFileWriter<File>,
+        rows: Vec<Document>,
+    },
+}
+
+/// One export file. Its columns are the fields of the first document, fields missing from a later
+/// document are written as empty/null, additional fields are dropped.
+struct Table {
+    path: PathBuf,
+    columns: Vec<(String, ColumnKind)>,
+    writer: TableWriter,
+    rows: u64,
+}
+
+impl Table {
+    fn create(path: PathBuf, format: ExportFormat, first: &Document) -> Table {
+        let columns: Vec<(String, ColumnKind)> = first
+            .iter()
+            .filter(|(name, _)| name.as_str() != "_id")
+            .map(|(name, value)| (name.to_owned(), ColumnKind::of(value)))
+            .collect();
+        let file = File::create(&path)
+            .unwrap_or_else(|err| panic!("Failed to create {}: {err}", path.display()));
+
+        let writer = match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer
+                    .write_record(columns.iter().map(|(name, _)| name))
+                    .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+                TableWriter::Csv(writer)
+            }
+            ExportFormat::Parquet => {
+                let fields = columns
+                    .iter()
+                    .map(|(name, kind)| {
+                        let field = match kind {
+                            ColumnKind::Int => {
+                                Type::primitive_type_builder(name, PhysicalType::INT64)
+                            }
+                            ColumnKind::Double => {
+                                Type::primitive_type_builder(name, PhysicalType::DOUBLE)
+                            }
+                            ColumnKind::Bool => {
+                                Type::primitive_type_builder(name, PhysicalType::BOOLEAN)
+                            }
+                            ColumnKind::Text => {
+                                Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                                    .with_converted_type(ConvertedType::UTF8)
+                            }
+                        };
+
+                        Arc::new(
+                            field
+                                .with_repetition(Repetition::OPTIONAL)
+                                .build()
+                                .expect("Invalid parquet column"),
+                        )
+                    })
+                    .collect();
+                let schema = Type::group_type_builder("schema")
+                    .with_fields(fields)
+                    .build()
+                    .expect("Invalid parquet schema");
+
+                TableWriter::Parquet {
+                    writer: SerializedFileWriter::new(
+                        file,
+                        Arc::new(schema),
+                        Arc::new(
+                            WriterProperties::builder()
+                                .set_compression(Compression::SNAPPY)
+                                .build(),
+                        ),
+                    )
+                    .unwrap_or_else(|err| panic!("Failed to create {}: {err}", path.display())),
+                    rows: vec![],
+                }
+            }
+        };
+
+        Table {
+            path,
+            columns,
+            writer,
+            rows: 0,
+        }
+    }
+
+    fn write(&mut self, document: Document) {
+        self.rows += 1;
+
+        match &mut self.writer {
+            TableWriter::Csv(writer) => {
+                let record = self
+                    .columns
+                    .iter()
+                    .map(|(name, _)| match document.get(name) {
+                        None | Some(Bson::Null) => String::new(),
+                        Some(value) => text(value),
+                    });
+                writer
+                    .write_record(record)
+                    .unwrap_or_else(|err| panic!("Failed to write {}: {err}", self.path.display()));
+            }
+            TableWriter::Parquet { rows, .. } => {
+                rows.push(document);
+                if rows.len() >= ROW_GROUP_ROWS {
+                    self.flush_row_group();
+                }
+            }
+        }
+    }
+
+    /// Writes the buffered rows as one Parquet row group.
+    fn flush_row_group(&mut self) {
+        let (writer, rows) = match &mut self.writer {
+            TableWriter::Parquet { writer, rows } if !rows.is_empty() => (writer, rows),
+            _ => return,
+        };
+
+        write_row_group(writer, &self.columns, rows)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {err}", self.path.display()));
+        rows.clear();
+    }
+
+    fn finish(mut self) -> u64 {
+        self.flush_row_group();
+
+        match self.writer {
+            TableWriter::Csv(mut writer) => writer.flush().map_err(|err| err.to_string()),
+            TableWriter::Parquet { writer, .. } => {
+                writer.close().map(|_| ()).map_err(|err| err.to_string())
+            }
+        }
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", self.path.display()));
+
+        info!(
+            "[INFO] Exported {} rows to {}",
+            self.rows,
+            self.path.display()
+        );
+        self.rows
+    }
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<File>,
+    columns: &[(String, ColumnKind)],
+    rows: &[Document],
+) -> parquet::errors::Result<()> {
+    let mut row_group = writer.next_row_group()?;
+
+    for (name, kind) in columns.iter() {
+        let mut column = row_group
+            .next_column()?
+            .expect("Parquet schema and columns differ");
+        let values = rows.iter().map(|row| row.get(name));
+        let levels: Vec<i16> = values
+            .clone()
+            .map(|value| match (kind, value) {
+                (_, None | Some(Bson::Null)) => 0,
+                (ColumnKind::Int, Some(Bson::Int32(_) | Bson::Int64(_)))
+                | (ColumnKind::Double, Some(Bson::Double(_)))
+                | (ColumnKind::Bool, Some(Bson::Boolean(_)))
+                | (ColumnKind::Text, Some(_)) => 1,
+                _ => 0,
+            })
+            .collect();
+
+        match kind {
+            ColumnKind::Int => {
+                let values: Vec<i64> = values
+                    .filter_map(|value| match value {
+                        Some(Bson::Int32(value)) => Some(*value as i64),
+                        Some(Bson::Int64(value)) => Some(*value),
+                        _ => None,
+                    })
+                    .collect();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, Some(&levels), None)?
+            }
+            ColumnKind::Double => {
+                let values: Vec<f64> = values.filter_map(|value| value?.as_f64()).collect();
+                column
+                    .typed::<DoubleType>()
+                    .write_batch(&values, Some(&levels), None)?
+            }
+            ColumnKind::Bool => {
+                let values: Vec<bool> = values.filter_map(|value| value?.as_bool()).collect();
+                column
+                    .typed::<BoolType>()
+                    .write_batch(&values, Some(&levels), None)?
+            }
+            ColumnKind::Text => {
+                let values: Vec<ByteArray> = values
+                    .filter_map(|value| match value {
+                        None | Some(Bson::Null) => None,
+                        Some(value) => Some(ByteArray::from(text(value).into_bytes())),
+                    })
+                    .collect();
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&levels), None)?
+            }
+        };
+        column.close()?;
+    }
+
+    row_group.close().map(|_| ())
+}
+
+/// Writes the documents of collection `name` matching `filter` within `range` to `path`, from the
+/// hot and cold database. No file is created if nothing matches. Returns the number of rows
+/// written.
+async fn export_file(
+    database: &Database,
+    name: &str,
+    filter: Document,
+    range: (Block, Block),
+    format: ExportFormat,
+    path: PathBuf,
+) -> u64 {
+    let collection = |db: &Database| db._database.collection::<Document>(name);
+    let mut cursor = tiering::find(database, collection, filter, None, range)
+        .await
+        .unwrap_or_else(|_| panic!("Failed to query {name}"));
+    let mut table: Option<Table> = None;
+
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|_| panic!("Failed to read {name}"))
+    {
+        let document = cursor
+            .deserialize_current()
+            .unwrap_or_else(|_| panic!("Failed to deserialize {name}"));
+
+        table
+            .get_or_insert_with(|| Table::create(path.to_owned(), format, &document))
+            .write(document);
+    }
+
+    table.map(Table::finish).unwrap_or(0)
+}
+
+/// Streams `collection` to CSV or Parquet files in `out`, one file per `partition_blocks` blocks
+/// named `<collection>_<first block>_<last block>`. With `partition_blocks` 0 a single file is
+/// written, which also works for collections without a `block` field as long as no block range
+/// is given.
+pub async fn export_collection(
+    database: &Database,
+    name: &str,
+    (from_block, to_block): (Block, Block),
+    format: ExportFormat,
+    out: &Path,
+    partition_blocks: u64,
+) {
+    fs::create_dir_all(out)
+        .unwrap_or_else(|err| panic!("Failed to create {}: {err}", out.display()));
+
+    let extension = match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Parquet => "parquet",
+    };
+
+    if partition_blocks == 0 {
+        let mut filter = match from_block > 0 || to_block > 0 {
+            true => block_range_filter(from_block, to_block),
+            false => doc! {},
+        };
+        spam::exclude(&mut filter);
+        let rows = export_file(
+            database,
+            name,
+            filter,
+            (from_block, to_block),
+            format,
+            out.join(f!("{name}.{extension}")),
+        )
+        .await;
+
+        println!("Exported {rows} {name} documents");
+        return;
+    }
+
+    let to_block = match to_block {
+        0 => latest_block(&database._database.collection::<Document>(name)).await,
+        to_block => to_block,
+    };
+    let (mut start, mut rows, mut files) = (from_block, 0, 0);
+
+    while start <= to_block {
+        let end = (start + partition_blocks - 1).min(to_block);
+        let mut filter = block_range_filter(start, end);
+        spam::exclude(&mut filter);
+        let exported = export_file(
+            database,
+            name,
+            filter,
+            (start, end),
+            format,
+            out.join(f!("{name}_{start:012}_{end:012}.{extension}")),
+        )
+        .await;
+
+        if exported > 0 {
+            rows += exported;
+            files += 1;
+        }
+        start = end + 1;
+    }
+
+    println!("Exported {rows} {name} documents to {files} files");
+}
@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use web3::types::{Block as Web3Block, BlockId, BlockNumber, Transaction, TransactionReceipt};
+
+use crate::mongo::collections::Block;
+use crate::ronin::{DecodeFeatures, DecodedReceipt, Ronin};
+
+/// A mainnet block with all of its receipts, captured from a node so decoder output can be
+/// checked offline.
+#[derive(Serialize, Deserialize)]
+pub struct BlockFixture {
+    pub block: Web3Block<Transaction>,
+    pub receipts: Vec<TransactionReceipt>,
+}
+
+impl BlockFixture {
+    pub fn number(&self) -> Block {
+        self.block.number.map(|n| n.as_u64()).unwrap_or_default()
+    }
+
+    pub fn timestamp(&self) -> DateTime {
+        DateTime::from_millis(self.block.timestamp.as_u64() as i64 * 1000)
+    }
+}
+
+fn fixture_path(dir: &Path, block: Block) -> PathBuf {
+    dir.join(f!("block_{block}.json"))
+}
+
+fn expected_path(dir: &Path, block: Block) -> PathBuf {
+    dir.join(f!("block_{block}.expected.json"))
+}
+
+/// Decodes every receipt of a fixture with the built in contract list and all decoders enabled.
+pub fn decode(ronin: &Ronin, fixture: &BlockFixture) -> Vec<DecodedReceipt> {
+    let contracts = Ronin::contract_list();
+
+    fixture
+        .receipts
+        .iter()
+        .map(|receipt| {
+            ronin.decode_receipt(
+                receipt,
+                fixture.number(),
+                fixture.timestamp(),
+                &contracts,
+                DecodeFeatures::all(),
+            )
+        })
+        .collect()
+}
+
+/// Records `blocks` from the node into `dir`, together with the documents the current decoders
+/// produce for them. The regression tests assert that decoding the fixture yields exactly the
+/// recorded documents, so review the expected file before committing it.
+pub async fn capture(ronin: &Ronin, blocks: &[Block], dir: &Path) {
+    fs::create_dir_all(dir).expect("Failed to create fixture directory");
+
+    for number in blocks {
+        let block = ronin
+            .provider
+            .eth()
+            .block_with_txs(BlockId::Number(BlockNumber::from(*number)))
+            .await
+            .unwrap_or_else(|_| panic!("Failed to load block {} from provider!", number))
+            .unwrap_or_else(|| panic!("Failed to unwrap block {} from result!", number));
+
+        let mut receipts = vec![];
+        for tx in block.transactions.iter() {
+            receipts.push(
+                ronin
+                    .provider
+                    .eth()
+                    .transaction_receipt(tx.hash)
+                    .await
+                    .expect("Failed to retrieve transaction receipt!")
+                    .expect("Failed to unwrap transaction receipt!"),
+            );
+        }
+
+        let fixture = BlockFixture { block, receipts };
+        let expected = decode(ronin, &fixture);
+
+        fs::write(
+            fixture_path(dir, *number),
+            serde_json::to_string_pretty(&fixture).unwrap(),
+        )
+        .expect("Failed to write fixture");
+        fs::write(
+            expected_path(dir, *number),
+            serde_json::to_string_pretty(&expected).unwrap(),
+        )
+        .expect("Failed to write expected documents");
+
+        println!(
+            "Captured block {} with {} receipts into {}",
+            number,
+            fixture.receipts.len(),
+            dir.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use mongodb::options::{ClientOptions, ServerAddress};
+    use mongodb::Client;
+    use web3::transports::{Either, Http};
+    use web3::types::{Bytes, Log, H160, H256, U256};
+    use web3::Web3;
+
+    use super::*;
+    use crate::mongo;
+    use crate::rate_limit::RateLimited;
+    use crate::ronin::ContractType;
+
+    const WETH: &str = "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5";
+    const TRANSFER_TOPIC: &str =
+        "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    /// Ronin instance that never talks to a node or database, decoding is fully offline.
+    fn offline_ronin() -> Ronin {
+        let options = ClientOptions::builder()
+            .hosts(vec![ServerAddress::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: None,
+            }])
+            .build();
+        let client = Client::with_options(options).unwrap();
+        let http = Http::new("http://127.0.0.1:8545").unwrap();
+
+        Ronin::from_parts(
+            Web3::new(RateLimited::new(Either::Right(http))),
+            mongo::build(client, "fixtures", None),
+        )
+    }
+
+    fn topic_address(address: &str) -> H256 {
+        let mut topic = [0u8; 32];
+        topic[12..].copy_from_slice(H160::from_str(address).unwrap().as_bytes());
+        H256::from(topic)
+    }
+
+    fn transfer_log(topics: Vec<H256>, value: u64) -> Log {
+        let mut data = [0u8; 32];
+        U256::from(value).to_big_endian(&mut data);
+
+        Log {
+            address: H160::from_str(WETH).unwrap(),
+            topics,
+            data: Bytes(data.to_vec()),
+            block_hash: None,
+            block_number: Some(20_000_000u64.into()),
+            transaction_hash: Some(H256::repeat_byte(0xab)),
+            transaction_index: Some(0u64.into()),
+            log_index: Some(U256::from(3)),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    fn decode_logs(ronin: &Ronin, logs: Vec<Log>) -> DecodedReceipt {
+        let receipt = TransactionReceipt {
+            logs,
+            ..Default::default()
+        };
+
+        ronin.decode_receipt(
+            &receipt,
+            20_000_000,
+            DateTime::from_millis(0),
+            &Ronin::contract_list(),
+            DecodeFeatures::all(),
+        )
+    }
+
+    #[tokio::test]
+    async fn decodes_erc20_transfer() {
+        let ronin = offline_ronin();
+        let from = "0x1111111111111111111111111111111111111111";
+        let to = "0x2222222222222222222222222222222222222222";
+
+        let decoded = decode_logs(
+            &ronin,
+            vec![transfer_log(
+                vec![
+                    H256::from_str(TRANSFER_TOPIC).unwrap(),
+                    topic_address(from),
+                    topic_address(to),
+                ],
+                255,
+            )],
+        );
+
+        assert_eq!(decoded.erc_transfers.len(), 1);
+        assert!(decoded.decode_failures.is_empty());
+
+        let transfer = &decoded.erc_transfers[0];
+        assert_eq!(transfer.from, from);
+        assert_eq!(transfer.to, to);
+        assert_eq!(transfer.token, WETH);
+        assert_eq!(transfer.value_or_token_id, "ff");
+        assert_eq!(
+            transfer.value_decimal.map(|value| value.to_string()),
+            Some("2.55E-16".to_string())
+        );
+        assert_eq!(transfer.erc, ContractType::ERC20);
+        assert_eq!(transfer.block, 20_000_000);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_malformed_transfer() {
+        let ronin = offline_ronin();
+
+        let decoded = decode_logs(
+            &ronin,
+            vec![transfer_log(
+                vec![H256::from_str(TRANSFER_TOPIC).unwrap()],
+                1,
+            )],
+        );
+
+        assert!(decoded.erc_transfers.is_empty());
+        assert_eq!(decoded.decode_failures.len(), 1);
+        assert_eq!(decoded.decode_failures[0].address, WETH);
+    }
+
+    #[tokio::test]
+    async fn fixtures_decode_to_expected_documents() {
+        let ronin = offline_ronin();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+
+        let mut fixtures: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", dir.display()))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.ends_with(".json") && !name.ends_with(".expected.json")
+            })
+            .collect();
+        fixtures.sort();
+        assert!(!fixtures.is_empty(), "No fixtures in {}", dir.display());
+
+        for path in fixtures {
+            let fixture: BlockFixture =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let expected: serde_json::Value = serde_json::from_str(
+                &fs::read_to_string(expected_path(&dir, fixture.number())).unwrap(),
+            )
+            .unwrap();
+
+            assert_eq!(
+                serde_json::to_value(decode(&ronin, &fixture)).unwrap(),
+                expected,
+                "Decoded documents of block {} changed",
+                fixture.number()
+            );
+        }
+    }
+}
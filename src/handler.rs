@@ -0,0 +1,125 @@
+use std::sync::{Arc, RwLock};
+
+use mongodb::bson::DateTime;
+
+use crate::mongo::collections::auction::Auction;
+use crate::mongo::collections::axie_sale::Sale;
+use crate::mongo::collections::contract_event::ContractEvent;
+use crate::mongo::collections::equipment_event::EquipmentEvent;
+use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::governance_event::GovernanceEvent;
+use crate::mongo::collections::order::OrderEvent;
+use crate::mongo::collections::pair_reserve::PairReserve;
+use crate::mongo::collections::staking_event::StakingEvent;
+use crate::mongo::collections::transaction::Transaction;
+use crate::mongo::collections::Block;
+use crate::ronin::DecodedReceipt;
+
+/// Handlers registered with [`register`], called by every indexing loop of the process.
+static HANDLERS: RwLock<Vec<Arc<dyn EventHandler>>> = RwLock::new(vec![]);
+
+/// Custom processing of the indexed chain data, next to the MongoDB writes. The hooks are called
+/// for every decoded item in chain order, before the item is committed and in debug mode too, so
+/// a handler has to tolerate items a crash later replays. Hooks run on the indexing loop and
+/// should hand slow work off to a task or channel.
+///
+/// All hooks default to doing nothing. `--logs-mode` does not load transactions and only calls
+/// `on_block` for blocks with tracked logs.
+pub trait EventHandler: Send + Sync {
+    /// Called once all items of `block` were handed out.
+    fn on_block(&self, _block: Block, _timestamp: DateTime) {}
+
+    fn on_transaction(&self, _transaction: &Transaction) {}
+
+    /// ERC20 and ERC721 transfers.
+    fn on_transfer(&self, _transfer: &ERCTransfer) {}
+
+    fn on_erc1155_transfer(&self, _transfer: &ERC1155Transfer) {}
+
+    fn on_sale(&self, _sale: &Sale) {}
+
+    fn on_auction(&self, _auction: &Auction) {}
+
+    fn on_order(&self, _order: &OrderEvent) {}
+
+    fn on_pair_reserve(&self, _reserve: &PairReserve) {}
+
+    fn on_governance_event(&self, _event: &GovernanceEvent) {}
+
+    fn on_staking_event(&self, _event: &StakingEvent) {}
+
+    fn on_equipment_event(&self, _event: &EquipmentEvent) {}
+
+    /// Events of the `--abi` registry and the `--events` subscriptions.
+    fn on_contract_event(&self, _event: &ContractEvent) {}
+}
+
+/// Adds a handler to every indexing loop started afterwards.
+pub fn register(handler: Arc<dyn EventHandler>) {
+    HANDLERS
+        .write()
+        .expect("Event handlers poisoned!")
+        .push(handler);
+}
+
+fn handlers() -> Vec<Arc<dyn EventHandler>> {
+    HANDLERS.read().expect("Event handlers poisoned!").to_vec()
+}
+
+/// Hands the documents of a decoded receipt to the handlers.
+pub(crate) fn decoded(decoded: &DecodedReceipt) {
+    for handler in handlers() {
+        decoded
+            .erc_transfers
+            .iter()
+            .for_each(|transfer| handler.on_transfer(transfer));
+        decoded
+            .erc1155_transfers
+            .iter()
+            .for_each(|transfer| handler.on_erc1155_transfer(transfer));
+        decoded.sales.iter().for_each(|sale| handler.on_sale(sale));
+        decoded
+            .auctions
+            .iter()
+            .for_each(|auction| handler.on_auction(auction));
+        decoded
+            .orders
+            .iter()
+            .for_each(|order| handler.on_order(order));
+        decoded
+            .pair_reserves
+            .iter()
+            .for_each(|reserve| handler.on_pair_reserve(reserve));
+        decoded
+            .governance_events
+            .iter()
+            .for_each(|event| handler.on_governance_event(event));
+        decoded
+            .staking_events
+            .iter()
+            .for_each(|event| handler.on_staking_event(event));
+        decoded
+            .equipment_events
+            .iter()
+            .for_each(|event| handler.on_equipment_event(event));
+    }
+}
+
+pub(crate) fn transaction(transaction: &Transaction) {
+    for handler in handlers() {
+        handler.on_transaction(transaction);
+    }
+}
+
+pub(crate) fn contract_event(event: &ContractEvent) {
+    for handler in handlers() {
+        handler.on_contract_event(event);
+    }
+}
+
+pub(crate) fn block(block: Block, timestamp: DateTime) {
+    for handler in handlers() {
+        handler.on_block(block, timestamp);
+    }
+}
@@ -0,0 +1,303 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use mongodb::bson::{Bson, DateTime, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::{InsertManyOptions, UpdateOptions};
+use mongodb::Collection;
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::record::Field;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::storage_profile::{self, compact, profile, StorageProfile};
+use crate::mongo::collections::transaction_pool::DUPLICATE_KEY_ERROR;
+use crate::mongo::Database;
+
+/// Settings key holding the files already imported.
+const IMPORTED_FILES_KEY: &str = "import.files";
+
+/// Imported files as `<name>:<size>`. Updates are not idempotent, a file is only imported again
+/// once it changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct ImportedFiles {
+    files: Vec<String>,
+}
+
+impl SettingsValue for ImportedFiles {}
+
+enum ImportFile {
+    /// Documents of the file sink, `<collection>.ndjson`.
+    Documents(String),
+    /// Updates of the file sink, `<collection>.updates.ndjson`.
+    Updates(String),
+    /// Export of a collection, `<collection>.parquet` or `<collection>_<start>_<end>.parquet`.
+    Parquet(String),
+}
+
+impl ImportFile {
+    fn of(path: &Path) -> Option<ImportFile> {
+        let name = path.file_name()?.to_str()?;
+
+        if let Some(collection) = name.strip_suffix(".updates.ndjson") {
+            return Some(ImportFile::Updates(collection.to_string()));
+        }
+        if let Some(collection) = name.strip_suffix(".ndjson") {
+            return Some(ImportFile::Documents(collection.to_string()));
+        }
+
+        let stem = name.strip_suffix(".parquet")?;
+        let block = |part: &str| part.len() == 12 && part.bytes().all(|b| b.is_ascii_digit());
+        let mut parts = stem.rsplitn(3, '_');
+        let collection = match (parts.next(), parts.next(), parts.next()) {
+            (Some(end), Some(start), Some(collection)) if block(start) && block(end) => collection,
+            _ => stem,
+        };
+
+        Some(ImportFile::Parquet(collection.to_string()))
+    }
+
+    fn collection(&self) -> &str {
+        match self {
+            ImportFile::Documents(collection)
+            | ImportFile::Updates(collection)
+            | ImportFile::Parquet(collection) => collection,
+        }
+    }
+}
+
+fn read_lines(path: &Path) -> impl Iterator<Item = Document> + '_ {
+    let file =
+        File::open(path).unwrap_or_else(|err| panic!("Failed to open {}: {err}", path.display()));
+
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(line, content)| {
+            let content =
+                content.unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()));
+            if content.trim().is_empty() {
+                return None;
+            }
+
+            let value: serde_json::Value = serde_json::from_str(&content).unwrap_or_else(|err| {
+                panic!(
+                    "Invalid JSON in {} line {}: {err}",
+                    path.display(),
+                    line + 1
+                )
+            });
+            match Bson::try_from(value) {
+                Ok(Bson::Document(document)) => Some(document),
+                _ => panic!("No document in {} line {}", path.display(), line + 1),
+            }
+        })
+}
+
+/// Converts an exported Parquet value back. Dates and nested values were exported as text, RFC
+/// 3339 timestamps and JSON objects or arrays are parsed again.
+fn bson(field: &Field) -> Option<Bson> {
+    match field {
+        Field::Null => None,
+        Field::Bool(value) => Some(Bson::Boolean(*value)),
+        Field::Long(value) => Some(Bson::Int64(*value)),
+        Field::Int(value) => Some(Bson::Int32(*value)),
+        Field::Double(value) => Some(Bson::Double(*value)),
+        Field::Str(value) => {
+            if value.starts_with('{') || value.starts_with('[') {
+                if let Some(bson) = serde_json::from_str::<serde_json::Value>(value)
+                    .ok()
+                    .and_then(|json| Bson::try_from(json).ok())
+                {
+                    return Some(bson);
+                }
+            }
+            if value.len() >= 20 && value.as_bytes()[10] == b'T' {
+                if let Ok(date) = DateTime::parse_rfc3339_str(value) {
+                    return Some(Bson::DateTime(date));
+                }
+            }
+
+            Some(Bson::String(value.to_owned()))
+        }
+        field => Some(Bson::String(field.to_string())),
+    }
+}
+
+fn read_parquet(path: &Path) -> impl Iterator<Item = Document> + '_ {
+    let file =
+        File::open(path).unwrap_or_else(|err| panic!("Failed to open {}: {err}", path.display()));
+    let reader = SerializedFileReader::new(file)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()));
+
+    reader.into_iter().map(move |row| {
+        let row = row.unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()));
+
+        row.get_column_iter()
+            .filter_map(|(name, field)| bson(field).map(|value| (name.to_owned(), value)))
+            .collect()
+    })
+}
+
+/// Inserts `documents`, skipping those rejected by a unique index. Returns the inserted and
+/// duplicate documents.
+async fn insert(collection: &Collection<Document>, documents: &mut Vec<Document>) -> (u64, u64) {
+    if documents.is_empty() {
+        return (0, 0);
+    }
+
+    let total = documents.len() as u64;
+    let options = InsertManyOptions::builder().ordered(false).build();
+    let result = collection.insert_many(documents.drain(..), options).await;
+
+    let duplicates = match result {
+        Ok(_) => 0,
+        Err(error) => match error.kind.as_ref() {
+            ErrorKind::BulkWrite(failure) if failure.write_concern_error.is_none() => {
+                let write_errors = failure.write_errors.to_owned().unwrap_or_default();
+                if let Some(failed) = write_errors.iter().find(|e| e.code != DUPLICATE_KEY_ERROR) {
+                    panic!("Failed to import into {}: {failed:?}", collection.name());
+                }
+
+                write_errors.len() as u64
+            }
+            _ => panic!("Failed to import into {}: {error:?}", collection.name()),
+        },
+    };
+
+    (total - duplicates, duplicates)
+}
+
+async fn import_documents(
+    collection: &Collection<Document>,
+    documents: impl Iterator<Item = Document>,
+    batch_size: usize,
+) -> (u64, u64) {
+    let (mut inserted, mut duplicates) = (0, 0);
+    let mut batch = Vec::with_capacity(batch_size);
+
+    for mut document in documents {
+        if profile() == StorageProfile::Compact {
+            compact(collection.name(), &mut document);
+        }
+        batch.push(document);
+
+        if batch.len() >= batch_size {
+            let (batch_inserted, batch_duplicates) = insert(collection, &mut batch).await;
+            inserted += batch_inserted;
+            duplicates += batch_duplicates;
+        }
+    }
+
+    let (batch_inserted, batch_duplicates) = insert(collection, &mut batch).await;
+
+    (inserted + batch_inserted, duplicates + batch_duplicates)
+}
+
+async fn import_updates(collection: &Collection<Document>, path: &Path) -> u64 {
+    let mut applied = 0;
+
+    for line in read_lines(path) {
+        let (filter, update) = match (line.get_document("filter"), line.get_document("update")) {
+            (Ok(filter), Ok(update)) => (filter.to_owned(), update.to_owned()),
+            _ => panic!("Invalid update in {}: {line}", path.display()),
+        };
+        let upsert = line.get_bool("upsert").unwrap_or(false);
+
+        collection
+            .update_one(
+                filter,
+                update,
+                UpdateOptions::builder().upsert(Some(upsert)).build(),
+            )
+            .await
+            .unwrap_or_else(|err| panic!("Failed to update {}: {err}", collection.name()));
+        applied += 1;
+    }
+
+    applied
+}
+
+/// Imports the NDJSON files of `--sink file:<dir>` and the Parquet files of `export` from `path`,
+/// a directory or a single file, into the collection named by each file. Documents go through the
+/// unique indexes of the collections, so already present ones are skipped. The updates of the
+/// file sink are applied after all documents, each file only once.
+pub async fn import(database: &Database, path: &Path, batch_size: usize, force: bool) {
+    let mut paths: Vec<PathBuf> = match path.is_dir() {
+        true => fs::read_dir(path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()))
+            .map(|entry| entry.expect("Failed to read directory entry").path())
+            .collect(),
+        false => vec![path.to_path_buf()],
+    };
+    paths.sort();
+
+    let mut files: Vec<(PathBuf, ImportFile)> = paths
+        .into_iter()
+        .filter_map(|path| ImportFile::of(&path).map(|file| (path, file)))
+        .collect();
+    files.sort_by_key(|(_, file)| matches!(file, ImportFile::Updates(_)));
+
+    let mut imported: ImportedFiles = database
+        .settings
+        .load(IMPORTED_FILES_KEY)
+        .await
+        .unwrap_or_default();
+
+    if !files.is_empty() {
+        storage_profile::record(database).await;
+    }
+    for (path, file) in files {
+        let size = fs::metadata(&path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()))
+            .len();
+        let key = f!(
+            "{}:{size}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        if !force && imported.files.contains(&key) {
+            info!("[INFO] Skipping {}, already imported", path.display());
+            continue;
+        }
+
+        let collection = database._database.collection::<Document>(file.collection());
+
+        match &file {
+            ImportFile::Documents(_) => {
+                let (inserted, duplicates) =
+                    import_documents(&collection, read_lines(&path), batch_size).await;
+                println!(
+                    "Imported {inserted} {} documents from {}, {duplicates} already present",
+                    collection.name(),
+                    path.display()
+                );
+            }
+            ImportFile::Parquet(_) => {
+                let (inserted, duplicates) =
+                    import_documents(&collection, read_parquet(&path), batch_size).await;
+                println!(
+                    "Imported {inserted} {} documents from {}, {duplicates} already present",
+                    collection.name(),
+                    path.display()
+                );
+            }
+            ImportFile::Updates(_) => {
+                let applied = import_updates(&collection, &path).await;
+                println!(
+                    "Applied {applied} {} updates from {}",
+                    collection.name(),
+                    path.display()
+                );
+            }
+        }
+
+        imported.files.push(key);
+        database
+            .settings
+            .store(IMPORTED_FILES_KEY, &imported)
+            .await
+            .expect("Failed to store imported files!");
+    }
+}
@@ -0,0 +1,520 @@
+use log::info;
+use web3::types::U256;
+
+use crate::cli_args::{Args, Command, Date, Sink, StopBlock};
+use crate::mongo::collections::failed_block::FailedStage;
+use crate::mongo::collections::storage_profile;
+use crate::mongo::Database;
+use crate::ronin::Ronin;
+use crate::sink::FileSink;
+use crate::{
+    abi, admin, analysis, cache, explorer, export, fixtures, import, integrity, metadata, migrate,
+    mongo, probe, rate_limit, reorg, rollup, shadow, spam, stats, status, tiering,
+};
+
+/// Resolves `--start-date` and `--stop-date` to the block range `[start, stop)`. The start is
+/// `None` without a start date, the stop `None` without a stop date or if it is still ahead of the
+/// chain.
+async fn date_range(args: &Args, db: &Database) -> (Option<u64>, Option<u64>) {
+    if args.start_date.is_none() && args.stop_date.is_none() {
+        return (None, None);
+    }
+
+    let ronin = Ronin::new(&args.web3_hostname, db.clone()).await;
+
+    let start = match args.start_date {
+        None => None,
+        Some(Date(date)) => Some(
+            ronin
+                .block_at(date)
+                .await
+                .unwrap_or_else(|| panic!("Start date {} is after the chain head", date)),
+        ),
+    };
+    let stop = match args.stop_date {
+        None => None,
+        Some(Date(date)) => ronin.block_at(date).await,
+    };
+
+    if let (Some(start), Some(stop)) = (start, stop) {
+        info!("[INFO] Resolved dates to blocks {start}..{stop}");
+    }
+
+    (start, stop)
+}
+
+/// Applies the `--start-date`/`--stop-date` range to the `from_block`/`to_block` of a subcommand,
+/// whose `to_block` is inclusive.
+fn analysis_range(range: (Option<u64>, Option<u64>), from_block: u64, to_block: u64) -> (u64, u64) {
+    (
+        range.0.unwrap_or(from_block),
+        range
+            .1
+            .map(|stop| stop.saturating_sub(1))
+            .unwrap_or(to_block),
+    )
+}
+
+/// Parses a token id given as decimal or 0x prefixed hex.
+fn parse_id(id: &str) -> U256 {
+    match id.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(id).ok(),
+    }
+    .unwrap_or_else(|| panic!("Invalid token id {id}"))
+}
+
+/// The indexer behind the `fri` binary, for services embedding it instead of shelling out.
+pub struct Indexer;
+
+impl Indexer {
+    /// Runs the subcommand of `args`, or the indexing loop without one, until it is done. Build the
+    /// arguments with [`crate::cli_args::parse`], or start from `Args::default()` and set the fields
+    /// without going through clap. Logging is left to the caller, the binary sets it up with
+    /// [`crate::logging::init`]. The RPC rate limit and the spam list are process wide, so this is run
+    /// once per process.
+    pub async fn run(mut args: Args) {
+        if let Some(addr) = args.status_addr {
+            status::serve(addr, &args).await;
+        }
+
+        if let Some(contracts) = &args.contracts {
+            Ronin::load_contract_list(contracts);
+        }
+        if let Some(contract) = &args.equipment_contract {
+            Ronin::set_equipment_contract(contract);
+        }
+        if !args.governance_contracts.is_empty() {
+            Ronin::set_governance_contracts(&args.governance_contracts);
+        }
+        spam::include_spam(args.include_spam);
+        rate_limit::max_qps(args.rpc_max_qps);
+        if let Sink::File(dir) = &args.sink {
+            if args.command.is_some() {
+                panic!("--sink only applies to the stream");
+            }
+            FileSink::init(dir);
+        }
+
+        if args.shadow_version.is_some() && (args.command.is_some() || !args.logs_mode) {
+            panic!("--shadow-version only applies to the stream in --logs-mode");
+        }
+
+        mongo::set_write_concerns(&args.write_concerns);
+        mongo::set_update_batch_size(args.update_batch_size);
+        if let Some(uri) = &args.cold_db_uri {
+            tiering::set_cold_db_uri(uri);
+        }
+        let db = mongo::connect_version(&args.db_uri, &args.db_name, args.shadow_version).await;
+        storage_profile::init(&db, args.storage_profile).await;
+        let range = date_range(&args, &db).await;
+
+        match args.command {
+            Some(Command::ClusterWallets {
+                from_block,
+                to_block,
+                max_funder_fanout,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+
+                analysis::cluster_wallets(&db, from_block, to_block, max_funder_fanout).await
+            }
+            Some(Command::ClassifySpam {
+                from_block,
+                to_block,
+                min_recipients,
+                max_active_ratio,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+
+                spam::classify(&db, from_block, to_block, min_recipients, max_active_ratio).await
+            }
+            Some(Command::CheckIntegrity {
+                from_block,
+                to_block,
+                batch_size,
+                repair,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+                let report = integrity::check(
+                    &db,
+                    from_block,
+                    to_block,
+                    batch_size.max(1),
+                    (args.feature_transactions, args.feature_blocks),
+                )
+                .await;
+
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+                if repair && !report.blocks.is_empty() {
+                    let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                    for (start, end) in integrity::ranges(&report.blocks) {
+                        info!("[INFO] Repairing blocks {start}..={end}");
+                        // The stream stops before its stop block.
+                        let repair_args = Args {
+                            command: None,
+                            replay: false,
+                            leader_election: false,
+                            start_block: Some(start),
+                            stop_block: Some(StopBlock::Number(end + 1)),
+                            ..args.clone()
+                        };
+                        ronin.stream(0, repair_args).await;
+                    }
+                }
+            }
+            Some(Command::DetectDepositAddresses {
+                from_block,
+                to_block,
+                hot_wallets,
+                min_depositors,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+
+                analysis::detect_deposit_addresses(
+                    &db,
+                    from_block,
+                    to_block,
+                    &hot_wallets,
+                    min_depositors,
+                )
+                .await
+            }
+            Some(Command::Counterparties {
+                wallet,
+                from_block,
+                to_block,
+                limit,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+                let counterparties = cache::cached(
+                    &db,
+                    args.cache_blocks,
+                    &f!(
+                        "counterparties:{wallet}:{from_block}:{to_block}:{limit}:{}",
+                        args.include_spam
+                    ),
+                    || analysis::top_counterparties(&db, &wallet, from_block, to_block, limit),
+                )
+                .await;
+
+                println!("{}", serde_json::to_string_pretty(&counterparties).unwrap());
+            }
+            Some(Command::AirdropWallets {
+                from_block,
+                to_block,
+                hold_token,
+                min_amount,
+                bought_from,
+                match_any,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+                let key = f!(
+                "airdrop_wallets:{from_block}:{to_block}:{hold_token:?}:{min_amount}:{}:{match_any}:{}",
+                bought_from.join(","),
+                args.include_spam
+            );
+                let wallets = cache::cached(&db, args.cache_blocks, &key, || {
+                    analysis::airdrop_wallets(
+                        &db,
+                        from_block,
+                        to_block,
+                        hold_token.as_deref(),
+                        min_amount,
+                        &bought_from,
+                        match_any,
+                    )
+                })
+                .await;
+
+                println!("{}", serde_json::to_string_pretty(&wallets).unwrap());
+            }
+            Some(Command::AddContractMetadata {
+                address,
+                name,
+                abi,
+                compiler,
+            }) => abi::add_contract_metadata(&db, &address, &name, &abi, compiler).await,
+            Some(Command::ImportSourcify { dir }) => abi::import_sourcify(&db, &dir).await,
+            Some(Command::Transaction { hash }) => {
+                let ronin = Ronin::new(&args.web3_hostname, db.clone()).await;
+                let bundle = explorer::transaction_bundle(&db, &ronin, &hash).await;
+
+                println!("{}", serde_json::to_string_pretty(&bundle).unwrap());
+            }
+            Some(Command::BlockLookup { date, block }) => {
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                let block = match (date, block) {
+                    (Some(Date(date)), _) => ronin.block_at(date).await,
+                    (None, block) => block,
+                };
+                let lookup = match block {
+                    None => serde_json::Value::Null,
+                    Some(block) => serde_json::json!({
+                        "block": block,
+                        "timestamp": ronin
+                            .timestamp_of(block)
+                            .await
+                            .unwrap_or_else(|err| panic!("Failed to load block {block}: {err}"))
+                            .try_to_rfc3339_string()
+                            .unwrap_or_default(),
+                    }),
+                };
+
+                println!("{}", serde_json::to_string_pretty(&lookup).unwrap());
+            }
+            Some(Command::Rollup { treasury }) => {
+                rollup::token_daily(&db).await;
+                rollup::marketplace_fees(&db, treasury).await;
+                rollup::net_worth(&db).await;
+            }
+            Some(Command::Watchlist { add, remove }) => {
+                rollup::update_watchlist(&db, &add, &remove).await
+            }
+            Some(Command::Portfolio { wallet }) => {
+                let series = db.net_worth.series(&wallet.to_lowercase()).await;
+
+                println!("{}", serde_json::to_string_pretty(&series).unwrap());
+            }
+            Some(Command::CaptureFixtures { blocks, dir }) => {
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                fixtures::capture(&ronin, &blocks, &dir).await;
+            }
+            Some(Command::FetchMetadata {
+                token,
+                limit,
+                ipfs_gateway,
+                retry_failed,
+            }) => {
+                let ronin = Ronin::new(&args.web3_hostname, db.clone()).await;
+
+                metadata::fetch_metadata(
+                    &db,
+                    &ronin,
+                    token.as_deref(),
+                    limit,
+                    &ipfs_gateway,
+                    retry_failed,
+                )
+                .await;
+            }
+            Some(Command::ExportCohort {
+                wallets,
+                out,
+                from_block,
+                to_block,
+                batch_size,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+
+                export::export_cohort(&db, &wallets, &out, from_block, to_block, batch_size).await
+            }
+            Some(Command::Export {
+                collection,
+                from_block,
+                to_block,
+                format,
+                out,
+                partition_blocks,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+
+                export::export_collection(
+                    &db,
+                    &collection,
+                    (from_block, to_block),
+                    format,
+                    &out,
+                    partition_blocks,
+                )
+                .await
+            }
+            Some(Command::Import {
+                path,
+                batch_size,
+                force,
+            }) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "import").await;
+
+                import::import(&db, &path, batch_size.max(1), force).await
+            }
+            Some(Command::PriceHistory { token, token_id }) => {
+                let history = db
+                    .price_history
+                    .get(&token.to_lowercase(), &format!("{:x}", parse_id(&token_id)))
+                    .await;
+
+                println!("{}", serde_json::to_string_pretty(&history).unwrap());
+            }
+            Some(Command::AxieEquipment { axie_id }) => {
+                let equipment = db
+                    .axie_equipment
+                    .equipped(&format!("{:x}", parse_id(&axie_id)))
+                    .await;
+
+                println!("{}", serde_json::to_string_pretty(&equipment).unwrap());
+            }
+            Some(Command::Probe {
+                sample_blocks,
+                max_concurrency,
+            }) => {
+                let mut profiles = vec![];
+                for endpoint in
+                    std::iter::once(&args.web3_hostname).chain(&args.fallback_web3_hostname)
+                {
+                    profiles
+                        .push(probe::probe(&db, endpoint, sample_blocks, max_concurrency).await);
+                }
+
+                println!("{}", serde_json::to_string_pretty(&profiles).unwrap());
+            }
+            Some(Command::RetryFailedBlocks) => {
+                let failed_blocks = db.failed_blocks.to_owned();
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                for failed in failed_blocks.all().await {
+                    info!(
+                        "[INFO] Retrying blocks {}..={}",
+                        failed.block, failed.to_block
+                    );
+                    // A block that fails again is recorded again.
+                    failed_blocks
+                        .remove(failed.block)
+                        .await
+                        .expect("Failed to remove failed block!");
+
+                    match failed.stage {
+                        FailedStage::Receipts => {
+                            // The stream stops before its stop block.
+                            let retry_args = Args {
+                                command: None,
+                                replay: false,
+                                leader_election: false,
+                                start_block: Some(failed.block),
+                                stop_block: Some(StopBlock::Number(failed.to_block + 1)),
+                                ..args.clone()
+                            };
+                            ronin.stream(0, retry_args).await;
+                        }
+                        FailedStage::Logs => {
+                            ronin
+                                .reindex_logs(failed.block, failed.to_block, &args)
+                                .await
+                        }
+                    }
+                }
+            }
+            Some(Command::RepairBlocks) => {
+                let pending = db.block_status.pending().await;
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                for (start, end) in integrity::ranges(&pending) {
+                    info!("[INFO] Repairing blocks {start}..={end}");
+                    // The stream stops before its stop block.
+                    let repair_args = Args {
+                        command: None,
+                        replay: false,
+                        leader_election: false,
+                        start_block: Some(start),
+                        stop_block: Some(StopBlock::Number(end + 1)),
+                        ..args.clone()
+                    };
+                    ronin.stream(0, repair_args).await;
+                }
+            }
+            Some(Command::RetryDecodeFailures) => {
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                ronin.retry_decode_failures().await;
+            }
+            Some(Command::Migrate) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "migrate").await;
+
+                migrate::numeric_fields(&db).await
+            }
+            Some(Command::PruneTombstones { older_than_days }) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "prune-tombstones").await;
+
+                reorg::prune(&db, older_than_days).await
+            }
+            Some(Command::Tier {
+                cold_db_name,
+                keep_blocks,
+                batch_size,
+            }) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "tier").await;
+
+                let cold_db_uri = args.cold_db_uri.as_deref().unwrap_or(&args.db_uri);
+                let cold = mongo::connect(cold_db_uri, &cold_db_name).await;
+
+                tiering::move_to_cold(&db, &cold, keep_blocks, batch_size).await;
+            }
+            Some(Command::Rollback {
+                from_block,
+                to_block,
+            }) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "rollback").await;
+
+                if from_block > to_block {
+                    panic!("--from-block {from_block} is after --to-block {to_block}");
+                }
+                reorg::rollback_range(&db, from_block, to_block).await
+            }
+            Some(Command::PromoteShadow { version, force }) => {
+                admin::authorize(&db, args.admin_token.as_deref(), "promote-shadow").await;
+
+                shadow::promote(&db, version, force).await
+            }
+            Some(Command::Stats {
+                from_block,
+                to_block,
+                limit,
+                days,
+                json,
+            }) => {
+                let (from_block, to_block) = analysis_range(range, from_block, to_block);
+                let stats = stats::stats(&db, from_block, to_block, limit, days).await;
+
+                match json {
+                    true => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+                    false => stats.print(),
+                }
+            }
+            Some(Command::Status { json }) => status::report(&db, &args, json).await,
+            Some(Command::SetAdminToken { token }) => {
+                admin::set_token(&db, &token, args.admin_token.as_deref()).await
+            }
+            None => {
+                if args.replay {
+                    admin::authorize(&db, args.admin_token.as_deref(), "replay").await;
+                }
+
+                let ronin = Ronin::new(&args.web3_hostname, db).await;
+
+                if let Some(start) = range.0 {
+                    args.start_block = Some(start);
+                }
+                if let Some(distance) = args.from_head {
+                    args.start_block = Some(ronin.head_stop_block(distance).await.max(1));
+                }
+                if let Some(stop) = range.1 {
+                    // The stream stops before its stop block, logs mode includes it.
+                    let stop = match args.logs_mode {
+                        true => stop.saturating_sub(1),
+                        false => stop,
+                    };
+                    args.stop_block = Some(StopBlock::Number(stop));
+                }
+
+                match args.logs_mode {
+                    true => ronin.stream_logs(args.reorg_offset, args).await,
+                    false => ronin.stream(args.reorg_offset, args).await,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use log::warn;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::Serialize;
+
+use crate::analysis::block_range_filter;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+
+/// Collections whose documents reference their transaction by `transaction_id` and their block by
+/// `block`.
+pub const LINKED_COLLECTIONS: [&str; 8] = [
+    "erc_transfers",
+    "erc1155_transfers",
+    "wallet_transfers",
+    "whale_transfers",
+    "native_transfers",
+    "erc721_sales",
+    "auctions",
+    "orders",
+];
+
+/// Orphans of one collection: documents whose transaction or block is not stored.
+#[derive(Serialize, Debug, Default)]
+pub struct LinkageReport {
+    pub checked: u64,
+    pub missing_transaction: u64,
+    pub missing_block: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IntegrityReport {
+    pub from_block: Block,
+    pub to_block: Block,
+    pub collections: BTreeMap<String, LinkageReport>,
+    /// Blocks holding at least one orphan, the ones to index again.
+    pub blocks: BTreeSet<Block>,
+}
+
+/// Values of `field` in `collection` among `values`, as strings.
+async fn stored<T>(collection: &Collection<T>, field: &str, values: Vec<Bson>) -> HashSet<String> {
+    let options = FindOptions::builder()
+        .projection(doc! {"_id": 0, field: 1})
+        .build();
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(doc! {field: {"$in": values}}, options)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to query {}: {err}", collection.name()));
+
+    let mut stored = HashSet::new();
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()))
+    {
+        let document = cursor
+            .deserialize_current()
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()));
+        if let Some(value) = document.get(field) {
+            stored.insert(match value {
+                Bson::String(value) => value.to_owned(),
+                value => value.to_string(),
+            });
+        }
+    }
+
+    stored
+}
+
+/// References of one batch, resolved against `transactions` and `blocks` together.
+#[derive(Default)]
+struct Batch {
+    documents: Vec<(String, Block)>,
+}
+
+impl Batch {
+    async fn resolve(
+        &mut self,
+        database: &Database,
+        (transactions, blocks): (bool, bool),
+        report: &mut LinkageReport,
+        orphans: &mut BTreeSet<Block>,
+    ) {
+        if self.documents.is_empty() {
+            return;
+        }
+
+        let stored_transactions = match transactions {
+            true => {
+                let hashes: HashSet<&String> =
+                    self.documents.iter().map(|(hash, _)| hash).collect();
+                stored(
+                    &database.transactions.collection,
+                    "hash",
+                    hashes
+                        .into_iter()
+                        .map(|hash| hash.as_str().into())
+                        .collect(),
+                )
+                .await
+            }
+            false => HashSet::new(),
+        };
+        let stored_blocks = match blocks {
+            true => {
+                let numbers: HashSet<Block> =
+                    self.documents.iter().map(|(_, block)| *block).collect();
+                stored(
+                    &database.blocks.collection,
+                    "block",
+                    numbers
+                        .into_iter()
+                        .map(|block| (block as i64).into())
+                        .collect(),
+                )
+                .await
+            }
+            false => HashSet::new(),
+        };
+
+        for (hash, block) in self.documents.drain(..) {
+            report.checked += 1;
+
+            let mut orphan = false;
+            // Documents stored without their transaction have nothing to link.
+            if transactions && !hash.is_empty() && !stored_transactions.contains(&hash) {
+                report.missing_transaction += 1;
+                orphan = true;
+            }
+            if blocks && !stored_blocks.contains(&block.to_string()) {
+                report.missing_block += 1;
+                orphan = true;
+            }
+            if orphan {
+                orphans.insert(block);
+            }
+        }
+    }
+}
+
+/// Verifies that the documents of [`LINKED_COLLECTIONS`] in the block range reference a stored
+/// transaction (with `transactions`) and block (with `blocks`). Orphans are left behind when only
+/// some pools of a block were committed.
+pub async fn check(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    batch_size: usize,
+    (transactions, blocks): (bool, bool),
+) -> IntegrityReport {
+    let mut report = IntegrityReport {
+        from_block,
+        to_block,
+        collections: BTreeMap::new(),
+        blocks: BTreeSet::new(),
+    };
+    if !transactions && !blocks {
+        warn!("[WARN] Neither transactions nor blocks are indexed, nothing to check");
+        return report;
+    }
+
+    for name in LINKED_COLLECTIONS {
+        let collection = database._database.collection::<Document>(name);
+        let options = FindOptions::builder()
+            .projection(doc! {"_id": 0, "transaction_id": 1, "block": 1})
+            .build();
+        let mut cursor = collection
+            .find(block_range_filter(from_block, to_block), options)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to query {name}: {err}"));
+
+        let mut linkage = LinkageReport::default();
+        let mut batch = Batch::default();
+        while cursor
+            .advance()
+            .await
+            .unwrap_or_else(|err| panic!("Failed to read {name}: {err}"))
+        {
+            let document = cursor
+                .deserialize_current()
+                .unwrap_or_else(|err| panic!("Failed to read {name}: {err}"));
+            let hash = document.get_str("transaction_id").unwrap_or_default();
+            let block = match document.get("block") {
+                Some(Bson::Int64(block)) => *block as Block,
+                Some(Bson::Int32(block)) => *block as Block,
+                _ => continue,
+            };
+            batch.documents.push((hash.to_string(), block));
+
+            if batch.documents.len() >= batch_size {
+                batch
+                    .resolve(
+                        database,
+                        (transactions, blocks),
+                        &mut linkage,
+                        &mut report.blocks,
+                    )
+                    .await;
+            }
+        }
+        batch
+            .resolve(
+                database,
+                (transactions, blocks),
+                &mut linkage,
+                &mut report.blocks,
+            )
+            .await;
+
+        if linkage.missing_transaction > 0 || linkage.missing_block > 0 {
+            warn!(
+                "[WARN] {name}: {} of {} documents without transaction, {} without block",
+                linkage.missing_transaction, linkage.checked, linkage.missing_block
+            );
+        }
+        report.collections.insert(name.to_string(), linkage);
+    }
+
+    report
+}
+
+/// Groups blocks into inclusive ranges of consecutive blocks.
+pub fn ranges(blocks: &BTreeSet<Block>) -> Vec<(Block, Block)> {
+    let mut ranges: Vec<(Block, Block)> = vec![];
+
+    for &block in blocks {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == block => *end = block,
+            _ => ranges.push((block, block)),
+        }
+    }
+
+    ranges
+}
@@ -0,0 +1,38 @@
+#[macro_use]
+extern crate fstrings;
+
+pub use crate::handler::EventHandler;
+pub use crate::indexer::Indexer;
+
+pub mod abi;
+mod admin;
+mod analysis;
+mod cache;
+pub mod cli_args;
+mod explorer;
+mod export;
+mod fixtures;
+pub mod handler;
+mod import;
+mod indexer;
+mod integrity;
+pub mod logging;
+mod metadata;
+mod migrate;
+pub mod mongo;
+mod probe;
+mod rate_limit;
+mod reorg;
+mod report;
+mod retry;
+mod rollup;
+pub mod ronin;
+mod rules;
+pub mod runtime;
+mod shadow;
+mod sink;
+mod spam;
+mod stats;
+mod status;
+mod subscriptions;
+mod tiering;
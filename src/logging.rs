@@ -0,0 +1,111 @@
+use std::io::Write;
+
+use env_logger::Env;
+use mongodb::bson::DateTime;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cli_args::LogFormat;
+use crate::mongo::collections::Block;
+
+/// Installs the logger printing in `format`. `RUST_LOG` overrides the default level, which is every
+/// level with `debug` and warnings otherwise. Does nothing when a logger is already installed.
+pub fn init(format: LogFormat, debug: bool) {
+    let default_level = match debug {
+        true => "on",
+        false => "warn",
+    };
+
+    let mut builder =
+        env_logger::Builder::from_env(Env::default().default_filter_or(default_level));
+
+    match format {
+        LogFormat::Pretty => {}
+        LogFormat::Compact => {
+            builder.format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()));
+        }
+        LogFormat::Json => {
+            builder.format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{}",
+                    json_line(
+                        record.level().as_str(),
+                        record.target(),
+                        &record.args().to_string()
+                    )
+                )
+            });
+        }
+    }
+
+    let _ = builder.try_init();
+}
+
+/// Wraps a message into a JSON log line. Messages that are JSON objects themselves, such as
+/// block summaries, are embedded as `fields` instead of being escaped into a string.
+pub fn json_line(level: &str, target: &str, message: &str) -> String {
+    let mut line = json!({
+        "timestamp": DateTime::now().try_to_rfc3339_string().unwrap_or_default(),
+        "level": level,
+        "target": target,
+    });
+
+    match serde_json::from_str::<serde_json::Value>(message) {
+        Ok(fields) if fields.is_object() => line["fields"] = fields,
+        _ => line["message"] = json!(message),
+    }
+
+    line.to_string()
+}
+
+/// Per block counters printed by the stream.
+#[derive(Serialize, Default)]
+pub struct BlockSummary {
+    pub block: Block,
+    pub transactions: usize,
+    pub erc_transfers: usize,
+    pub erc1155_transfers: usize,
+    pub wallet_updates: usize,
+    pub erc_sales: usize,
+}
+
+impl BlockSummary {
+    pub fn render(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Pretty => f!(
+                "Block: {:>12}\t\tTransactions: {:>4}\tERC Transfers: {:>5}\tERC 1155 Transfers: {:>5}\tWallet Updates: {:>5}\tERC721 Sales: {:>5}",
+                self.block,
+                self.transactions,
+                self.erc_transfers,
+                self.erc1155_transfers,
+                self.wallet_updates,
+                self.erc_sales
+            ),
+            LogFormat::Compact => f!(
+                "block={} txs={} erc={} erc1155={} wallets={} sales={}",
+                self.block,
+                self.transactions,
+                self.erc_transfers,
+                self.erc1155_transfers,
+                self.wallet_updates,
+                self.erc_sales
+            ),
+            LogFormat::Json => serde_json::to_string(self).unwrap(),
+        }
+    }
+
+    /// Prints the summary through the logger, or straight to stdout when info logging is off.
+    pub fn print(&self, format: LogFormat) {
+        let line = self.render(format);
+
+        if log::log_enabled!(log::Level::Info) {
+            log::info!("{}", line);
+        } else {
+            match format {
+                LogFormat::Json => println!("{}", json_line("INFO", module_path!(), &line)),
+                _ => println!("{}", line),
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use log::{info, warn};
+use mongodb::bson::{doc, DateTime};
+use serde_json::Value;
+use web3::types::{H160, U256};
+
+use crate::mongo::collections::nft_owner::NftOwner;
+use crate::mongo::collections::token_metadata::TokenMetadata;
+use crate::mongo::collections::Address;
+use crate::mongo::Database;
+use crate::ronin::{ContractType, Ronin};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Turns a metadata URI into something that can be fetched over HTTP, or the inline JSON of a
+/// `data:` URI.
+enum Resolved {
+    Url(String),
+    Inline(String),
+}
+
+fn resolve_uri(uri: &str, ipfs_gateway: &str) -> Result<Resolved, String> {
+    let gateway = ipfs_gateway.trim_end_matches('/');
+
+    if let Some(path) = uri.strip_prefix("ipfs://") {
+        let path = path.strip_prefix("ipfs/").unwrap_or(path);
+        return Ok(Resolved::Url(f!("{gateway}/ipfs/{path}")));
+    }
+    if let Some(path) = uri.strip_prefix("ar://") {
+        return Ok(Resolved::Url(f!("https://arweave.net/{path}")));
+    }
+    if let Some(data) = uri.strip_prefix("data:application/json;base64,") {
+        let json = base64::decode(data).map_err(|err| f!("Invalid base64 metadata: {err}"))?;
+        return String::from_utf8(json)
+            .map(Resolved::Inline)
+            .map_err(|err| f!("Invalid utf-8 metadata: {err}"));
+    }
+    if let Some((_, json)) = uri
+        .strip_prefix("data:application/json")
+        .and_then(|rest| rest.split_once(','))
+    {
+        return Ok(Resolved::Inline(json.to_string()));
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        return Ok(Resolved::Url(uri.to_string()));
+    }
+
+    Err(f!("Unsupported metadata URI {uri}"))
+}
+
+async fn download(
+    client: &reqwest::Client,
+    uri: &str,
+    ipfs_gateway: &str,
+) -> Result<Value, String> {
+    let json = match resolve_uri(uri, ipfs_gateway)? {
+        Resolved::Inline(json) => json,
+        Resolved::Url(url) => client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| f!("Failed to fetch {url}: {err}"))?
+            .text()
+            .await
+            .map_err(|err| f!("Failed to read {url}: {err}"))?,
+    };
+
+    serde_json::from_str(&json).map_err(|err| f!("Invalid metadata JSON: {err}"))
+}
+
+async fn fetch(
+    ronin: &Ronin,
+    client: &reqwest::Client,
+    owner: &NftOwner,
+    block: u64,
+    ipfs_gateway: &str,
+) -> TokenMetadata {
+    let mut metadata = TokenMetadata {
+        token: owner.token.to_owned(),
+        token_id: owner.token_id.to_owned(),
+        erc: owner.erc.to_owned(),
+        uri: None,
+        name: None,
+        description: None,
+        image: None,
+        attributes: None,
+        error: None,
+        fetched_at: DateTime::now(),
+    };
+
+    let (token, token_id) = match (
+        H160::from_str(&owner.token),
+        U256::from_str_radix(&owner.token_id, 16),
+    ) {
+        (Ok(token), Ok(token_id)) => (token, token_id),
+        _ => {
+            metadata.error = Some("Invalid token address or id".to_string());
+            return metadata;
+        }
+    };
+
+    metadata.uri = ronin.token_uri(token, token_id, &owner.erc, block).await;
+    let uri = match &metadata.uri {
+        None => {
+            metadata.error = Some("tokenURI/uri call failed".to_string());
+            return metadata;
+        }
+        Some(uri) => uri,
+    };
+
+    match download(client, uri, ipfs_gateway).await {
+        Ok(json) => {
+            let text = |key: &str| json.get(key).and_then(Value::as_str).map(str::to_string);
+            metadata.name = text("name");
+            metadata.description = text("description");
+            metadata.image = text("image").or_else(|| text("image_url"));
+            metadata.attributes = json
+                .get("attributes")
+                .or_else(|| json.get("properties"))
+                .and_then(|attributes| mongodb::bson::to_bson(attributes).ok());
+        }
+        Err(err) => metadata.error = Some(err),
+    }
+
+    metadata
+}
+
+/// Fetches the metadata of the NFTs in `nft_owners` that have none stored yet, optionally only of
+/// one `token`, and stores it in `token_metadata`. Tokens whose last fetch failed are fetched again
+/// with `retry_failed`.
+pub async fn fetch_metadata(
+    database: &Database,
+    ronin: &Ronin,
+    token: Option<&str>,
+    limit: usize,
+    ipfs_gateway: &str,
+    retry_failed: bool,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build http client!");
+    let block = ronin.head_stop_block(0).await;
+
+    let mut filter =
+        doc! {"erc": {"$in": [ContractType::ERC721.as_str(), ContractType::ERC1155.as_str()]}};
+    if let Some(token) = token {
+        filter.insert("token", token.to_lowercase());
+    }
+
+    let mut cursor = database
+        .nft_owners
+        .collection
+        .find(filter, None)
+        .await
+        .expect("Failed to query nft owners!");
+
+    let mut seen: HashSet<(Address, String)> = HashSet::new();
+    let (mut fetched, mut failed) = (0, 0);
+
+    while fetched + failed < limit && cursor.advance().await.expect("Failed to read nft owners!") {
+        let owner: NftOwner = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize nft owner!");
+
+        if !seen.insert((owner.token.to_owned(), owner.token_id.to_owned())) {
+            continue;
+        }
+
+        if let Some(existing) = database
+            .token_metadata
+            .get(&owner.token, &owner.token_id)
+            .await
+        {
+            if existing.error.is_none() || !retry_failed {
+                continue;
+            }
+        }
+
+        let metadata = fetch(ronin, &client, &owner, block, ipfs_gateway).await;
+        match &metadata.error {
+            None => {
+                info!(
+                    "[INFO] Fetched metadata of {} #{}",
+                    metadata.token, metadata.token_id
+                );
+                fetched += 1;
+            }
+            Some(err) => {
+                warn!(
+                    "[WARN] Failed to fetch metadata of {} #{}: {err}",
+                    metadata.token, metadata.token_id
+                );
+                failed += 1;
+            }
+        }
+
+        database
+            .token_metadata
+            .store(&metadata)
+            .await
+            .expect("Failed to store token metadata!");
+    }
+
+    println!("Fetched metadata of {fetched} tokens, {failed} failed");
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use futures::future::join_all;
+use log::{info, warn};
+use mongodb::bson::{doc, from_document, to_document, Bson, Document};
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::{decimal_amount, hex_number, NumericFields};
+use crate::mongo::Database;
+use crate::ronin::{ContractType, Ronin};
+
+/// Documents updated concurrently.
+const BATCH_SIZE: usize = 1000;
+
+async fn update_batch(collection: &Collection<Document>, batch: &mut Vec<(Bson, Document)>) {
+    join_all(
+        batch
+            .drain(..)
+            .map(|(id, set)| collection.update_one(doc! {"_id": id}, doc! {"$set": set}, None)),
+    )
+    .await
+    .into_iter()
+    .for_each(|result| {
+        result.unwrap_or_else(|err| panic!("Failed to migrate {}: {err}", collection.name()));
+    });
+}
+
+/// Adds the number fields of `T` to the documents of `collection` written before them. `fill`
+/// sets the fields [`NumericFields::fill_numeric`] can't derive on its own.
+async fn migrate_collection<T>(collection: &Collection<T>, fill: impl Fn(&mut T))
+where
+    T: NumericFields + Serialize + DeserializeOwned,
+{
+    let collection = collection.clone_with_type::<Document>();
+    let mut filter = Document::new();
+    filter.insert(T::FIELDS[0], doc! {"$exists": false});
+
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to query {}: {err}", collection.name()));
+
+    let mut migrated = 0;
+    let mut batch = vec![];
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()))
+    {
+        let document: Document = cursor
+            .deserialize_current()
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()));
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+
+        let mut value: T = match from_document(document) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("[WARN] Skipping {} document {id}: {err}", collection.name());
+                continue;
+            }
+        };
+        value.fill_numeric();
+        fill(&mut value);
+
+        let numbers = to_document(&value).expect("Failed to serialize document");
+        let mut set = Document::new();
+        for field in T::FIELDS {
+            // Fields skipped when empty stay unset.
+            if let Some(number) = numbers.get(field) {
+                set.insert(*field, number.to_owned());
+            }
+        }
+
+        batch.push((id, set));
+        if batch.len() >= BATCH_SIZE {
+            migrated += batch.len() as u64;
+            update_batch(&collection, &mut batch).await;
+            info!("[INFO] Migrated {migrated} {} documents", collection.name());
+        }
+    }
+    migrated += batch.len() as u64;
+    update_batch(&collection, &mut batch).await;
+
+    println!("Migrated {migrated} {} documents", collection.name());
+}
+
+/// Stores the native number fields on the transactions, transfers and sales written by older
+/// releases, which only have the hex encoded strings. Documents that already have them are
+/// skipped, so an interrupted migration resumes where it stopped.
+pub async fn numeric_fields(database: &Database) {
+    let decimals: HashMap<String, usize> = Ronin::contract_list()
+        .into_values()
+        .filter(|contract| contract.erc == ContractType::ERC20)
+        .map(|contract| (contract.address.to_string(), contract.decimals))
+        .collect();
+    let value_decimal = |transfer: &mut ERCTransfer| {
+        if let Some(decimals) = decimals.get(&transfer.token) {
+            transfer.value_decimal = hex_number(&transfer.value_or_token_id)
+                .map(|value| decimal_amount(value, *decimals));
+        }
+    };
+
+    migrate_collection(&database.transactions.collection, |_| {}).await;
+    migrate_collection(&database.erc_transfers.collection, value_decimal).await;
+    migrate_collection(&database.whale_transfers.collection, value_decimal).await;
+    migrate_collection(&database.erc1155_transfers.collection, |_| {}).await;
+    migrate_collection(&database.erc_sales.collection, |_| {}).await;
+}
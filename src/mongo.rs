@@ -1,10 +1,54 @@
-use mongodb::options::IndexOptions;
-use mongodb::{bson::Document, Client};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
 
+use log::info;
+use mongodb::bson::doc;
+use mongodb::options::{CollectionOptions, IndexOptions, WriteConcern};
+use mongodb::{bson::Document, Client, ClientSession, Collection};
+
+use crate::cli_args::CollectionWriteConcern;
+use crate::mongo::collections::auction::{Auction, AuctionProvider};
+use crate::mongo::collections::axie_equipment::{AxieEquipment, AxieEquipmentProvider};
 use crate::mongo::collections::axie_sale::{Sale, SaleProvider};
+use crate::mongo::collections::backfill_chunk::{BackfillChunk, BackfillChunkProvider};
+use crate::mongo::collections::balance::{Balance, BalanceProvider};
+use crate::mongo::collections::block::{BlockProvider, IndexedBlock};
+use crate::mongo::collections::block_status::{BlockStatus, BlockStatusProvider};
+use crate::mongo::collections::contract::{ContractMetadata, ContractProvider};
+use crate::mongo::collections::contract_event::{ContractEvent, ContractEventProvider};
+use crate::mongo::collections::decode_failure::{DecodeFailure, DecodeFailureProvider};
+use crate::mongo::collections::equipment_event::{EquipmentEvent, EquipmentEventProvider};
+use crate::mongo::collections::erc1155_supply::{Erc1155Supply, Erc1155SupplyProvider};
 use crate::mongo::collections::erc1155_transfer::{ERC1155Transfer, Erc1155TransferProvider};
 use crate::mongo::collections::erc_transfer::ErcTransferProvider;
+use crate::mongo::collections::failed_block::{FailedBlock, FailedBlockProvider};
+use crate::mongo::collections::governance_event::{GovernanceEvent, GovernanceEventProvider};
+use crate::mongo::collections::lease::{Lease, LeaseProvider};
+use crate::mongo::collections::marketplace_fee::{MarketplaceFee, MarketplaceFeeProvider};
+use crate::mongo::collections::metrics::{Metrics, MetricsProvider};
+use crate::mongo::collections::missing_block::{MissingBlock, MissingBlockProvider};
+use crate::mongo::collections::native_transfer::{NativeTransfer, NativeTransferProvider};
+use crate::mongo::collections::net_worth::{NetWorth, NetWorthProvider};
+use crate::mongo::collections::nft_owner::{NftOwner, NftOwnerProvider};
+use crate::mongo::collections::order::{OrderEvent, OrderProvider};
+use crate::mongo::collections::pair_reserve::{PairReserve, PairReserveProvider};
+use crate::mongo::collections::price_history::{PriceHistory, PriceHistoryProvider};
+use crate::mongo::collections::query_cache::{CachedQuery, QueryCacheProvider};
+use crate::mongo::collections::receipt_anomaly::{ReceiptAnomaly, ReceiptAnomalyProvider};
+use crate::mongo::collections::ron_balance::{
+    RonBalance, RonBalanceChange, RonBalanceChangeIndexes, RonBalanceProvider,
+};
+use crate::mongo::collections::spam_token::{SpamToken, SpamTokenProvider};
+use crate::mongo::collections::staking_event::{StakingEvent, StakingEventProvider};
+use crate::mongo::collections::token::{DiscoveredToken, TokenProvider};
+use crate::mongo::collections::token_daily::{
+    TokenAddress, TokenAddressIndexes, TokenDaily, TokenDailyProvider,
+};
+use crate::mongo::collections::token_metadata::{TokenMetadata, TokenMetadataProvider};
+use crate::mongo::collections::tombstone::{Tombstone, TombstoneProvider};
 use crate::mongo::collections::transaction::TransactionProvider;
+use crate::mongo::collections::wallet_transfer::{WalletTransfer, WalletTransferProvider};
+use crate::mongo::collections::whale_transfer::WhaleTransferProvider;
 use crate::mongo::collections::{
     erc_transfer::ERCTransfer,
     settings::{Settings, SettingsProvider},
@@ -12,6 +56,7 @@ use crate::mongo::collections::{
     wallet::Wallet,
     wallet::WalletProvider,
 };
+use crate::ronin::ContractType;
 
 pub struct IndexModel {
     pub model: Document,
@@ -36,37 +81,181 @@ fn index_model(key: &'static str, unique: bool) -> IndexModel {
     }
 }
 
+fn compound_index_model(keys: &[&'static str], unique: bool) -> IndexModel {
+    let mut doc = Document::new();
+    for key in keys {
+        doc.insert(*key, 1u32);
+    }
+
+    IndexModel {
+        model: doc,
+        options: match unique {
+            true => IndexOptions::builder().unique(true).build(),
+            false => Default::default(),
+        },
+    }
+}
+
+#[derive(Clone)]
 pub struct Database {
     pub wallets: WalletProvider,
     pub transactions: TransactionProvider,
+    pub blocks: BlockProvider,
     pub settings: SettingsProvider,
     pub erc_transfers: ErcTransferProvider,
     pub erc1155_transfers: Erc1155TransferProvider,
+    pub wallet_transfers: WalletTransferProvider,
+    pub whale_transfers: WhaleTransferProvider,
+    pub erc1155_supply: Erc1155SupplyProvider,
+    pub tokens: TokenProvider,
+    pub ron_balances: RonBalanceProvider,
+    pub native_transfers: NativeTransferProvider,
+    pub balances: BalanceProvider,
+    pub nft_owners: NftOwnerProvider,
+    pub price_history: PriceHistoryProvider,
+    pub token_metadata: TokenMetadataProvider,
+    pub query_cache: QueryCacheProvider,
     pub erc_sales: SaleProvider,
+    pub auctions: AuctionProvider,
+    pub pair_reserves: PairReserveProvider,
+    pub governance_events: GovernanceEventProvider,
+    pub staking_events: StakingEventProvider,
+    pub orders: OrderProvider,
+    pub equipment_events: EquipmentEventProvider,
+    pub axie_equipment: AxieEquipmentProvider,
+    pub metrics: MetricsProvider,
+    pub token_daily: TokenDailyProvider,
+    pub marketplace_fees: MarketplaceFeeProvider,
+    pub net_worth: NetWorthProvider,
+    pub spam_tokens: SpamTokenProvider,
+    pub decode_failures: DecodeFailureProvider,
+    pub receipt_anomalies: ReceiptAnomalyProvider,
+    pub missing_blocks: MissingBlockProvider,
+    pub failed_blocks: FailedBlockProvider,
+    pub block_status: BlockStatusProvider,
+    pub backfill_chunks: BackfillChunkProvider,
+    pub tombstones: TombstoneProvider,
+    pub leases: LeaseProvider,
+    pub contracts: ContractProvider,
+    pub contract_events: ContractEventProvider,
     pub _client: Client,
     pub _database: mongodb::Database,
 }
 
 pub mod collections {
+    use mongodb::bson::Decimal128;
+    use web3::types::U256;
+
     pub type Address = String;
     pub type TransactionHash = String;
     pub type Block = u64;
 
+    /// Significant digits of a Decimal128.
+    const DECIMAL128_DIGITS: usize = 34;
+
+    /// `amount / 10^decimals` as Decimal128, truncated to its precision.
+    pub fn decimal_amount(amount: U256, decimals: usize) -> Decimal128 {
+        let digits = amount.to_string();
+        let dropped = digits.len().saturating_sub(DECIMAL128_DIGITS);
+        let exponent = dropped as i64 - decimals as i64;
+
+        f!("{}E{exponent:+}", &digits[..digits.len() - dropped])
+            .parse()
+            .expect("Failed to convert amount to Decimal128")
+    }
+
+    /// Number of a hex encoded string field, with or without 0x prefix.
+    pub fn hex_number(value: &str) -> Option<U256> {
+        U256::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+    }
+
+    /// Decimal128 of a hex encoded string field, `None` if the number has more digits than a
+    /// Decimal128 holds exactly.
+    pub fn hex_decimal(value: &str) -> Option<Decimal128> {
+        hex_number(value)
+            .filter(|number| number.to_string().len() <= DECIMAL128_DIGITS)
+            .map(|number| decimal_amount(number, 0))
+    }
+
+    pub fn hex_u64(value: &str) -> Option<u64> {
+        hex_number(value)
+            .filter(|number| *number <= U256::from(i64::MAX))
+            .map(|number| number.as_u64())
+    }
+
+    /// Hex encoded log index of a `log_index_num`, for documents stored without `log_index`.
+    pub fn log_index(log_index_num: Option<u64>) -> String {
+        log_index_num
+            .map(|index| f!("{index:#x}"))
+            .unwrap_or_default()
+    }
+
+    /// Documents storing native BSON numbers next to their hex encoded string fields, so range
+    /// queries, sorts and sums work on the server. The string fields stay the source of truth.
+    pub trait NumericFields: Sized {
+        /// The number fields. The first one is stored on every document, the `migrate` subcommand
+        /// fills the documents missing it.
+        const FIELDS: &'static [&'static str];
+
+        /// Derives the number fields from the string fields.
+        fn fill_numeric(&mut self);
+
+        fn with_numeric(mut self) -> Self {
+            self.fill_numeric();
+            self
+        }
+    }
+
     pub mod settings {
-        use mongodb::bson::doc;
-        use mongodb::options::UpdateOptions;
+        use mongodb::bson::{doc, Bson};
+        use mongodb::options::{FindOptions, UpdateOptions};
         use mongodb::results::UpdateResult;
         use mongodb::Collection;
+        use serde::de::DeserializeOwned;
         pub use serde::{Deserialize, Serialize};
 
         use crate::mongo::{index_model, IndexModel, Indexable};
 
+        /// A settings document. Typed documents keep their value in `data` together with the
+        /// `version` of their model. Documents written by older releases only have the string
+        /// `value`, which is still written alongside `data` so a downgrade keeps working.
         #[derive(Serialize, Deserialize)]
         pub struct Settings {
             key: String,
+            #[serde(default)]
             pub value: String,
+            #[serde(default)]
+            pub data: Option<Bson>,
+            #[serde(default)]
+            pub version: u32,
+        }
+
+        impl Settings {
+            fn decode<T: SettingsValue>(self) -> Option<T> {
+                match self.data {
+                    Some(data) if self.version == T::VERSION => Some(
+                        mongodb::bson::from_bson(data)
+                            .unwrap_or_else(|_| panic!("Invalid settings document {}", self.key)),
+                    ),
+                    Some(_) => None,
+                    None => Some(
+                        serde_json::from_str(&self.value)
+                            .unwrap_or_else(|_| panic!("Invalid settings value {}", self.key)),
+                    ),
+                }
+            }
+        }
+
+        /// Model of a typed settings document. Bump `VERSION` on incompatible changes, documents
+        /// with another version are ignored by [`SettingsProvider::load`].
+        pub trait SettingsValue: Serialize + DeserializeOwned {
+            const VERSION: u32 = 1;
         }
 
+        impl SettingsValue for u64 {}
+        impl SettingsValue for i64 {}
+
+        #[derive(Clone)]
         pub struct SettingsProvider {
             pub collection: Collection<Settings>,
         }
@@ -76,7 +265,7 @@ pub mod collections {
                 SettingsProvider { collection }
             }
 
-            pub async fn get(&self, key: &'static str) -> Option<Settings> {
+            pub async fn get(&self, key: &str) -> Option<Settings> {
                 self.collection
                     .find_one(
                         doc! {
@@ -88,9 +277,66 @@ pub mod collections {
                     .unwrap()
             }
 
+            /// Reads a typed settings document, falling back to parsing the legacy string value
+            /// as JSON.
+            pub async fn load<T: SettingsValue>(&self, key: &str) -> Option<T> {
+                Settings::decode(self.get(key).await?)
+            }
+
+            /// Typed settings documents whose key starts with `prefix`, by key.
+            pub async fn load_prefixed<T: SettingsValue>(&self, prefix: &str) -> Vec<(String, T)> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"key": {"$regex": f!("^{}", prefix.replace('.', "\\."))}},
+                        FindOptions::builder().sort(doc! {"key": 1}).build(),
+                    )
+                    .await
+                    .expect("Failed to query settings!");
+
+                let mut values = vec![];
+                while cursor.advance().await.expect("Failed to read settings!") {
+                    let settings: Settings = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize settings!");
+                    let key = settings.key.to_owned();
+                    if let Some(value) = Settings::decode(settings) {
+                        values.push((key, value));
+                    }
+                }
+
+                values
+            }
+
+            pub async fn store<T: SettingsValue>(
+                &self,
+                key: &str,
+                value: &T,
+            ) -> mongodb::error::Result<UpdateResult> {
+                let data = mongodb::bson::to_bson(value)?;
+                let legacy = serde_json::to_string(value).unwrap_or_default();
+
+                self.collection
+                    .update_one(
+                        doc! {
+                            "key": key
+                        },
+                        doc! {
+                            "$set": {
+                                "key": key,
+                                "value": legacy,
+                                "data": data,
+                                "version": T::VERSION as i64
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+            }
+
             pub async fn set<S: Into<String>>(
                 &self,
-                key: &'static str,
+                key: &str,
                 value: S,
             ) -> mongodb::error::Result<UpdateResult> {
                 self.collection
@@ -119,6 +365,83 @@ pub mod collections {
             }
         }
     }
+    pub mod block {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::FindOneOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Header of a streamed block, used to detect reorgs and to map between block numbers and
+        /// timestamps without the node.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct IndexedBlock {
+            pub block: Block,
+            pub hash: String,
+            #[serde(default)]
+            pub parent_hash: String,
+            pub timestamp: DateTime,
+            #[serde(default)]
+            pub miner: Address,
+            #[serde(default)]
+            pub gas_used: u64,
+            pub transactions: u64,
+            /// Size in bytes, if the node reports it.
+            #[serde(default)]
+            pub size: Option<u64>,
+        }
+
+        #[derive(Clone)]
+        pub struct BlockProvider {
+            pub collection: Collection<IndexedBlock>,
+        }
+
+        impl BlockProvider {
+            pub fn new(collection: Collection<IndexedBlock>) -> BlockProvider {
+                BlockProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<IndexedBlock> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            pub async fn get(&self, block: Block) -> Option<IndexedBlock> {
+                self.collection
+                    .find_one(doc! {"block": block as i64}, None)
+                    .await
+                    .expect("Failed to query blocks!")
+            }
+
+            /// First stored block with a timestamp at or after `date`.
+            pub async fn first_at(&self, date: DateTime) -> Option<IndexedBlock> {
+                self.collection
+                    .find_one(
+                        doc! {"timestamp": {"$gte": date}},
+                        FindOneOptions::builder().sort(doc! {"block": 1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query blocks!")
+            }
+        }
+
+        impl Indexable for BlockProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("block", true),
+                    index_model("timestamp", false),
+                    index_model("hash", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.blocks"
+            }
+        }
+    }
+
     pub mod wallet {
         use mongodb::bson::{doc, Document};
         use mongodb::Collection;
@@ -137,7 +460,12 @@ pub mod collections {
         #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
         pub struct Wallet {
             address: Address,
-            last_seen: WalletActivity,
+            #[serde(default)]
+            last_seen: Option<WalletActivity>,
+            #[serde(default)]
+            cluster: Option<Address>,
+            #[serde(default)]
+            deposit_for: Option<Address>,
         }
 
         #[derive(Clone)]
@@ -179,6 +507,32 @@ pub mod collections {
                 ]
             }
 
+            pub fn set_cluster(&self, address: &Address, cluster: &Address) -> [Document; 2] {
+                [
+                    doc! {"address": &address},
+                    doc! {
+                        "$set": {
+                            "cluster": cluster
+                        }
+                    },
+                ]
+            }
+
+            pub fn set_deposit_for(
+                &self,
+                address: &Address,
+                hot_wallet: &Address,
+            ) -> [Document; 2] {
+                [
+                    doc! {"address": &address},
+                    doc! {
+                        "$set": {
+                            "deposit_for": hot_wallet
+                        }
+                    },
+                ]
+            }
+
             pub fn new(collection: Collection<Wallet>) -> WalletProvider {
                 WalletProvider { collection }
             }
@@ -186,13 +540,14 @@ pub mod collections {
     }
 
     pub mod axie_sale {
-        use mongodb::bson::DateTime;
+        use log::debug;
+        use mongodb::bson::{DateTime, Decimal128};
         use mongodb::Collection;
         use serde::{Deserialize, Serialize};
 
         use crate::mongo::collections::transaction_pool::Pool;
-        use crate::mongo::collections::{Address, Block};
-        use crate::mongo::{index_model, IndexModel, Indexable};
+        use crate::mongo::collections::{hex_decimal, Address, Block, NumericFields};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
 
         #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
         pub struct Sale {
@@ -202,11 +557,66 @@ pub mod collections {
             pub seller_received: String,
             pub token: Address,
             pub token_id: String,
+            /// Hex encoded amount of ERC1155 sales, ERC721 sales have none.
+            #[serde(default)]
+            pub quantity: Option<String>,
             pub transaction_id: String,
             pub created_at: DateTime,
             pub block: Block,
+            /// Listing index of legacy auction sales, links to `auctions.listing_index`.
+            #[serde(default)]
+            pub listing_index: Option<String>,
+            /// Payment token of marketplace v2 sales.
+            #[serde(default)]
+            pub payment_token: Option<Address>,
+            /// Marketplace fee taken by marketplace v2 sales (marketFeeTaken).
+            #[serde(default)]
+            pub market_fee: Option<String>,
+            /// Marketplace fee of marketplace v2 sales in basis points (marketFeePercentage).
+            #[serde(default)]
+            pub market_fee_percentage: Option<String>,
+            /// Token of the matched bid of marketplace v2 sales (bidToken).
+            #[serde(default)]
+            pub bid_token: Option<Address>,
+            /// Order kind of marketplace v2 sales.
+            #[serde(default)]
+            pub order_kind: Option<u8>,
+            /// Order hash of marketplace v2 sales, links to `orders.hash`.
+            #[serde(default)]
+            pub order_hash: Option<String>,
+            /// Log index of the sale event, sales indexed before bundles were detected have none.
+            #[serde(default)]
+            pub log_index: Option<String>,
+            /// Transaction hash shared by the sales of a cart checkout settling several orders.
+            #[serde(default)]
+            pub bundle_id: Option<String>,
+            #[serde(default)]
+            pub price_num: Option<Decimal128>,
+            #[serde(default)]
+            pub seller_received_num: Option<Decimal128>,
+            #[serde(default)]
+            pub token_id_num: Option<Decimal128>,
+            #[serde(default)]
+            pub quantity_num: Option<Decimal128>,
         }
 
+        impl NumericFields for Sale {
+            const FIELDS: &'static [&'static str] = &[
+                "price_num",
+                "seller_received_num",
+                "token_id_num",
+                "quantity_num",
+            ];
+
+            fn fill_numeric(&mut self) {
+                self.price_num = hex_decimal(&self.price);
+                self.seller_received_num = hex_decimal(&self.seller_received);
+                self.token_id_num = hex_decimal(&self.token_id);
+                self.quantity_num = self.quantity.as_deref().and_then(hex_decimal);
+            }
+        }
+
+        #[derive(Clone)]
         pub struct SaleProvider {
             pub(crate) collection: Collection<Sale>,
         }
@@ -219,6 +629,14 @@ pub mod collections {
             pub(crate) fn get_pool(&self) -> Pool<Sale> {
                 Pool::new(self.collection.to_owned())
             }
+
+            /// Drops the unique transaction_id index of databases created before sale bundles,
+            /// which allowed a single sale per transaction.
+            pub async fn drop_unique_transaction_index(&self) {
+                if let Err(err) = self.collection.drop_index("transaction_id_1", None).await {
+                    debug!("No unique transaction_id index to drop on erc sales: {err}");
+                }
+            }
         }
 
         impl Indexable for SaleProvider {
@@ -229,33 +647,86 @@ pub mod collections {
                     index_model("token_id", false),
                     index_model("token", false),
                     index_model("created_at", false),
-                    index_model("transaction_id", true),
+                    index_model("transaction_id", false),
+                    compound_index_model(&["transaction_id", "log_index"], true),
+                    index_model("listing_index", false),
+                    index_model("bundle_id", false),
+                    index_model("payment_token", false),
+                    index_model("order_hash", false),
                 ]
             }
 
             fn index_setup_key(&self) -> &'static str {
-                "setup.erc_sales"
+                "setup.erc_sales.order_details"
             }
         }
     }
 
     pub mod transaction {
-        use mongodb::bson::doc;
+        use mongodb::bson::{doc, Decimal128};
         use mongodb::Collection;
         pub use serde::{Deserialize, Serialize};
 
-        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{
+            hex_decimal, hex_u64, Address, Block, NumericFields, TransactionHash,
+        };
         use crate::mongo::{index_model, IndexModel, Indexable};
 
-        #[derive(Serialize, Deserialize)]
+        /// Earlier inclusion of a transaction that moved to another block in a reorg.
+        #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+        pub struct Inclusion {
+            pub block: Block,
+            pub timestamp: mongodb::bson::DateTime,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
         pub struct Transaction {
             pub from: Address,
             pub to: Address,
             pub hash: TransactionHash,
             pub block: Block,
             pub timestamp: mongodb::bson::DateTime,
+            #[serde(default, skip_serializing_if = "Vec::is_empty")]
+            pub history: Vec<Inclusion>,
+            /// Receipt status, 1 for success and 0 for reverted transactions.
+            #[serde(default)]
+            pub status: Option<u64>,
+            /// Hex encoded gas used by the transaction.
+            #[serde(default, alias = "gu")]
+            pub gas_used: Option<String>,
+            /// Hex encoded gas price paid per unit of gas.
+            #[serde(default, alias = "gp")]
+            pub effective_gas_price: Option<String>,
+            #[serde(default, alias = "n")]
+            pub nonce: u64,
+            /// Hex encoded RON value transferred.
+            #[serde(default)]
+            pub value: String,
+            /// Address of the contract deployed by the transaction.
+            #[serde(default)]
+            pub contract_created: Option<Address>,
+            #[serde(default)]
+            pub value_num: Option<Decimal128>,
+            #[serde(default)]
+            pub gas_used_num: Option<u64>,
+            #[serde(default)]
+            pub effective_gas_price_num: Option<u64>,
+        }
+
+        impl NumericFields for Transaction {
+            const FIELDS: &'static [&'static str] =
+                &["value_num", "gas_used_num", "effective_gas_price_num"];
+
+            fn fill_numeric(&mut self) {
+                self.value_num = hex_decimal(&self.value);
+                self.gas_used_num = self.gas_used.as_deref().and_then(hex_u64);
+                self.effective_gas_price_num =
+                    self.effective_gas_price.as_deref().and_then(hex_u64);
+            }
         }
 
+        #[derive(Clone)]
         pub struct TransactionProvider {
             pub(crate) collection: Collection<Transaction>,
         }
@@ -264,6 +735,45 @@ pub mod collections {
             pub fn new(collection: Collection<Transaction>) -> TransactionProvider {
                 TransactionProvider { collection }
             }
+
+            pub(crate) fn get_pool(&self) -> Pool<Transaction> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            /// Moves transactions that were rejected as duplicates to their new block when they
+            /// were included in another block before (reorg). The previous block and timestamp
+            /// are appended to `history`. Returns the number of moved transactions.
+            pub async fn reinclude(
+                &self,
+                transactions: &[Transaction],
+            ) -> mongodb::error::Result<u64> {
+                let mut moved = 0;
+
+                for tx in transactions {
+                    let result = self
+                        .collection
+                        .update_one(
+                            doc! {"hash": &tx.hash, "block": {"$ne": tx.block as i64}},
+                            vec![doc! {
+                                "$set": {
+                                    "history": {
+                                        "$concatArrays": [
+                                            {"$ifNull": ["$history", []]},
+                                            [{"block": "$block", "timestamp": "$timestamp"}]
+                                        ]
+                                    },
+                                    "block": tx.block as i64,
+                                    "timestamp": tx.timestamp,
+                                }
+                            }],
+                            None,
+                        )
+                        .await?;
+                    moved += result.modified_count;
+                }
+
+                Ok(moved)
+            }
         }
 
         impl Indexable for TransactionProvider {
@@ -273,28 +783,37 @@ pub mod collections {
                     index_model("block", false),
                     index_model("from", false),
                     index_model("to", false),
+                    index_model("status", false),
+                    index_model("contract_created", false),
                 ]
             }
 
             fn index_setup_key(&self) -> &'static str {
-                "setup.transactions"
+                "setup.transactions.receipt_fields"
             }
         }
     }
 
     pub mod erc1155_transfer {
+        use mongodb::bson::Decimal128;
         use mongodb::Collection;
-        use serde::{Deserialize, Serialize};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
         use sha2::digest::Update;
         use sha2::{Digest, Sha256};
 
         use crate::mongo::collections::transaction_pool::Pool;
-        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::collections::{
+            hex_decimal, hex_u64, log_index, Address, Block, NumericFields,
+        };
         use crate::mongo::{index_model, IndexModel, Indexable};
 
+        /// Serialized through [`ERC1155Transfer::serialize`] and [`ERC1155Transfer::deserialize`],
+        /// which restore the `log_index` the compact storage profile omits.
         #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        #[serde(remote = "Self")]
         pub struct ERC1155Transfer {
             pub token: Address,
+            #[serde(default)]
             pub operator: Address,
             pub from: Address,
             pub to: Address,
@@ -302,10 +821,47 @@ pub mod collections {
             pub value: String,
             pub block: Block,
             pub transaction_id: String,
+            #[serde(default)]
             pub log_index: String,
             pub log_id: String,
+            /// Transfer of a token flagged in `spam_tokens`.
+            #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+            pub spam: bool,
+            #[serde(default)]
+            pub log_index_num: Option<u64>,
+            #[serde(default)]
+            pub token_id_num: Option<Decimal128>,
+            #[serde(default)]
+            pub value_num: Option<Decimal128>,
         }
 
+        impl Serialize for ERC1155Transfer {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                ERC1155Transfer::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for ERC1155Transfer {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let mut transfer = ERC1155Transfer::deserialize(deserializer)?;
+                if transfer.log_index.is_empty() {
+                    transfer.log_index = log_index(transfer.log_index_num);
+                }
+                Ok(transfer)
+            }
+        }
+
+        impl NumericFields for ERC1155Transfer {
+            const FIELDS: &'static [&'static str] = &["log_index_num", "token_id_num", "value_num"];
+
+            fn fill_numeric(&mut self) {
+                self.log_index_num = hex_u64(&self.log_index);
+                self.token_id_num = hex_decimal(&self.token_id);
+                self.value_num = hex_decimal(&self.value);
+            }
+        }
+
+        #[derive(Clone)]
         pub struct Erc1155TransferProvider {
             pub collection: Collection<ERC1155Transfer>,
         }
@@ -343,7 +899,7 @@ pub mod collections {
             pub fn get_transfer_id(hash: &str, index: &str) -> String {
                 let mut hasher = Sha256::new();
                 Update::update(&mut hasher, hash.as_bytes());
-                Update::update(&mut hasher, &[b'-']);
+                Update::update(&mut hasher, b"-");
                 Update::update(&mut hasher, index.as_bytes());
                 format!("{:x}", hasher.finalize())
             }
@@ -351,30 +907,78 @@ pub mod collections {
     }
 
     pub mod erc_transfer {
-        use mongodb::bson::doc;
+        use mongodb::bson::{doc, Decimal128};
         use mongodb::Collection;
-        use serde::{Deserialize, Serialize};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
         use sha2::digest::Update;
         use sha2::{Digest, Sha256};
 
         use crate::mongo::collections::transaction_pool::Pool;
-        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::collections::{
+            hex_decimal, hex_u64, log_index, Address, Block, NumericFields,
+        };
         use crate::mongo::{index_model, IndexModel, Indexable};
         use crate::ronin::ContractType;
 
+        /// Serialized through [`ERCTransfer::serialize`] and [`ERCTransfer::deserialize`], which
+        /// restore the `log_index` the compact storage profile omits.
         #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        #[serde(remote = "Self")]
         pub struct ERCTransfer {
             pub from: Address,
             pub to: Address,
             pub token: String,
             pub value_or_token_id: String,
+            /// ERC20 amount in whole tokens, `value_or_token_id / 10^decimals`. Amounts beyond the
+            /// 34 significant digits of Decimal128 are truncated.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub value_decimal: Option<Decimal128>,
+            /// ERC721 token id, `None` for ids beyond the 34 digits of Decimal128.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub token_id: Option<Decimal128>,
             pub block: Block,
             pub transaction_id: String,
             pub erc: ContractType,
+            #[serde(default)]
             pub log_index: String,
             pub log_id: String,
+            /// Transfer of a token flagged in `spam_tokens`.
+            #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+            pub spam: bool,
+            #[serde(default)]
+            pub log_index_num: Option<u64>,
         }
 
+        impl Serialize for ERCTransfer {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                ERCTransfer::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for ERCTransfer {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let mut transfer = ERCTransfer::deserialize(deserializer)?;
+                if transfer.log_index.is_empty() {
+                    transfer.log_index = log_index(transfer.log_index_num);
+                }
+                Ok(transfer)
+            }
+        }
+
+        impl NumericFields for ERCTransfer {
+            /// `value_decimal` needs the decimals of the token and is set by the caller.
+            const FIELDS: &'static [&'static str] = &["log_index_num", "token_id", "value_decimal"];
+
+            fn fill_numeric(&mut self) {
+                self.log_index_num = hex_u64(&self.log_index);
+                self.token_id = match self.erc {
+                    ContractType::ERC721 => hex_decimal(&self.value_or_token_id),
+                    _ => None,
+                };
+            }
+        }
+
+        #[derive(Clone)]
         pub struct ErcTransferProvider {
             pub collection: Collection<ERCTransfer>,
         }
@@ -388,6 +992,7 @@ pub mod collections {
                     index_model("token", false),
                     index_model("block", false),
                     index_model("value_or_token_id", false),
+                    index_model("token_id", false),
                     index_model("block", false),
                     index_model("transaction_id", false),
                     index_model("erc", false),
@@ -413,63 +1018,3266 @@ pub mod collections {
             pub fn get_transfer_id(hash: &str, index: &str) -> String {
                 let mut hasher = Sha256::new();
                 Update::update(&mut hasher, hash.as_bytes());
-                Update::update(&mut hasher, &[b'-']);
+                Update::update(&mut hasher, b"-");
                 Update::update(&mut hasher, index.as_bytes());
                 format!("{:x}", hasher.finalize())
             }
         }
     }
-    pub mod transaction_pool {
-        use log::error;
-        use mongodb::bson::Document;
-        use mongodb::error::Error;
-        use mongodb::options::{InsertManyOptions, UpdateOptions};
+    pub mod token {
+        use mongodb::bson::doc;
         use mongodb::Collection;
-        use serde::Serialize;
+        use serde::{Deserialize, Serialize};
 
-        pub struct Pool<T> {
-            collection: Collection<T>,
-            updates: Vec<[Document; 2]>,
-            inserts: Vec<T>,
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        /// Token contract discovered at deployment or, with contract discovery, from its transfer
+        /// events (without a `deployer`). Tokens deployed by an allowlisted deployer are `official`.
+        /// All of them get decoded like the built in contract list.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct DiscoveredToken {
+            pub address: Address,
+            pub name: String,
+            pub symbol: String,
+            pub decimals: usize,
+            pub erc: ContractType,
+            pub deployer: Address,
+            pub official: bool,
+            pub block: Block,
         }
 
-        impl<T> Pool<T>
-        where
-            T: Serialize + Clone + Eq + PartialEq,
-        {
-            pub fn new(collection: Collection<T>) -> Self {
-                Pool {
-                    collection,
-                    updates: vec![],
-                    inserts: vec![],
-                }
-            }
+        #[derive(Clone)]
+        pub struct TokenProvider {
+            pub collection: Collection<DiscoveredToken>,
+        }
 
-            fn has_update(&self, doc: &Document) -> Option<usize> {
-                self.updates.iter().position(|d| d[0].eq(doc))
+        impl TokenProvider {
+            pub fn new(collection: Collection<DiscoveredToken>) -> TokenProvider {
+                TokenProvider { collection }
             }
 
-            fn has_insert(&self, doc: &T) -> Option<usize> {
-                self.inserts.iter().position(|d| d.eq(doc))
+            pub(crate) fn get_pool(&self) -> Pool<DiscoveredToken> {
+                Pool::new(self.collection.to_owned())
             }
 
-            pub fn insert(&mut self, insert: T) {
-                let existing = self.has_insert(&insert);
+            /// Tokens whose transfers are indexed: the deployments of official deployers and, with
+            /// contract discovery, the untracked emitters of transfer events.
+            pub async fn tracked(&self) -> Vec<DiscoveredToken> {
+                let mut cursor = self
+                    .collection
+                    .find(None, None)
+                    .await
+                    .expect("Failed to query tokens");
 
-                match existing {
-                    None => {
-                        self.inserts.push(insert);
-                    }
-                    Some(index) => {
-                        self.inserts.remove(index);
-                        self.inserts.push(insert);
-                    }
+                let mut tokens = vec![];
+                while cursor.advance().await.expect("Failed to read tokens") {
+                    tokens.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize token"),
+                    );
                 }
+                tokens
             }
+        }
+
+        impl Indexable for TokenProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("address", true),
+                    index_model("deployer", false),
+                    index_model("official", false),
+                    index_model("erc", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.tokens.erc"
+            }
+        }
+    }
+
+    pub mod whale_transfer {
+        use mongodb::Collection;
+
+        use crate::mongo::collections::erc_transfer::ERCTransfer;
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// ERC20 transfers above the configured per token thresholds, stored as copies of the
+        /// `erc_transfers` documents.
+        #[derive(Clone)]
+        pub struct WhaleTransferProvider {
+            pub collection: Collection<ERCTransfer>,
+        }
+
+        impl WhaleTransferProvider {
+            pub fn new(collection: Collection<ERCTransfer>) -> WhaleTransferProvider {
+                WhaleTransferProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<ERCTransfer> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for WhaleTransferProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["token", "block"], false),
+                    index_model("block", false),
+                    index_model("from", false),
+                    index_model("to", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.whale_transfers"
+            }
+        }
+    }
+
+    pub mod erc1155_supply {
+        use std::collections::HashMap;
+
+        use mongodb::bson::{doc, Document};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Supply of a single ERC1155 token id, maintained from its mints and burns.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct Erc1155Supply {
+            pub token: Address,
+            pub token_id: String,
+            pub minted: i64,
+            pub burned: i64,
+            pub circulating: i64,
+            pub last_block: Block,
+        }
+
+        #[derive(Clone)]
+        pub struct Erc1155SupplyProvider {
+            pub collection: Collection<Erc1155Supply>,
+        }
+
+        impl Erc1155SupplyProvider {
+            pub fn new(collection: Collection<Erc1155Supply>) -> Erc1155SupplyProvider {
+                Erc1155SupplyProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<Erc1155Supply> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            pub fn update(
+                &self,
+                token: &Address,
+                token_id: &str,
+                minted: i64,
+                burned: i64,
+                block: Block,
+            ) -> [Document; 2] {
+                [
+                    doc! {"token": token, "token_id": token_id},
+                    doc! {
+                        "$inc": {
+                            "minted": minted,
+                            "burned": burned,
+                            "circulating": minted - burned
+                        },
+                        "$max": {
+                            "last_block": block as i64
+                        }
+                    },
+                ]
+            }
+
+            /// Last block applied to the supply of each of `token_ids` of `tokens` that has one, by
+            /// token and token id.
+            pub async fn last_blocks(
+                &self,
+                tokens: &[&Address],
+                token_ids: &[&String],
+            ) -> HashMap<(Address, String), Block> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"token": {"$in": tokens}, "token_id": {"$in": token_ids}},
+                        None,
+                    )
+                    .await
+                    .expect("Failed to query erc 1155 supply!");
+
+                let mut last_blocks = HashMap::new();
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read erc 1155 supply!")
+                {
+                    let supply: Erc1155Supply = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize erc 1155 supply!");
+                    last_blocks.insert((supply.token, supply.token_id), supply.last_block);
+                }
+                last_blocks
+            }
+        }
+
+        impl Indexable for Erc1155SupplyProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["token", "token_id"], true),
+                    index_model("circulating", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.erc1155_supply"
+            }
+        }
+    }
+
+    pub mod ron_balance {
+        use std::collections::HashMap;
+
+        use mongodb::bson::{doc, Document};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        const WEI_PER_GWEI: i128 = 1_000_000_000;
+
+        /// Native RON balance of a wallet as seen from transaction values and gas fees. The
+        /// balance in wei is `gwei * 10^9 + wei`; it is split so it can be maintained with `$inc`
+        /// on 64 bit integers. Internal transfers are not traced and therefore not included.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct RonBalance {
+            pub address: Address,
+            pub gwei: i64,
+            pub wei: i64,
+            pub last_block: Block,
+        }
+
+        /// Balance change of a wallet caused by one transaction. `received` and `spent` (value plus
+        /// gas fee) are hex encoded wei amounts.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct RonBalanceChange {
+            pub address: Address,
+            pub block: Block,
+            pub transaction_id: TransactionHash,
+            pub received: String,
+            pub spent: String,
+        }
+
+        #[derive(Clone)]
+        pub struct RonBalanceProvider {
+            pub collection: Collection<RonBalance>,
+            pub changes: Collection<RonBalanceChange>,
+        }
+
+        impl RonBalanceProvider {
+            pub fn new(
+                collection: Collection<RonBalance>,
+                changes: Collection<RonBalanceChange>,
+            ) -> RonBalanceProvider {
+                RonBalanceProvider {
+                    collection,
+                    changes,
+                }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<RonBalance> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            pub(crate) fn get_change_pool(&self) -> Pool<RonBalanceChange> {
+                Pool::new(self.changes.to_owned())
+            }
+
+            pub fn update(&self, address: &Address, delta: i128, block: Block) -> [Document; 2] {
+                [
+                    doc! {"address": address},
+                    doc! {
+                        "$inc": {
+                            "gwei": delta.div_euclid(WEI_PER_GWEI) as i64,
+                            "wei": delta.rem_euclid(WEI_PER_GWEI) as i64
+                        },
+                        "$max": {
+                            "last_block": block as i64
+                        }
+                    },
+                ]
+            }
+
+            /// Last block applied to the balance of each of `addresses` that has one.
+            pub async fn last_blocks(&self, addresses: &[&Address]) -> HashMap<Address, Block> {
+                let mut cursor = self
+                    .collection
+                    .find(doc! {"address": {"$in": addresses}}, None)
+                    .await
+                    .expect("Failed to query RON balances!");
+
+                let mut last_blocks = HashMap::new();
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read RON balances!")
+                {
+                    let balance: RonBalance = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize RON balance!");
+                    last_blocks.insert(balance.address, balance.last_block);
+                }
+                last_blocks
+            }
+        }
+
+        impl Indexable for RonBalanceProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("address", true), index_model("gwei", false)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.ron_balances"
+            }
+        }
+
+        /// Index setup of the `ron_balance_changes` collection backing `RonBalanceProvider::changes`.
+        pub struct RonBalanceChangeIndexes;
+
+        impl Indexable for RonBalanceChangeIndexes {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["transaction_id", "address"], true),
+                    compound_index_model(&["address", "block"], false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.ron_balance_changes"
+            }
+        }
+    }
+
+    pub mod balance {
+        use std::collections::HashMap;
+
+        use mongodb::bson::{doc, Document};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        const UNITS_PER_GWEI: i128 = 1_000_000_000;
+
+        /// Current ERC20 balance of a wallet, maintained from the indexed transfers. Like
+        /// `RonBalance` the raw amount is `gwei * 10^9 + wei`, split so it can be maintained with
+        /// `$inc` on 64 bit integers.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct Balance {
+            pub wallet: Address,
+            pub token: Address,
+            pub gwei: i64,
+            pub wei: i64,
+            pub last_block: Block,
+        }
+
+        impl Balance {
+            pub fn amount(&self) -> i128 {
+                self.gwei as i128 * UNITS_PER_GWEI + self.wei as i128
+            }
+        }
+
+        #[derive(Clone)]
+        pub struct BalanceProvider {
+            pub collection: Collection<Balance>,
+        }
+
+        impl BalanceProvider {
+            pub fn new(collection: Collection<Balance>) -> BalanceProvider {
+                BalanceProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<Balance> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            pub fn update(
+                &self,
+                wallet: &Address,
+                token: &Address,
+                delta: i128,
+                block: Block,
+            ) -> [Document; 2] {
+                [
+                    doc! {"wallet": wallet, "token": token},
+                    doc! {
+                        "$inc": {
+                            "gwei": delta.div_euclid(UNITS_PER_GWEI) as i64,
+                            "wei": delta.rem_euclid(UNITS_PER_GWEI) as i64
+                        },
+                        "$max": {
+                            "last_block": block as i64
+                        }
+                    },
+                ]
+            }
+
+            /// Overwrites a balance with the amount read on chain.
+            pub fn set(
+                &self,
+                wallet: &Address,
+                token: &Address,
+                amount: i128,
+                block: Block,
+            ) -> [Document; 2] {
+                [
+                    doc! {"wallet": wallet, "token": token},
+                    doc! {
+                        "$set": {
+                            "gwei": amount.div_euclid(UNITS_PER_GWEI) as i64,
+                            "wei": amount.rem_euclid(UNITS_PER_GWEI) as i64
+                        },
+                        "$max": {
+                            "last_block": block as i64
+                        }
+                    },
+                ]
+            }
+
+            /// Last block applied to each balance of `wallets` in `tokens` that has one, by wallet
+            /// and token.
+            pub async fn last_blocks(
+                &self,
+                wallets: &[&Address],
+                tokens: &[&Address],
+            ) -> HashMap<(Address, Address), Block> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"wallet": {"$in": wallets}, "token": {"$in": tokens}},
+                        None,
+                    )
+                    .await
+                    .expect("Failed to query balances!");
+
+                let mut last_blocks = HashMap::new();
+                while cursor.advance().await.expect("Failed to read balances!") {
+                    let balance: Balance = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize balance!");
+                    last_blocks.insert((balance.wallet, balance.token), balance.last_block);
+                }
+                last_blocks
+            }
+
+            /// Balances changed after `block`.
+            pub async fn changed_since(&self, block: Block) -> Vec<Balance> {
+                let mut cursor = self
+                    .collection
+                    .find(doc! {"last_block": {"$gt": block as i64}}, None)
+                    .await
+                    .expect("Failed to query balances!");
+
+                let mut balances = vec![];
+                while cursor.advance().await.expect("Failed to read balances!") {
+                    balances.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize balance!"),
+                    );
+                }
+                balances
+            }
+        }
+
+        impl Indexable for BalanceProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["wallet", "token"], true),
+                    compound_index_model(&["token", "gwei"], false),
+                    index_model("last_block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.balances"
+            }
+        }
+    }
+
+    pub mod nft_owner {
+        use std::collections::HashMap;
+
+        use mongodb::bson::{doc, Document};
+        use mongodb::options::UpdateOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        /// Current owner of an NFT. ERC721 tokens have a single document per (token, token_id)
+        /// whose `owner` follows the transfers, ERC1155 tokens one document per holder with the
+        /// held `amount`; holders that sent all their items keep a document with amount 0.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct NftOwner {
+            pub token: Address,
+            pub token_id: String,
+            pub owner: Address,
+            pub erc: ContractType,
+            pub amount: i64,
+            pub last_block: Block,
+        }
+
+        #[derive(Clone)]
+        pub struct NftOwnerProvider {
+            pub collection: Collection<NftOwner>,
+        }
+
+        impl NftOwnerProvider {
+            pub fn new(collection: Collection<NftOwner>) -> NftOwnerProvider {
+                NftOwnerProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<NftOwner> {
+                Pool::new(self.collection.to_owned())
+            }
+
+            /// Moves an ERC721 token to `owner` unless a transfer of a later block was already
+            /// applied, so re-indexing an older range does not roll the owner back.
+            pub async fn transfer(
+                &self,
+                token: &Address,
+                token_id: &str,
+                owner: &Address,
+                block: Block,
+            ) -> mongodb::error::Result<()> {
+                let newer = doc! {"$gt": [{"$ifNull": ["$last_block", -1i64]}, block as i64]};
+
+                self.collection
+                    .update_one(
+                        doc! {"token": token, "token_id": token_id},
+                        vec![doc! {
+                            "$set": {
+                                "owner": {"$cond": [&newer, "$owner", owner]},
+                                "erc": ContractType::ERC721.as_str(),
+                                "amount": 1i64,
+                                "last_block": {"$cond": [&newer, "$last_block", block as i64]},
+                            }
+                        }],
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            /// Changes the amount of an ERC1155 token id held by `owner`.
+            pub fn update(
+                &self,
+                token: &Address,
+                token_id: &str,
+                owner: &Address,
+                delta: i64,
+                block: Block,
+            ) -> [Document; 2] {
+                [
+                    doc! {"token": token, "token_id": token_id, "owner": owner},
+                    doc! {
+                        "$inc": {
+                            "amount": delta
+                        },
+                        "$set": {
+                            "erc": ContractType::ERC1155.as_str()
+                        },
+                        "$max": {
+                            "last_block": block as i64
+                        }
+                    },
+                ]
+            }
+
+            /// Last block applied to each holding of `owners` in `token_ids` of `tokens` that has
+            /// one, by token, token id and owner.
+            pub async fn last_blocks(
+                &self,
+                tokens: &[&Address],
+                token_ids: &[&String],
+                owners: &[&Address],
+            ) -> HashMap<(Address, String, Address), Block> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {
+                            "token": {"$in": tokens},
+                            "token_id": {"$in": token_ids},
+                            "owner": {"$in": owners}
+                        },
+                        None,
+                    )
+                    .await
+                    .expect("Failed to query nft owners!");
+
+                let mut last_blocks = HashMap::new();
+                while cursor.advance().await.expect("Failed to read nft owners!") {
+                    let owner: NftOwner = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize nft owner!");
+                    last_blocks
+                        .insert((owner.token, owner.token_id, owner.owner), owner.last_block);
+                }
+                last_blocks
+            }
+        }
+
+        impl Indexable for NftOwnerProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["token", "token_id", "owner"], true),
+                    compound_index_model(&["owner", "token"], false),
+                    index_model("last_block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.nft_owners"
+            }
+        }
+    }
+
+    pub mod price_history {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::error::ErrorKind;
+        use mongodb::options::UpdateOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::axie_sale::Sale;
+        use crate::mongo::collections::transaction_pool::DUPLICATE_KEY_ERROR;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct PricePoint {
+            pub price: String,
+            pub payment_token: Option<Address>,
+            pub quantity: Option<String>,
+            pub seller: Address,
+            pub buyer: Address,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            /// Log index of the sale, points recorded before it was stored have none.
+            #[serde(default)]
+            pub log_index: Option<String>,
+        }
+
+        /// All sales of one NFT ordered by block, maintained from the `erc721_sales` collection.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct PriceHistory {
+            pub token: Address,
+            pub token_id: String,
+            pub sales: Vec<PricePoint>,
+            pub sale_count: u64,
+            pub first_block: Block,
+            pub last_block: Block,
+        }
+
+        #[derive(Clone)]
+        pub struct PriceHistoryProvider {
+            pub collection: Collection<PriceHistory>,
+        }
+
+        impl PriceHistoryProvider {
+            pub fn new(collection: Collection<PriceHistory>) -> PriceHistoryProvider {
+                PriceHistoryProvider { collection }
+            }
+
+            pub async fn get(&self, token: &Address, token_id: &str) -> Option<PriceHistory> {
+                self.collection
+                    .find_one(doc! {"token": token, "token_id": token_id}, None)
+                    .await
+                    .expect("Failed to query price history!")
+            }
+
+            /// Adds a sale to the history of its token, unless the history already holds it. Sales
+            /// are told apart by transaction and log index, a point without a log index stands for
+            /// any sale of its transaction.
+            pub async fn record(&self, sale: &Sale) -> mongodb::error::Result<()> {
+                let point = PricePoint {
+                    price: sale.price.to_owned(),
+                    payment_token: sale.payment_token.to_owned(),
+                    quantity: sale.quantity.to_owned(),
+                    seller: sale.seller.to_owned(),
+                    buyer: sale.buyer.to_owned(),
+                    block: sale.block,
+                    timestamp: sale.created_at,
+                    transaction_id: sale.transaction_id.to_owned(),
+                    log_index: sale.log_index.to_owned(),
+                };
+
+                let result = self
+                    .collection
+                    .update_one(
+                        doc! {
+                            "token": &sale.token,
+                            "token_id": &sale.token_id,
+                            "sales": {
+                                "$not": {
+                                    "$elemMatch": {
+                                        "transaction_id": &sale.transaction_id,
+                                        "log_index": {"$in": [&sale.log_index, null]}
+                                    }
+                                }
+                            }
+                        },
+                        doc! {
+                            "$push": {
+                                "sales": {
+                                    "$each": [mongodb::bson::to_bson(&point)?],
+                                    "$sort": {"block": 1i64}
+                                }
+                            },
+                            "$inc": {
+                                "sale_count": 1i64
+                            },
+                            "$min": {
+                                "first_block": sale.block as i64
+                            },
+                            "$max": {
+                                "last_block": sale.block as i64
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await;
+
+                // The upsert of a history that already holds the sale hits the unique index.
+                match result {
+                    Err(error) => match error.kind.as_ref() {
+                        ErrorKind::Write(mongodb::error::WriteFailure::WriteError(write))
+                            if write.code == DUPLICATE_KEY_ERROR =>
+                        {
+                            Ok(())
+                        }
+                        _ => Err(error),
+                    },
+                    Ok(_) => Ok(()),
+                }
+            }
+        }
+
+        impl Indexable for PriceHistoryProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["token", "token_id"], true),
+                    index_model("last_block", false),
+                    index_model("sale_count", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.price_history"
+            }
+        }
+    }
+
+    pub mod token_metadata {
+        use mongodb::bson::{doc, Bson, DateTime};
+        use mongodb::options::ReplaceOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Address;
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        /// Off chain metadata of an NFT as served from its `tokenURI`/`uri`. `error` is set when
+        /// the URI could not be read or resolved.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct TokenMetadata {
+            pub token: Address,
+            pub token_id: String,
+            pub erc: ContractType,
+            pub uri: Option<String>,
+            pub name: Option<String>,
+            pub description: Option<String>,
+            pub image: Option<String>,
+            pub attributes: Option<Bson>,
+            pub error: Option<String>,
+            pub fetched_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct TokenMetadataProvider {
+            pub collection: Collection<TokenMetadata>,
+        }
+
+        impl TokenMetadataProvider {
+            pub fn new(collection: Collection<TokenMetadata>) -> TokenMetadataProvider {
+                TokenMetadataProvider { collection }
+            }
+
+            pub async fn get(&self, token: &Address, token_id: &str) -> Option<TokenMetadata> {
+                self.collection
+                    .find_one(doc! {"token": token, "token_id": token_id}, None)
+                    .await
+                    .expect("Failed to query token metadata!")
+            }
+
+            pub async fn store(&self, metadata: &TokenMetadata) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"token": &metadata.token, "token_id": &metadata.token_id},
+                        metadata,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for TokenMetadataProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["token", "token_id"], true),
+                    index_model("name", false),
+                    index_model("error", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.token_metadata"
+            }
+        }
+    }
+
+    pub mod query_cache {
+        use mongodb::bson::{doc, Bson, DateTime};
+        use mongodb::options::ReplaceOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Result of an analysis query, `block` is the indexed head it was computed at.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct CachedQuery {
+            pub key: String,
+            pub block: Block,
+            pub value: Bson,
+            pub created_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct QueryCacheProvider {
+            pub collection: Collection<CachedQuery>,
+        }
+
+        impl QueryCacheProvider {
+            pub fn new(collection: Collection<CachedQuery>) -> QueryCacheProvider {
+                QueryCacheProvider { collection }
+            }
+
+            pub async fn get(&self, key: &str) -> Option<CachedQuery> {
+                self.collection
+                    .find_one(doc! {"key": key}, None)
+                    .await
+                    .expect("Failed to query cache!")
+            }
+
+            pub async fn store(&self, query: &CachedQuery) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"key": &query.key},
+                        query,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for QueryCacheProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("key", true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.query_cache"
+            }
+        }
+    }
+
+    pub mod pair_reserve {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Reserves of a UniswapV2 style (Katana) pair after a `Sync` event.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct PairReserve {
+            pub pair: Address,
+            pub reserve0: String,
+            pub reserve1: String,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct PairReserveProvider {
+            pub collection: Collection<PairReserve>,
+        }
+
+        impl PairReserveProvider {
+            pub fn new(collection: Collection<PairReserve>) -> PairReserveProvider {
+                PairReserveProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<PairReserve> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for PairReserveProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["pair", "block"], false),
+                    index_model("timestamp", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.pair_reserves"
+            }
+        }
+    }
+
+    pub mod governance_event {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum GovernanceAction {
+            Submitted,
+            Confirmed,
+            Revoked,
+            Executed,
+            ExecutionFailed,
+        }
+
+        /// Lifecycle event of a multisig/governance proposal. `proposal_id` is the transaction id
+        /// assigned by the multisig contract and only unique per `contract`.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct GovernanceEvent {
+            pub contract: Address,
+            pub proposal_id: String,
+            pub action: GovernanceAction,
+            pub sender: Option<Address>,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct GovernanceEventProvider {
+            pub collection: Collection<GovernanceEvent>,
+        }
+
+        impl GovernanceEventProvider {
+            pub fn new(collection: Collection<GovernanceEvent>) -> GovernanceEventProvider {
+                GovernanceEventProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<GovernanceEvent> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for GovernanceEventProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["contract", "proposal_id"], false),
+                    index_model("action", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.governance_events"
+            }
+        }
+    }
+
+    pub mod staking_event {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum StakingAction {
+            Staked,
+            Unstaked,
+            RewardClaimed,
+            Delegated,
+            Undelegated,
+        }
+
+        /// Event of the RON/AXS staking contracts. `validator` is the consensus address of
+        /// delegation events, `amount` the hex encoded raw amount.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct StakingEvent {
+            pub contract: Address,
+            pub action: StakingAction,
+            pub wallet: Address,
+            pub validator: Option<Address>,
+            pub amount: String,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct StakingEventProvider {
+            pub collection: Collection<StakingEvent>,
+        }
+
+        impl StakingEventProvider {
+            pub fn new(collection: Collection<StakingEvent>) -> StakingEventProvider {
+                StakingEventProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<StakingEvent> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for StakingEventProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["wallet", "block"], false),
+                    compound_index_model(&["contract", "action"], false),
+                    index_model("validator", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.staking_events"
+            }
+        }
+    }
+
+    pub mod equipment_event {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum EquipmentAction {
+            Equipped,
+            Unequipped,
+        }
+
+        /// Charm or rune (un)equipped to an Axie by the equipment contract. `owner` is the wallet
+        /// the item was moved from on equip and back to on unequip, if the ERC1155 movement was
+        /// part of the same transaction.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct EquipmentEvent {
+            pub contract: Address,
+            pub action: EquipmentAction,
+            pub axie_id: String,
+            pub token: Address,
+            pub token_id: String,
+            pub owner: Option<Address>,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct EquipmentEventProvider {
+            pub collection: Collection<EquipmentEvent>,
+        }
+
+        impl EquipmentEventProvider {
+            pub fn new(collection: Collection<EquipmentEvent>) -> EquipmentEventProvider {
+                EquipmentEventProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<EquipmentEvent> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for EquipmentEventProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["axie_id", "block"], false),
+                    compound_index_model(&["token", "token_id"], false),
+                    index_model("owner", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.equipment_events"
+            }
+        }
+    }
+
+    pub mod axie_equipment {
+        use mongodb::bson::doc;
+        use mongodb::error::ErrorKind;
+        use mongodb::options::UpdateOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::equipment_event::{EquipmentAction, EquipmentEvent};
+        use crate::mongo::collections::transaction_pool::DUPLICATE_KEY_ERROR;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Current state of one charm or rune slot of an Axie, maintained from the
+        /// `equipment_events` collection. Unequipped items are kept with `equipped: false` so
+        /// older events can't overwrite a newer state.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct AxieEquipment {
+            pub axie_id: String,
+            pub token: Address,
+            pub token_id: String,
+            pub equipped: bool,
+            pub owner: Option<Address>,
+            pub block: Block,
+            pub log_index: u64,
+            pub transaction_id: TransactionHash,
+        }
+
+        #[derive(Clone)]
+        pub struct AxieEquipmentProvider {
+            pub collection: Collection<AxieEquipment>,
+        }
+
+        impl AxieEquipmentProvider {
+            pub fn new(collection: Collection<AxieEquipment>) -> AxieEquipmentProvider {
+                AxieEquipmentProvider { collection }
+            }
+
+            /// Items currently equipped to an Axie.
+            pub async fn equipped(&self, axie_id: &str) -> Vec<AxieEquipment> {
+                let mut cursor = self
+                    .collection
+                    .find(doc! {"axie_id": axie_id, "equipped": true}, None)
+                    .await
+                    .expect("Failed to query axie equipment!");
+
+                let mut equipment = vec![];
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read axie equipment!")
+                {
+                    equipment.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize axie equipment!"),
+                    );
+                }
+
+                equipment
+            }
+
+            /// Applies an equipment event unless the slot already holds a newer state.
+            pub async fn apply(&self, event: &EquipmentEvent) -> mongodb::error::Result<()> {
+                let block = event.block as i64;
+                let log_index =
+                    u64::from_str_radix(&event.log_index, 16).unwrap_or_default() as i64;
+
+                let result = self
+                    .collection
+                    .update_one(
+                        doc! {
+                            "axie_id": &event.axie_id,
+                            "token": &event.token,
+                            "token_id": &event.token_id,
+                            "$or": [
+                                {"block": {"$lt": block}},
+                                {"block": block, "log_index": {"$lt": log_index}}
+                            ]
+                        },
+                        doc! {
+                            "$set": {
+                                "equipped": event.action == EquipmentAction::Equipped,
+                                "owner": &event.owner,
+                                "block": block,
+                                "log_index": log_index,
+                                "transaction_id": &event.transaction_id
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await;
+
+                match result {
+                    Err(error) => match error.kind.as_ref() {
+                        ErrorKind::Write(mongodb::error::WriteFailure::WriteError(write))
+                            if write.code == DUPLICATE_KEY_ERROR =>
+                        {
+                            Ok(())
+                        }
+                        _ => Err(error),
+                    },
+                    Ok(_) => Ok(()),
+                }
+            }
+        }
+
+        impl Indexable for AxieEquipmentProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["axie_id", "token", "token_id"], true),
+                    compound_index_model(&["token", "token_id", "equipped"], false),
+                    index_model("owner", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.axie_equipment"
+            }
+        }
+    }
+
+    pub mod order {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum OrderStatus {
+            Created,
+            Cancelled,
+            Matched,
+        }
+
+        /// Lifecycle event of a marketplace v2 order, all events of a listing share its `hash`.
+        /// `token` and `token_id` are only known for created orders, `matcher` only for matched
+        /// ones. `price` is the listing price of created and the settle price of matched orders.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct OrderEvent {
+            pub hash: String,
+            pub status: OrderStatus,
+            pub maker: Option<Address>,
+            pub matcher: Option<Address>,
+            pub kind: Option<String>,
+            pub token: Option<Address>,
+            pub token_id: Option<String>,
+            pub payment_token: Option<Address>,
+            pub price: Option<String>,
+            pub expired_at: Option<String>,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct OrderProvider {
+            pub collection: Collection<OrderEvent>,
+        }
+
+        impl OrderProvider {
+            pub fn new(collection: Collection<OrderEvent>) -> OrderProvider {
+                OrderProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<OrderEvent> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for OrderProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    compound_index_model(&["hash", "block"], false),
+                    compound_index_model(&["maker", "block"], false),
+                    compound_index_model(&["token", "token_id"], false),
+                    index_model("status", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.orders"
+            }
+        }
+    }
+
+    pub mod native_transfer {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// RON sent as the value of a successful transaction, `value` is hex encoded in wei.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct NativeTransfer {
+            pub from: Address,
+            pub to: Address,
+            pub value: String,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+        }
+
+        #[derive(Clone)]
+        pub struct NativeTransferProvider {
+            pub collection: Collection<NativeTransfer>,
+        }
+
+        impl NativeTransferProvider {
+            pub fn new(collection: Collection<NativeTransfer>) -> NativeTransferProvider {
+                NativeTransferProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<NativeTransfer> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for NativeTransferProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("transaction_id", true),
+                    compound_index_model(&["from", "block"], false),
+                    compound_index_model(&["to", "block"], false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.native_transfers"
+            }
+        }
+    }
+
+    pub mod auction {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Listing of the legacy clock auction marketplace. The price of each token decays
+        /// linearly from `starting_prices` to `ending_prices` over `durations` seconds.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct Auction {
+            pub seller: Address,
+            pub listing_index: String,
+            pub starting_prices: Vec<String>,
+            pub ending_prices: Vec<String>,
+            pub exchange_tokens: Vec<Address>,
+            pub durations: Vec<String>,
+            pub starting_timestamp: String,
+            pub block: Block,
+            pub created_at: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_id: String,
+        }
+
+        #[derive(Clone)]
+        pub struct AuctionProvider {
+            pub collection: Collection<Auction>,
+        }
+
+        impl AuctionProvider {
+            pub fn new(collection: Collection<Auction>) -> AuctionProvider {
+                AuctionProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<Auction> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for AuctionProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    index_model("listing_index", false),
+                    index_model("seller", false),
+                    index_model("block", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.auctions"
+            }
+        }
+    }
+
+    pub mod wallet_transfer {
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+        use crate::mongo::collections::erc_transfer::ERCTransfer;
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum TransferDirection {
+            In,
+            Out,
+        }
+
+        /// One side of an ERC or ERC1155 transfer, denormalized per wallet so the transfers of a
+        /// wallet can be read with a single index scan over (wallet, block).
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct WalletTransfer {
+            pub wallet: Address,
+            pub counterparty: Address,
+            pub direction: TransferDirection,
+            pub token: Address,
+            pub erc: ContractType,
+            pub token_id: Option<String>,
+            pub value: String,
+            pub block: Block,
+            #[serde(default)]
+            pub transaction_id: String,
+            pub log_id: String,
+            #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+            pub spam: bool,
+        }
+
+        impl WalletTransfer {
+            fn pair(from: &Address, to: &Address, template: WalletTransfer) -> [WalletTransfer; 2] {
+                [
+                    WalletTransfer {
+                        wallet: from.to_owned(),
+                        counterparty: to.to_owned(),
+                        direction: TransferDirection::Out,
+                        ..template.clone()
+                    },
+                    WalletTransfer {
+                        wallet: to.to_owned(),
+                        counterparty: from.to_owned(),
+                        direction: TransferDirection::In,
+                        ..template
+                    },
+                ]
+            }
+
+            pub fn from_erc_transfer(transfer: &ERCTransfer) -> [WalletTransfer; 2] {
+                let (token_id, value) = match transfer.erc {
+                    ContractType::ERC721 => {
+                        (Some(transfer.value_or_token_id.to_owned()), "1".to_string())
+                    }
+                    _ => (None, transfer.value_or_token_id.to_owned()),
+                };
+
+                WalletTransfer::pair(
+                    &transfer.from,
+                    &transfer.to,
+                    WalletTransfer {
+                        wallet: String::new(),
+                        counterparty: String::new(),
+                        direction: TransferDirection::Out,
+                        token: transfer.token.to_owned(),
+                        erc: transfer.erc.to_owned(),
+                        token_id,
+                        value,
+                        block: transfer.block,
+                        transaction_id: transfer.transaction_id.to_owned(),
+                        log_id: transfer.log_id.to_owned(),
+                        spam: transfer.spam,
+                    },
+                )
+            }
+
+            pub fn from_erc1155_transfer(transfer: &ERC1155Transfer) -> [WalletTransfer; 2] {
+                WalletTransfer::pair(
+                    &transfer.from,
+                    &transfer.to,
+                    WalletTransfer {
+                        wallet: String::new(),
+                        counterparty: String::new(),
+                        direction: TransferDirection::Out,
+                        token: transfer.token.to_owned(),
+                        erc: ContractType::ERC1155,
+                        token_id: Some(transfer.token_id.to_owned()),
+                        value: transfer.value.to_owned(),
+                        block: transfer.block,
+                        transaction_id: transfer.transaction_id.to_owned(),
+                        log_id: transfer.log_id.to_owned(),
+                        spam: transfer.spam,
+                    },
+                )
+            }
+        }
+
+        #[derive(Clone)]
+        pub struct WalletTransferProvider {
+            pub collection: Collection<WalletTransfer>,
+        }
+
+        impl WalletTransferProvider {
+            pub fn new(collection: Collection<WalletTransfer>) -> WalletTransferProvider {
+                WalletTransferProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<WalletTransfer> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for WalletTransferProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["log_id", "direction"], true),
+                    compound_index_model(&["wallet", "block"], false),
+                    compound_index_model(&["wallet", "token", "block"], false),
+                    index_model("erc", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.wallet_transfers.erc"
+            }
+        }
+    }
+
+    pub mod missing_block {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::UpdateOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Block skipped by the stream because the provider did not return it.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct MissingBlock {
+            pub block: Block,
+            pub error: String,
+            pub attempts: u32,
+            pub created_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct MissingBlockProvider {
+            pub collection: Collection<MissingBlock>,
+        }
+
+        impl MissingBlockProvider {
+            pub fn new(collection: Collection<MissingBlock>) -> MissingBlockProvider {
+                MissingBlockProvider { collection }
+            }
+
+            pub async fn record(&self, missing: &MissingBlock) -> mongodb::error::Result<()> {
+                self.collection
+                    .update_one(
+                        doc! {"block": missing.block as i64},
+                        doc! {
+                            "$set": {
+                                "error": &missing.error,
+                                "attempts": missing.attempts,
+                                "created_at": missing.created_at,
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for MissingBlockProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("block", true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.missing_blocks"
+            }
+        }
+    }
+
+    pub mod failed_block {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::{FindOptions, UpdateOptions};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Request that kept failing, used to pick the indexer when retrying.
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum FailedStage {
+            /// Receipts of a block loaded by the stream.
+            Receipts,
+            /// eth_getLogs over a block range in logs mode.
+            Logs,
+        }
+
+        /// Blocks skipped because an RPC call still failed after `--rpc-retries` retries.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct FailedBlock {
+            pub block: Block,
+            /// Last block of the failed range, `block` itself outside of logs mode.
+            pub to_block: Block,
+            pub stage: FailedStage,
+            pub error: String,
+            pub attempts: u32,
+            pub created_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct FailedBlockProvider {
+            pub collection: Collection<FailedBlock>,
+        }
+
+        impl FailedBlockProvider {
+            pub fn new(collection: Collection<FailedBlock>) -> FailedBlockProvider {
+                FailedBlockProvider { collection }
+            }
+
+            pub async fn record(&self, failed: &FailedBlock) -> mongodb::error::Result<()> {
+                self.collection
+                    .update_one(
+                        doc! {"block": failed.block as i64},
+                        doc! {
+                            "$set": {
+                                "to_block": failed.to_block as i64,
+                                "stage": mongodb::bson::to_bson(&failed.stage)?,
+                                "error": &failed.error,
+                                "attempts": failed.attempts,
+                                "created_at": failed.created_at,
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            pub async fn all(&self) -> Vec<FailedBlock> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        None,
+                        FindOptions::builder().sort(doc! {"block": 1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query failed blocks!");
+
+                let mut failed = vec![];
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read failed blocks!")
+                {
+                    failed.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize failed block!"),
+                    );
+                }
+
+                failed
+            }
+
+            pub async fn remove(&self, block: Block) -> mongodb::error::Result<()> {
+                self.collection
+                    .delete_one(doc! {"block": block as i64}, None)
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for FailedBlockProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("block", true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.failed_blocks"
+            }
+        }
+    }
+
+    pub mod block_status {
+        use std::collections::BTreeSet;
+
+        use mongodb::bson::{doc, to_bson, DateTime};
+        use mongodb::options::InsertManyOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        pub enum BlockState {
+            /// The documents of the block are being written.
+            Pending,
+            /// The documents and derived collections of the block are written.
+            Complete,
+        }
+
+        /// Commit marker of a block of the stream. A block left pending by a crash is indexed
+        /// again by `repair-blocks`, the unique indexes of the collections make that safe.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct BlockStatus {
+            pub block: Block,
+            pub status: BlockState,
+            pub updated_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct BlockStatusProvider {
+            pub collection: Collection<BlockStatus>,
+        }
+
+        impl BlockStatusProvider {
+            pub fn new(collection: Collection<BlockStatus>) -> BlockStatusProvider {
+                BlockStatusProvider { collection }
+            }
+
+            /// Sets the status of the blocks `from..=to`, adding the markers that don't exist.
+            pub async fn mark(
+                &self,
+                from: Block,
+                to: Block,
+                status: BlockState,
+            ) -> mongodb::error::Result<()> {
+                let updated_at = DateTime::now();
+                let existing = self
+                    .collection
+                    .update_many(
+                        doc! {"block": {"$gte": from as i64, "$lte": to as i64}},
+                        doc! {"$set": {"status": to_bson(&status)?, "updated_at": updated_at}},
+                        None,
+                    )
+                    .await?;
+                if existing.matched_count > to.saturating_sub(from) {
+                    return Ok(());
+                }
+
+                let markers = (from..=to).map(|block| BlockStatus {
+                    block,
+                    status,
+                    updated_at,
+                });
+                // The blocks that already have a marker are rejected by the unique index.
+                match self
+                    .collection
+                    .insert_many(markers, InsertManyOptions::builder().ordered(false).build())
+                    .await
+                {
+                    Err(err) if matches!(*err.kind, mongodb::error::ErrorKind::BulkWrite(_)) => {
+                        Ok(())
+                    }
+                    result => result.map(|_| ()),
+                }
+            }
+
+            /// Blocks whose commit did not finish.
+            pub async fn pending(&self) -> BTreeSet<Block> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"status": to_bson(&BlockState::Pending).unwrap()},
+                        None,
+                    )
+                    .await
+                    .expect("Failed to query block status!");
+
+                let mut pending = BTreeSet::new();
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read block status!")
+                {
+                    let marker = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize block status!");
+                    pending.insert(marker.block);
+                }
+
+                pending
+            }
+        }
+
+        impl Indexable for BlockStatusProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("block", true), index_model("status", false)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.block_status"
+            }
+        }
+    }
+
+    pub mod backfill_chunk {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::{FindOneOptions, FindOptions, ReplaceOptions, UpdateOptions};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Block range of a logs mode backfill. `last_block` is the last block of the range that
+        /// is committed, `from - 1` before the chunk started.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct BackfillChunk {
+            pub from: Block,
+            pub to: Block,
+            pub last_block: Block,
+            pub done: bool,
+            pub updated_at: DateTime,
+        }
+
+        impl BackfillChunk {
+            pub fn new(from: Block, to: Block) -> BackfillChunk {
+                BackfillChunk {
+                    from,
+                    to,
+                    last_block: from - 1,
+                    done: false,
+                    updated_at: DateTime::now(),
+                }
+            }
+        }
+
+        #[derive(Clone)]
+        pub struct BackfillChunkProvider {
+            pub collection: Collection<BackfillChunk>,
+        }
+
+        impl BackfillChunkProvider {
+            pub fn new(collection: Collection<BackfillChunk>) -> BackfillChunkProvider {
+                BackfillChunkProvider { collection }
+            }
+
+            /// Chunks of earlier runs that did not finish, lowest block first.
+            pub async fn unfinished(&self) -> Vec<BackfillChunk> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"done": false},
+                        FindOptions::builder().sort(doc! {"from": 1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query backfill chunks");
+
+                let mut chunks = vec![];
+                while cursor
+                    .advance()
+                    .await
+                    .expect("Failed to read backfill chunks")
+                {
+                    chunks.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize backfill chunk"),
+                    );
+                }
+
+                chunks
+            }
+
+            /// Last block of all planned chunks.
+            pub async fn planned_to(&self) -> Option<Block> {
+                self.collection
+                    .find_one(
+                        None,
+                        FindOneOptions::builder().sort(doc! {"to": -1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query backfill chunks")
+                    .map(|chunk| chunk.to)
+            }
+
+            pub async fn plan(&self, chunk: &BackfillChunk) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"from": chunk.from as i64},
+                        chunk,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            /// Ends the chunk starting at `from` at `to`, after another worker took over the rest.
+            pub async fn shrink(&self, from: Block, to: Block) -> mongodb::error::Result<()> {
+                self.collection
+                    .update_one(
+                        doc! {"from": from as i64},
+                        doc! {"$set": {"to": to as i64, "updated_at": DateTime::now()}},
+                        UpdateOptions::builder().upsert(Some(false)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            /// Reopens the chunks committed beyond `block - 1`, so they continue before `block`.
+            pub async fn rewind(&self, block: Block) -> mongodb::error::Result<()> {
+                let last_block = block.saturating_sub(1) as i64;
+                self.collection
+                    .update_many(
+                        doc! {"last_block": {"$gt": last_block}},
+                        vec![doc! {
+                            "$set": {
+                                "last_block": {"$max": [last_block, {"$subtract": ["$from", 1i64]}]},
+                                "done": false,
+                                "updated_at": DateTime::now(),
+                            }
+                        }],
+                        None,
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            /// Records that the chunk starting at `from` is committed up to `last_block`.
+            pub async fn progress(
+                &self,
+                chunk: &BackfillChunk,
+                last_block: Block,
+            ) -> mongodb::error::Result<()> {
+                self.collection
+                    .update_one(
+                        doc! {"from": chunk.from as i64},
+                        doc! {
+                            "$set": {
+                                "last_block": last_block as i64,
+                                "done": last_block >= chunk.to,
+                                "updated_at": DateTime::now(),
+                            }
+                        },
+                        UpdateOptions::builder().upsert(Some(false)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for BackfillChunkProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("from", true), index_model("done", false)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.backfill_chunks"
+            }
+        }
+    }
+
+    pub mod tombstone {
+        use mongodb::bson::{doc, DateTime, Document};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Block at which a reorg was detected and its canonical hash.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct OrphanedAt {
+            pub block: Block,
+            pub hash: String,
+        }
+
+        /// Document rolled back in a reorg, kept as a record of what the chain briefly contained.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct Tombstone {
+            pub collection: String,
+            pub block: Block,
+            pub orphaned_at: OrphanedAt,
+            pub created_at: DateTime,
+            pub document: Document,
+        }
+
+        #[derive(Clone)]
+        pub struct TombstoneProvider {
+            pub collection: Collection<Tombstone>,
+        }
+
+        impl TombstoneProvider {
+            pub fn new(collection: Collection<Tombstone>) -> TombstoneProvider {
+                TombstoneProvider { collection }
+            }
+
+            /// Deletes the tombstones created before `before`. Returns the number deleted.
+            pub async fn prune(&self, before: DateTime) -> mongodb::error::Result<u64> {
+                self.collection
+                    .delete_many(doc! {"created_at": {"$lt": before}}, None)
+                    .await
+                    .map(|result| result.deleted_count)
+            }
+        }
+
+        impl Indexable for TombstoneProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("collection", false),
+                    index_model("block", false),
+                    index_model("created_at", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.tombstones"
+            }
+        }
+    }
+
+    pub mod lease {
+        use std::time::Duration;
+
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::error::{ErrorKind, WriteFailure};
+        use mongodb::options::UpdateOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::DUPLICATE_KEY_ERROR;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Lease held by the replica that is allowed to write, valid until `expires_at`.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct Lease {
+            pub name: String,
+            pub holder: String,
+            pub expires_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct LeaseProvider {
+            pub collection: Collection<Lease>,
+        }
+
+        impl LeaseProvider {
+            pub fn new(collection: Collection<Lease>) -> LeaseProvider {
+                LeaseProvider { collection }
+            }
+
+            /// Takes or renews the lease `name` for `holder` for `ttl`. Returns false while the
+            /// lease of another holder has not expired.
+            pub async fn acquire(
+                &self,
+                name: &str,
+                holder: &str,
+                ttl: Duration,
+            ) -> mongodb::error::Result<bool> {
+                let now = DateTime::now();
+                let expires_at =
+                    DateTime::from_millis(now.timestamp_millis() + ttl.as_millis() as i64);
+
+                let result = self
+                    .collection
+                    .update_one(
+                        doc! {
+                            "name": name,
+                            "$or": [{"holder": holder}, {"expires_at": {"$lt": now}}]
+                        },
+                        doc! {"$set": {"holder": holder, "expires_at": expires_at}},
+                        UpdateOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await;
+
+                match result {
+                    Ok(result) => Ok(result.matched_count > 0 || result.upserted_id.is_some()),
+                    Err(err) => match err.kind.as_ref() {
+                        ErrorKind::Write(WriteFailure::WriteError(error))
+                            if error.code == DUPLICATE_KEY_ERROR =>
+                        {
+                            Ok(false)
+                        }
+                        _ => Err(err),
+                    },
+                }
+            }
+
+            /// Gives up the lease so a standby can take over without waiting for it to expire.
+            pub async fn release(&self, name: &str, holder: &str) -> mongodb::error::Result<()> {
+                self.collection
+                    .delete_one(doc! {"name": name, "holder": holder}, None)
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for LeaseProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("name", true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.leases"
+            }
+        }
+    }
+
+    pub mod decode_failure {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        /// Decoder path that failed, used to pick the decoder when retrying.
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum DecodeStage {
+            Transfer,
+            Sale,
+            Order,
+            Governance,
+        }
+
+        /// Raw log that could not be decoded, kept for inspection and retries.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct DecodeFailure {
+            pub log_id: String,
+            pub transaction_id: TransactionHash,
+            pub block: Block,
+            pub log_index: String,
+            pub address: Address,
+            pub topics: Vec<String>,
+            pub data: String,
+            pub decoder: ContractType,
+            pub stage: DecodeStage,
+            pub error: String,
+            pub retries: u32,
+            pub created_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct DecodeFailureProvider {
+            pub collection: Collection<DecodeFailure>,
+        }
+
+        impl DecodeFailureProvider {
+            pub fn new(collection: Collection<DecodeFailure>) -> DecodeFailureProvider {
+                DecodeFailureProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<DecodeFailure> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for DecodeFailureProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    index_model("log_id", true),
+                    index_model("transaction_id", false),
+                    index_model("block", false),
+                    index_model("decoder", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.decode_failures"
+            }
+        }
+    }
+
+    pub mod receipt_anomaly {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum AnomalyKind {
+            /// The log indexes of a complete receipt have a gap or repeat.
+            NonContiguousLogIndex,
+            /// A log of another transaction or block.
+            ForeignLog,
+            /// A decoded document whose log is not part of the receipt.
+            MissingLog,
+        }
+
+        /// Malformed transaction receipt returned by the node. `log_id` is the id of the log the
+        /// anomaly was found at, the same id decoded documents use.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct ReceiptAnomaly {
+            pub transaction_id: TransactionHash,
+            pub block: Block,
+            pub kind: AnomalyKind,
+            pub log_id: String,
+            pub detail: String,
+            pub created_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct ReceiptAnomalyProvider {
+            pub collection: Collection<ReceiptAnomaly>,
+        }
+
+        impl ReceiptAnomalyProvider {
+            pub fn new(collection: Collection<ReceiptAnomaly>) -> ReceiptAnomalyProvider {
+                ReceiptAnomalyProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<ReceiptAnomaly> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        impl Indexable for ReceiptAnomalyProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["log_id", "kind"], true),
+                    index_model("transaction_id", false),
+                    index_model("block", false),
+                    index_model("kind", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.receipt_anomalies"
+            }
+        }
+    }
+
+    pub mod metrics {
+        use mongodb::bson::DateTime;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Block;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        /// Throughput, lag and error summary for one reporting interval of the stream.
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        pub struct Metrics {
+            pub timestamp: DateTime,
+            pub interval_seconds: u64,
+            pub block: Block,
+            pub head: Block,
+            pub lag: u64,
+            pub blocks: u64,
+            pub blocks_per_second: f64,
+            pub transactions: u64,
+            pub erc_transfers: u64,
+            pub erc1155_transfers: u64,
+            pub erc_sales: u64,
+            pub wallet_updates: u64,
+            pub duplicates: u64,
+            pub errors: u64,
+        }
+
+        #[derive(Clone)]
+        pub struct MetricsProvider {
+            pub collection: Collection<Metrics>,
+        }
+
+        impl MetricsProvider {
+            pub fn new(collection: Collection<Metrics>) -> MetricsProvider {
+                MetricsProvider { collection }
+            }
+        }
+
+        impl Indexable for MetricsProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("timestamp", false), index_model("block", false)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.metrics"
+            }
+        }
+    }
+
+    pub mod token_daily {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::{FindOneOptions, ReplaceOptions};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::Address;
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Network health metrics of an ERC20 token for one UTC day. `volume` and `supply` are hex
+        /// encoded raw token amounts, `velocity` is volume divided by supply.
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        pub struct TokenDaily {
+            pub day: DateTime,
+            pub token: Address,
+            pub transfers: u64,
+            pub active_addresses: u64,
+            pub new_addresses: u64,
+            pub volume: String,
+            pub supply: String,
+            pub velocity: f64,
+        }
+
+        /// First day an address took part in a transfer of a token, used to count new addresses.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct TokenAddress {
+            pub token: Address,
+            pub address: Address,
+            pub first_day: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct TokenDailyProvider {
+            pub collection: Collection<TokenDaily>,
+            pub addresses: Collection<TokenAddress>,
+        }
+
+        impl TokenDailyProvider {
+            pub fn new(
+                collection: Collection<TokenDaily>,
+                addresses: Collection<TokenAddress>,
+            ) -> TokenDailyProvider {
+                TokenDailyProvider {
+                    collection,
+                    addresses,
+                }
+            }
+
+            pub(crate) fn get_address_pool(&self) -> Pool<TokenAddress> {
+                Pool::new(self.addresses.to_owned())
+            }
+
+            pub async fn latest(&self, token: &str) -> Option<TokenDaily> {
+                self.collection
+                    .find_one(
+                        doc! {"token": token},
+                        FindOneOptions::builder().sort(doc! {"day": -1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query token daily metrics")
+            }
+
+            pub async fn store(&self, daily: &TokenDaily) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"day": daily.day, "token": &daily.token},
+                        daily,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for TokenDailyProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["token", "day"], true),
+                    index_model("day", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.token_daily"
+            }
+        }
+
+        /// Index setup of the `token_addresses` collection backing `TokenDailyProvider::addresses`.
+        pub struct TokenAddressIndexes;
+
+        impl Indexable for TokenAddressIndexes {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![compound_index_model(&["token", "address"], true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.token_addresses"
+            }
+        }
+    }
+
+    pub mod net_worth {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::{FindOneOptions, FindOptions, ReplaceOptions};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Address;
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Balance of one token at the end of a day. `amount` is the hex encoded raw amount,
+        /// `weth` its value or `None` without a price.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct Holding {
+            pub token: Address,
+            pub amount: String,
+            pub weth: Option<f64>,
+        }
+
+        /// Net worth of a watchlisted wallet at the end of a UTC day, valued with the Katana
+        /// reserves of that day. Native RON is listed under the zero address.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct NetWorth {
+            pub wallet: Address,
+            pub day: DateTime,
+            pub holdings: Vec<Holding>,
+            pub weth: f64,
+            pub usd: Option<f64>,
+        }
+
+        #[derive(Clone)]
+        pub struct NetWorthProvider {
+            pub collection: Collection<NetWorth>,
+        }
+
+        impl NetWorthProvider {
+            pub fn new(collection: Collection<NetWorth>) -> NetWorthProvider {
+                NetWorthProvider { collection }
+            }
+
+            /// Latest day of a wallet before `day`.
+            pub async fn before(&self, wallet: &str, day: DateTime) -> Option<NetWorth> {
+                self.collection
+                    .find_one(
+                        doc! {"wallet": wallet, "day": {"$lt": day}},
+                        FindOneOptions::builder().sort(doc! {"day": -1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query net worth")
+            }
+
+            /// Daily series of a wallet, oldest day first.
+            pub async fn series(&self, wallet: &str) -> Vec<NetWorth> {
+                let mut cursor = self
+                    .collection
+                    .find(
+                        doc! {"wallet": wallet},
+                        FindOptions::builder().sort(doc! {"day": 1i64}).build(),
+                    )
+                    .await
+                    .expect("Failed to query net worth");
+
+                let mut series = vec![];
+                while cursor.advance().await.expect("Failed to read net worth") {
+                    series.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize net worth"),
+                    );
+                }
+
+                series
+            }
+
+            pub async fn store(&self, net_worth: &NetWorth) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"wallet": &net_worth.wallet, "day": net_worth.day},
+                        net_worth,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for NetWorthProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["wallet", "day"], true),
+                    index_model("day", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.net_worth"
+            }
+        }
+    }
+
+    pub mod spam_token {
+        use std::collections::HashSet;
+
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::ReplaceOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::{Address, Block};
+        use crate::mongo::{index_model, IndexModel, Indexable};
+        use crate::ronin::ContractType;
+
+        /// Token classified as a spam airdrop: `sender` sent the identical amount to `recipients`
+        /// wallets without liquidity behind it, and only `active_ratio` of them moved it on.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct SpamToken {
+            pub token: Address,
+            pub erc: ContractType,
+            pub sender: Address,
+            pub recipients: u64,
+            pub active_ratio: f64,
+            pub from_block: Block,
+            pub to_block: Block,
+            pub flagged_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct SpamTokenProvider {
+            pub collection: Collection<SpamToken>,
+        }
+
+        impl SpamTokenProvider {
+            pub fn new(collection: Collection<SpamToken>) -> SpamTokenProvider {
+                SpamTokenProvider { collection }
+            }
+
+            pub async fn tokens(&self) -> HashSet<Address> {
+                let mut cursor = self
+                    .collection
+                    .find(None, None)
+                    .await
+                    .expect("Failed to query spam tokens");
+
+                let mut tokens = HashSet::new();
+                while cursor.advance().await.expect("Failed to read spam tokens") {
+                    let token: SpamToken = cursor
+                        .deserialize_current()
+                        .expect("Failed to deserialize spam token");
+                    tokens.insert(token.token);
+                }
+
+                tokens
+            }
+
+            pub async fn store(&self, token: &SpamToken) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"token": &token.token},
+                        token,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for SpamTokenProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("token", true)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.spam_tokens"
+            }
+        }
+    }
+
+    pub mod marketplace_fee {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::ReplaceOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Address;
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+        pub enum FeeSource {
+            /// marketFeeTaken of OrderMatched events, per collection.
+            OrderMatched,
+            /// ERC20 transfers into the treasury address, `collection` is empty.
+            Treasury,
+        }
+
+        /// Marketplace protocol revenue of one UTC day. `fees` is the hex encoded raw amount of
+        /// `payment_token`.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct MarketplaceFee {
+            pub day: DateTime,
+            pub source: FeeSource,
+            pub collection: Option<Address>,
+            pub payment_token: Address,
+            pub sales: u64,
+            pub fees: String,
+        }
+
+        #[derive(Clone)]
+        pub struct MarketplaceFeeProvider {
+            pub collection: Collection<MarketplaceFee>,
+        }
+
+        impl MarketplaceFeeProvider {
+            pub fn new(collection: Collection<MarketplaceFee>) -> MarketplaceFeeProvider {
+                MarketplaceFeeProvider { collection }
+            }
+
+            pub async fn store(&self, fee: &MarketplaceFee) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {
+                            "day": fee.day,
+                            "source": mongodb::bson::to_bson(&fee.source)?,
+                            "collection": &fee.collection,
+                            "payment_token": &fee.payment_token
+                        },
+                        fee,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+        }
+
+        impl Indexable for MarketplaceFeeProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![
+                    compound_index_model(&["day", "source", "collection", "payment_token"], true),
+                    index_model("collection", false),
+                ]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.marketplace_fees"
+            }
+        }
+    }
+
+    pub mod contract {
+        use mongodb::bson::{doc, DateTime};
+        use mongodb::options::ReplaceOptions;
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::Address;
+        use crate::mongo::{index_model, IndexModel, Indexable};
+
+        #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        pub enum MetadataSource {
+            Manual,
+            Sourcify,
+        }
+
+        /// Contract entry with optional verified source metadata. `abi` is the JSON ABI as
+        /// published by the compiler and is used to decode inputs and events of the contract.
+        #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+        pub struct ContractMetadata {
+            pub address: Address,
+            pub name: String,
+            #[serde(default)]
+            pub abi: Option<String>,
+            #[serde(default)]
+            pub compiler: Option<String>,
+            #[serde(default)]
+            pub metadata_source: Option<MetadataSource>,
+            pub updated_at: DateTime,
+        }
+
+        #[derive(Clone)]
+        pub struct ContractProvider {
+            pub collection: Collection<ContractMetadata>,
+        }
+
+        impl ContractProvider {
+            pub fn new(collection: Collection<ContractMetadata>) -> ContractProvider {
+                ContractProvider { collection }
+            }
+
+            pub async fn store(&self, contract: &ContractMetadata) -> mongodb::error::Result<()> {
+                self.collection
+                    .replace_one(
+                        doc! {"address": &contract.address},
+                        contract,
+                        ReplaceOptions::builder().upsert(Some(true)).build(),
+                    )
+                    .await
+                    .map(|_| ())
+            }
+
+            /// Contracts with an ABI attached.
+            pub async fn with_abi(&self) -> Vec<ContractMetadata> {
+                let mut cursor = self
+                    .collection
+                    .find(doc! {"abi": {"$type": "string"}}, None)
+                    .await
+                    .expect("Failed to query contracts!");
+
+                let mut contracts = vec![];
+                while cursor.advance().await.expect("Failed to read contracts!") {
+                    contracts.push(
+                        cursor
+                            .deserialize_current()
+                            .expect("Failed to deserialize contract!"),
+                    );
+                }
+                contracts
+            }
+        }
+
+        impl Indexable for ContractProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                vec![index_model("address", true), index_model("name", false)]
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.contracts"
+            }
+        }
+    }
+
+    pub mod contract_event {
+        use mongodb::bson::{DateTime, Document};
+        use mongodb::Collection;
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::transaction_pool::Pool;
+        use crate::mongo::collections::{Address, Block, TransactionHash};
+        use crate::mongo::{compound_index_model, index_model, IndexModel, Indexable};
+
+        /// Event decoded with the ABI attached to its contract. `params` maps the event inputs by
+        /// name, addresses are 0x prefixed and integers hex encoded like the other collections.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct ContractEvent {
+            pub address: Address,
+            pub contract: String,
+            pub event: String,
+            pub params: Document,
+            pub block: Block,
+            pub timestamp: DateTime,
+            pub transaction_id: TransactionHash,
+            pub log_index: String,
+            pub log_id: String,
+        }
+
+        // Decoded params never contain floating point values.
+        impl Eq for ContractEvent {}
+
+        #[derive(Clone)]
+        pub struct ContractEventProvider {
+            pub collection: Collection<ContractEvent>,
+        }
+
+        impl ContractEventProvider {
+            pub fn new(collection: Collection<ContractEvent>) -> ContractEventProvider {
+                ContractEventProvider { collection }
+            }
+
+            pub(crate) fn get_pool(&self) -> Pool<ContractEvent> {
+                Pool::new(self.collection.to_owned())
+            }
+        }
+
+        fn event_indexes() -> Vec<IndexModel> {
+            vec![
+                index_model("log_id", true),
+                compound_index_model(&["address", "event", "block"], false),
+                index_model("transaction_id", false),
+                index_model("block", false),
+            ]
+        }
+
+        impl Indexable for ContractEventProvider {
+            fn index_model(&self) -> Vec<IndexModel> {
+                event_indexes()
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                "setup.contract_events"
+            }
+        }
+
+        /// Indexes of a collection configured for an event subscription.
+        pub struct SubscriptionIndexes {
+            pub setup_key: &'static str,
+        }
+
+        impl Indexable for SubscriptionIndexes {
+            fn index_model(&self) -> Vec<IndexModel> {
+                event_indexes()
+            }
+
+            fn index_setup_key(&self) -> &'static str {
+                self.setup_key
+            }
+        }
+    }
+
+    pub mod storage_profile {
+        use std::sync::OnceLock;
+
+        use mongodb::bson::{doc, Document};
+        use serde::{Deserialize, Serialize};
+
+        use crate::mongo::collections::settings::SettingsValue;
+        use crate::mongo::Database;
+
+        /// Settings key holding the storage profile chosen at the initial sync.
+        pub const STORAGE_PROFILE_KEY: &str = "storage.profile";
+
+        #[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        pub enum StorageProfile {
+            /// Every field under its full name.
+            Full,
+            /// Omits fields that can be derived from others and stores rarely read fields under
+            /// short names, see [`COMPACT_RULES`].
+            Compact,
+        }
+
+        impl SettingsValue for StorageProfile {}
+
+        impl StorageProfile {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    StorageProfile::Full => "full",
+                    StorageProfile::Compact => "compact",
+                }
+            }
+        }
+
+        /// Fields the compact profile drops from and renames in the documents of a collection.
+        /// Renamed fields are read back through a serde alias, so only fields that are never
+        /// filtered or indexed on are renamed.
+        pub struct CompactRule {
+            pub collection: &'static str,
+            pub omit: &'static [&'static str],
+            pub rename: &'static [(&'static str, &'static str)],
+        }
+
+        pub const COMPACT_RULES: [CompactRule; 4] = [
+            CompactRule {
+                collection: "transactions",
+                omit: &[],
+                rename: &[
+                    ("gas_used", "gu"),
+                    ("effective_gas_price", "gp"),
+                    ("nonce", "n"),
+                ],
+            },
+            // The log index is restored from `log_index_num` on read.
+            CompactRule {
+                collection: "erc_transfers",
+                omit: &["log_index"],
+                rename: &[],
+            },
+            CompactRule {
+                collection: "whale_transfers",
+                omit: &["log_index"],
+                rename: &[],
+            },
+            CompactRule {
+                collection: "erc1155_transfers",
+                omit: &["log_index"],
+                rename: &[],
+            },
+        ];
+
+        static PROFILE: OnceLock<StorageProfile> = OnceLock::new();
+
+        /// Profile the pools write with, [`StorageProfile::Full`] until [`init`] ran.
+        pub fn profile() -> StorageProfile {
+            PROFILE.get().copied().unwrap_or(StorageProfile::Full)
+        }
+
+        /// Applies the compact profile to a document about to be inserted into `collection`.
+        pub fn compact(collection: &str, document: &mut Document) {
+            let rule = match COMPACT_RULES
+                .iter()
+                .find(|rule| rule.collection == collection)
+            {
+                None => return,
+                Some(rule) => rule,
+            };
+
+            for field in rule.omit {
+                document.remove(*field);
+            }
+            for (field, short) in rule.rename {
+                if let Some(value) = document.remove(*field) {
+                    document.insert(*short, value);
+                }
+            }
+        }
+
+        /// Selects the storage profile, `requested` or else the stored one. It can only be chosen
+        /// while nothing is indexed yet, a database keeps the profile of its initial sync.
+        pub async fn init(database: &Database, requested: Option<StorageProfile>) {
+            let profile = match database
+                .settings
+                .load::<StorageProfile>(STORAGE_PROFILE_KEY)
+                .await
+            {
+                Some(stored) => stored,
+                None => {
+                    let indexed = database
+                        .transactions
+                        .collection
+                        .find_one(doc! {}, None)
+                        .await
+                        .expect("Failed to query transactions!")
+                        .is_some()
+                        || database
+                            .erc_transfers
+                            .collection
+                            .find_one(doc! {}, None)
+                            .await
+                            .expect("Failed to query erc transfers!")
+                            .is_some();
+
+                    match indexed {
+                        true => StorageProfile::Full,
+                        false => requested.unwrap_or(StorageProfile::Full),
+                    }
+                }
+            };
+
+            if let Some(requested) = requested.filter(|requested| *requested != profile) {
+                panic!(
+                    "The database was synced with the {} storage profile, it can't be changed to {}",
+                    profile.as_str(),
+                    requested.as_str()
+                );
+            }
+
+            PROFILE
+                .set(profile)
+                .expect("Storage profile was already initialized!");
+        }
+
+        /// Stores the selected profile unless one is stored already. Called once documents are
+        /// about to be written, so runs that only read don't fix the profile of an empty database.
+        pub async fn record(database: &Database) {
+            if database
+                .settings
+                .load::<StorageProfile>(STORAGE_PROFILE_KEY)
+                .await
+                .is_some()
+            {
+                return;
+            }
+
+            database
+                .settings
+                .store(STORAGE_PROFILE_KEY, &profile())
+                .await
+                .expect("Failed to store storage profile!");
+        }
+    }
+
+    pub mod transaction_pool {
+        use std::collections::{BTreeMap, HashSet};
+        use std::sync::Mutex;
+
+        use log::error;
+        use mongodb::bson::{doc, Bson, Document};
+        use mongodb::error::{Error, ErrorKind};
+        use mongodb::options::{FindOptions, InsertManyOptions};
+        use mongodb::{ClientSession, Collection};
+        use serde::Serialize;
+
+        use crate::mongo::collections::storage_profile::{compact, profile, StorageProfile};
+        use crate::mongo::update_batch_size;
+        use crate::sink::FileSink;
+
+        pub(crate) const DUPLICATE_KEY_ERROR: i32 = 11000;
+
+        const NAMESPACE_NOT_FOUND_ERROR: i32 = 26;
+
+        /// Fields of the unique indexes of each collection by namespace, read once per collection.
+        static UNIQUE_KEYS: Mutex<BTreeMap<String, Vec<Vec<String>>>> = Mutex::new(BTreeMap::new());
+
+        pub struct Pool<T> {
+            collection: Collection<T>,
+            updates: Vec<[Document; 2]>,
+            inserts: Vec<T>,
+            inserted: Vec<T>,
+            duplicated: Vec<T>,
+            duplicates: usize,
+            errors: usize,
+            /// Whether the pending documents were written by [`Pool::write_in`].
+            written: bool,
+            /// Positions of the pending inserts [`Pool::write_in`] left out as already stored.
+            stored: HashSet<usize>,
+            /// Updates [`Pool::write_in`] matched to a stored document without changing it.
+            unchanged: usize,
+        }
+
+        /// Fields of each unique index of `collection`, except the one on `_id`.
+        async fn unique_keys(collection: &Collection<Document>) -> Result<Vec<Vec<String>>, Error> {
+            let namespace = collection.namespace().to_string();
+            if let Some(keys) = UNIQUE_KEYS.lock().unwrap().get(&namespace) {
+                return Ok(keys.to_owned());
+            }
+
+            let mut indexes = match collection.list_indexes(None).await {
+                Ok(indexes) => indexes,
+                Err(error) => {
+                    return match error.kind.as_ref() {
+                        ErrorKind::Command(error) if error.code == NAMESPACE_NOT_FOUND_ERROR => {
+                            Ok(vec![])
+                        }
+                        _ => Err(error),
+                    };
+                }
+            };
+            let mut keys = vec![];
+            while indexes.advance().await? {
+                let index = indexes.deserialize_current()?;
+                if index.options.and_then(|options| options.unique) == Some(true) {
+                    keys.push(index.keys.keys().cloned().collect());
+                }
+            }
+
+            UNIQUE_KEYS
+                .lock()
+                .unwrap()
+                .insert(namespace, keys.to_owned());
+            Ok(keys)
+        }
+
+        /// The `fields` of `document`, missing ones as null like the index stores them.
+        fn unique_key(fields: &[String], document: &Document) -> Document {
+            fields
+                .iter()
+                .map(|field| {
+                    (
+                        field.to_owned(),
+                        document.get(field).cloned().unwrap_or(Bson::Null),
+                    )
+                })
+                .collect()
+        }
+
+        /// Positions of the `documents` whose key in a unique index of `collection` is already
+        /// stored, as seen by the transaction of `session`, or taken by an earlier document.
+        async fn stored_keys(
+            collection: &Collection<Document>,
+            documents: &[Document],
+            session: &mut ClientSession,
+        ) -> Result<HashSet<usize>, Error> {
+            let mut stored = HashSet::new();
+
+            for fields in unique_keys(collection).await? {
+                let mut projection = doc! {"_id": 0};
+                for field in fields.iter() {
+                    projection.insert(field, 1);
+                }
+                let options = FindOptions::builder().projection(projection).build();
+
+                let mut keys: HashSet<String> = HashSet::new();
+                for batch in documents.chunks(update_batch_size()) {
+                    let filter = match fields.as_slice() {
+                        [field] => doc! {
+                            field: {
+                                "$in": batch
+                                    .iter()
+                                    .map(|document| document.get(field).cloned().unwrap_or(Bson::Null))
+                                    .collect::<Vec<Bson>>()
+                            }
+                        },
+                        _ => doc! {
+                            "$or": batch
+                                .iter()
+                                .map(|document| unique_key(&fields, document))
+                                .collect::<Vec<Document>>()
+                        },
+                    };
+
+                    let mut cursor = collection
+                        .find_with_session(filter, options.to_owned(), session)
+                        .await?;
+                    while cursor.advance(session).await? {
+                        keys.insert(
+                            unique_key(&fields, &cursor.deserialize_current()?).to_string(),
+                        );
+                    }
+                }
+
+                for (index, document) in documents.iter().enumerate() {
+                    if !keys.insert(unique_key(&fields, document).to_string()) {
+                        stored.insert(index);
+                    }
+                }
+            }
+
+            Ok(stored)
+        }
+
+        /// Write error of a bulk update, at `index` of the batch.
+        fn write_error(error: &Bson) -> Option<(usize, i32, &str)> {
+            let error = error.as_document()?;
+            let index = match error.get("index")? {
+                Bson::Int32(index) => *index as usize,
+                Bson::Int64(index) => *index as usize,
+                _ => return None,
+            };
+
+            Some((
+                index,
+                error.get_i32("code").unwrap_or_default(),
+                error.get_str("errmsg").unwrap_or_default(),
+            ))
+        }
+
+        /// Updates of a bulk update response that matched a stored document without changing it,
+        /// e.g. on a rerun of the block.
+        fn unchanged_updates(response: &Document) -> usize {
+            let count = |key: &str| match response.get(key) {
+                Some(Bson::Int32(count)) => *count as usize,
+                Some(Bson::Int64(count)) => *count as usize,
+                _ => 0,
+            };
+            let upserted = response
+                .get_array("upserted")
+                .map(|upserted| upserted.len())
+                .unwrap_or_default();
+
+            count("n").saturating_sub(upserted + count("nModified"))
+        }
+
+        impl<T> Pool<T>
+        where
+            T: Serialize + Clone + Eq + PartialEq,
+        {
+            pub fn new(collection: Collection<T>) -> Self {
+                Pool {
+                    collection,
+                    updates: vec![],
+                    inserts: vec![],
+                    inserted: vec![],
+                    duplicated: vec![],
+                    duplicates: 0,
+                    errors: 0,
+                    written: false,
+                    stored: HashSet::new(),
+                    unchanged: 0,
+                }
+            }
+
+            pub fn name(&self) -> &str {
+                self.collection.name()
+            }
+
+            /// Inserts suppressed by a unique index and updates that left a stored document unchanged
+            /// during the last commit.
+            pub fn duplicates(&self) -> usize {
+                self.duplicates
+            }
+
+            /// Documents that were newly written by the last commit, without duplicates and failures.
+            pub fn inserted(&self) -> &[T] {
+                &self.inserted
+            }
+
+            /// Documents that were rejected by a unique index during the last commit.
+            pub fn duplicated(&self) -> &[T] {
+                &self.duplicated
+            }
+
+            /// Failed inserts and updates, excluding duplicates, during the last commit.
+            pub fn errors(&self) -> usize {
+                self.errors
+            }
+
+            fn has_update(&self, doc: &Document) -> Option<usize> {
+                self.updates.iter().position(|d| d[0].eq(doc))
+            }
+
+            fn has_insert(&self, doc: &T) -> Option<usize> {
+                self.inserts.iter().position(|d| d.eq(doc))
+            }
+
+            pub fn insert(&mut self, insert: T) {
+                let existing = self.has_insert(&insert);
+
+                match existing {
+                    None => {
+                        self.inserts.push(insert);
+                    }
+                    Some(index) => {
+                        self.inserts.remove(index);
+                        self.inserts.push(insert);
+                    }
+                }
+            }
+
+            /// Queues `inserts` that are known to be distinct without the duplicate check of
+            /// [`Pool::insert`], which scans the pending inserts for each document.
+            pub fn extend(&mut self, inserts: impl IntoIterator<Item = T>) {
+                self.inserts.extend(inserts);
+            }
+
+            pub fn update(&mut self, update: [Document; 2]) {
+                let existing = self.has_update(&update[0]);
 
-            pub fn update(&mut self, update: [Document; 2]) {
-                let existing = self.has_update(&update[0]);
-
                 match existing {
                     None => {
                         self.updates.push(update);
@@ -485,41 +4293,266 @@ pub mod collections {
                 self.updates.len() + self.inserts.len()
             }
 
-            pub async fn commit(&mut self, upsert: bool) -> Result<&mut Pool<T>, Error> {
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            fn update_command(&self, batch: &[[Document; 2]], upsert: bool) -> Document {
+                let updates: Vec<Document> = batch
+                    .iter()
+                    .map(|[filter, update]| doc! {"q": filter, "u": update, "upsert": upsert})
+                    .collect();
+
+                doc! {
+                    "update": self.collection.namespace().coll,
+                    "updates": updates,
+                    "ordered": false,
+                }
+            }
+
+            /// The pending inserts as stored by the storage profile.
+            fn documents(&self) -> Result<Vec<Document>, Error> {
+                let mut documents = Vec::with_capacity(self.inserts.len());
+                for insert in self.inserts.iter() {
+                    let mut document = mongodb::bson::to_document(insert)?;
+                    if profile() == StorageProfile::Compact {
+                        compact(self.name(), &mut document);
+                    }
+                    documents.push(document);
+                }
+
+                Ok(documents)
+            }
+
+            /// Writes the pending inserts and updates in the transaction of `session`, leaving them
+            /// in the pool. Inserts whose unique keys are already stored, e.g. by an earlier run of
+            /// the block, are left out and count as duplicates like updates that leave a stored
+            /// document unchanged, any other failed write fails the transaction. Once it is
+            /// committed, [`Pool::commit`] only takes the written documents out of the pool, after
+            /// an abort [`Pool::discard_write`] makes it write them again.
+            pub async fn write_in(
+                &mut self,
+                upsert: bool,
+                session: &mut ClientSession,
+            ) -> Result<(), Error> {
                 if !self.inserts.is_empty() {
-                    self.collection
-                        .insert_many(
-                            &self.inserts,
-                            InsertManyOptions::builder().ordered(false).build(),
-                        )
-                        .await
-                        .ok(); // Todo: figure out a way how to handle errors without inserting docs one by one
+                    let collection = self.collection.clone_with_type::<Document>();
+                    let documents = self.documents()?;
+                    self.stored = stored_keys(&collection, &documents, session).await?;
+
+                    let documents: Vec<Document> = documents
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(index, _)| !self.stored.contains(index))
+                        .map(|(_, document)| document)
+                        .collect();
+                    if !documents.is_empty() {
+                        collection
+                            .insert_many_with_session(documents, None, session)
+                            .await?;
+                    }
+                }
+
+                let namespace = self.collection.namespace();
+                self.unchanged = 0;
+                for batch in self.updates.chunks(update_batch_size()) {
+                    // The write concern is the one of the transaction.
+                    let response = self
+                        .collection
+                        .client()
+                        .database(&namespace.db)
+                        .run_command_with_session(self.update_command(batch, upsert), None, session)
+                        .await?;
+
+                    if let Some((_, code, message)) = response
+                        .get_array("writeErrors")
+                        .ok()
+                        .and_then(|errors| errors.iter().find_map(write_error))
+                    {
+                        return Err(Error::from(std::io::Error::other(f!(
+                            "Failed to update {} with error {code}: {message}",
+                            self.name()
+                        ))));
+                    }
+                    self.unchanged += unchanged_updates(&response);
+                }
+
+                self.written = true;
+                Ok(())
+            }
+
+            /// Forgets a [`Pool::write_in`] whose transaction was aborted.
+            pub fn discard_write(&mut self) {
+                self.written = false;
+                self.stored.clear();
+                self.unchanged = 0;
+            }
+
+            /// Sends `batch` as one unordered update command, so a failed update doesn't hold back
+            /// the others. Logs the failures of the batch together and returns their number.
+            async fn update_batch(
+                &self,
+                batch: &[[Document; 2]],
+                upsert: bool,
+            ) -> Result<(usize, usize), Error> {
+                let namespace = self.collection.namespace();
+                let mut command = self.update_command(batch, upsert);
+                if let Some(write_concern) = self.collection.write_concern() {
+                    command.insert("writeConcern", mongodb::bson::to_document(write_concern)?);
+                }
+
+                let response = match self
+                    .collection
+                    .client()
+                    .database(&namespace.db)
+                    .run_command(command, None)
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(error) => {
+                        error!(
+                            "Failed to update {} documents of {} with error {:?}",
+                            batch.len(),
+                            self.name(),
+                            error
+                        );
+                        return Ok((0, batch.len()));
+                    }
+                };
+
+                let (duplicate_keys, write_errors): (Vec<_>, Vec<_>) = response
+                    .get_array("writeErrors")
+                    .map(|errors| errors.iter().filter_map(write_error).collect::<Vec<_>>())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .partition(|(_, code, _)| *code == DUPLICATE_KEY_ERROR);
+                if let Some((index, code, message)) = write_errors.first() {
+                    error!(
+                        "Failed {} of {} updates of {}, first error {code} on {:?}: {message}",
+                        write_errors.len(),
+                        batch.len(),
+                        self.name(),
+                        batch.get(*index).map(|update| &update[0])
+                    );
                 }
+                let duplicates = unchanged_updates(&response) + duplicate_keys.len();
 
-                if !self.updates.is_empty() {
-                    let options: UpdateOptions = match upsert {
-                        true => UpdateOptions::builder().upsert(Some(true)).build(),
-                        false => UpdateOptions::builder().build(),
+                if let Ok(write_concern_error) = response.get_document("writeConcernError") {
+                    error!(
+                        "Updates of {} missed the write concern: {:?}",
+                        self.name(),
+                        write_concern_error
+                    );
+                    return Ok((duplicates, write_errors.len().max(1)));
+                }
+
+                Ok((duplicates, write_errors.len()))
+            }
+
+            pub async fn commit(&mut self, upsert: bool) -> Result<&mut Pool<T>, Error> {
+                self.duplicates = 0;
+                self.errors = 0;
+                self.inserted.clear();
+                self.duplicated.clear();
+
+                if self.written {
+                    self.written = false;
+                    let stored = std::mem::take(&mut self.stored);
+                    for (index, insert) in self.inserts.drain(..).enumerate() {
+                        match stored.contains(&index) {
+                            true => self.duplicated.push(insert),
+                            false => self.inserted.push(insert),
+                        }
+                    }
+                    self.duplicates = self.duplicated.len() + std::mem::take(&mut self.unchanged);
+                    self.updates.clear();
+
+                    return Ok(self);
+                }
+
+                if let Some(sink) = FileSink::get() {
+                    let updates: Vec<serde_json::Value> = self
+                        .updates
+                        .drain(..)
+                        .map(|[filter, update]| {
+                            serde_json::json!({"filter": filter, "update": update, "upsert": upsert})
+                        })
+                        .collect();
+                    sink.append(self.name(), &self.inserts);
+                    sink.append(&f!("{}.updates", self.name()), &updates);
+                    self.inserted.append(&mut self.inserts);
+
+                    return Ok(self);
+                }
+
+                if !self.inserts.is_empty() {
+                    let options = InsertManyOptions::builder().ordered(false).build();
+                    let result = match profile() {
+                        StorageProfile::Full => {
+                            self.collection.insert_many(&self.inserts, options).await
+                        }
+                        StorageProfile::Compact => {
+                            self.collection
+                                .clone_with_type::<Document>()
+                                .insert_many(self.documents()?, options)
+                                .await
+                        }
                     };
 
-                    for update in self.updates.as_slice() {
-                        match self
-                            .collection
-                            .update_one(
-                                update[0].to_owned(),
-                                update[1].to_owned(),
-                                options.to_owned(),
-                            )
-                            .await
-                        {
-                            Ok(_) => {}
-                            Err(error) => {
-                                error!("Failed to upsert {:?} with error {:?}", update, error);
+                    let mut duplicated: HashSet<usize> = HashSet::new();
+                    let rejected: HashSet<usize> = match result {
+                        Ok(_) => HashSet::new(),
+                        Err(error) => match error.kind.as_ref() {
+                            ErrorKind::BulkWrite(failure) => {
+                                let write_errors =
+                                    failure.write_errors.to_owned().unwrap_or_default();
+                                duplicated = write_errors
+                                    .iter()
+                                    .filter(|e| e.code == DUPLICATE_KEY_ERROR)
+                                    .map(|e| e.index)
+                                    .collect();
+                                self.duplicates = duplicated.len();
+
+                                let failed = write_errors.len() - self.duplicates;
+                                if failed > 0 || failure.write_concern_error.is_some() {
+                                    self.errors += failed.max(1);
+                                    error!(
+                                        "Failed to insert into {} with error {:?}",
+                                        self.name(),
+                                        error
+                                    );
+                                }
+
+                                write_errors.iter().map(|e| e.index).collect()
+                            }
+                            _ => {
+                                self.errors += 1;
+                                error!(
+                                    "Failed to insert into {} with error {:?}",
+                                    self.name(),
+                                    error
+                                );
+
+                                (0..self.inserts.len()).collect()
                             }
+                        },
+                    };
+
+                    for (index, insert) in self.inserts.drain(..).enumerate() {
+                        if !rejected.contains(&index) {
+                            self.inserted.push(insert);
+                        } else if duplicated.contains(&index) {
+                            self.duplicated.push(insert);
                         }
                     }
                 }
 
+                for batch in self.updates.chunks(update_batch_size()) {
+                    let (duplicates, errors) = self.update_batch(batch, upsert).await?;
+                    self.duplicates += duplicates;
+                    self.errors += errors;
+                }
+
                 self.updates.clear();
                 self.inserts.clear();
 
@@ -530,187 +4563,450 @@ pub mod collections {
 }
 
 pub async fn connect(hostname: &str, database: &str) -> Database {
-    let client = Client::with_uri_str(&hostname)
-        .await
-        .unwrap_or_else(|_| panic!("Failed to connect to mongodb at {}", &hostname));
+    connect_version(hostname, database, None).await
+}
+
+/// Connects to the collections of a shadow decoder `version`, see [`VERSIONED_COLLECTIONS`].
+/// Connections to the same `hostname` share one client and its connection pool.
+pub async fn connect_version(hostname: &str, database: &str, version: Option<u32>) -> Database {
+    let client = {
+        let mut clients = CLIENTS.lock().await;
+        match clients.get(hostname) {
+            Some(client) => client.to_owned(),
+            None => {
+                let client = Client::with_uri_str(&hostname)
+                    .await
+                    .unwrap_or_else(|_| panic!("Failed to connect to mongodb at {}", &hostname));
+                clients.insert(hostname.to_string(), client.to_owned());
+                client
+            }
+        }
+    };
+
+    from_client(client, database, version).await
+}
+
+/// Builds the collection providers on top of an existing client, so several databases or tasks
+/// can share a single connection pool.
+pub async fn from_client(client: Client, database: &str, version: Option<u32>) -> Database {
+    let database = build(client, database, version);
+
+    database.create_indexes().await;
+
+    database
+}
+
+/// Collections written by the decoders, and the aggregates derived from them, that a shadow
+/// decoder version writes to `<name>_v<version>` until it is promoted. The shadow also keeps its
+/// own `settings_v<version>` and `backfill_chunks_v<version>`, so its progress and index setup
+/// are independent of the live pipeline.
+pub const VERSIONED_COLLECTIONS: [&str; 18] = [
+    "erc_transfers",
+    "erc1155_transfers",
+    "erc721_sales",
+    "auctions",
+    "orders",
+    "pair_reserves",
+    "governance_events",
+    "staking_events",
+    "decode_failures",
+    "receipt_anomalies",
+    "erc1155_supply",
+    "balances",
+    "nft_owners",
+    "price_history",
+    "whale_transfers",
+    "wallet_transfers",
+    "equipment_events",
+    "axie_equipment",
+];
+
+/// Name of `collection` for a shadow decoder `version`.
+pub fn versioned_name(collection: &str, version: Option<u32>) -> String {
+    match version {
+        Some(version)
+            if collection == "settings"
+                || collection == "backfill_chunks"
+                || VERSIONED_COLLECTIONS.contains(&collection) =>
+        {
+            f!("{collection}_v{version}")
+        }
+        _ => collection.to_string(),
+    }
+}
+
+/// Clients by connection string, see [`connect_version`].
+static CLIENTS: tokio::sync::Mutex<BTreeMap<String, Client>> =
+    tokio::sync::Mutex::const_new(BTreeMap::new());
+
+/// Write concerns from `--write-concern` by collection, "*" holds the default.
+static WRITE_CONCERNS: OnceLock<HashMap<String, WriteConcern>> = OnceLock::new();
+
+pub fn set_write_concerns(concerns: &[CollectionWriteConcern]) {
+    let concerns = concerns
+        .iter()
+        .map(|concern| {
+            let write_concern = WriteConcern::builder()
+                .w(concern.w.to_owned())
+                .journal(concern.journal.then_some(true))
+                .build();
+            (concern.collection.to_owned(), write_concern)
+        })
+        .collect();
+
+    if WRITE_CONCERNS.set(concerns).is_err() {
+        panic!("Write concerns set twice!");
+    }
+}
+
+/// `--update-batch-size`, the updates of a [`Pool`] commit sent per bulk update.
+static UPDATE_BATCH_SIZE: OnceLock<usize> = OnceLock::new();
+
+pub fn set_update_batch_size(size: usize) {
+    if UPDATE_BATCH_SIZE.set(size.max(1)).is_err() {
+        panic!("Update batch size set twice!");
+    }
+}
+
+pub(crate) fn update_batch_size() -> usize {
+    UPDATE_BATCH_SIZE.get().copied().unwrap_or(1000)
+}
+
+/// Opens the collections of [`build`] under their versioned name with their write concern.
+struct Collections<'a> {
+    db: &'a mongodb::Database,
+    version: Option<u32>,
+}
+
+impl Collections<'_> {
+    fn get<T>(&self, collection: &str) -> Collection<T> {
+        let write_concern = WRITE_CONCERNS.get().and_then(|concerns| {
+            concerns
+                .get(collection)
+                .or_else(|| concerns.get("*"))
+                .cloned()
+        });
 
+        self.db.collection_with_options(
+            &versioned_name(collection, self.version),
+            CollectionOptions::builder()
+                .write_concern(write_concern)
+                .build(),
+        )
+    }
+}
+
+/// Builds the collection providers without touching the server, e.g. for offline decoding.
+pub fn build(client: Client, database: &str, version: Option<u32>) -> Database {
     let db = client.database(database);
+    let collections = Collections { db: &db, version };
 
-    let wallets = WalletProvider::new(db.collection::<Wallet>("wallets"));
-    let transactions = TransactionProvider::new(db.collection::<Transaction>("transactions"));
-    let erc_transfers = ErcTransferProvider::new(db.collection::<ERCTransfer>("erc_transfers"));
+    let wallets = WalletProvider::new(collections.get::<Wallet>("wallets"));
+    let transactions = TransactionProvider::new(collections.get::<Transaction>("transactions"));
+    let blocks = BlockProvider::new(collections.get::<IndexedBlock>("blocks"));
+    let erc_transfers = ErcTransferProvider::new(collections.get::<ERCTransfer>("erc_transfers"));
     let erc1155_transfers =
-        Erc1155TransferProvider::new(db.collection::<ERC1155Transfer>("erc1155_transfers"));
-    let settings = SettingsProvider::new(db.collection::<Settings>("settings"));
-    let erc_sales = SaleProvider::new(db.collection::<Sale>("erc721_sales"));
+        Erc1155TransferProvider::new(collections.get::<ERC1155Transfer>("erc1155_transfers"));
+    let erc1155_supply =
+        Erc1155SupplyProvider::new(collections.get::<Erc1155Supply>("erc1155_supply"));
+    let tokens = TokenProvider::new(collections.get::<DiscoveredToken>("tokens"));
+    let ron_balances = RonBalanceProvider::new(
+        collections.get::<RonBalance>("ron_balances"),
+        collections.get::<RonBalanceChange>("ron_balance_changes"),
+    );
+    let native_transfers =
+        NativeTransferProvider::new(collections.get::<NativeTransfer>("native_transfers"));
+    let balances = BalanceProvider::new(collections.get::<Balance>("balances"));
+    let nft_owners = NftOwnerProvider::new(collections.get::<NftOwner>("nft_owners"));
+    let price_history = PriceHistoryProvider::new(collections.get::<PriceHistory>("price_history"));
+    let token_metadata =
+        TokenMetadataProvider::new(collections.get::<TokenMetadata>("token_metadata"));
+    let query_cache = QueryCacheProvider::new(collections.get::<CachedQuery>("query_cache"));
+    let settings = SettingsProvider::new(collections.get::<Settings>("settings"));
+    let erc_sales = SaleProvider::new(collections.get::<Sale>("erc721_sales"));
+    let wallet_transfers =
+        WalletTransferProvider::new(collections.get::<WalletTransfer>("wallet_transfers"));
+    let whale_transfers =
+        WhaleTransferProvider::new(collections.get::<ERCTransfer>("whale_transfers"));
+    let auctions = AuctionProvider::new(collections.get::<Auction>("auctions"));
+    let pair_reserves = PairReserveProvider::new(collections.get::<PairReserve>("pair_reserves"));
+    let governance_events =
+        GovernanceEventProvider::new(collections.get::<GovernanceEvent>("governance_events"));
+    let staking_events =
+        StakingEventProvider::new(collections.get::<StakingEvent>("staking_events"));
+    let orders = OrderProvider::new(collections.get::<OrderEvent>("orders"));
+    let equipment_events =
+        EquipmentEventProvider::new(collections.get::<EquipmentEvent>("equipment_events"));
+    let axie_equipment =
+        AxieEquipmentProvider::new(collections.get::<AxieEquipment>("axie_equipment"));
+    let metrics = MetricsProvider::new(collections.get::<Metrics>("metrics"));
+    let token_daily = TokenDailyProvider::new(
+        collections.get::<TokenDaily>("token_daily"),
+        collections.get::<TokenAddress>("token_addresses"),
+    );
+    let marketplace_fees =
+        MarketplaceFeeProvider::new(collections.get::<MarketplaceFee>("marketplace_fees"));
+    let net_worth = NetWorthProvider::new(collections.get::<NetWorth>("net_worth"));
+    let spam_tokens = SpamTokenProvider::new(collections.get::<SpamToken>("spam_tokens"));
+    let decode_failures =
+        DecodeFailureProvider::new(collections.get::<DecodeFailure>("decode_failures"));
+    let receipt_anomalies =
+        ReceiptAnomalyProvider::new(collections.get::<ReceiptAnomaly>("receipt_anomalies"));
+    let missing_blocks =
+        MissingBlockProvider::new(collections.get::<MissingBlock>("missing_blocks"));
+    let failed_blocks = FailedBlockProvider::new(collections.get::<FailedBlock>("failed_blocks"));
+    let block_status = BlockStatusProvider::new(collections.get::<BlockStatus>("block_status"));
+    let backfill_chunks =
+        BackfillChunkProvider::new(collections.get::<BackfillChunk>("backfill_chunks"));
+    let tombstones = TombstoneProvider::new(collections.get::<Tombstone>("tombstones"));
+    let leases = LeaseProvider::new(collections.get::<Lease>("leases"));
+    let contracts = ContractProvider::new(collections.get::<ContractMetadata>("contracts"));
+    let contract_events =
+        ContractEventProvider::new(collections.get::<ContractEvent>("contract_events"));
 
-    let database = Database {
+    Database {
         wallets,
         transactions,
+        blocks,
         settings,
         erc_sales,
+        auctions,
         erc_transfers,
         erc1155_transfers,
+        wallet_transfers,
+        whale_transfers,
+        erc1155_supply,
+        tokens,
+        ron_balances,
+        native_transfers,
+        balances,
+        nft_owners,
+        price_history,
+        token_metadata,
+        query_cache,
+        pair_reserves,
+        governance_events,
+        staking_events,
+        orders,
+        equipment_events,
+        axie_equipment,
+        metrics,
+        token_daily,
+        marketplace_fees,
+        net_worth,
+        spam_tokens,
+        decode_failures,
+        receipt_anomalies,
+        missing_blocks,
+        failed_blocks,
+        block_status,
+        backfill_chunks,
+        tombstones,
+        leases,
+        contracts,
+        contract_events,
         _client: client,
         _database: db,
-    };
+    }
+}
 
-    database.create_indexes().await;
+/// Collections and fields holding a [`ContractType`].
+const ERC_FIELDS: [(&str, &str); 5] = [
+    ("erc_transfers", "erc"),
+    ("whale_transfers", "erc"),
+    ("wallet_transfers", "erc"),
+    ("tokens", "erc"),
+    ("decode_failures", "decoder"),
+];
 
-    database
-}
+const ERC_MIGRATION_KEY: &str = "migration.erc_lowercase";
 
 impl Database {
-    pub async fn create_indexes(&self) {
-        let create_settings = match self.settings.get(self.settings.index_setup_key()).await {
-            None => true,
-            Some(_) => false,
-        };
-
-        let create_wallets = match self.settings.get(self.wallets.index_setup_key()).await {
-            None => true,
-            Some(_) => false,
-        };
-        let create_transactions = match self.settings.get(self.transactions.index_setup_key()).await
-        {
-            None => true,
-            Some(_) => false,
-        };
-        let create_erc_transfers = match self
-            .settings
-            .get(self.erc_transfers.index_setup_key())
-            .await
-        {
-            None => true,
-            Some(_) => false,
-        };
-        let create_erc1155_transfers = match self
+    /// Whether the server is a replica set or sharded cluster, which multi-document transactions
+    /// require.
+    pub async fn supports_transactions(&self) -> bool {
+        let hello = self
             .settings
-            .get(self.erc1155_transfers.index_setup_key())
-            .await
-        {
-            None => true,
-            Some(_) => false,
-        };
-        let create_erc_sales = match self.settings.get(self.erc_sales.index_setup_key()).await {
-            None => true,
-            Some(_) => false,
-        };
+            .collection
+            .client()
+            .database("admin")
+            .run_command(doc! {"hello": 1}, None)
+            .await;
 
-        if create_settings {
-            for model in self.settings.index_model() {
-                self.settings
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
-                    .await
-                    .expect("Failed to create settings index!");
-            }
+        match hello {
+            Ok(hello) => hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"),
+            Err(_) => false,
+        }
+    }
 
-            self.settings
-                .set(self.settings.index_setup_key(), "1")
-                .await
-                .expect("Failed to complete setup!");
+    pub async fn start_session(&self) -> mongodb::error::Result<ClientSession> {
+        self.settings.collection.client().start_session(None).await
+    }
+
+    /// Rewrites the contract types stored with their variant names by older releases to the
+    /// lowercase names of [`ContractType::as_str`].
+    pub async fn migrate_erc(&self) {
+        if self.settings.get(ERC_MIGRATION_KEY).await.is_some() {
+            return;
         }
-        if create_wallets {
-            for model in self.wallets.index_model() {
-                self.wallets
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
+
+        for (collection, field) in ERC_FIELDS {
+            let collection = self._database.collection::<Document>(collection);
+            let mut migrated = 0;
+
+            for erc in ContractType::ALL {
+                let mut filter = Document::new();
+                filter.insert(field, erc.legacy_str());
+                let mut update = Document::new();
+                update.insert(field, erc.as_str());
+
+                migrated += collection
+                    .update_many(filter, doc! {"$set": update}, None)
                     .await
-                    .expect("Failed to create wallet index!");
+                    .unwrap_or_else(|_| panic!("Failed to migrate {}.{field}!", collection.name()))
+                    .modified_count;
             }
-            self.settings
-                .set(self.wallets.index_setup_key(), "1")
-                .await
-                .expect("Failed to complete setup!");
-        }
-        if create_transactions {
-            for model in self.transactions.index_model() {
-                self.transactions
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
-                    .await
-                    .expect("Failed to create transaction index!");
+
+            if migrated > 0 {
+                info!(
+                    "[INFO] Migrated {migrated} {} documents to lowercase {field}",
+                    collection.name()
+                );
             }
-            self.settings
-                .set(self.transactions.index_setup_key(), "1")
-                .await
-                .expect("Failed to complete setup!");
         }
-        if create_erc_transfers {
-            for model in self.erc_transfers.index_model() {
-                self.erc_transfers
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
-                    .await
-                    .expect("Failed to create erc_transfer index!");
-            }
-            self.settings
-                .set(self.erc_transfers.index_setup_key(), "1")
-                .await
-                .expect("Failed to complete setup!");
+
+        self.settings
+            .set(ERC_MIGRATION_KEY, "1")
+            .await
+            .expect("Failed to complete erc migration!");
+    }
+
+    pub(crate) async fn setup_indexes<T>(
+        &self,
+        provider: &impl Indexable,
+        collection: &Collection<T>,
+    ) {
+        if self
+            .settings
+            .get(provider.index_setup_key())
+            .await
+            .is_some()
+        {
+            return;
         }
-        if create_erc1155_transfers {
-            for model in self.erc1155_transfers.index_model() {
-                self.erc1155_transfers
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
-                    .await
-                    .expect("Failed to create erc1155_transfer index!");
-            }
-            self.settings
-                .set(self.erc1155_transfers.index_setup_key(), "1")
+
+        for model in provider.index_model() {
+            collection
+                .create_index(
+                    mongodb::IndexModel::builder()
+                        .keys(model.model)
+                        .options(model.options)
+                        .build(),
+                    None,
+                )
                 .await
-                .expect("Failed to complete setup!");
+                .unwrap_or_else(|_| panic!("Failed to create {} index!", collection.name()));
         }
-        if create_erc_sales {
-            for model in self.erc_sales.index_model() {
-                self.erc_sales
-                    .collection
-                    .create_index(
-                        mongodb::IndexModel::builder()
-                            .keys(model.model)
-                            .options(model.options)
-                            .build(),
-                        None,
-                    )
-                    .await
-                    .expect("Failed to create erc_sales index!");
-            }
-            self.settings
-                .set(self.erc_sales.index_setup_key(), "1")
-                .await
-                .expect("Failed to complete setup!");
+
+        self.settings
+            .set(provider.index_setup_key(), "1")
+            .await
+            .expect("Failed to complete setup!");
+    }
+
+    pub async fn create_indexes(&self) {
+        self.setup_indexes(&self.settings, &self.settings.collection)
+            .await;
+        self.migrate_erc().await;
+        self.setup_indexes(&self.wallets, &self.wallets.collection)
+            .await;
+        self.setup_indexes(&self.transactions, &self.transactions.collection)
+            .await;
+        self.setup_indexes(&self.blocks, &self.blocks.collection)
+            .await;
+        self.setup_indexes(&self.erc_transfers, &self.erc_transfers.collection)
+            .await;
+        self.setup_indexes(&self.erc1155_transfers, &self.erc1155_transfers.collection)
+            .await;
+        self.setup_indexes(&self.wallet_transfers, &self.wallet_transfers.collection)
+            .await;
+        self.setup_indexes(&self.whale_transfers, &self.whale_transfers.collection)
+            .await;
+        self.setup_indexes(&self.erc1155_supply, &self.erc1155_supply.collection)
+            .await;
+        if self
+            .settings
+            .get(self.erc_sales.index_setup_key())
+            .await
+            .is_none()
+        {
+            self.erc_sales.drop_unique_transaction_index().await;
         }
+        self.setup_indexes(&self.erc_sales, &self.erc_sales.collection)
+            .await;
+        self.setup_indexes(&self.auctions, &self.auctions.collection)
+            .await;
+        self.setup_indexes(&self.tokens, &self.tokens.collection)
+            .await;
+        self.setup_indexes(&self.ron_balances, &self.ron_balances.collection)
+            .await;
+        self.setup_indexes(&RonBalanceChangeIndexes, &self.ron_balances.changes)
+            .await;
+        self.setup_indexes(&self.native_transfers, &self.native_transfers.collection)
+            .await;
+        self.setup_indexes(&self.balances, &self.balances.collection)
+            .await;
+        self.setup_indexes(&self.nft_owners, &self.nft_owners.collection)
+            .await;
+        self.setup_indexes(&self.price_history, &self.price_history.collection)
+            .await;
+        self.setup_indexes(&self.token_metadata, &self.token_metadata.collection)
+            .await;
+        self.setup_indexes(&self.query_cache, &self.query_cache.collection)
+            .await;
+        self.setup_indexes(&self.pair_reserves, &self.pair_reserves.collection)
+            .await;
+        self.setup_indexes(&self.governance_events, &self.governance_events.collection)
+            .await;
+        self.setup_indexes(&self.staking_events, &self.staking_events.collection)
+            .await;
+        self.setup_indexes(&self.orders, &self.orders.collection)
+            .await;
+        self.setup_indexes(&self.equipment_events, &self.equipment_events.collection)
+            .await;
+        self.setup_indexes(&self.axie_equipment, &self.axie_equipment.collection)
+            .await;
+        self.setup_indexes(&self.metrics, &self.metrics.collection)
+            .await;
+        self.setup_indexes(&self.token_daily, &self.token_daily.collection)
+            .await;
+        self.setup_indexes(&TokenAddressIndexes, &self.token_daily.addresses)
+            .await;
+        self.setup_indexes(&self.marketplace_fees, &self.marketplace_fees.collection)
+            .await;
+        self.setup_indexes(&self.net_worth, &self.net_worth.collection)
+            .await;
+        self.setup_indexes(&self.spam_tokens, &self.spam_tokens.collection)
+            .await;
+        self.setup_indexes(&self.decode_failures, &self.decode_failures.collection)
+            .await;
+        self.setup_indexes(&self.receipt_anomalies, &self.receipt_anomalies.collection)
+            .await;
+        self.setup_indexes(&self.missing_blocks, &self.missing_blocks.collection)
+            .await;
+        self.setup_indexes(&self.failed_blocks, &self.failed_blocks.collection)
+            .await;
+        self.setup_indexes(&self.block_status, &self.block_status.collection)
+            .await;
+        self.setup_indexes(&self.backfill_chunks, &self.backfill_chunks.collection)
+            .await;
+        self.setup_indexes(&self.tombstones, &self.tombstones.collection)
+            .await;
+        self.setup_indexes(&self.leases, &self.leases.collection)
+            .await;
+        self.setup_indexes(&self.contracts, &self.contracts.collection)
+            .await;
+        self.setup_indexes(&self.contract_events, &self.contract_events.collection)
+            .await;
     }
 }
@@ -0,0 +1,255 @@
+use std::str::FromStr;
+use std::time::Instant;
+
+use futures::future::join_all;
+use log::{info, warn};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use web3::transports::Batch;
+use web3::types::{BlockId, BlockNumber, FilterBuilder, H160};
+use web3::Transport;
+
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+use crate::ronin::{Provider, Ronin};
+
+/// Block ranges tried per eth_getLogs request, smallest first.
+const LOGS_RANGES: [u64; 7] = [100, 500, 1000, 2000, 5000, 10_000, 50_000];
+
+/// Requests sent in the batch support check.
+const BATCH_SIZE: u64 = 10;
+
+/// Benchmark of an RPC endpoint, stored under `probe.<endpoint>` and read by the indexer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderProfile {
+    pub endpoint: String,
+    pub probed_at: DateTime,
+    pub head: Block,
+    /// Whether the node serves eth_getBlockReceipts.
+    pub block_receipts: bool,
+    /// Whether the node answers JSON-RPC batch requests.
+    pub batch_requests: bool,
+    /// Receipts loaded per second at the recommended concurrency.
+    pub receipts_per_second: f64,
+    /// Blocks scanned per second by eth_getLogs over the tracked contracts at the largest
+    /// accepted range.
+    pub logs_blocks_per_second: f64,
+    /// Largest block range the node accepted per eth_getLogs request.
+    pub max_logs_range: u64,
+    /// `stream` for the receipt based indexer, `logs` for `--logs-mode`.
+    pub recommended_mode: String,
+    pub recommended_logs_range: u64,
+    pub recommended_workers: usize,
+}
+
+impl SettingsValue for ProviderProfile {}
+
+fn settings_key(endpoint: &str) -> String {
+    f!("probe.{endpoint}")
+}
+
+/// Stored benchmark of `endpoint`, if it was probed.
+pub async fn profile(database: &Database, endpoint: &str) -> Option<ProviderProfile> {
+    database.settings.load(&settings_key(endpoint)).await
+}
+
+/// Loads the receipts of `block`, over eth_getBlockReceipts when `block_receipts` is set and one
+/// eth_getTransactionReceipt per transaction otherwise. Returns the number of receipts.
+async fn load_receipts(provider: &Provider, block: Block, block_receipts: bool) -> Option<usize> {
+    if block_receipts {
+        let receipts = provider
+            .transport()
+            .execute(
+                "eth_getBlockReceipts",
+                vec![serde_json::Value::String(f!("0x{block:x}"))],
+            )
+            .await
+            .ok()?;
+
+        return receipts.as_array().map(Vec::len);
+    }
+
+    let transactions = provider
+        .eth()
+        .block(BlockId::Number(BlockNumber::from(block)))
+        .await
+        .ok()??
+        .transactions;
+    let receipts = join_all(
+        transactions
+            .iter()
+            .map(|hash| provider.eth().transaction_receipt(*hash)),
+    )
+    .await;
+
+    receipts
+        .iter()
+        .try_fold(0, |loaded, receipt| match receipt {
+            Ok(Some(_)) => Some(loaded + 1),
+            _ => None,
+        })
+}
+
+/// Receipts per second loading `blocks` with `concurrency` blocks in flight, and the receipts
+/// loaded.
+async fn receipts_per_second(
+    provider: &Provider,
+    blocks: &[Block],
+    concurrency: usize,
+    block_receipts: bool,
+) -> (f64, usize) {
+    let started = Instant::now();
+    let mut receipts = 0;
+
+    for chunk in blocks.chunks(concurrency) {
+        for loaded in join_all(
+            chunk
+                .iter()
+                .map(|block| load_receipts(provider, *block, block_receipts)),
+        )
+        .await
+        {
+            receipts += loaded.unwrap_or_default();
+        }
+    }
+
+    (
+        receipts as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+        receipts,
+    )
+}
+
+async fn batch_requests(provider: &Provider, head: Block) -> bool {
+    let batch = Batch::new(provider.transport().to_owned());
+    let requests: Vec<_> = (0..BATCH_SIZE)
+        .map(|offset| {
+            batch.execute(
+                "eth_getBlockByNumber",
+                vec![
+                    serde_json::Value::String(f!("0x{:x}", head.saturating_sub(offset))),
+                    serde_json::Value::Bool(false),
+                ],
+            )
+        })
+        .collect();
+
+    match batch.submit_batch().await {
+        Ok(results) => results.len() == requests.len() && results.iter().all(Result::is_ok),
+        Err(_) => false,
+    }
+}
+
+/// Largest accepted range of [`LOGS_RANGES`] and the blocks per second scanned with it.
+async fn logs_throughput(provider: &Provider, head: Block) -> (u64, f64) {
+    let addresses: Vec<H160> = Ronin::contract_list()
+        .keys()
+        .filter_map(|address| H160::from_str(address).ok())
+        .collect();
+    let mut accepted = (0, 0.0);
+
+    for range in LOGS_RANGES {
+        let filter = FilterBuilder::default()
+            .from_block(BlockNumber::from(head.saturating_sub(range - 1)))
+            .to_block(BlockNumber::from(head))
+            .address(addresses.to_owned())
+            .build();
+
+        let started = Instant::now();
+        match provider.eth().logs(filter).await {
+            Ok(_) => {
+                accepted = (
+                    range,
+                    range as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+                )
+            }
+            Err(err) => {
+                info!("[INFO] eth_getLogs over {range} blocks failed: {err}");
+                break;
+            }
+        }
+    }
+
+    accepted
+}
+
+/// Benchmarks `endpoint` over the `sample_blocks` blocks below its head: receipt throughput at
+/// up to `max_concurrency` blocks in flight, eth_getBlockReceipts, batch requests and the
+/// eth_getLogs range limit. Recommends the indexing mode and concurrency for the node and stores
+/// the profile, which `stream` and `--logs-mode` use to skip unsupported calls and to cap
+/// `--logs-range`.
+pub async fn probe(
+    database: &Database,
+    endpoint: &str,
+    sample_blocks: u64,
+    max_concurrency: usize,
+) -> ProviderProfile {
+    let provider = Ronin::connect(endpoint).await;
+    let head = provider
+        .eth()
+        .block_number()
+        .await
+        .expect("Failed to retrieve head block number from chain!")
+        .as_u64();
+    let blocks: Vec<Block> = (head.saturating_sub(sample_blocks)..head).collect();
+
+    let block_receipts = match blocks.first() {
+        Some(block) => load_receipts(&provider, *block, true).await.is_some(),
+        None => false,
+    };
+    let batch_requests = batch_requests(&provider, head).await;
+
+    let mut concurrency = 1;
+    let mut recommended = (1, 0.0);
+    let mut sample_receipts = 0;
+    while concurrency <= max_concurrency.max(1) {
+        let (throughput, receipts) =
+            receipts_per_second(&provider, &blocks, concurrency, block_receipts).await;
+        sample_receipts = receipts;
+        info!("[INFO] {endpoint}: {throughput:.1} receipts/s with {concurrency} blocks in flight");
+
+        // More concurrency has to pay off by at least 10%.
+        if throughput < recommended.1 * 1.1 {
+            break;
+        }
+        recommended = (concurrency, throughput);
+        concurrency *= 2;
+    }
+    let (recommended_workers, receipts_per_second) = recommended;
+
+    let (max_logs_range, logs_blocks_per_second) = logs_throughput(&provider, head).await;
+    if max_logs_range == 0 {
+        warn!("[WARN] {endpoint} does not serve eth_getLogs, logs mode is not available");
+    }
+
+    // Logs mode skips transactions and wallet updates, it is only worth it for a backfill when it
+    // scans blocks an order of magnitude faster than the receipts are loaded.
+    let receipts_per_block = sample_receipts.max(1) as f64 / blocks.len().max(1) as f64;
+    let blocks_per_second = receipts_per_second / receipts_per_block;
+    let recommended_mode = match logs_blocks_per_second > blocks_per_second * 10.0 {
+        true => "logs",
+        false => "stream",
+    };
+
+    let profile = ProviderProfile {
+        endpoint: endpoint.to_string(),
+        probed_at: DateTime::now(),
+        head,
+        block_receipts,
+        batch_requests,
+        receipts_per_second,
+        logs_blocks_per_second,
+        max_logs_range,
+        recommended_mode: recommended_mode.to_string(),
+        recommended_logs_range: max_logs_range.max(1),
+        recommended_workers,
+    };
+
+    database
+        .settings
+        .store(&settings_key(endpoint), &profile)
+        .await
+        .expect("Failed to store provider profile!");
+
+    profile
+}
@@ -0,0 +1,129 @@
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use jsonrpc_core as rpc;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use web3::{error, BatchTransport, RequestId, Transport};
+
+/// `--rpc-max-qps`, shared by every provider and worker of the process.
+static RPC_LIMITER: OnceLock<Option<Arc<RateLimiter>>> = OnceLock::new();
+
+tokio::task_local! {
+    /// `--rpc-worker-qps` of the worker polling the request.
+    static WORKER_LIMITER: Option<Arc<RateLimiter>>;
+}
+
+/// Spaces requests evenly so no more than `qps` are sent per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(qps: u32) -> Option<Arc<RateLimiter>> {
+        match qps {
+            0 => None,
+            qps => Some(Arc::new(RateLimiter {
+                interval: Duration::from_secs(1) / qps,
+                next: Mutex::new(Instant::now()),
+            })),
+        }
+    }
+
+    /// Waits until `requests` more requests fit into the budget.
+    async fn acquire(&self, requests: u32) {
+        let at = {
+            let mut next = self.next.lock().await;
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval * requests;
+            at
+        };
+
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+/// Sets the process wide request budget, 0 for no limit.
+pub fn max_qps(qps: u32) {
+    if RPC_LIMITER.set(RateLimiter::new(qps)).is_err() {
+        panic!("RPC rate limit set twice!");
+    }
+}
+
+/// Runs `future` as a worker with its own budget of `qps` requests per second on top of the
+/// process wide one, 0 for no limit.
+pub async fn worker<F: Future>(qps: u32, future: F) -> F::Output {
+    WORKER_LIMITER.scope(RateLimiter::new(qps), future).await
+}
+
+async fn acquire(requests: u32) {
+    if let Some(limiter) = RPC_LIMITER.get().and_then(Option::as_ref) {
+        limiter.acquire(requests).await;
+    }
+    if let Ok(Some(limiter)) = WORKER_LIMITER.try_with(Option::clone) {
+        limiter.acquire(requests).await;
+    }
+}
+
+/// Transport waiting for the request budget before sending, so shared or third-party providers
+/// are not flooded.
+#[derive(Debug, Clone)]
+pub struct RateLimited<T> {
+    inner: T,
+}
+
+impl<T> RateLimited<T> {
+    pub fn new(inner: T) -> RateLimited<T> {
+        RateLimited { inner }
+    }
+}
+
+impl<T> Transport for RateLimited<T>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send + 'static,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.inner.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let inner = self.inner.clone();
+
+        async move {
+            acquire(1).await;
+            inner.send(id, request).await
+        }
+        .boxed()
+    }
+}
+
+impl<T> BatchTransport for RateLimited<T>
+where
+    T: BatchTransport + Send + Sync + 'static,
+    T::Out: Send + 'static,
+    T::Batch: Send + 'static,
+{
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let inner = self.inner.clone();
+        let requests: Vec<(RequestId, rpc::Call)> = requests.into_iter().collect();
+
+        async move {
+            acquire(requests.len() as u32).await;
+            inner.send_batch(requests).await
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{info, warn};
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::FindOneOptions;
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+use web3::types::U256;
+
+use crate::analysis::ZERO_ADDRESS;
+use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::ron_balance::RonBalanceChange;
+use crate::mongo::collections::tombstone::{OrphanedAt, Tombstone};
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+use crate::ronin::{ContractType, LOGS_MODE_KEY, STREAM_KEY};
+use crate::tiering::{self, TIERED_COLLECTIONS};
+
+/// Collections keyed by `block` that are rolled back on a reorg, besides
+/// [`TIERED_COLLECTIONS`]. What they added to the aggregates is taken back out by
+/// [`reverse_aggregates`] first.
+const ROLLBACK_COLLECTIONS: [&str; 4] = [
+    "blocks",
+    "whale_transfers",
+    "receipt_anomalies",
+    "ron_balance_changes",
+];
+
+/// Collections keyed by `block` that indexing a range writes again, deleted by [`rollback_range`]
+/// besides the reorg collections. Failed blocks are kept for `retry-failed-blocks`.
+const RANGE_COLLECTIONS: [&str; 4] = [
+    "contract_events",
+    "decode_failures",
+    "missing_blocks",
+    "block_status",
+];
+
+/// Documents of `collection` matching `filter`.
+async fn read<T>(collection: &Collection<T>, filter: &Document) -> Vec<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let mut cursor = collection
+        .find(filter.to_owned(), None)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to query {}: {err}", collection.name()));
+
+    let mut documents = vec![];
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()))
+    {
+        documents.push(
+            cursor
+                .deserialize_current()
+                .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name())),
+        );
+    }
+
+    documents
+}
+
+/// Takes the ERC20 transfers back out of `balances`, skipping the amounts the stream skips.
+async fn reverse_balances(database: &Database, transfers: &[ERCTransfer]) {
+    let mut deltas: HashMap<(&String, &String), i128> = HashMap::new();
+
+    for transfer in transfers.iter().filter(|t| t.erc == ContractType::ERC20) {
+        let value = U256::from_str_radix(&transfer.value_or_token_id, 16).unwrap_or_default();
+        if value.bits() > 126 {
+            continue;
+        }
+
+        let value = value.as_u128() as i128;
+        if transfer.from != ZERO_ADDRESS {
+            *deltas.entry((&transfer.from, &transfer.token)).or_default() += value;
+        }
+        if transfer.to != ZERO_ADDRESS {
+            *deltas.entry((&transfer.to, &transfer.token)).or_default() -= value;
+        }
+    }
+
+    let mut pool = database.balances.get_pool();
+    for ((wallet, token), delta) in deltas {
+        pool.update(database.balances.update(wallet, token, delta, 0));
+    }
+    pool.commit(false)
+        .await
+        .expect("Failed to roll back balances");
+}
+
+/// Takes the balance changes back out of `ron_balances`.
+async fn reverse_ron_balances(database: &Database, changes: &[RonBalanceChange]) {
+    let mut deltas: HashMap<&String, i128> = HashMap::new();
+
+    for change in changes {
+        let received = U256::from_str_radix(&change.received, 16).unwrap_or_default();
+        let spent = U256::from_str_radix(&change.spent, 16).unwrap_or_default();
+        if received.bits() > 126 || spent.bits() > 126 {
+            continue;
+        }
+
+        *deltas.entry(&change.address).or_default() +=
+            spent.as_u128() as i128 - received.as_u128() as i128;
+    }
+
+    let mut pool = database.ron_balances.get_pool();
+    for (address, delta) in deltas {
+        pool.update(database.ron_balances.update(address, delta, 0));
+    }
+    pool.commit(false)
+        .await
+        .expect("Failed to roll back RON balances");
+}
+
+/// Takes the ERC1155 transfers back out of `erc1155_supply` and the holdings in `nft_owners`.
+async fn reverse_erc1155(database: &Database, transfers: &[ERC1155Transfer]) {
+    let mut supply: HashMap<(&String, &String), (i64, i64)> = HashMap::new();
+    let mut holdings: HashMap<(&String, &String, &String), i64> = HashMap::new();
+
+    for transfer in transfers {
+        let value = match U256::from_str_radix(&transfer.value, 16) {
+            Ok(value) if value <= U256::from(i64::MAX) => value.as_u64() as i64,
+            _ => continue,
+        };
+
+        let token = (&transfer.token, &transfer.token_id);
+        match transfer.from == ZERO_ADDRESS {
+            true => supply.entry(token).or_default().0 -= value,
+            false => {
+                *holdings
+                    .entry((token.0, token.1, &transfer.from))
+                    .or_default() += value
+            }
+        }
+        match transfer.to == ZERO_ADDRESS {
+            true => supply.entry(token).or_default().1 -= value,
+            false => {
+                *holdings
+                    .entry((token.0, token.1, &transfer.to))
+                    .or_default() -= value
+            }
+        }
+    }
+
+    let mut supply_pool = database.erc1155_supply.get_pool();
+    for ((token, token_id), (minted, burned)) in supply {
+        supply_pool.update(
+            database
+                .erc1155_supply
+                .update(token, token_id, minted, burned, 0),
+        );
+    }
+    supply_pool
+        .commit(false)
+        .await
+        .expect("Failed to roll back erc 1155 supply");
+
+    let mut owner_pool = database.nft_owners.get_pool();
+    for ((token, token_id, owner), delta) in holdings {
+        owner_pool.update(database.nft_owners.update(token, token_id, owner, delta, 0));
+    }
+    owner_pool
+        .commit(false)
+        .await
+        .expect("Failed to roll back nft owners");
+}
+
+/// Gives the ERC721 tokens last moved within `from_block..=to_block` back to the receiver of
+/// their latest transfer before the range, tokens minted within it lose their owner.
+async fn restore_erc721_owners(
+    database: &Database,
+    transfers: &[ERCTransfer],
+    from_block: Block,
+    to_block: Block,
+) {
+    let tokens: HashSet<(&String, &String)> = transfers
+        .iter()
+        .filter(|t| t.erc == ContractType::ERC721)
+        .map(|t| (&t.token, &t.value_or_token_id))
+        .collect();
+
+    for (token, token_id) in tokens {
+        let filter = doc! {"token": token, "token_id": token_id};
+        let owner = database
+            .nft_owners
+            .collection
+            .find_one(filter.to_owned(), None)
+            .await
+            .expect("Failed to query nft owners!");
+        // Owners moved by a later block keep it.
+        if owner.is_none_or(|owner| owner.last_block < from_block || owner.last_block > to_block) {
+            continue;
+        }
+
+        let previous = tiering::find_one(
+            database,
+            |db| db.erc_transfers.collection.clone(),
+            doc! {
+                "token": token,
+                "value_or_token_id": token_id,
+                "block": {"$lt": from_block as i64}
+            },
+            FindOneOptions::builder()
+                .sort(doc! {"block": -1i64, "log_index_num": -1i64})
+                .build(),
+        )
+        .await
+        .expect("Failed to query erc transfers!");
+
+        match previous {
+            Some(previous) => database
+                .nft_owners
+                .collection
+                .update_one(
+                    filter,
+                    doc! {"$set": {"owner": &previous.to, "last_block": previous.block as i64}},
+                    None,
+                )
+                .await
+                .map(|_| ()),
+            None => database
+                .nft_owners
+                .collection
+                .delete_one(filter, None)
+                .await
+                .map(|_| ()),
+        }
+        .expect("Failed to roll back nft owner");
+    }
+}
+
+/// Removes the sales of `from_block..=to_block` from `price_history`, deleting the histories left
+/// without sales.
+async fn reverse_price_history(database: &Database, from_block: Block, to_block: Block) {
+    let (from, to) = (from_block as i64, to_block as i64);
+    let collection = &database.price_history.collection;
+
+    collection
+        .update_many(
+            doc! {"sales": {"$elemMatch": {"block": {"$gte": from, "$lte": to}}}},
+            vec![
+                doc! {
+                    "$set": {
+                        "sales": {
+                            "$filter": {
+                                "input": "$sales",
+                                "cond": {
+                                    "$or": [
+                                        {"$lt": ["$$this.block", from]},
+                                        {"$gt": ["$$this.block", to]}
+                                    ]
+                                }
+                            }
+                        }
+                    }
+                },
+                doc! {
+                    "$set": {
+                        "sale_count": {"$toLong": {"$size": "$sales"}},
+                        "first_block": {"$min": "$sales.block"},
+                        "last_block": {"$max": "$sales.block"}
+                    }
+                },
+            ],
+            None,
+        )
+        .await
+        .expect("Failed to roll back price history");
+    collection
+        .delete_many(doc! {"sales": {"$size": 0}}, None)
+        .await
+        .expect("Failed to roll back price history");
+}
+
+/// Takes what the documents of blocks `from_block..=to_block` added to the aggregates back out,
+/// before the documents are rolled back: ERC20 and RON balances, ERC1155 supply, NFT owners and
+/// price history. Only aggregates that exist are updated, so features that never ran are left
+/// alone. Only the hot database is read, like the rollbacks only touch it.
+async fn reverse_aggregates(database: &Database, from_block: Block, to_block: Block) {
+    let filter = doc! {"block": {"$gte": from_block as i64, "$lte": to_block as i64}};
+
+    let transfers = read(&database.erc_transfers.collection, &filter).await;
+    reverse_balances(database, &transfers).await;
+    restore_erc721_owners(database, &transfers, from_block, to_block).await;
+
+    let transfers = read(&database.erc1155_transfers.collection, &filter).await;
+    reverse_erc1155(database, &transfers).await;
+
+    let changes = read(&database.ron_balances.changes, &filter).await;
+    reverse_ron_balances(database, &changes).await;
+
+    reverse_price_history(database, from_block, to_block).await;
+}
+
+/// Moves the documents of `collection` above `fork` to `tombstones`. Returns the number moved.
+async fn rollback_collection(
+    database: &Database,
+    collection: &Collection<Document>,
+    fork: Block,
+    orphaned_at: &OrphanedAt,
+) -> u64 {
+    let filter = doc! {"block": {"$gt": fork as i64}};
+    let mut cursor = collection
+        .find(filter.to_owned(), None)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to query {}: {err}", collection.name()));
+
+    let mut tombstones: Vec<Tombstone> = vec![];
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()))
+    {
+        let document: Document = cursor
+            .deserialize_current()
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", collection.name()));
+        let block = match document.get("block") {
+            Some(Bson::Int64(block)) => *block as Block,
+            Some(Bson::Int32(block)) => *block as Block,
+            _ => continue,
+        };
+
+        tombstones.push(Tombstone {
+            collection: collection.name().to_string(),
+            block,
+            orphaned_at: orphaned_at.to_owned(),
+            created_at: DateTime::now(),
+            document,
+        });
+    }
+
+    if tombstones.is_empty() {
+        return 0;
+    }
+
+    database
+        .tombstones
+        .collection
+        .insert_many(&tombstones, None)
+        .await
+        .expect("Failed to store tombstones!");
+    collection
+        .delete_many(filter, None)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to roll back {}: {err}", collection.name()));
+
+    tombstones.len() as u64
+}
+
+/// Rolls the indexed documents back to `fork`, the last block that is still canonical. Instead of
+/// being deleted, the documents of the orphaned blocks are moved to `tombstones`, so queries no
+/// longer see them while the record of what the chain briefly contained is kept until
+/// `prune-tombstones` removes it.
+pub async fn rollback(database: &Database, fork: Block, orphaned_at: OrphanedAt) -> u64 {
+    reverse_aggregates(database, fork + 1, i64::MAX as Block).await;
+
+    let mut moved = 0;
+
+    for name in TIERED_COLLECTIONS.iter().chain(ROLLBACK_COLLECTIONS.iter()) {
+        let collection = database._database.collection::<Document>(name);
+        moved += rollback_collection(database, &collection, fork, &orphaned_at).await;
+    }
+
+    warn!(
+        "[WARN] Reorg at block {}: rolled back {moved} documents after block {fork}",
+        orphaned_at.block
+    );
+
+    moved
+}
+
+/// Deletes the documents of blocks `from_block..=to_block` from every collection keyed by `block`
+/// and rewinds the stream, logs mode and backfill chunk checkpoints to `from_block - 1`, so the
+/// next run indexes the range again. Unlike a reorg nothing is kept in `tombstones`. Balances,
+/// owners, supply and price history are rolled back with the documents, the equipment state and
+/// the rollups are not. Only the hot database is touched, so ranges reaching below
+/// [`tiering::COLD_CUTOFF_KEY`] are refused.
+pub async fn rollback_range(database: &Database, from_block: Block, to_block: Block) {
+    if let Some(cutoff) = database
+        .settings
+        .load::<Block>(tiering::COLD_CUTOFF_KEY)
+        .await
+    {
+        if from_block < cutoff {
+            panic!("Blocks below {cutoff} are in cold storage, roll back from block {cutoff} on");
+        }
+    }
+
+    reverse_aggregates(database, from_block, to_block).await;
+
+    let filter = doc! {"block": {"$gte": from_block as i64, "$lte": to_block as i64}};
+    let mut deleted = 0;
+
+    for name in TIERED_COLLECTIONS
+        .iter()
+        .chain(ROLLBACK_COLLECTIONS.iter())
+        .chain(RANGE_COLLECTIONS.iter())
+    {
+        let result = database
+            ._database
+            .collection::<Document>(name)
+            .delete_many(filter.to_owned(), None)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to roll back {name}: {err}"));
+        info!("[INFO] Deleted {} {name} documents", result.deleted_count);
+        deleted += result.deleted_count;
+    }
+
+    let resume = from_block.saturating_sub(1);
+    for key in [STREAM_KEY, LOGS_MODE_KEY] {
+        if database
+            .settings
+            .load::<u64>(key)
+            .await
+            .is_some_and(|last_block| last_block > resume)
+        {
+            database
+                .settings
+                .store(key, &resume)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to reset {key}: {err}"));
+        }
+    }
+    database
+        .backfill_chunks
+        .rewind(from_block)
+        .await
+        .expect("Failed to reset backfill chunks!");
+
+    println!("Rolled back {deleted} documents of blocks {from_block}..={to_block}");
+}
+
+/// Deletes the tombstones older than `days` days.
+pub async fn prune(database: &Database, days: u64) {
+    let before = DateTime::from_millis(
+        DateTime::now().timestamp_millis() - (days * 24 * 60 * 60 * 1000) as i64,
+    );
+
+    let pruned = database
+        .tombstones
+        .prune(before)
+        .await
+        .expect("Failed to prune tombstones!");
+
+    println!("Pruned {pruned} tombstones older than {days} days");
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use log::info;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::transaction_pool::Pool;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+
+/// Settings key holding the report of the last completed logs mode backfill.
+pub const BACKFILL_REPORT_KEY: &str = "logs_mode.report";
+
+/// Number of slowest blocks kept in a report.
+const SLOWEST_BLOCKS: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkReport {
+    pub from: Block,
+    pub to: Block,
+    pub receipts: usize,
+    pub millis: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockTiming {
+    pub block: Block,
+    pub millis: u64,
+}
+
+/// Completion report of a chunked backfill, written once the stop block is reached so runs can be
+/// compared with each other.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackfillReport {
+    pub from_block: Block,
+    pub to_block: Block,
+    pub started_at: String,
+    pub finished_at: String,
+    pub millis: u64,
+    pub chunks: Vec<ChunkReport>,
+    /// Documents written per collection.
+    pub collections: BTreeMap<String, u64>,
+    /// Documents already present per collection.
+    pub duplicates: BTreeMap<String, u64>,
+    /// Documents a pool failed to write.
+    pub errors: u64,
+    /// Logs stored in `decode_failures`.
+    pub decode_failures: u64,
+    pub slowest_blocks: Vec<BlockTiming>,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl SettingsValue for BackfillReport {}
+
+fn rfc3339(date: DateTime) -> String {
+    date.try_to_rfc3339_string().unwrap_or_default()
+}
+
+impl BackfillReport {
+    pub fn new(from_block: Block, to_block: Block) -> BackfillReport {
+        BackfillReport {
+            from_block,
+            to_block,
+            started_at: rfc3339(DateTime::now()),
+            finished_at: String::new(),
+            millis: 0,
+            chunks: vec![],
+            collections: BTreeMap::new(),
+            duplicates: BTreeMap::new(),
+            errors: 0,
+            decode_failures: 0,
+            slowest_blocks: vec![],
+            started: Some(Instant::now()),
+        }
+    }
+
+    pub fn chunk(&mut self, from: Block, to: Block, receipts: usize, elapsed: Duration) {
+        self.chunks.push(ChunkReport {
+            from,
+            to,
+            receipts,
+            millis: elapsed.as_millis() as u64,
+        });
+    }
+
+    /// Records the decode time of a block, keeping the slowest ones.
+    pub fn block(&mut self, block: Block, elapsed: Duration) {
+        let millis = elapsed.as_millis() as u64;
+
+        if self.slowest_blocks.len() == SLOWEST_BLOCKS
+            && self.slowest_blocks.last().map(|b| b.millis).unwrap_or(0) >= millis
+        {
+            return;
+        }
+
+        self.slowest_blocks.push(BlockTiming { block, millis });
+        self.slowest_blocks
+            .sort_by_key(|b| std::cmp::Reverse(b.millis));
+        self.slowest_blocks.truncate(SLOWEST_BLOCKS);
+    }
+
+    /// Adds the outcome of a committed pool.
+    pub fn pool<T>(&mut self, pool: &Pool<T>)
+    where
+        T: Serialize + Clone + Eq + PartialEq,
+    {
+        *self.collections.entry(pool.name().to_owned()).or_default() +=
+            pool.inserted().len() as u64;
+        *self.duplicates.entry(pool.name().to_owned()).or_default() += pool.duplicates() as u64;
+        self.errors += pool.errors() as u64;
+    }
+
+    /// Stores the report in the settings and, if given, writes it as JSON to `path`.
+    pub async fn finish(mut self, database: &Database, path: Option<&Path>) {
+        self.finished_at = rfc3339(DateTime::now());
+        self.millis = self
+            .started
+            .map(|started| started.elapsed().as_millis() as u64)
+            .unwrap_or(0);
+
+        database
+            .settings
+            .store(BACKFILL_REPORT_KEY, &self)
+            .await
+            .expect("Failed to store backfill report!");
+
+        if let Some(path) = path {
+            let json = serde_json::to_string_pretty(&self).expect("Failed to serialize report!");
+            fs::write(path, json)
+                .unwrap_or_else(|err| panic!("Failed to write report {}: {err}", path.display()));
+        }
+
+        info!(
+            "[INFO] Backfilled {}..{} in {} chunks ({} ms): {} documents, {} errors, {} decode failures",
+            self.from_block,
+            self.to_block,
+            self.chunks.len(),
+            self.millis,
+            self.collections.values().sum::<u64>(),
+            self.errors,
+            self.decode_failures
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
+use crate::cli_args::Args;
+
+/// Exponential backoff of RPC calls: the delay starts at `--retry-backoff-ms`, doubles per attempt
+/// up to `--retry-max-backoff-ms` and varies by up to `--retry-jitter` of itself, so replicas
+/// hitting the same node do not retry in lockstep.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub retries: u32,
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+}
+
+impl From<&Args> for Backoff {
+    fn from(args: &Args) -> Self {
+        Backoff {
+            retries: args.rpc_retries,
+            base: Duration::from_millis(args.retry_backoff_ms),
+            max: Duration::from_millis(args.retry_max_backoff_ms),
+            jitter: args.retry_jitter.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Default for Backoff {
+    /// The defaults of the retry flags.
+    fn default() -> Self {
+        Backoff {
+            retries: 5,
+            base: Duration::from_millis(500),
+            max: Duration::from_millis(30_000),
+            jitter: 0.25,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before retry `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max);
+
+        // The sub-second clock is random enough to spread the retries.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let spread = (nanos as f64 / 1_000_000_000.0) * 2.0 - 1.0;
+
+        delay.mul_f64((1.0 + spread * self.jitter).max(0.0))
+    }
+
+    /// Runs `call` until it succeeds or `retries` retries failed. Returns the last error.
+    pub async fn retry<T, E, F, Fut>(&self, what: &str, mut call: F) -> Result<T, String>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt >= self.retries => return Err(err.to_string()),
+                Err(err) => {
+                    attempt += 1;
+                    let delay = self.delay(attempt);
+                    warn!(
+                        "[WARN] Failed to load {what} (attempt {attempt}): {err}, retrying in {}ms",
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::{HashMap, HashSet};
+
+use log::info;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOneOptions;
+use serde::{Deserialize, Serialize};
+use web3::types::U256;
+
+use crate::analysis::{block_range_filter, ZERO_ADDRESS};
+use crate::mongo::collections::axie_sale::Sale;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::marketplace_fee::{FeeSource, MarketplaceFee};
+use crate::mongo::collections::net_worth::{Holding, NetWorth};
+use crate::mongo::collections::pair_reserve::PairReserve;
+use crate::mongo::collections::ron_balance::RonBalanceChange;
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::token_daily::{TokenAddress, TokenDaily};
+use crate::mongo::collections::{Address, Block};
+use crate::mongo::{update_batch_size, Database};
+use crate::ronin::ContractType;
+use crate::tiering;
+
+const DAY_MILLIS: i64 = 86_400_000;
+
+/// Settings key holding the last UTC day (days since epoch) rolled up into `token_daily`.
+pub const TOKEN_DAILY_KEY: &str = "rollup.token_daily";
+
+/// Settings key holding the last UTC day rolled up into `marketplace_fees`.
+pub const MARKETPLACE_FEES_KEY: &str = "rollup.marketplace_fees";
+
+/// Settings key holding the last UTC day rolled up into `net_worth`.
+pub const NET_WORTH_KEY: &str = "rollup.net_worth";
+
+/// Settings key holding the wallets whose net worth is rolled up.
+pub const NET_WORTH_WATCHLIST_KEY: &str = "net_worth.watchlist";
+
+const WETH: &str = "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5";
+const WRON: &str = "0xe514d9deb7966c8be0ca922de8a064264ea6bcd4";
+const USDC: &str = "0x0b7007c13325c48911f73a2dad5fa5dcbf808adc";
+
+/// Katana pairs of a token with WETH that price the token: (pair, token).
+const WETH_PAIRS: [(&str, &str); 4] = [
+    (
+        "0xc6344bc1604fcab1a5aad712d766796e2b7a70b9",
+        "0x97a9107c1793bc407d6f527b77e7fff4d812bece",
+    ),
+    (
+        "0x306a28279d04a47468ed83d55088d0dcd1369294",
+        "0xa8754b9fa15fc18bb59458815510e40a12cd2014",
+    ),
+    ("0x2ecb08f87f075b5769fe543d0e52e40140575ea7", WRON),
+    ("0xa7964991f339668107e2b6a6f6b8e8b74aa9d017", USDC),
+];
+
+const WEI_PER_ETHER: f64 = 1e18;
+const USDC_UNITS: f64 = 1e6;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Watchlist {
+    pub wallets: Vec<Address>,
+}
+
+impl SettingsValue for Watchlist {}
+
+#[derive(Default)]
+struct TokenDay {
+    transfers: u64,
+    active: HashSet<Address>,
+    volume: U256,
+    minted: U256,
+    burned: U256,
+}
+
+/// First indexed block with a timestamp at or after `millis`.
+async fn first_block_at(database: &Database, millis: i64) -> Option<Block> {
+    tiering::find_one(
+        database,
+        |db| db.transactions.collection.clone(),
+        doc! {"timestamp": {"$gte": DateTime::from_millis(millis)}},
+        FindOneOptions::builder().sort(doc! {"block": 1i64}).build(),
+    )
+    .await
+    .expect("Failed to query transactions!")
+    .map(|tx| tx.block)
+}
+
+async fn first_indexed_day(database: &Database) -> Option<i64> {
+    tiering::find_one(
+        database,
+        |db| db.transactions.collection.clone(),
+        doc! {},
+        FindOneOptions::builder().sort(doc! {"block": 1i64}).build(),
+    )
+    .await
+    .expect("Failed to query transactions!")
+    .map(|tx| tx.timestamp.timestamp_millis() / DAY_MILLIS)
+}
+
+/// Day after the last rolled up day of `key`, or the first indexed day on the first run.
+async fn next_day(database: &Database, key: &str) -> Option<i64> {
+    match database.settings.load::<i64>(key).await {
+        Some(day) => Some(day + 1),
+        None => first_indexed_day(database).await,
+    }
+}
+
+/// Block range `[from, to)` of a UTC day, once the indexer has passed the end of the day.
+async fn day_blocks(database: &Database, day: i64) -> Option<(Block, Block)> {
+    match (
+        first_block_at(database, day * DAY_MILLIS).await,
+        first_block_at(database, (day + 1) * DAY_MILLIS).await,
+    ) {
+        (Some(from_block), Some(to_block)) => Some((from_block, to_block)),
+        _ => None,
+    }
+}
+
+async fn finish_day(database: &Database, key: &str, day: i64) {
+    database
+        .settings
+        .store(key, &day)
+        .await
+        .expect("Failed to store rollup progress");
+}
+
+fn velocity(volume: U256, supply: U256) -> f64 {
+    if supply.is_zero() {
+        return 0.0;
+    }
+
+    match volume.checked_mul(U256::from(1_000_000u64)) {
+        Some(scaled) => (scaled / supply).low_u128() as f64 / 1_000_000.0,
+        None => (volume / supply).low_u128() as f64,
+    }
+}
+
+/// Rolls the ERC20 transfers of every completed UTC day since the last run up into `token_daily`:
+/// transfers, active and new addresses, volume, supply (from mints and burns) and velocity.
+///
+/// Days are mapped to blocks through the `transactions` collection, so the transaction feature
+/// has to be enabled for the rolled up range.
+pub async fn token_daily(database: &Database) {
+    let mut day = match next_day(database, TOKEN_DAILY_KEY).await {
+        None => {
+            println!("Nothing to roll up: no transactions indexed");
+            return;
+        }
+        Some(day) => day,
+    };
+
+    let today = DateTime::now().timestamp_millis() / DAY_MILLIS;
+    let mut supplies: HashMap<Address, U256> = HashMap::new();
+
+    while day < today {
+        let (from_block, to_block) = match day_blocks(database, day).await {
+            None => break,
+            Some(blocks) => blocks,
+        };
+
+        let day_start = DateTime::from_millis(day * DAY_MILLIS);
+        let mut tokens: HashMap<Address, TokenDay> = HashMap::new();
+
+        if to_block > from_block {
+            let mut filter = block_range_filter(from_block, to_block - 1);
+            filter.insert("erc", ContractType::ERC20.as_str());
+
+            let mut cursor = tiering::find(
+                database,
+                |db| db.erc_transfers.collection.clone(),
+                filter,
+                None,
+                (from_block, to_block - 1),
+            )
+            .await
+            .expect("Failed to query erc transfers!");
+
+            while cursor
+                .advance()
+                .await
+                .expect("Failed to read erc transfers!")
+            {
+                let transfer: ERCTransfer = cursor
+                    .deserialize_current()
+                    .expect("Failed to deserialize erc transfer!");
+                let value =
+                    U256::from_str_radix(&transfer.value_or_token_id, 16).unwrap_or_default();
+
+                let stats = tokens.entry(transfer.token).or_default();
+                stats.transfers += 1;
+                stats.volume = stats.volume.saturating_add(value);
+
+                if transfer.from == ZERO_ADDRESS {
+                    stats.minted = stats.minted.saturating_add(value);
+                } else {
+                    stats.active.insert(transfer.from);
+                }
+                if transfer.to == ZERO_ADDRESS {
+                    stats.burned = stats.burned.saturating_add(value);
+                } else {
+                    stats.active.insert(transfer.to);
+                }
+            }
+        }
+
+        let addresses: Vec<TokenAddress> = tokens
+            .iter()
+            .flat_map(|(token, stats)| {
+                stats.active.iter().map(|address| TokenAddress {
+                    token: token.to_owned(),
+                    address: address.to_owned(),
+                    first_day: day_start,
+                })
+            })
+            .collect();
+
+        let mut address_pool = database.token_daily.get_address_pool();
+        let mut new_addresses: HashMap<Address, u64> = HashMap::new();
+        for chunk in addresses.chunks(update_batch_size()) {
+            address_pool.extend(chunk.iter().cloned());
+            address_pool
+                .commit(false)
+                .await
+                .expect("Failed to insert token addresses");
+
+            for address in address_pool.inserted() {
+                *new_addresses.entry(address.token.to_owned()).or_default() += 1;
+            }
+        }
+
+        for (token, stats) in tokens.iter() {
+            if !supplies.contains_key(token) {
+                let supply = database
+                    .token_daily
+                    .latest(token)
+                    .await
+                    .and_then(|daily| U256::from_str_radix(&daily.supply, 16).ok())
+                    .unwrap_or_default();
+                supplies.insert(token.to_owned(), supply);
+            }
+
+            let supply = supplies.get_mut(token).expect("Supply was just loaded");
+            *supply = supply
+                .saturating_add(stats.minted)
+                .saturating_sub(stats.burned);
+
+            database
+                .token_daily
+                .store(&TokenDaily {
+                    day: day_start,
+                    token: token.to_owned(),
+                    transfers: stats.transfers,
+                    active_addresses: stats.active.len() as u64,
+                    new_addresses: new_addresses.get(token).copied().unwrap_or(0),
+                    volume: format!("{:x}", stats.volume),
+                    supply: format!("{:x}", supply),
+                    velocity: velocity(stats.volume, *supply),
+                })
+                .await
+                .expect("Failed to store token daily metrics");
+        }
+
+        finish_day(database, TOKEN_DAILY_KEY, day).await;
+
+        info!(
+            "[INFO] Rolled up {} tokens for day {}",
+            tokens.len(),
+            day_start
+        );
+        day += 1;
+    }
+
+    println!(
+        "Token daily metrics rolled up to {}",
+        DateTime::from_millis(day * DAY_MILLIS)
+    );
+}
+
+/// Rolls marketplace revenue of every completed UTC day since the last run up into
+/// `marketplace_fees`: the marketFeeTaken of marketplace v2 sales per collection and payment token
+/// and, with a `treasury` address, all ERC20 transfers into the treasury per token.
+pub async fn marketplace_fees(database: &Database, treasury: Option<Address>) {
+    let treasury = treasury.map(|address| address.to_lowercase());
+
+    let mut day = match next_day(database, MARKETPLACE_FEES_KEY).await {
+        None => {
+            println!("Nothing to roll up: no transactions indexed");
+            return;
+        }
+        Some(day) => day,
+    };
+
+    let today = DateTime::now().timestamp_millis() / DAY_MILLIS;
+
+    while day < today {
+        let (from_block, to_block) = match day_blocks(database, day).await {
+            None => break,
+            Some(blocks) => blocks,
+        };
+
+        let day_start = DateTime::from_millis(day * DAY_MILLIS);
+        let mut fees: HashMap<(FeeSource, Option<Address>, Address), (u64, U256)> = HashMap::new();
+
+        let mut cursor = tiering::find(
+            database,
+            |db| db.erc_sales.collection.clone(),
+            doc! {
+                "created_at": {
+                    "$gte": day_start,
+                    "$lt": DateTime::from_millis((day + 1) * DAY_MILLIS)
+                },
+                "market_fee": {"$ne": null}
+            },
+            None,
+            (0, 0),
+        )
+        .await
+        .expect("Failed to query erc sales!");
+
+        while cursor.advance().await.expect("Failed to read erc sales!") {
+            let sale: Sale = cursor
+                .deserialize_current()
+                .expect("Failed to deserialize erc sale!");
+
+            let fee = sale
+                .market_fee
+                .and_then(|fee| U256::from_str_radix(&fee, 16).ok())
+                .unwrap_or_default();
+            let entry = fees
+                .entry((
+                    FeeSource::OrderMatched,
+                    Some(sale.token),
+                    sale.payment_token.unwrap_or_default(),
+                ))
+                .or_default();
+            entry.0 += 1;
+            entry.1 = entry.1.saturating_add(fee);
+        }
+
+        if let Some(treasury) = treasury.as_ref().filter(|_| to_block > from_block) {
+            let mut filter = block_range_filter(from_block, to_block - 1);
+            filter.insert("erc", ContractType::ERC20.as_str());
+            filter.insert("to", treasury);
+
+            let mut cursor = tiering::find(
+                database,
+                |db| db.erc_transfers.collection.clone(),
+                filter,
+                None,
+                (from_block, to_block - 1),
+            )
+            .await
+            .expect("Failed to query erc transfers!");
+
+            while cursor
+                .advance()
+                .await
+                .expect("Failed to read erc transfers!")
+            {
+                let transfer: ERCTransfer = cursor
+                    .deserialize_current()
+                    .expect("Failed to deserialize erc transfer!");
+                let value =
+                    U256::from_str_radix(&transfer.value_or_token_id, 16).unwrap_or_default();
+
+                let entry = fees
+                    .entry((FeeSource::Treasury, None, transfer.token))
+                    .or_default();
+                entry.0 += 1;
+                entry.1 = entry.1.saturating_add(value);
+            }
+        }
+
+        for ((source, collection, payment_token), (sales, total)) in fees.iter() {
+            database
+                .marketplace_fees
+                .store(&MarketplaceFee {
+                    day: day_start,
+                    source: *source,
+                    collection: collection.to_owned(),
+                    payment_token: payment_token.to_owned(),
+                    sales: *sales,
+                    fees: format!("{:x}", total),
+                })
+                .await
+                .expect("Failed to store marketplace fees");
+        }
+
+        finish_day(database, MARKETPLACE_FEES_KEY, day).await;
+        day += 1;
+    }
+
+    println!(
+        "Marketplace fees rolled up to {}",
+        DateTime::from_millis(day * DAY_MILLIS)
+    );
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(0.0)
+}
+
+/// WETH value of one raw unit of every priced token, from the last reserves of the
+/// [`WETH_PAIRS`] before `millis`. Uniswap V2 style pairs order their tokens by address.
+async fn weth_prices(database: &Database, millis: i64) -> HashMap<Address, f64> {
+    let mut prices = HashMap::from([(WETH.to_string(), 1.0)]);
+
+    for (pair, token) in WETH_PAIRS {
+        let reserve: Option<PairReserve> = tiering::find_one(
+            database,
+            |db| db.pair_reserves.collection.clone(),
+            doc! {"pair": pair, "timestamp": {"$lt": DateTime::from_millis(millis)}},
+            FindOneOptions::builder()
+                .sort(doc! {"block": -1i64})
+                .build(),
+        )
+        .await
+        .expect("Failed to query pair reserves!");
+
+        if let Some(reserve) = reserve {
+            let reserve0 = to_f64(U256::from_str_radix(&reserve.reserve0, 16).unwrap_or_default());
+            let reserve1 = to_f64(U256::from_str_radix(&reserve.reserve1, 16).unwrap_or_default());
+            let (token_reserve, weth_reserve) = match token < WETH {
+                true => (reserve0, reserve1),
+                false => (reserve1, reserve0),
+            };
+
+            if token_reserve > 0.0 {
+                prices.insert(token.to_string(), weth_reserve / token_reserve);
+            }
+        }
+    }
+
+    // Native RON is valued like wrapped RON.
+    if let Some(price) = prices.get(WRON).copied() {
+        prices.insert(ZERO_ADDRESS.to_string(), price);
+    }
+
+    prices
+}
+
+/// Adds the ERC20 transfers and RON balance changes of `wallet` in `[from_block, to_block)` to its
+/// raw `holdings`.
+async fn apply_wallet_changes(
+    database: &Database,
+    wallet: &str,
+    from_block: Block,
+    to_block: Block,
+    holdings: &mut HashMap<Address, i128>,
+) {
+    if to_block <= from_block {
+        return;
+    }
+
+    let mut filter = block_range_filter(from_block, to_block - 1);
+    filter.insert("erc", ContractType::ERC20.as_str());
+    filter.insert("$or", vec![doc! {"from": wallet}, doc! {"to": wallet}]);
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_transfers.collection.clone(),
+        filter,
+        None,
+        (from_block, to_block - 1),
+    )
+    .await
+    .expect("Failed to query erc transfers!");
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc transfers!")
+    {
+        let transfer: ERCTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc transfer!");
+        let value = U256::from_str_radix(&transfer.value_or_token_id, 16)
+            .unwrap_or_default()
+            .low_u128() as i128;
+
+        let balance = holdings.entry(transfer.token).or_default();
+        if transfer.to == wallet {
+            *balance = balance.saturating_add(value);
+        }
+        if transfer.from == wallet {
+            *balance = balance.saturating_sub(value);
+        }
+    }
+
+    let mut filter = block_range_filter(from_block, to_block - 1);
+    filter.insert("address", wallet);
+
+    let mut cursor = database
+        .ron_balances
+        .changes
+        .find(filter, None)
+        .await
+        .expect("Failed to query RON balance changes!");
+
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read RON balance changes!")
+    {
+        let change: RonBalanceChange = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize RON balance change!");
+        let amount =
+            |hex: &str| U256::from_str_radix(hex, 16).unwrap_or_default().low_u128() as i128;
+
+        let balance = holdings.entry(ZERO_ADDRESS.to_string()).or_default();
+        *balance = balance
+            .saturating_add(amount(&change.received))
+            .saturating_sub(amount(&change.spent));
+    }
+}
+
+/// Rolls the net worth of every watchlisted wallet up into `net_worth` for every completed UTC day
+/// since the last run: ERC20 and RON balances replayed from the indexed transfers, valued in WETH
+/// (and USD through USDC) with the Katana reserves at the end of the day. Tokens without a
+/// [`WETH_PAIRS`] entry are listed without a value.
+///
+/// Wallets added to the watchlist later start with their balance at the current rollup day. Like
+/// the other rollups this needs the transaction feature for the rolled up range.
+pub async fn net_worth(database: &Database) {
+    let watchlist = database
+        .settings
+        .load::<Watchlist>(NET_WORTH_WATCHLIST_KEY)
+        .await
+        .unwrap_or_default();
+
+    if watchlist.wallets.is_empty() {
+        return;
+    }
+
+    let mut day = match next_day(database, NET_WORTH_KEY).await {
+        None => {
+            println!("Nothing to roll up: no transactions indexed");
+            return;
+        }
+        Some(day) => day,
+    };
+
+    let today = DateTime::now().timestamp_millis() / DAY_MILLIS;
+    let mut holdings: HashMap<Address, HashMap<Address, i128>> = HashMap::new();
+
+    while day < today {
+        let (from_block, to_block) = match day_blocks(database, day).await {
+            None => break,
+            Some(blocks) => blocks,
+        };
+
+        let day_start = DateTime::from_millis(day * DAY_MILLIS);
+        let prices = weth_prices(database, (day + 1) * DAY_MILLIS).await;
+        let usd_per_weth = prices
+            .get(USDC)
+            .filter(|price| **price > 0.0)
+            .map(|price| WEI_PER_ETHER / price / USDC_UNITS);
+
+        for wallet in watchlist.wallets.iter() {
+            if !holdings.contains_key(wallet) {
+                let mut opening = HashMap::new();
+
+                match database.net_worth.before(wallet, day_start).await {
+                    Some(previous) => {
+                        for holding in previous.holdings {
+                            let amount = U256::from_str_radix(&holding.amount, 16)
+                                .unwrap_or_default()
+                                .low_u128() as i128;
+                            opening.insert(holding.token, amount);
+                        }
+                    }
+                    None => {
+                        apply_wallet_changes(database, wallet, 0, from_block, &mut opening).await
+                    }
+                }
+
+                holdings.insert(wallet.to_owned(), opening);
+            }
+
+            let wallet_holdings = holdings.get_mut(wallet).expect("Holdings were just loaded");
+            apply_wallet_changes(database, wallet, from_block, to_block, wallet_holdings).await;
+
+            let mut entries: Vec<Holding> = wallet_holdings
+                .iter()
+                .filter(|(_, amount)| **amount > 0)
+                .map(|(token, amount)| Holding {
+                    token: token.to_owned(),
+                    amount: format!("{:x}", amount),
+                    weth: prices
+                        .get(token)
+                        .map(|price| *amount as f64 * price / WEI_PER_ETHER),
+                })
+                .collect();
+            entries.sort_by(|a, b| a.token.cmp(&b.token));
+
+            let weth: f64 = entries.iter().filter_map(|holding| holding.weth).sum();
+
+            database
+                .net_worth
+                .store(&NetWorth {
+                    wallet: wallet.to_owned(),
+                    day: day_start,
+                    holdings: entries,
+                    weth,
+                    usd: usd_per_weth.map(|usd| weth * usd),
+                })
+                .await
+                .expect("Failed to store net worth");
+        }
+
+        finish_day(database, NET_WORTH_KEY, day).await;
+        day += 1;
+    }
+
+    println!(
+        "Net worth of {} wallets rolled up to {}",
+        watchlist.wallets.len(),
+        DateTime::from_millis(day * DAY_MILLIS)
+    );
+}
+
+/// Adds wallets to and removes wallets from the net worth watchlist and prints it.
+pub async fn update_watchlist(database: &Database, add: &[Address], remove: &[Address]) {
+    let mut watchlist = database
+        .settings
+        .load::<Watchlist>(NET_WORTH_WATCHLIST_KEY)
+        .await
+        .unwrap_or_default();
+
+    for wallet in add.iter().map(|wallet| wallet.to_lowercase()) {
+        if !watchlist.wallets.contains(&wallet) {
+            watchlist.wallets.push(wallet);
+        }
+    }
+    let remove: HashSet<Address> = remove.iter().map(|wallet| wallet.to_lowercase()).collect();
+    watchlist.wallets.retain(|wallet| !remove.contains(wallet));
+
+    database
+        .settings
+        .store(NET_WORTH_WATCHLIST_KEY, &watchlist)
+        .await
+        .expect("Failed to store net worth watchlist");
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&watchlist.wallets).unwrap()
+    );
+}
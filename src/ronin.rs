@@ -1,31 +1,87 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::Display;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use futures::future::join_all;
+use futures::stream::{self, Stream, StreamExt};
 use log::Level::Info;
-use log::{debug, info, log_enabled, warn};
+use log::{debug, error, info, log_enabled, warn};
 use mongodb::bson::{doc, DateTime};
 use mongodb::options::FindOneOptions;
+use mongodb::ClientSession;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
 use url::Url;
-use web3::ethabi::{Event, EventParam, ParamType, RawLog};
+use web3::ethabi::{Event, EventParam, ParamType, RawLog, Token};
+use web3::signing::keccak256;
 use web3::transports::{Either, Http, WebSocket};
-use web3::types::{BlockId, BlockNumber, Log, TransactionReceipt};
-use web3::Web3;
-use ParamType::{Address, FixedBytes, Uint};
+use web3::types::{
+    BlockId, BlockNumber, Bytes, CallRequest, FilterBuilder, Log, SyncState,
+    Transaction as Web3Transaction, TransactionReceipt, H160, H2048, H256, U256,
+};
+use web3::{Transport, Web3};
+use ParamType::{Address, Array, FixedBytes, Uint};
 
-use ContractType::{LegacyErc721Sale, MarketplaceV2, ERC1155, ERC20, ERC721};
+use ContractType::{
+    AxieItemEquipped, AxieItemUnequipped, Erc1155Bulk, KatanaPair, LegacyAuctionCreated,
+    LegacyErc721Sale, MarketplaceV2, MarketplaceV2OrderCancelled, MarketplaceV2OrderCreated,
+    MultisigConfirmation, MultisigExecution, MultisigExecutionFailure, MultisigRevocation,
+    MultisigSubmission, StakingDelegated, StakingRewardClaimed, StakingStaked, StakingUndelegated,
+    StakingUnstaked, ERC1155, ERC20, ERC721,
+};
 
-use crate::cli_args::Args;
+use crate::abi::AbiRegistry;
+use crate::analysis::ZERO_ADDRESS;
+use crate::cli_args::{Args, MissingBlockPolicy, StopBlock, WhaleThreshold};
+use crate::handler;
+use crate::logging::BlockSummary;
+use crate::mongo::collections::auction::Auction;
 use crate::mongo::collections::axie_sale::Sale;
+use crate::mongo::collections::backfill_chunk::BackfillChunk;
+use crate::mongo::collections::block::IndexedBlock;
+use crate::mongo::collections::block_status::BlockState;
+use crate::mongo::collections::contract_event::ContractEvent;
+use crate::mongo::collections::decode_failure::{DecodeFailure, DecodeStage};
+use crate::mongo::collections::equipment_event::{EquipmentAction, EquipmentEvent};
 use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+use crate::mongo::collections::failed_block::{FailedBlock, FailedStage};
+use crate::mongo::collections::governance_event::{GovernanceAction, GovernanceEvent};
+use crate::mongo::collections::metrics::Metrics;
+use crate::mongo::collections::missing_block::MissingBlock;
+use crate::mongo::collections::native_transfer::NativeTransfer;
+use crate::mongo::collections::order::{OrderEvent, OrderStatus};
+use crate::mongo::collections::pair_reserve::PairReserve;
+use crate::mongo::collections::receipt_anomaly::{AnomalyKind, ReceiptAnomaly};
+use crate::mongo::collections::ron_balance::RonBalanceChange;
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::staking_event::{StakingAction, StakingEvent};
+use crate::mongo::collections::storage_profile;
+use crate::mongo::collections::token::DiscoveredToken;
+use crate::mongo::collections::tombstone::OrphanedAt;
 use crate::mongo::collections::transaction::Transaction;
 use crate::mongo::collections::transaction_pool::Pool;
 use crate::mongo::collections::wallet::Wallet;
-use crate::mongo::collections::{erc_transfer::ERCTransfer, Block};
+use crate::mongo::collections::wallet_transfer::WalletTransfer;
+use crate::mongo::collections::{
+    decimal_amount, erc_transfer::ERCTransfer, Address as WalletAddress, Block, NumericFields,
+};
 use crate::mongo::Database;
+use crate::probe;
+use crate::rate_limit::{self, RateLimited};
+use crate::reorg;
+use crate::report::BackfillReport;
+use crate::retry::Backoff;
+use crate::sink::{FileSink, NatsSink};
+use crate::status;
+use crate::subscriptions::{self, EventSubscription};
 
 const ERC_TRANSFER_TOPIC: &str =
     "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
@@ -35,14 +91,86 @@ const MARKETPLACE_V2_ORDER_MATCHED_TOPIC: &str =
 
 const MARKETPLACE_V2_DEPLOY_BLOCK: Block = 16027461;
 
+/// Marketplace v2 gateway, the only emitter of indexed order events.
+const MARKETPLACE_V2_CONTRACT: &str = "0xfff9ce5f71ca6178d3beecedb61e7eff1602950e";
+
+const ORDER_EVENTS: [(ContractType, OrderStatus); 3] = [
+    (MarketplaceV2OrderCreated, OrderStatus::Created),
+    (MarketplaceV2OrderCancelled, OrderStatus::Cancelled),
+    (MarketplaceV2, OrderStatus::Matched),
+];
+
 const MARKETPLACE_AXIE_SALE_TOPIC: &str =
     "0x0c0258cd7f0d9474f62106c6981c027ea54bee0b323ea1991f4caa7e288a5725";
 
 const ERC1155_TRANSFER_SINGLE_TOPIC: &str =
     "0xc3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62";
 
+const ERC1155_TRANSFER_BATCH_TOPIC: &str =
+    "0x4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb";
+
 const ERC1155_DEPLOY_BLOCK: Block = 16171588;
 
+const PAIR_SYNC_TOPIC: &str = "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1";
+
+/// Multisig wallet events (Gnosis MultiSigWallet, as used by the Ronin governance contracts).
+/// They are only matched on the contracts of [`GOVERNANCE_CONTRACTS`].
+const GOVERNANCE_EVENTS: [(ContractType, GovernanceAction); 5] = [
+    (MultisigSubmission, GovernanceAction::Submitted),
+    (MultisigConfirmation, GovernanceAction::Confirmed),
+    (MultisigRevocation, GovernanceAction::Revoked),
+    (MultisigExecution, GovernanceAction::Executed),
+    (MultisigExecutionFailure, GovernanceAction::ExecutionFailed),
+];
+
+/// Staking contracts whose events are indexed: the AXS staking pool and the RON (DPoS) staking
+/// contract.
+const STAKING_CONTRACTS: [&str; 2] = [
+    "0x05b0bb3c1c320b280501b86706c3551995bc8571",
+    "0x545edb750eb8769c868429be9586f5857a768758",
+];
+
+const STAKING_EVENTS: [(ContractType, StakingAction); 5] = [
+    (StakingStaked, StakingAction::Staked),
+    (StakingUnstaked, StakingAction::Unstaked),
+    (StakingRewardClaimed, StakingAction::RewardClaimed),
+    (StakingDelegated, StakingAction::Delegated),
+    (StakingUndelegated, StakingAction::Undelegated),
+];
+
+const EQUIPMENT_EVENTS: [(ContractType, EquipmentAction); 2] = [
+    (AxieItemEquipped, EquipmentAction::Equipped),
+    (AxieItemUnequipped, EquipmentAction::Unequipped),
+];
+
+const ERC20_NAME_SELECTOR: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+const ERC20_SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+const ERC20_DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+const ERC20_BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+const ERC721_TOKEN_URI_SELECTOR: [u8; 4] = [0xc8, 0x7b, 0x56, 0xdd];
+const ERC1155_URI_SELECTOR: [u8; 4] = [0x0e, 0x89, 0x34, 0x1c];
+const SUPPORTS_INTERFACE_SELECTOR: [u8; 4] = [0x01, 0xff, 0xc9, 0xa7];
+const ERC721_INTERFACE_ID: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+const ERC1155_INTERFACE_ID: [u8; 4] = [0xd9, 0xb6, 0x7a, 0x26];
+
+const NODE_SYNC_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+const ARCHIVE_PROBE_BLOCKS: u64 = 1000;
+
+/// Blocks kept in [`BLOCK_TIMESTAMPS`].
+const BLOCK_TIMESTAMP_CACHE: usize = 10_000;
+
+/// Settings key holding the block the ERC20 balances were last reconciled at.
+const BALANCES_RECONCILED_KEY: &str = "balances.reconciled_block";
+/// Name of the lease held by the replica that streams.
+const LEADER_LEASE: &str = "stream";
+/// Last block scanned by [`Ronin::stream_logs`].
+pub const LOGS_MODE_KEY: &str = "logs_mode.last_block";
+/// Last block committed by [`Ronin::stream`], where it resumes without a start block.
+pub const STREAM_KEY: &str = "stream.last_block";
+/// Features the last [`Ronin::stream`] ran with, reported by `status`.
+pub const STREAM_FEATURES_KEY: &str = "stream.features";
+
 const _ERC721_TOKEN: [&str; 3] = [
     "0xcbb5cc4b59a6993d6fb1ac439761dd5bf751a8c2",
     "0xa96660f0e4a3e9bc7388925d245a6d4d79e21259",
@@ -62,9 +190,11 @@ const _ERC20_TOKEN: [&str; 10] = [
     "0xa7964991f339668107e2b6a6f6b8e8b74aa9d017",
 ];
 
+#[derive(Clone)]
 pub struct Ronin {
     database: Database,
-    pub provider: Web3<Either<WebSocket, Http>>,
+    pub provider: Provider,
+    events: HashMap<ContractType, Event>,
 }
 
 pub enum AddressPrefix {
@@ -72,33 +202,535 @@ pub enum AddressPrefix {
     Ethereum,
 }
 
+/// Contract or decoder type. Stored as the lowercase names of [`ContractType::as_str`], the
+/// variant names written by older releases are still accepted and rewritten by
+/// `Database::migrate_erc`.
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Debug, Clone)]
 pub enum ContractType {
+    #[serde(rename = "erc20", alias = "ERC20")]
     ERC20,
+    #[serde(rename = "erc721", alias = "ERC721")]
     ERC721,
+    #[serde(rename = "erc1155", alias = "ERC1155")]
     ERC1155,
+    #[serde(rename = "erc1155_bulk", alias = "Erc1155Bulk")]
     Erc1155Bulk,
+    #[serde(rename = "unknown", alias = "Unknown")]
     Unknown,
+    #[serde(rename = "marketplace_v2", alias = "MarketplaceV2")]
     MarketplaceV2,
+    #[serde(
+        rename = "marketplace_v2_order_created",
+        alias = "MarketplaceV2OrderCreated"
+    )]
+    MarketplaceV2OrderCreated,
+    #[serde(
+        rename = "marketplace_v2_order_cancelled",
+        alias = "MarketplaceV2OrderCancelled"
+    )]
+    MarketplaceV2OrderCancelled,
+    #[serde(rename = "legacy_erc721_sale", alias = "LegacyErc721Sale")]
     LegacyErc721Sale,
+    #[serde(rename = "legacy_auction_created", alias = "LegacyAuctionCreated")]
+    LegacyAuctionCreated,
+    #[serde(rename = "katana_pair", alias = "KatanaPair")]
+    KatanaPair,
+    #[serde(rename = "multisig_submission", alias = "MultisigSubmission")]
+    MultisigSubmission,
+    #[serde(rename = "multisig_confirmation", alias = "MultisigConfirmation")]
+    MultisigConfirmation,
+    #[serde(rename = "multisig_revocation", alias = "MultisigRevocation")]
+    MultisigRevocation,
+    #[serde(rename = "multisig_execution", alias = "MultisigExecution")]
+    MultisigExecution,
+    #[serde(
+        rename = "multisig_execution_failure",
+        alias = "MultisigExecutionFailure"
+    )]
+    MultisigExecutionFailure,
+    #[serde(rename = "staking_staked", alias = "StakingStaked")]
+    StakingStaked,
+    #[serde(rename = "staking_unstaked", alias = "StakingUnstaked")]
+    StakingUnstaked,
+    #[serde(rename = "staking_reward_claimed", alias = "StakingRewardClaimed")]
+    StakingRewardClaimed,
+    #[serde(rename = "staking_delegated", alias = "StakingDelegated")]
+    StakingDelegated,
+    #[serde(rename = "staking_undelegated", alias = "StakingUndelegated")]
+    StakingUndelegated,
+    #[serde(rename = "axie_item_equipped", alias = "AxieItemEquipped")]
+    AxieItemEquipped,
+    #[serde(rename = "axie_item_unequipped", alias = "AxieItemUnequipped")]
+    AxieItemUnequipped,
 }
 
-#[derive(Serialize, Deserialize)]
+impl ContractType {
+    pub const ALL: [ContractType; 23] = [
+        ContractType::ERC20,
+        ContractType::ERC721,
+        ContractType::ERC1155,
+        ContractType::Erc1155Bulk,
+        ContractType::Unknown,
+        ContractType::MarketplaceV2,
+        ContractType::MarketplaceV2OrderCreated,
+        ContractType::MarketplaceV2OrderCancelled,
+        ContractType::LegacyErc721Sale,
+        ContractType::LegacyAuctionCreated,
+        ContractType::KatanaPair,
+        ContractType::MultisigSubmission,
+        ContractType::MultisigConfirmation,
+        ContractType::MultisigRevocation,
+        ContractType::MultisigExecution,
+        ContractType::MultisigExecutionFailure,
+        ContractType::StakingStaked,
+        ContractType::StakingUnstaked,
+        ContractType::StakingRewardClaimed,
+        ContractType::StakingDelegated,
+        ContractType::StakingUndelegated,
+        ContractType::AxieItemEquipped,
+        ContractType::AxieItemUnequipped,
+    ];
+
+    /// Stored representation of the type.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContractType::ERC20 => "erc20",
+            ContractType::ERC721 => "erc721",
+            ContractType::ERC1155 => "erc1155",
+            ContractType::Erc1155Bulk => "erc1155_bulk",
+            ContractType::Unknown => "unknown",
+            ContractType::MarketplaceV2 => "marketplace_v2",
+            ContractType::MarketplaceV2OrderCreated => "marketplace_v2_order_created",
+            ContractType::MarketplaceV2OrderCancelled => "marketplace_v2_order_cancelled",
+            ContractType::LegacyErc721Sale => "legacy_erc721_sale",
+            ContractType::LegacyAuctionCreated => "legacy_auction_created",
+            ContractType::KatanaPair => "katana_pair",
+            ContractType::MultisigSubmission => "multisig_submission",
+            ContractType::MultisigConfirmation => "multisig_confirmation",
+            ContractType::MultisigRevocation => "multisig_revocation",
+            ContractType::MultisigExecution => "multisig_execution",
+            ContractType::MultisigExecutionFailure => "multisig_execution_failure",
+            ContractType::StakingStaked => "staking_staked",
+            ContractType::StakingUnstaked => "staking_unstaked",
+            ContractType::StakingRewardClaimed => "staking_reward_claimed",
+            ContractType::StakingDelegated => "staking_delegated",
+            ContractType::StakingUndelegated => "staking_undelegated",
+            ContractType::AxieItemEquipped => "axie_item_equipped",
+            ContractType::AxieItemUnequipped => "axie_item_unequipped",
+        }
+    }
+
+    /// Representation written by releases before the lowercase names.
+    pub fn legacy_str(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Event types the decode loop can index for a contract. Stored in lowercase like
+/// [`ContractType`], the names of older contract files are still accepted.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    #[serde(alias = "Transfer")]
+    Transfer,
+    #[serde(alias = "Sale")]
+    Sale,
+    Order,
+    Reserve,
+    Governance,
+    Staking,
+    Equipment,
+}
+
+impl EventType {
+    pub fn all() -> Vec<EventType> {
+        vec![
+            EventType::Transfer,
+            EventType::Sale,
+            EventType::Order,
+            EventType::Reserve,
+            EventType::Governance,
+            EventType::Staking,
+            EventType::Equipment,
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Contract {
     pub name: &'static str,
     pub decimals: usize,
     pub erc: ContractType,
     pub address: &'static str,
+    pub events: Vec<EventType>,
+    /// Block the contract was deployed at, 0 if unknown.
+    pub deploy_block: Block,
+}
+
+/// Contract entry of a `--contracts` file.
+#[derive(Deserialize)]
+struct ContractConfig {
+    address: String,
+    name: String,
+    #[serde(default)]
+    decimals: usize,
+    erc: ContractType,
+    #[serde(default)]
+    deploy_block: Block,
+    #[serde(default = "EventType::all")]
+    events: Vec<EventType>,
+}
+
+#[derive(Deserialize)]
+struct ContractFile {
+    contracts: Vec<ContractConfig>,
+}
+
+/// Contract list loaded from `--contracts`, replaces the built in list.
+static CONFIGURED_CONTRACTS: OnceLock<ContractList> = OnceLock::new();
+
+/// Timestamps of the recently loaded blocks, so retried decodes, logs mode and `block_at` don't
+/// ask the node for a block the process already loaded. The lowest block is evicted first.
+static BLOCK_TIMESTAMPS: Mutex<BTreeMap<Block, DateTime>> = Mutex::new(BTreeMap::new());
+
+/// Strings of the contract list entries built at runtime, see [`intern`].
+static INTERNED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Connections by endpoint, shared by every [`Ronin`] of the process so subcommands, workers and
+/// the fallback node don't each open a websocket to the node.
+static PROVIDERS: tokio::sync::Mutex<BTreeMap<String, Provider>> =
+    tokio::sync::Mutex::const_new(BTreeMap::new());
+
+/// Axie equipment contract from `--equipment-contract`, the emitter of the equipment events.
+static EQUIPMENT_CONTRACT: OnceLock<String> = OnceLock::new();
+
+/// Multisig contracts from `--governance-contract`, the emitters of the governance events.
+static GOVERNANCE_CONTRACTS: OnceLock<Vec<String>> = OnceLock::new();
+
+impl Contract {
+    pub fn indexes(&self, event: EventType) -> bool {
+        self.events.contains(&event)
+    }
 }
 
 pub type ContractList = HashMap<&'static str, Contract>;
 
+/// Web3 client of a node, sending requests within the `--rpc-max-qps` budget.
+pub type Provider = Web3<RateLimited<Either<WebSocket, Http>>>;
+
+pub type DecodeResult<T> = Result<T, Box<DecodeFailure>>;
+
+/// Log filters as addresses, any address when `None`, and topics of which one has to match.
+type LogFilters = Vec<(Option<Vec<H160>>, Vec<H256>)>;
+
+/// Feature switches of [`Ronin::decode_receipt`].
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeFeatures {
+    pub erc_transfers: bool,
+    pub erc_721_sales: bool,
+    pub pair_reserves: bool,
+    pub governance: bool,
+    pub staking: bool,
+    pub axie_equipment: bool,
+}
+
+impl DecodeFeatures {
+    pub fn all() -> DecodeFeatures {
+        DecodeFeatures {
+            erc_transfers: true,
+            erc_721_sales: true,
+            pair_reserves: true,
+            governance: true,
+            staking: true,
+            axie_equipment: true,
+        }
+    }
+}
+
+impl From<&Args> for DecodeFeatures {
+    fn from(args: &Args) -> DecodeFeatures {
+        DecodeFeatures {
+            erc_transfers: args.feature_erc_transfers,
+            erc_721_sales: args.feature_erc_721_sales,
+            pair_reserves: args.feature_pair_reserves,
+            governance: args.feature_governance,
+            staking: args.feature_staking,
+            axie_equipment: args.feature_axie_equipment,
+        }
+    }
+}
+
+/// Documents decoded from the logs of one transaction receipt.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct DecodedReceipt {
+    pub erc_transfers: Vec<ERCTransfer>,
+    pub erc1155_transfers: Vec<ERC1155Transfer>,
+    pub sales: Vec<Sale>,
+    pub auctions: Vec<Auction>,
+    pub pair_reserves: Vec<PairReserve>,
+    pub governance_events: Vec<GovernanceEvent>,
+    #[serde(default)]
+    pub staking_events: Vec<StakingEvent>,
+    #[serde(default)]
+    pub orders: Vec<OrderEvent>,
+    #[serde(default)]
+    pub equipment_events: Vec<EquipmentEvent>,
+    pub decode_failures: Vec<DecodeFailure>,
+}
+
+/// Decoded chain data of [`Ronin::stream_events`].
+#[derive(Serialize, Clone, PartialEq)]
+pub enum RoninEvent {
+    Block {
+        block: Block,
+        hash: String,
+        timestamp: DateTime,
+        transactions: usize,
+    },
+    Transaction(Transaction),
+    ErcTransfer(ERCTransfer),
+    Erc1155Transfer(ERC1155Transfer),
+    Sale(Sale),
+}
+
 #[derive(Serialize, Deserialize)]
 struct LargestBlock {
     number: Block,
     tx_num: u64,
 }
 
+impl SettingsValue for LargestBlock {}
+
+/// Feature flags of the stream by name, in the order of [`Args::features`].
+#[derive(Serialize, Deserialize)]
+pub struct StreamFeatures {
+    pub features: Vec<(String, bool)>,
+}
+
+impl SettingsValue for StreamFeatures {}
+
+/// Leader lease held by this replica while it streams, see `--leader-election`.
+struct Leadership {
+    holder: String,
+    ttl: Duration,
+    renewed: Instant,
+}
+
+/// Counters accumulated between two writes to the metrics collection.
+struct MetricsWindow {
+    started: Instant,
+    blocks: u64,
+    transactions: u64,
+    erc_transfers: u64,
+    erc1155_transfers: u64,
+    erc_sales: u64,
+    wallet_updates: u64,
+    duplicates: u64,
+    errors: u64,
+}
+
+impl MetricsWindow {
+    fn new() -> MetricsWindow {
+        MetricsWindow {
+            started: Instant::now(),
+            blocks: 0,
+            transactions: 0,
+            erc_transfers: 0,
+            erc1155_transfers: 0,
+            erc_sales: 0,
+            wallet_updates: 0,
+            duplicates: 0,
+            errors: 0,
+        }
+    }
+
+    fn add_pool<T>(&mut self, pool: &Pool<T>)
+    where
+        T: Serialize + Clone + Eq + PartialEq,
+    {
+        self.duplicates += pool.duplicates() as u64;
+        self.errors += pool.errors() as u64;
+    }
+}
+
+/// Pools of the stream, filled over up to `--flush-blocks` blocks and committed together.
+struct StreamPools {
+    blocks: Pool<IndexedBlock>,
+    transactions: Pool<Transaction>,
+    erc_transfers: Pool<ERCTransfer>,
+    erc1155_transfers: Pool<ERC1155Transfer>,
+    sales: Pool<Sale>,
+    auctions: Pool<Auction>,
+    orders: Pool<OrderEvent>,
+    pair_reserves: Pool<PairReserve>,
+    ron_changes: Pool<RonBalanceChange>,
+    native_transfers: Pool<NativeTransfer>,
+    tokens: Pool<DiscoveredToken>,
+    governance_events: Pool<GovernanceEvent>,
+    staking_events: Pool<StakingEvent>,
+    equipment_events: Pool<EquipmentEvent>,
+    decode_failures: Pool<DecodeFailure>,
+    anomalies: Pool<ReceiptAnomaly>,
+    contract_events: Pool<ContractEvent>,
+    subscriptions: Vec<Pool<ContractEvent>>,
+    wallets: Pool<Wallet>,
+    /// Blocks added since the last commit.
+    first_block: Option<Block>,
+    last_block: Option<Block>,
+    blocks_pending: u64,
+    transactions_pending: usize,
+}
+
+impl StreamPools {
+    fn new(database: &Database, subscriptions: &[EventSubscription]) -> StreamPools {
+        StreamPools {
+            blocks: database.blocks.get_pool(),
+            transactions: database.transactions.get_pool(),
+            erc_transfers: database.erc_transfers.get_pool(),
+            erc1155_transfers: database.erc1155_transfers.get_pool(),
+            sales: database.erc_sales.get_pool(),
+            auctions: database.auctions.get_pool(),
+            orders: database.orders.get_pool(),
+            pair_reserves: database.pair_reserves.get_pool(),
+            ron_changes: database.ron_balances.get_change_pool(),
+            native_transfers: database.native_transfers.get_pool(),
+            tokens: database.tokens.get_pool(),
+            governance_events: database.governance_events.get_pool(),
+            staking_events: database.staking_events.get_pool(),
+            equipment_events: database.equipment_events.get_pool(),
+            decode_failures: database.decode_failures.get_pool(),
+            anomalies: database.receipt_anomalies.get_pool(),
+            contract_events: database.contract_events.get_pool(),
+            subscriptions: subscriptions.iter().map(|s| s.get_pool()).collect(),
+            wallets: database.wallets.get_pool(),
+            first_block: None,
+            last_block: None,
+            blocks_pending: 0,
+            transactions_pending: 0,
+        }
+    }
+
+    fn add_block(&mut self, block: Block, transactions: usize) {
+        self.first_block.get_or_insert(block);
+        self.last_block = Some(block);
+        self.blocks_pending += 1;
+        self.transactions_pending += transactions;
+    }
+
+    /// Pending inserts and updates of all pools.
+    fn len(&self) -> usize {
+        [
+            self.blocks.len(),
+            self.transactions.len(),
+            self.erc_transfers.len(),
+            self.erc1155_transfers.len(),
+            self.sales.len(),
+            self.auctions.len(),
+            self.orders.len(),
+            self.pair_reserves.len(),
+            self.ron_changes.len(),
+            self.native_transfers.len(),
+            self.tokens.len(),
+            self.governance_events.len(),
+            self.staking_events.len(),
+            self.equipment_events.len(),
+            self.decode_failures.len(),
+            self.anomalies.len(),
+            self.contract_events.len(),
+            self.wallets.len(),
+        ]
+        .iter()
+        .sum::<usize>()
+            + self.subscriptions.iter().map(Pool::len).sum::<usize>()
+    }
+
+    /// Writes the pools of the enabled features in the transaction of `session`, see
+    /// [`Pool::write_in`].
+    async fn write_in(
+        &mut self,
+        args: &Args,
+        session: &mut ClientSession,
+    ) -> mongodb::error::Result<()> {
+        if args.feature_blocks {
+            self.blocks.write_in(false, session).await?;
+        }
+        if args.feature_transactions {
+            self.transactions.write_in(false, session).await?;
+        }
+        if args.feature_erc_transfers {
+            self.erc_transfers.write_in(true, session).await?;
+            self.erc1155_transfers.write_in(true, session).await?;
+        }
+        if args.feature_erc_721_sales {
+            self.sales.write_in(true, session).await?;
+            self.auctions.write_in(false, session).await?;
+            self.orders.write_in(false, session).await?;
+        }
+        if args.feature_wallet_updates {
+            self.wallets.write_in(true, session).await?;
+        }
+        if args.feature_pair_reserves {
+            self.pair_reserves.write_in(false, session).await?;
+        }
+        if args.feature_ron_balances {
+            self.ron_changes.write_in(false, session).await?;
+        }
+        if args.feature_native_transfers {
+            self.native_transfers.write_in(false, session).await?;
+        }
+        if args.feature_governance {
+            self.governance_events.write_in(false, session).await?;
+        }
+        if args.feature_staking {
+            self.staking_events.write_in(false, session).await?;
+        }
+        if args.feature_axie_equipment {
+            self.equipment_events.write_in(false, session).await?;
+        }
+        self.tokens.write_in(false, session).await?;
+        self.decode_failures.write_in(false, session).await?;
+        self.anomalies.write_in(false, session).await?;
+        self.contract_events.write_in(false, session).await?;
+        for pool in self.subscriptions.iter_mut() {
+            pool.write_in(false, session).await?;
+        }
+
+        Ok(())
+    }
+
+    fn discard_write(&mut self) {
+        self.blocks.discard_write();
+        self.transactions.discard_write();
+        self.erc_transfers.discard_write();
+        self.erc1155_transfers.discard_write();
+        self.sales.discard_write();
+        self.auctions.discard_write();
+        self.orders.discard_write();
+        self.pair_reserves.discard_write();
+        self.ron_changes.discard_write();
+        self.native_transfers.discard_write();
+        self.tokens.discard_write();
+        self.governance_events.discard_write();
+        self.staking_events.discard_write();
+        self.equipment_events.discard_write();
+        self.decode_failures.discard_write();
+        self.anomalies.discard_write();
+        self.contract_events.discard_write();
+        self.subscriptions
+            .iter_mut()
+            .for_each(|pool| pool.discard_write());
+        self.wallets.discard_write();
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SuppressedDuplicates {
+    pub count: u64,
+    pub last_block: Block,
+}
+
+impl SettingsValue for SuppressedDuplicates {}
+
 impl Ronin {
     pub fn transfer_events() -> HashMap<ContractType, Event> {
         let mut map: HashMap<ContractType, Event> = HashMap::new();
@@ -138,6 +770,165 @@ impl Ronin {
             },
         );
 
+        map.insert(
+            Erc1155Bulk,
+            Event {
+                name: "TransferBatch".to_string(),
+                inputs: vec![
+                    EventParam {
+                        name: "operator".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "from".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "to".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "ids".to_string(),
+                        kind: Array(Box::new(Uint(256))),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "values".to_string(),
+                        kind: Array(Box::new(Uint(256))),
+                        indexed: false,
+                    },
+                ],
+                anonymous: false,
+            },
+        );
+
+        let proposal_id = || EventParam {
+            name: "transactionId".to_string(),
+            kind: Uint(256),
+            indexed: true,
+        };
+        let sender = || EventParam {
+            name: "sender".to_string(),
+            kind: Address,
+            indexed: true,
+        };
+
+        for (contract_type, name, inputs) in [
+            (MultisigSubmission, "Submission", vec![proposal_id()]),
+            (
+                MultisigConfirmation,
+                "Confirmation",
+                vec![sender(), proposal_id()],
+            ),
+            (
+                MultisigRevocation,
+                "Revocation",
+                vec![sender(), proposal_id()],
+            ),
+            (MultisigExecution, "Execution", vec![proposal_id()]),
+            (
+                MultisigExecutionFailure,
+                "ExecutionFailure",
+                vec![proposal_id()],
+            ),
+        ] {
+            map.insert(
+                contract_type,
+                Event {
+                    name: name.to_string(),
+                    inputs,
+                    anonymous: false,
+                },
+            );
+        }
+
+        let wallet = |name: &str| EventParam {
+            name: name.to_string(),
+            kind: Address,
+            indexed: true,
+        };
+        let amount = || EventParam {
+            name: "amount".to_string(),
+            kind: Uint(256),
+            indexed: false,
+        };
+
+        for (contract_type, name, inputs) in [
+            (StakingStaked, "Staked", vec![wallet("user"), amount()]),
+            (StakingUnstaked, "Unstaked", vec![wallet("user"), amount()]),
+            (
+                StakingRewardClaimed,
+                "RewardClaimed",
+                vec![wallet("user"), amount()],
+            ),
+            (
+                StakingDelegated,
+                "Delegated",
+                vec![wallet("delegator"), wallet("consensusAddr"), amount()],
+            ),
+            (
+                StakingUndelegated,
+                "Undelegated",
+                vec![wallet("delegator"), wallet("consensusAddr"), amount()],
+            ),
+        ] {
+            map.insert(
+                contract_type,
+                Event {
+                    name: name.to_string(),
+                    inputs,
+                    anonymous: false,
+                },
+            );
+        }
+
+        let item = |name: &str, indexed: bool, kind: ParamType| EventParam {
+            name: name.to_string(),
+            kind,
+            indexed,
+        };
+
+        for (contract_type, name) in [
+            (AxieItemEquipped, "ItemEquipped"),
+            (AxieItemUnequipped, "ItemUnequipped"),
+        ] {
+            map.insert(
+                contract_type,
+                Event {
+                    name: name.to_string(),
+                    inputs: vec![
+                        item("axieId", true, Uint(256)),
+                        item("item", true, Address),
+                        item("itemId", false, Uint(256)),
+                    ],
+                    anonymous: false,
+                },
+            );
+        }
+
+        map.insert(
+            KatanaPair,
+            Event {
+                name: "Sync".to_string(),
+                inputs: vec![
+                    EventParam {
+                        name: "reserve0".to_string(),
+                        kind: Uint(112),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "reserve1".to_string(),
+                        kind: Uint(112),
+                        indexed: false,
+                    },
+                ],
+                anonymous: false,
+            },
+        );
+
         map.insert(
             ERC20,
             Event {
@@ -198,6 +989,51 @@ impl Ronin {
             },
         );
 
+        map.insert(
+            LegacyAuctionCreated,
+            Event {
+                name: "AuctionCreated".to_string(),
+                inputs: vec![
+                    EventParam {
+                        name: "_seller".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "_listingIndex".to_string(),
+                        kind: Uint(256),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "_startingPrices".to_string(),
+                        kind: Array(Box::new(Uint(256))),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "_endingPrices".to_string(),
+                        kind: Array(Box::new(Uint(256))),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "_exchangeTokens".to_string(),
+                        kind: Array(Box::new(Address)),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "_durations".to_string(),
+                        kind: Array(Box::new(Uint(256))),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "_startingTimestamps".to_string(),
+                        kind: Uint(256),
+                        indexed: false,
+                    },
+                ],
+                anonymous: false,
+            },
+        );
+
         map.insert(
             ERC721,
             Event {
@@ -288,29 +1124,160 @@ impl Ronin {
             },
         );
 
-        map
-    }
-
-    pub fn contract_list() -> ContractList {
-        let mut map: ContractList = ContractList::new();
-
         map.insert(
-            "0x814a9c959a3ef6ca44b5e2349e3bba9845393947",
-            Contract {
-                name: "CHARM",
-                decimals: 0,
-                erc: ERC1155,
-                address: "0x814a9c959a3ef6ca44b5e2349e3bba9845393947",
+            MarketplaceV2OrderCreated,
+            Event {
+                name: "OrderCreated".to_string(),
+                inputs: vec![
+                    EventParam {
+                        name: "hash".to_string(),
+                        kind: FixedBytes(32),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "maker".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "kind".to_string(),
+                        kind: Uint(8),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "token".to_string(),
+                        kind: Address,
+                        indexed: true,
+                    },
+                    EventParam {
+                        name: "tokenId".to_string(),
+                        kind: Uint(256),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "paymentToken".to_string(),
+                        kind: Address,
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "price".to_string(),
+                        kind: Uint(256),
+                        indexed: false,
+                    },
+                    EventParam {
+                        name: "expiredAt".to_string(),
+                        kind: Uint(256),
+                        indexed: false,
+                    },
+                ],
+                anonymous: false,
             },
         );
 
         map.insert(
-            "0xc25970724f032af21d801978c73653c440cf787c",
-            Contract {
-                name: "RUNE",
-                decimals: 0,
+            MarketplaceV2OrderCancelled,
+            Event {
+                name: "OrderCancelled".to_string(),
+                inputs: vec![EventParam {
+                    name: "hash".to_string(),
+                    kind: FixedBytes(32),
+                    indexed: false,
+                }],
+                anonymous: false,
+            },
+        );
+
+        map
+    }
+
+    /// Loads the tracked contracts from a TOML or JSON file (by extension) with a `contracts`
+    /// array. Must be called before the contract list is used.
+    pub fn load_contract_list(path: &Path) {
+        let content = fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Failed to read contracts {}: {err}", path.display()));
+
+        let file: ContractFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml_edit::de::from_str(&content)
+                .unwrap_or_else(|err| panic!("Invalid contracts {}: {err}", path.display())),
+            _ => serde_json::from_str(&content)
+                .unwrap_or_else(|err| panic!("Invalid contracts {}: {err}", path.display())),
+        };
+
+        let mut contracts = ContractList::new();
+        for config in file.contracts {
+            let address: &'static str = Box::leak(config.address.to_lowercase().into_boxed_str());
+            contracts.insert(
+                address,
+                Contract {
+                    name: Box::leak(config.name.into_boxed_str()),
+                    decimals: config.decimals,
+                    erc: config.erc,
+                    address,
+                    events: config.events,
+                    deploy_block: config.deploy_block,
+                },
+            );
+        }
+
+        info!(
+            "[INFO] Loaded {} contracts from {}",
+            contracts.len(),
+            path.display()
+        );
+
+        if CONFIGURED_CONTRACTS.set(contracts).is_err() {
+            panic!("Contract list loaded twice!");
+        }
+    }
+
+    /// Sets the contract whose `ItemEquipped`/`ItemUnequipped` events are indexed, equipment is
+    /// not tracked without one.
+    pub fn set_equipment_contract(address: &str) {
+        if EQUIPMENT_CONTRACT.set(address.to_lowercase()).is_err() {
+            panic!("Equipment contract set twice!");
+        }
+    }
+
+    /// Sets the multisig contracts whose proposal events are indexed, governance events are not
+    /// indexed without them.
+    pub fn set_governance_contracts(addresses: &[String]) {
+        let addresses = addresses
+            .iter()
+            .map(|address| address.to_lowercase())
+            .collect();
+        if GOVERNANCE_CONTRACTS.set(addresses).is_err() {
+            panic!("Governance contracts set twice!");
+        }
+    }
+
+    pub fn contract_list() -> ContractList {
+        if let Some(contracts) = CONFIGURED_CONTRACTS.get() {
+            return contracts.to_owned();
+        }
+
+        let mut map: ContractList = ContractList::new();
+
+        map.insert(
+            "0x814a9c959a3ef6ca44b5e2349e3bba9845393947",
+            Contract {
+                name: "CHARM",
+                decimals: 0,
+                erc: ERC1155,
+                address: "0x814a9c959a3ef6ca44b5e2349e3bba9845393947",
+                events: EventType::all(),
+                deploy_block: 0,
+            },
+        );
+
+        map.insert(
+            "0xc25970724f032af21d801978c73653c440cf787c",
+            Contract {
+                name: "RUNE",
+                decimals: 0,
                 erc: ERC1155,
                 address: "0xc25970724f032af21d801978c73653c440cf787c",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -321,6 +1288,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -331,6 +1300,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0x97a9107c1793bc407d6f527b77e7fff4d812bece",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -341,6 +1312,8 @@ impl Ronin {
                 decimals: 0,
                 erc: ERC20,
                 address: "0xa8754b9fa15fc18bb59458815510e40a12cd2014",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -351,6 +1324,8 @@ impl Ronin {
                 decimals: 0,
                 erc: ERC20,
                 address: "0x173a2d4fa585a63acd02c107d57f932be0a71bcc",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -361,6 +1336,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0x0b7007c13325c48911f73a2dad5fa5dcbf808adc",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -371,6 +1348,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0xe514d9deb7966c8be0ca922de8a064264ea6bcd4",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -381,6 +1360,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0xc6344bc1604fcab1a5aad712d766796e2b7a70b9",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -391,6 +1372,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0x306a28279d04a47468ed83d55088d0dcd1369294",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -401,6 +1384,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0x2ecb08f87f075b5769fe543d0e52e40140575ea7",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -411,6 +1396,8 @@ impl Ronin {
                 decimals: 18,
                 erc: ERC20,
                 address: "0xa7964991f339668107e2b6a6f6b8e8b74aa9d017",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -421,6 +1408,8 @@ impl Ronin {
                 decimals: 0,
                 erc: ERC721,
                 address: "0x32950db2a7164ae833121501c797d79e7b79d74c",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -431,6 +1420,8 @@ impl Ronin {
                 decimals: 0,
                 erc: ERC721,
                 address: "0x8c811e3c958e190f5ec15fb376533a3398620500",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
@@ -441,12 +1432,137 @@ impl Ronin {
                 decimals: 0,
                 erc: ERC721,
                 address: "0xa96660f0e4a3e9bc7388925d245a6d4d79e21259",
+                events: EventType::all(),
+                deploy_block: 0,
             },
         );
 
         map
     }
 
+    /// Built in contract list extended by the tokens of the `tokens` collection, the official
+    /// deployments and the contracts discovered from their transfer events. Build it once per
+    /// command, not per block or log.
+    pub async fn tracked_contracts(&self) -> ContractList {
+        let mut contracts = Ronin::contract_list();
+        for token in self.database.tokens.tracked().await {
+            Ronin::track_token(&mut contracts, &token);
+        }
+        contracts
+    }
+
+    /// Adds a discovered token to `contracts`.
+    fn track_token(contracts: &mut ContractList, token: &DiscoveredToken) {
+        if contracts.contains_key(token.address.as_str()) {
+            return;
+        }
+
+        let address = intern(&token.address);
+        contracts.insert(
+            address,
+            Contract {
+                name: intern(&token.symbol),
+                decimals: token.decimals,
+                erc: token.erc.to_owned(),
+                address,
+                events: EventType::all(),
+                deploy_block: token.block,
+            },
+        );
+    }
+
+    /// Classifies the untracked emitters of transfer events in `receipt` and adds them to
+    /// `contracts`. TransferSingle/TransferBatch emitters are ERC1155, Transfer emitters are told
+    /// apart by their topic count: ERC721 indexes the token id, ERC20 does not index the value.
+    /// Returns the new tokens.
+    async fn discover_contracts(
+        &self,
+        receipt: &TransactionReceipt,
+        block: Block,
+        contracts: &mut ContractList,
+        ignored: &mut HashSet<H160>,
+    ) -> Vec<DiscoveredToken> {
+        let mut discovered = vec![];
+
+        for log in receipt.logs.iter() {
+            let address = self.to_string(&log.address);
+            if contracts.contains_key(address.as_str()) || ignored.contains(&log.address) {
+                continue;
+            }
+
+            let erc = if self.has_topic(log, ERC1155_TRANSFER_SINGLE_TOPIC)
+                || self.has_topic(log, ERC1155_TRANSFER_BATCH_TOPIC)
+            {
+                ERC1155
+            } else if log.topics.first() == Some(&H256::from_str(ERC_TRANSFER_TOPIC).unwrap()) {
+                match log.topics.len() {
+                    3 => ERC20,
+                    4 => ERC721,
+                    _ => {
+                        ignored.insert(log.address);
+                        continue;
+                    }
+                }
+            } else {
+                continue;
+            };
+
+            let decimals = match erc {
+                ERC20 => self
+                    .call_decode(
+                        log.address,
+                        ERC20_DECIMALS_SELECTOR.to_vec(),
+                        ParamType::Uint(8),
+                        block,
+                    )
+                    .await
+                    .and_then(Token::into_uint)
+                    .map(|d| d.low_u64() as usize),
+                _ => None,
+            };
+            let name = self
+                .call_decode(
+                    log.address,
+                    ERC20_NAME_SELECTOR.to_vec(),
+                    ParamType::String,
+                    block,
+                )
+                .await
+                .and_then(Token::into_string)
+                .unwrap_or_default();
+            let symbol = self
+                .call_decode(
+                    log.address,
+                    ERC20_SYMBOL_SELECTOR.to_vec(),
+                    ParamType::String,
+                    block,
+                )
+                .await
+                .and_then(Token::into_string)
+                .unwrap_or_else(|| address.to_owned());
+
+            info!(
+                "[INFO] Discovered {:?} contract {} ({})",
+                erc, symbol, address
+            );
+
+            let token = DiscoveredToken {
+                address,
+                name,
+                symbol,
+                decimals: decimals.unwrap_or(0),
+                erc,
+                deployer: String::new(),
+                official: false,
+                block,
+            };
+            Ronin::track_token(contracts, &token);
+            discovered.push(token);
+        }
+
+        discovered
+    }
+
     pub fn to_string<T: serde::Serialize>(&self, request: &T) -> String {
         web3::helpers::to_string(request).replace('\"', "")
     }
@@ -463,6 +1579,16 @@ impl Ronin {
     }
 
     pub async fn new(hostname: &str, database: Database) -> Ronin {
+        Ronin::from_parts(Ronin::connect(hostname).await, database)
+    }
+
+    /// Connection to `hostname`, opened on first use and shared afterwards.
+    pub async fn connect(hostname: &str) -> Provider {
+        let mut providers = PROVIDERS.lock().await;
+        if let Some(provider) = providers.get(hostname) {
+            return provider.to_owned();
+        }
+
         let parsed = Url::parse(hostname)
             .unwrap_or_else(|_| panic!("Failed to parse web3 hostname: {}", &hostname));
         let provider = match parsed.scheme() {
@@ -482,579 +1608,4221 @@ impl Ronin {
             _ => panic!("Invalid provider type"),
         };
 
+        let provider = Web3::new(RateLimited::new(provider));
+        providers.insert(hostname.to_string(), provider.to_owned());
+
+        provider
+    }
+
+    pub fn from_parts(provider: Provider, database: Database) -> Ronin {
         Ronin {
-            provider: Web3::new(provider),
+            provider,
             database,
+            events: Ronin::transfer_events(),
         }
     }
 
-    async fn legacy_erc_sale(&self, tx: &TransactionReceipt) -> Option<Sale> {
-        if !tx.logs.is_empty() {
-            let contracts: Vec<&str> = Ronin::contract_list()
-                .values()
-                .filter(|c| c.erc == ERC721)
-                .map(|c| c.address)
-                .collect();
+    fn has_topic(&self, log: &Log, topic: &str) -> bool {
+        log.topics.iter().any(|t| self.to_string(t) == topic)
+    }
 
-            let sale_log = tx
-                .logs
-                .iter()
-                .filter(|x| {
-                    match x
-                        .topics
-                        .iter()
-                        .find(|x| self.to_string(x) == MARKETPLACE_AXIE_SALE_TOPIC)
-                    {
-                        None => false,
-                        Some(_) => true,
-                    }
-                })
-                .collect::<Vec<&Log>>();
+    fn decode_failure(
+        &self,
+        decoder: &ContractType,
+        stage: DecodeStage,
+        log: &Log,
+        error: String,
+    ) -> Box<DecodeFailure> {
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
 
-            match sale_log.is_empty() {
-                true => None,
-                false => {
-                    let transfer_log = tx
-                        .logs
-                        .iter()
-                        .filter(|x| {
-                            self.to_string(&x.topics[0]) == ERC_TRANSFER_TOPIC
-                                && contracts.contains(&self.to_string(&x.address).as_str())
-                        })
-                        .collect::<Vec<&Log>>();
-
-                    if !transfer_log.is_empty() {
-                        let parsed_sale = Ronin::transfer_events()
-                            .get(&LegacyErc721Sale)
-                            .unwrap()
-                            .parse_log(RawLog {
-                                topics: sale_log[0].to_owned().topics,
-                                data: sale_log[0].to_owned().data.0,
-                            })
-                            .unwrap();
-
-                        let parsed_transfer = Ronin::transfer_events()
-                            .get(&ERC721)
-                            .unwrap()
-                            .parse_log(RawLog {
-                                topics: transfer_log[0].to_owned().topics,
-                                data: transfer_log[0].to_owned().data.0,
-                            })
-                            .unwrap();
-
-                        let block_data = self
-                            .provider
-                            .eth()
-                            .block(BlockId::Number(BlockNumber::from(tx.block_number.unwrap())))
-                            .await
-                            .unwrap()
-                            .unwrap();
-
-                        Some(Sale {
-                            seller: self.prefix(
-                                &self.to_string(&parsed_sale.params[0].value.to_string()),
-                                AddressPrefix::Ethereum,
-                            ),
-                            buyer: self.prefix(
-                                &self.to_string(&parsed_sale.params[1].value.to_string()),
-                                AddressPrefix::Ethereum,
-                            ),
-                            price: self.to_string(&parsed_sale.params[4].value.to_string()),
-                            seller_received: self
-                                .to_string(&parsed_sale.params[4].value.to_string()),
-                            token: self.prefix(
-                                &self.to_string(&parsed_sale.params[3].value.to_string()),
-                                AddressPrefix::Ethereum,
-                            ),
-                            token_id: self.to_string(&parsed_transfer.params[2].value.to_string()),
-                            transaction_id: self.to_string(&tx.transaction_hash),
-                            created_at: DateTime::from_millis(
-                                block_data.timestamp.as_u64() as i64 * 1000,
-                            ),
-                            block: tx.block_number.unwrap().as_u64(),
-                        })
-                    } else {
-                        None
-                    }
-                }
+        Box::new(DecodeFailure {
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            block: log.block_number.map(|b| b.as_u64()).unwrap_or_default(),
+            log_index,
+            address: self.to_string(&log.address),
+            topics: log.topics.iter().map(|t| self.to_string(t)).collect(),
+            data: self.prefix(&hex::encode(&log.data.0), AddressPrefix::Ethereum),
+            decoder: decoder.to_owned(),
+            stage,
+            error,
+            retries: 0,
+            created_at: DateTime::now(),
+        })
+    }
+
+    fn parse_log(
+        &self,
+        decoder: &ContractType,
+        stage: DecodeStage,
+        log: &Log,
+    ) -> DecodeResult<web3::ethabi::Log> {
+        let event = match self.events.get(decoder) {
+            None => {
+                return Err(self.decode_failure(
+                    decoder,
+                    stage,
+                    log,
+                    f!("No event definition for {:?}", decoder),
+                ))
             }
-        } else {
-            None
-        }
+            Some(event) => event,
+        };
+
+        event
+            .parse_log(RawLog {
+                topics: log.topics.to_owned(),
+                data: log.data.0.to_owned(),
+            })
+            .map_err(|err| self.decode_failure(decoder, stage, log, err.to_string()))
     }
 
-    fn has_order_matched(&self, logs: &Vec<Log>) -> Option<Log> {
-        let log = logs
-            .iter()
-            .filter(|l| l.topics[0] == MARKETPLACE_V2_ORDER_MATCHED_TOPIC.parse().unwrap())
-            .collect::<Vec<&Log>>();
-        let log = log.first();
+    fn dead_letter(&self, failures: &mut Vec<DecodeFailure>, failure: DecodeFailure) {
+        warn!(
+            "[WARN] Failed to decode log {} of transaction {} as {:?}: {}",
+            failure.log_index, failure.transaction_id, failure.decoder, failure.error
+        );
+        failures.push(failure);
+    }
+
+    pub fn decode_erc1155_transfer(
+        &self,
+        log: &Log,
+        block: Block,
+    ) -> DecodeResult<ERC1155Transfer> {
+        let event_data = self.parse_log(&ERC1155, DecodeStage::Transfer, log)?;
+
+        let operator = self.to_string(&event_data.params[0].value.to_string());
+        let operator = f!("0x{operator}");
+
+        let from = self.to_string(&event_data.params[1].value.to_string());
+        let from = f!("0x{from}");
+
+        let to = self.to_string(&event_data.params[2].value.to_string());
+        let to = f!("0x{to}");
 
-        match log {
-            None => None,
-            Some(l) => Some(l.clone().to_owned()),
+        let token_id = self.to_string(&event_data.params[3].value.to_string());
+
+        let value = self.to_string(&event_data.params[4].value.to_string());
+
+        let signature = ERC1155Transfer::get_transfer_id(
+            &self.to_string(&log.transaction_hash),
+            &self.to_string(&log.log_index),
+        );
+
+        Ok(ERC1155Transfer {
+            token: self.to_string(&log.address),
+            operator,
+            from,
+            to,
+            token_id,
+            value,
+            block,
+            transaction_id: self.to_string(&log.transaction_hash),
+            log_index: self.to_string(&log.log_index),
+            log_id: signature,
+            spam: false,
+            log_index_num: None,
+            token_id_num: None,
+            value_num: None,
         }
+        .with_numeric())
     }
 
-    pub async fn order_matched(&self, tx: &TransactionReceipt) -> Option<Sale> {
-        if !tx.logs.is_empty() {
-            if let Some(matched_order) = self.has_order_matched(&tx.logs) {
-                let contracts: Vec<&str> = Ronin::contract_list()
-                    .values()
-                    .filter(|c| c.erc == ERC721)
-                    .map(|c| c.address)
-                    .collect();
+    /// Splits a TransferBatch log into one transfer per token id. The position within the batch
+    /// is part of the log id so every entry stays unique.
+    pub fn decode_erc1155_batch_transfer(
+        &self,
+        log: &Log,
+        block: Block,
+    ) -> DecodeResult<Vec<ERC1155Transfer>> {
+        let event_data = self.parse_log(&Erc1155Bulk, DecodeStage::Transfer, log)?;
 
-                let rl = RawLog {
-                    topics: matched_order.topics,
-                    data: matched_order.data.0,
-                };
-                let parsed_sale_data = Ronin::transfer_events()
-                    .get(&MarketplaceV2)
-                    .unwrap()
-                    .parse_log(rl)
-                    .unwrap();
+        let operator = self.to_string(&event_data.params[0].value.to_string());
+        let from = self.to_string(&event_data.params[1].value.to_string());
+        let to = self.to_string(&event_data.params[2].value.to_string());
 
-                let erc_transfer_log_opt = tx
-                    .logs
-                    .iter()
-                    .find(|c| contracts.contains(&self.to_string(&c.address).as_str()))
-                    .map(|log| log.to_owned());
-
-                if erc_transfer_log_opt != None {
-                    let erc_transfer_log = erc_transfer_log_opt.unwrap();
-                    let erc_transfer = Ronin::transfer_events()
-                        .get(&ERC721)
-                        .unwrap()
-                        .parse_log(RawLog {
-                            topics: erc_transfer_log.topics,
-                            data: erc_transfer_log.data.0,
-                        })
-                        .unwrap();
+        let ids = event_data.params[3].value.to_owned().into_array();
+        let values = event_data.params[4].value.to_owned().into_array();
 
-                    let block_data = self
-                        .provider
-                        .eth()
-                        .block(BlockId::Number(BlockNumber::from(tx.block_number.unwrap())))
-                        .await
-                        .unwrap()
-                        .unwrap();
-                    Some(Sale {
-                        seller: self.prefix(
-                            &self.to_string(&parsed_sale_data.params[1].value.to_string()),
-                            AddressPrefix::Ethereum,
-                        ),
-                        buyer: self.prefix(
-                            &self.to_string(&parsed_sale_data.params[2].value.to_string()),
-                            AddressPrefix::Ethereum,
-                        ),
-                        price: self.to_string(&parsed_sale_data.params[7].value.to_string()),
-                        seller_received: self
-                            .to_string(&parsed_sale_data.params[8].value.to_string()),
-                        token: self.to_string(&erc_transfer_log.address),
-                        token_id: self.to_string(&erc_transfer.params[2].value.to_string()),
-                        transaction_id: self.to_string(&tx.transaction_hash),
-                        created_at: DateTime::from_millis(
-                            block_data.timestamp.as_u64() as i64 * 1000,
-                        ),
-                        block: tx.block_number.unwrap().as_u64(),
-                    })
-                } else {
-                    return None;
-                }
-            } else {
-                return None;
+        let (ids, values) = match (ids, values) {
+            (Some(ids), Some(values)) if ids.len() == values.len() => (ids, values),
+            _ => {
+                return Err(self.decode_failure(
+                    &Erc1155Bulk,
+                    DecodeStage::Transfer,
+                    log,
+                    "Mismatched ids and values in TransferBatch".to_string(),
+                ))
             }
-        } else {
-            return None;
-        }
+        };
+
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
+
+        Ok(ids
+            .iter()
+            .zip(values.iter())
+            .enumerate()
+            .map(|(position, (token_id, value))| {
+                ERC1155Transfer {
+                    token: self.to_string(&log.address),
+                    operator: f!("0x{operator}"),
+                    from: f!("0x{from}"),
+                    to: f!("0x{to}"),
+                    token_id: token_id.to_string(),
+                    value: value.to_string(),
+                    block,
+                    transaction_id: transaction_id.to_owned(),
+                    log_index: log_index.to_owned(),
+                    log_id: ERC1155Transfer::get_transfer_id(
+                        &transaction_id,
+                        &f!("{log_index}-{position}"),
+                    ),
+                    spam: false,
+                    log_index_num: None,
+                    token_id_num: None,
+                    value_num: None,
+                }
+                .with_numeric()
+            })
+            .collect())
     }
 
-    pub async fn stream(&self, offset: u64, args: Args) {
-        if args.debug {
-            debug!("W A R N I N G");
-            debug!("DEBUG MODE ENABLED! NOT SAVING ANYTHING TO DATABASE!");
-            debug!("Start Block: {}", args.debug_start_block);
-            debug!("Stop Block: {}", args.debug_stop_block);
-            thread::sleep(Duration::new(1, 0));
-        }
+    pub fn decode_pair_reserve(
+        &self,
+        log: &Log,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<PairReserve> {
+        let event_data = self.parse_log(&KatanaPair, DecodeStage::Transfer, log)?;
 
-        if args.replay {
-            if args.debug {
-                debug!("Can't replay while in debug mode!!!");
-            }
-            debug!("W A R N I N G");
-            debug!("About to drop ANY data stored in the database for this app!");
-            debug!("Waiting 15 seconds...");
-            thread::sleep(Duration::new(15, 0));
-            self.database
-                .settings
-                .collection
-                .drop(None)
-                .await
-                .expect("Failed to drop settings collection");
-            self.database
-                .wallets
-                .collection
-                .drop(None)
-                .await
-                .expect("Failed to drop wallets collection");
-            self.database
-                .transactions
-                .collection
-                .drop(None)
-                .await
-                .expect("Failed to drop transactions collection");
-            self.database
-                .erc_transfers
-                .collection
-                .drop(None)
-                .await
-                .expect("Failed to drop erc_transfers collection");
-            self.database
-                .erc1155_transfers
-                .collection
-                .drop(None)
-                .await
-                .expect("Failed to drop erc1155_transfers collection");
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
 
-            self.database.create_indexes().await;
+        Ok(PairReserve {
+            pair: self.to_string(&log.address),
+            reserve0: self.to_string(&event_data.params[0].value.to_string()),
+            reserve1: self.to_string(&event_data.params[1].value.to_string()),
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        })
+    }
+
+    fn governance_event_type(&self, log: &Log) -> Option<&(ContractType, GovernanceAction)> {
+        if !GOVERNANCE_CONTRACTS
+            .get()?
+            .contains(&self.to_string(&log.address))
+        {
+            return None;
         }
 
-        let contracts = Ronin::contract_list();
-        let transfer_events = Ronin::transfer_events();
+        let topic = log.topics.first()?;
+        GOVERNANCE_EVENTS.iter().find(|(contract_type, _)| {
+            self.events
+                .get(contract_type)
+                .map(|event| event.signature() == *topic)
+                .unwrap_or(false)
+        })
+    }
 
-        let chain_head_block = self
-            .provider
-            .eth()
-            .block_number()
-            .await
-            .expect("Failed to retrieve head block number from chain!");
+    pub fn decode_governance_event(
+        &self,
+        log: &Log,
+        contract_type: &ContractType,
+        action: GovernanceAction,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<GovernanceEvent> {
+        let event_data = self.parse_log(contract_type, DecodeStage::Governance, log)?;
+
+        let param = |name: &str| {
+            event_data
+                .params
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| self.to_string(&p.value.to_string()))
+        };
 
-        let mut stream_stop_block: Block = chain_head_block.as_u64() - offset;
+        let proposal_id = param("transactionId").ok_or_else(|| {
+            self.decode_failure(
+                contract_type,
+                DecodeStage::Governance,
+                log,
+                "Missing transactionId".to_string(),
+            )
+        })?;
+        let sender = param("sender").map(|sender| f!("0x{sender}"));
 
-        if args.debug {
-            stream_stop_block = args.debug_stop_block;
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
+
+        Ok(GovernanceEvent {
+            contract: self.to_string(&log.address),
+            proposal_id,
+            action,
+            sender,
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        })
+    }
+
+    fn staking_event_type(&self, log: &Log) -> Option<&(ContractType, StakingAction)> {
+        if !STAKING_CONTRACTS.contains(&self.to_string(&log.address).as_str()) {
+            return None;
         }
 
-        let opts = FindOneOptions::builder()
-            .sort(doc! {
-                "block": -1i64
-            })
-            .build();
+        let topic = log.topics.first()?;
+        STAKING_EVENTS.iter().find(|(contract_type, _)| {
+            self.events
+                .get(contract_type)
+                .map(|event| event.signature() == *topic)
+                .unwrap_or(false)
+        })
+    }
 
-        let latest_tx = self
-            .database
-            .transactions
-            .collection
-            .find_one(None, opts)
-            .await;
+    pub fn decode_staking_event(
+        &self,
+        log: &Log,
+        contract_type: &ContractType,
+        action: StakingAction,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<StakingEvent> {
+        let event_data = self.parse_log(contract_type, DecodeStage::Transfer, log)?;
 
-        let mut start: u64 = match latest_tx.unwrap() {
-            None => 1,
-            Some(settings) => settings.block,
+        let param = |name: &str| {
+            event_data
+                .params
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| self.to_string(&p.value.to_string()))
         };
 
-        if args.debug || args.debug_start_block > 0 {
-            start = args.debug_start_block;
-        }
+        let wallet = param("user")
+            .or_else(|| param("delegator"))
+            .ok_or_else(|| {
+                self.decode_failure(
+                    contract_type,
+                    DecodeStage::Transfer,
+                    log,
+                    "Missing staker".to_string(),
+                )
+            })?;
 
-        let mut largest_block_by_tx_num: LargestBlock =
-            match self.database.settings.get("largest_block_by_tx_num").await {
-                None => LargestBlock {
-                    number: 0,
-                    tx_num: 0,
-                },
-                Some(settings) => serde_json::from_str(settings.value.as_str()).unwrap(),
-            };
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
 
-        if start > stream_stop_block {
-            info!("[INFO] Offset not large enough. Exiting!");
-            return;
+        Ok(StakingEvent {
+            contract: self.to_string(&log.address),
+            action,
+            wallet: f!("0x{wallet}"),
+            validator: param("consensusAddr").map(|validator| f!("0x{validator}")),
+            amount: param("amount").unwrap_or_default(),
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        })
+    }
+
+    fn equipment_event_type(&self, log: &Log) -> Option<&(ContractType, EquipmentAction)> {
+        if Some(&self.to_string(&log.address)) != EQUIPMENT_CONTRACT.get() {
+            return None;
         }
 
-        info!("[INFO] Streaming from {} to {}", &start, &stream_stop_block);
+        let topic = log.topics.first()?;
+        EQUIPMENT_EVENTS.iter().find(|(contract_type, _)| {
+            self.events
+                .get(contract_type)
+                .map(|event| event.signature() == *topic)
+                .unwrap_or(false)
+        })
+    }
 
-        let mut current_block: Block = start.to_owned();
-        let mut wallet_pool: Pool<Wallet> = self.database.wallets.get_pool();
+    /// Decodes an equipment event. The owner is taken from the ERC1155 movement of the item into
+    /// (equip) or out of (unequip) the equipment contract in the same receipt.
+    pub fn decode_equipment_event(
+        &self,
+        receipt: &TransactionReceipt,
+        log: &Log,
+        contract_type: &ContractType,
+        action: EquipmentAction,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<EquipmentEvent> {
+        let event_data = self.parse_log(contract_type, DecodeStage::Transfer, log)?;
 
-        loop {
-            let block = self
-                .provider
-                .eth()
-                .block_with_txs(BlockId::Number(BlockNumber::from(current_block as u64)))
-                .await
-                .unwrap_or_else(|_| panic!("Failed to load block {} from provider!", current_block))
-                .unwrap_or_else(|| panic!("Failed to unwrap block {} from result!", current_block));
+        let param = |name: &str| {
+            event_data
+                .params
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| self.to_string(&p.value.to_string()))
+        };
 
-            let block_number: u64 = block.number.unwrap().as_u64();
-            let timestamp = block.timestamp.as_u64() * 1000;
-            let timestamp = DateTime::from_millis(i64::try_from(timestamp).unwrap());
-            let num_txs = block.transactions.len();
+        let (axie_id, token, token_id) = match (param("axieId"), param("item"), param("itemId")) {
+            (Some(axie_id), Some(token), Some(token_id)) => (axie_id, f!("0x{token}"), token_id),
+            _ => {
+                return Err(self.decode_failure(
+                    contract_type,
+                    DecodeStage::Transfer,
+                    log,
+                    "Missing axie or item".to_string(),
+                ))
+            }
+        };
 
-            if num_txs > 0 {
-                if !args.debug && num_txs as u64 > largest_block_by_tx_num.tx_num {
-                    largest_block_by_tx_num = LargestBlock {
-                        number: block_number,
-                        tx_num: num_txs as u64,
-                    };
-                    self.database
-                        .settings
-                        .set(
-                            "largest_block_by_tx_num",
-                            &serde_json::to_string(&largest_block_by_tx_num).unwrap(),
-                        )
-                        .await
-                        .expect("Failed to store largest_block_by_tx_num!");
-                }
+        let contract = self.to_string(&log.address);
+        let owner = receipt
+            .logs
+            .iter()
+            .filter(|l| {
+                self.to_string(&l.address) == token
+                    && self.has_topic(l, ERC1155_TRANSFER_SINGLE_TOPIC)
+            })
+            .filter_map(|l| self.decode_erc1155_transfer(l, block).ok())
+            .find(|transfer| transfer.token_id == token_id)
+            .and_then(|transfer| match action {
+                EquipmentAction::Equipped if transfer.to == contract => Some(transfer.from),
+                EquipmentAction::Unequipped if transfer.from == contract => Some(transfer.to),
+                _ => None,
+            });
 
-                let mut tx_pool: Vec<Transaction> = vec![];
-                let mut erc_pool: Pool<ERCTransfer> = self.database.erc_transfers.get_pool();
-                let mut erc1155_pool: Pool<ERC1155Transfer> =
-                    self.database.erc1155_transfers.get_pool();
-                let mut erc_sale_pool: Pool<Sale> = self.database.erc_sales.get_pool();
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
 
-                for tx in block.transactions {
-                    let tx_from = self.to_string(&tx.from);
-                    let tx_to = self.to_string(&tx.to);
-                    let tx_hash = self.to_string(&tx.hash);
+        Ok(EquipmentEvent {
+            contract,
+            action,
+            axie_id,
+            token,
+            token_id,
+            owner,
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        })
+    }
+
+    fn order_event_type(&self, log: &Log) -> Option<&(ContractType, OrderStatus)> {
+        if self.to_string(&log.address) != MARKETPLACE_V2_CONTRACT {
+            return None;
+        }
+
+        let topic = log.topics.first()?;
+        ORDER_EVENTS.iter().find(|(contract_type, _)| {
+            self.events
+                .get(contract_type)
+                .map(|event| event.signature() == *topic)
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn decode_order_event(
+        &self,
+        log: &Log,
+        contract_type: &ContractType,
+        status: OrderStatus,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<OrderEvent> {
+        let event_data = self.parse_log(contract_type, DecodeStage::Order, log)?;
+
+        let param = |name: &str| {
+            event_data
+                .params
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| self.to_string(&p.value.to_string()))
+        };
+        let address = |name: &str| param(name).map(|address| f!("0x{address}"));
+
+        let hash = address("hash").ok_or_else(|| {
+            self.decode_failure(
+                contract_type,
+                DecodeStage::Order,
+                log,
+                "Missing order hash".to_string(),
+            )
+        })?;
+
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
+
+        Ok(OrderEvent {
+            hash,
+            status,
+            maker: address("maker"),
+            matcher: address("matcher"),
+            kind: param("kind"),
+            token: address("token"),
+            token_id: param("tokenId"),
+            payment_token: address("paymentToken"),
+            price: param("price").or_else(|| param("settlePrice")),
+            expired_at: param("expiredAt"),
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        })
+    }
+
+    pub fn decode_erc_transfer(
+        &self,
+        log: &Log,
+        contract: &Contract,
+        block: Block,
+    ) -> DecodeResult<ERCTransfer> {
+        let event_data = self.parse_log(&contract.erc, DecodeStage::Transfer, log)?;
+
+        let from = self.to_string(&event_data.params[0].value.to_string());
+        let from = f!("0x{from}");
+
+        let to = self.to_string(&event_data.params[1].value.to_string());
+        let to = f!("0x{to}");
+
+        let signature = ERCTransfer::get_transfer_id(
+            &self.to_string(&log.transaction_hash),
+            &self.to_string(&log.log_index),
+        );
+
+        let value_decimal = match contract.erc {
+            ERC20 => event_data.params[2]
+                .value
+                .clone()
+                .into_uint()
+                .map(|value| decimal_amount(value, contract.decimals)),
+            _ => None,
+        };
+
+        Ok(ERCTransfer {
+            from,
+            to,
+            token: self.to_string(&log.address),
+            value_or_token_id: self.to_string(&event_data.params[2].value.to_string()),
+            value_decimal,
+            token_id: None,
+            block,
+            transaction_id: self.to_string(&log.transaction_hash),
+            erc: contract.erc.to_owned(),
+            log_index: self.to_string(&log.log_index),
+            log_id: signature,
+            spam: false,
+            log_index_num: None,
+        }
+        .with_numeric())
+    }
+
+    fn legacy_erc_sale(
+        &self,
+        tx: &TransactionReceipt,
+        timestamp: DateTime,
+    ) -> DecodeResult<Option<Sale>> {
+        if tx.logs.is_empty() {
+            return Ok(None);
+        }
+
+        let contracts: Vec<&str> = Ronin::contract_list()
+            .values()
+            .filter(|c| c.erc == ERC721 && c.indexes(EventType::Sale))
+            .map(|c| c.address)
+            .collect();
+
+        let sale_log = match tx
+            .logs
+            .iter()
+            .find(|x| self.has_topic(x, MARKETPLACE_AXIE_SALE_TOPIC))
+        {
+            None => return Ok(None),
+            Some(log) => log,
+        };
+
+        let transfer_log = match tx.logs.iter().find(|x| {
+            self.to_string(&x.topics[0]) == ERC_TRANSFER_TOPIC
+                && contracts.contains(&self.to_string(&x.address).as_str())
+        }) {
+            None => return Ok(None),
+            Some(log) => log,
+        };
+
+        let parsed_sale = self.parse_log(&LegacyErc721Sale, DecodeStage::Sale, sale_log)?;
+        let parsed_transfer = self.parse_log(&ERC721, DecodeStage::Sale, transfer_log)?;
+
+        Ok(Some(
+            Sale {
+                seller: self.prefix(
+                    &self.to_string(&parsed_sale.params[0].value.to_string()),
+                    AddressPrefix::Ethereum,
+                ),
+                buyer: self.prefix(
+                    &self.to_string(&parsed_sale.params[1].value.to_string()),
+                    AddressPrefix::Ethereum,
+                ),
+                price: self.to_string(&parsed_sale.params[4].value.to_string()),
+                seller_received: self.to_string(&parsed_sale.params[4].value.to_string()),
+                token: self.prefix(
+                    &self.to_string(&parsed_sale.params[3].value.to_string()),
+                    AddressPrefix::Ethereum,
+                ),
+                token_id: self.to_string(&parsed_transfer.params[2].value.to_string()),
+                quantity: None,
+                transaction_id: self.to_string(&tx.transaction_hash),
+                created_at: timestamp,
+                block: tx.block_number.unwrap().as_u64(),
+                listing_index: Some(self.to_string(&parsed_sale.params[2].value.to_string())),
+                payment_token: None,
+                market_fee: None,
+                market_fee_percentage: None,
+                bid_token: None,
+                order_kind: None,
+                order_hash: None,
+                log_index: Some(self.to_string(&sale_log.log_index)),
+                bundle_id: None,
+                price_num: None,
+                seller_received_num: None,
+                token_id_num: None,
+                quantity_num: None,
+            }
+            .with_numeric(),
+        ))
+    }
+
+    fn is_auction_created(&self, log: &Log) -> bool {
+        match (log.topics.first(), self.events.get(&LegacyAuctionCreated)) {
+            (Some(topic), Some(event)) => event.signature() == *topic,
+            _ => false,
+        }
+    }
+
+    pub fn decode_auction(
+        &self,
+        log: &Log,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<Auction> {
+        let event_data = self.parse_log(&LegacyAuctionCreated, DecodeStage::Transfer, log)?;
+
+        let values = |index: usize| -> Vec<String> {
+            event_data.params[index]
+                .value
+                .to_owned()
+                .into_array()
+                .unwrap_or_default()
+                .iter()
+                .map(|value| self.to_string(&value.to_string()))
+                .collect()
+        };
+
+        let transaction_id = self.to_string(&log.transaction_hash);
+        let log_index = self.to_string(&log.log_index);
+
+        Ok(Auction {
+            seller: self.prefix(
+                &self.to_string(&event_data.params[0].value.to_string()),
+                AddressPrefix::Ethereum,
+            ),
+            listing_index: self.to_string(&event_data.params[1].value.to_string()),
+            starting_prices: values(2),
+            ending_prices: values(3),
+            exchange_tokens: values(4)
+                .iter()
+                .map(|token| self.prefix(token, AddressPrefix::Ethereum))
+                .collect(),
+            durations: values(5),
+            starting_timestamp: self.to_string(&event_data.params[6].value.to_string()),
+            block,
+            created_at: timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+        })
+    }
+
+    /// Decodes the OrderMatched events of a transaction. Cart checkouts settle several orders in
+    /// one transaction: each order is paired with an unused ERC721 transfer or ERC1155 single
+    /// transfer from its maker (or the next unused one) and the sales share the transaction hash as
+    /// `bundle_id`.
+    pub fn order_matched(
+        &self,
+        tx: &TransactionReceipt,
+        timestamp: DateTime,
+    ) -> DecodeResult<Vec<Sale>> {
+        let orders: Vec<&Log> = tx
+            .logs
+            .iter()
+            .filter(|l| self.has_topic(l, MARKETPLACE_V2_ORDER_MATCHED_TOPIC))
+            .collect();
+
+        if orders.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let contracts: HashMap<&str, ContractType> = Ronin::contract_list()
+            .values()
+            .filter(|c| (c.erc == ERC721 || c.erc == ERC1155) && c.indexes(EventType::Sale))
+            .map(|c| (c.address, c.erc.to_owned()))
+            .collect();
+
+        // (log, from, token id, ERC1155 value) of every transfer an order can be paired with.
+        let mut transfers = vec![];
+        for log in tx.logs.iter() {
+            let (from, token_id, quantity) =
+                match contracts.get(self.to_string(&log.address).as_str()) {
+                    Some(ERC721) if self.has_topic(log, ERC_TRANSFER_TOPIC) => {
+                        let transfer = self.parse_log(&ERC721, DecodeStage::Sale, log)?;
+                        (
+                            transfer.params[0].value.to_owned(),
+                            transfer.params[2].value.to_owned(),
+                            None,
+                        )
+                    }
+                    Some(ERC1155) if self.has_topic(log, ERC1155_TRANSFER_SINGLE_TOPIC) => {
+                        let transfer = self.parse_log(&ERC1155, DecodeStage::Sale, log)?;
+                        (
+                            transfer.params[1].value.to_owned(),
+                            transfer.params[3].value.to_owned(),
+                            Some(self.to_string(&transfer.params[4].value.to_string())),
+                        )
+                    }
+                    _ => continue,
+                };
+            transfers.push(Some((log, from, token_id, quantity)));
+        }
+
+        let bundle_id = match orders.len() {
+            1 => None,
+            _ => Some(self.to_string(&tx.transaction_hash)),
+        };
+
+        let mut sales = vec![];
+        for order in orders {
+            let parsed_sale_data = self.parse_log(&MarketplaceV2, DecodeStage::Sale, order)?;
+            let maker = &parsed_sale_data.params[1].value;
+
+            let index = transfers
+                .iter()
+                .position(|t| matches!(t, Some((_, from, _, _)) if from == maker))
+                .or_else(|| transfers.iter().position(|t| t.is_some()));
+            let (transfer_log, _, token_id, quantity) =
+                match index.and_then(|i| transfers[i].take()) {
+                    None => continue,
+                    Some(transfer) => transfer,
+                };
+
+            sales.push(
+                Sale {
+                    seller: self.prefix(
+                        &self.to_string(&parsed_sale_data.params[1].value.to_string()),
+                        AddressPrefix::Ethereum,
+                    ),
+                    buyer: self.prefix(
+                        &self.to_string(&parsed_sale_data.params[2].value.to_string()),
+                        AddressPrefix::Ethereum,
+                    ),
+                    price: self.to_string(&parsed_sale_data.params[7].value.to_string()),
+                    seller_received: self.to_string(&parsed_sale_data.params[8].value.to_string()),
+                    token: self.to_string(&transfer_log.address),
+                    token_id: self.to_string(&token_id.to_string()),
+                    quantity,
+                    transaction_id: self.to_string(&tx.transaction_hash),
+                    created_at: timestamp,
+                    block: tx.block_number.unwrap().as_u64(),
+                    listing_index: None,
+                    payment_token: Some(self.prefix(
+                        &self.to_string(&parsed_sale_data.params[6].value.to_string()),
+                        AddressPrefix::Ethereum,
+                    )),
+                    market_fee: Some(
+                        self.to_string(&parsed_sale_data.params[10].value.to_string()),
+                    ),
+                    market_fee_percentage: Some(
+                        self.to_string(&parsed_sale_data.params[9].value.to_string()),
+                    ),
+                    bid_token: Some(self.prefix(
+                        &self.to_string(&parsed_sale_data.params[4].value.to_string()),
+                        AddressPrefix::Ethereum,
+                    )),
+                    order_kind: parsed_sale_data.params[3]
+                        .value
+                        .to_owned()
+                        .into_uint()
+                        .map(|kind| kind.low_u32() as u8),
+                    order_hash: Some(self.prefix(
+                        &self.to_string(&parsed_sale_data.params[0].value.to_string()),
+                        AddressPrefix::Ethereum,
+                    )),
+                    log_index: Some(self.to_string(&order.log_index)),
+                    bundle_id: bundle_id.to_owned(),
+                    price_num: None,
+                    seller_received_num: None,
+                    token_id_num: None,
+                    quantity_num: None,
+                }
+                .with_numeric(),
+            );
+        }
+
+        Ok(sales)
+    }
+
+    /// Decodes the sales of a transaction with the marketplace generation active at `block`.
+    fn decode_sale(
+        &self,
+        receipt: &TransactionReceipt,
+        block: Block,
+        timestamp: DateTime,
+    ) -> DecodeResult<Vec<Sale>> {
+        if block > MARKETPLACE_V2_DEPLOY_BLOCK {
+            self.order_matched(receipt, timestamp)
+        } else {
+            self.legacy_erc_sale(receipt, timestamp)
+                .map(|sale| sale.into_iter().collect())
+        }
+    }
+
+    /// Decodes all indexed event logs of a transaction receipt. Logs that fail to decode are
+    /// returned as dead letters instead of aborting the block.
+    pub fn decode_receipt(
+        &self,
+        receipt: &TransactionReceipt,
+        block_number: Block,
+        timestamp: DateTime,
+        contracts: &ContractList,
+        features: DecodeFeatures,
+    ) -> DecodedReceipt {
+        let mut decoded = DecodedReceipt::default();
+        let failures = &mut decoded.decode_failures;
+        // Contracts outside the registry index every event type.
+        let indexes = |log: &Log, event: EventType| {
+            contracts
+                .get(self.to_string(&log.address).as_str())
+                .is_none_or(|contract| contract.indexes(event))
+        };
+
+        if features.erc_721_sales {
+            match self.decode_sale(receipt, block_number, timestamp) {
+                Ok(sales) => {
+                    for sale in sales {
+                        debug!("[MARKETPLACE SALE] {:#?}", sale);
+                        decoded.sales.push(sale);
+                    }
+                }
+                Err(failure) => self.dead_letter(failures, *failure),
+            }
+
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|l| self.is_auction_created(l) && indexes(l, EventType::Sale))
+            {
+                match self.decode_auction(log, block_number, timestamp) {
+                    Ok(auction) => {
+                        debug!("[MARKETPLACE AUCTION] {:#?}", auction);
+                        decoded.auctions.push(auction);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+
+            for log in receipt.logs.iter().filter(|l| indexes(l, EventType::Order)) {
+                let (contract_type, status) = match self.order_event_type(log) {
+                    None => continue,
+                    Some(order_event) => order_event,
+                };
+
+                match self.decode_order_event(log, contract_type, *status, block_number, timestamp)
+                {
+                    Ok(order) => {
+                        debug!("[MARKETPLACE ORDER] {:#?}", order);
+                        decoded.orders.push(order);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+        }
+
+        if features.pair_reserves {
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|l| self.has_topic(l, PAIR_SYNC_TOPIC) && indexes(l, EventType::Reserve))
+            {
+                match self.decode_pair_reserve(log, block_number, timestamp) {
+                    Ok(reserve) => {
+                        debug!("[PAIR RESERVE] {:#?}", reserve);
+                        decoded.pair_reserves.push(reserve);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+        }
+
+        if features.governance {
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|l| indexes(l, EventType::Governance))
+            {
+                let (contract_type, action) = match self.governance_event_type(log) {
+                    None => continue,
+                    Some(governance_event) => governance_event,
+                };
+
+                match self.decode_governance_event(
+                    log,
+                    contract_type,
+                    *action,
+                    block_number,
+                    timestamp,
+                ) {
+                    Ok(event) => {
+                        debug!("[GOVERNANCE] {:#?}", event);
+                        decoded.governance_events.push(event);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+        }
+
+        if features.staking {
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|l| indexes(l, EventType::Staking))
+            {
+                let (contract_type, action) = match self.staking_event_type(log) {
+                    None => continue,
+                    Some(staking_event) => staking_event,
+                };
+
+                match self.decode_staking_event(
+                    log,
+                    contract_type,
+                    *action,
+                    block_number,
+                    timestamp,
+                ) {
+                    Ok(event) => {
+                        debug!("[STAKING] {:#?}", event);
+                        decoded.staking_events.push(event);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+        }
+
+        if features.axie_equipment {
+            for log in receipt
+                .logs
+                .iter()
+                .filter(|l| indexes(l, EventType::Equipment))
+            {
+                let (contract_type, action) = match self.equipment_event_type(log) {
+                    None => continue,
+                    Some(equipment_event) => equipment_event,
+                };
+
+                match self.decode_equipment_event(
+                    receipt,
+                    log,
+                    contract_type,
+                    *action,
+                    block_number,
+                    timestamp,
+                ) {
+                    Ok(event) => {
+                        debug!("[AXIE EQUIPMENT] {:#?}", event);
+                        decoded.equipment_events.push(event);
+                    }
+                    Err(failure) => self.dead_letter(failures, *failure),
+                }
+            }
+        }
+
+        if features.erc_transfers {
+            for log in receipt.logs.iter() {
+                let contract = match contracts
+                    .get(self.to_string(&log.address).as_str())
+                    .filter(|c| c.indexes(EventType::Transfer))
+                {
+                    None => continue,
+                    Some(contract) => contract,
+                };
+
+                if block_number > ERC1155_DEPLOY_BLOCK
+                    && self.has_topic(log, ERC1155_TRANSFER_SINGLE_TOPIC)
+                {
+                    match self.decode_erc1155_transfer(log, block_number) {
+                        Ok(transfer) => {
+                            debug!("[ERC1155 Transfer] {:#?}", transfer);
+                            decoded.erc1155_transfers.push(transfer)
+                        }
+                        Err(failure) => self.dead_letter(failures, *failure),
+                    }
+                }
+
+                if block_number > ERC1155_DEPLOY_BLOCK
+                    && self.has_topic(log, ERC1155_TRANSFER_BATCH_TOPIC)
+                {
+                    match self.decode_erc1155_batch_transfer(log, block_number) {
+                        Ok(transfers) => {
+                            for transfer in transfers {
+                                debug!("[ERC1155 Batch Transfer] {:#?}", transfer);
+                                decoded.erc1155_transfers.push(transfer)
+                            }
+                        }
+                        Err(failure) => self.dead_letter(failures, *failure),
+                    }
+                }
+
+                if self.has_topic(log, ERC_TRANSFER_TOPIC) {
+                    match self.decode_erc_transfer(log, contract, block_number) {
+                        Ok(transfer) => {
+                            debug!("[ERC Transfer] {:#?}", transfer);
+                            decoded.erc_transfers.push(transfer);
+                        }
+                        Err(failure) => self.dead_letter(failures, *failure),
+                    }
+                }
+            }
+        }
+
+        decoded
+    }
+
+    /// Checks a receipt for malformed logs: logs of another transaction or block, log indexes that
+    /// are not contiguous and documents decoded from logs that are not part of the receipt. Gaps
+    /// are only reported for `complete` receipts, logs mode receipts hold just the filtered logs.
+    pub fn receipt_anomalies(
+        &self,
+        receipt: &TransactionReceipt,
+        decoded: &DecodedReceipt,
+        complete: bool,
+    ) -> Vec<ReceiptAnomaly> {
+        let transaction_id = self.to_string(&receipt.transaction_hash);
+        let block = receipt.block_number.unwrap_or_default().as_u64();
+        let anomaly = |kind: AnomalyKind, log_index: &str, detail: String| ReceiptAnomaly {
+            transaction_id: transaction_id.to_owned(),
+            block,
+            kind,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, log_index),
+            detail,
+            created_at: DateTime::now(),
+        };
+
+        let mut anomalies = vec![];
+        let mut previous: Option<U256> = None;
+
+        for log in receipt.logs.iter() {
+            let log_index = self.to_string(&log.log_index);
+
+            if log.transaction_hash != Some(receipt.transaction_hash)
+                || log.block_number != receipt.block_number
+            {
+                anomalies.push(anomaly(
+                    AnomalyKind::ForeignLog,
+                    &log_index,
+                    f!(
+                        "Log of transaction {} in block {} in the receipt of block {block}",
+                        self.to_string(&log.transaction_hash),
+                        log.block_number.unwrap_or_default()
+                    ),
+                ));
+            }
+
+            if let (true, Some(previous), Some(index)) = (complete, previous, log.log_index) {
+                if index != previous + 1 {
+                    anomalies.push(anomaly(
+                        AnomalyKind::NonContiguousLogIndex,
+                        &log_index,
+                        f!("Log index {index} follows {previous}"),
+                    ));
+                }
+            }
+            previous = log.log_index;
+        }
+
+        let present: HashSet<String> = receipt
+            .logs
+            .iter()
+            .map(|log| self.to_string(&log.log_index))
+            .collect();
+
+        let referenced = decoded
+            .erc_transfers
+            .iter()
+            .map(|t| &t.log_index)
+            .chain(decoded.erc1155_transfers.iter().map(|t| &t.log_index))
+            .chain(decoded.sales.iter().filter_map(|s| s.log_index.as_ref()))
+            .chain(decoded.pair_reserves.iter().map(|r| &r.log_index))
+            .chain(decoded.governance_events.iter().map(|e| &e.log_index))
+            .chain(decoded.staking_events.iter().map(|e| &e.log_index))
+            .chain(decoded.orders.iter().map(|o| &o.log_index))
+            .chain(decoded.equipment_events.iter().map(|e| &e.log_index))
+            .collect::<HashSet<&String>>();
+
+        for log_index in referenced {
+            if !present.contains(log_index) {
+                anomalies.push(anomaly(
+                    AnomalyKind::MissingLog,
+                    log_index,
+                    f!("Decoded log {log_index} is not part of the receipt"),
+                ));
+            }
+        }
+
+        for anomaly in anomalies.iter() {
+            warn!(
+                "[WARN] Malformed receipt of transaction {}: {}",
+                anomaly.transaction_id, anomaly.detail
+            );
+        }
+
+        anomalies
+    }
+
+    /// Decodes the logs stored in the decode_failures collection again, for example after a decoder
+    /// fix. Logs that decode are written to their target collection and removed from the dead
+    /// letters, the others get their error and retry count updated.
+    pub async fn retry_decode_failures(&self) {
+        let mut cursor = self
+            .database
+            .decode_failures
+            .collection
+            .find(None, None)
+            .await
+            .expect("Failed to query decode failures!");
+
+        let contracts = self.tracked_contracts().await;
+        let mut resolved = 0;
+        let mut failing = 0;
+
+        while cursor
+            .advance()
+            .await
+            .expect("Failed to read decode failures!")
+        {
+            let failure: DecodeFailure = cursor
+                .deserialize_current()
+                .expect("Failed to deserialize decode failure!");
+
+            match self.retry_decode_failure(&failure, &contracts).await {
+                Ok(()) => {
+                    resolved += 1;
+                    self.database
+                        .decode_failures
+                        .collection
+                        .delete_one(doc! {"log_id": &failure.log_id}, None)
+                        .await
+                        .expect("Failed to remove decode failure!");
+                }
+                Err(error) => {
+                    failing += 1;
+                    warn!(
+                        "[WARN] Log {} of transaction {} still fails to decode: {}",
+                        failure.log_index, failure.transaction_id, error
+                    );
+                    self.database
+                        .decode_failures
+                        .collection
+                        .update_one(
+                            doc! {"log_id": &failure.log_id},
+                            doc! {
+                                "$set": {"error": error},
+                                "$inc": {"retries": 1}
+                            },
+                            None,
+                        )
+                        .await
+                        .expect("Failed to update decode failure!");
+                }
+            }
+        }
+
+        println!(
+            "Resolved {} decode failures, {} still failing",
+            resolved, failing
+        );
+    }
+
+    async fn retry_decode_failure(
+        &self,
+        failure: &DecodeFailure,
+        contracts: &ContractList,
+    ) -> Result<(), String> {
+        let hash = H256::from_str(&failure.transaction_id).map_err(|err| err.to_string())?;
+        let receipt = self
+            .provider
+            .eth()
+            .transaction_receipt(hash)
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "Transaction receipt not found".to_string())?;
+
+        match failure.stage {
+            DecodeStage::Sale => {
+                let timestamp = self.block_timestamp(failure.block).await?;
+
+                let mut pool = self.database.erc_sales.get_pool();
+                for sale in self
+                    .decode_sale(&receipt, failure.block, timestamp)
+                    .map_err(|f| f.error)?
+                {
+                    pool.insert(sale);
+                }
+                store(pool).await?;
+            }
+            DecodeStage::Order => {
+                let log = receipt
+                    .logs
+                    .iter()
+                    .find(|l| self.to_string(&l.log_index) == failure.log_index)
+                    .ok_or_else(|| "Log not found in transaction receipt".to_string())?;
+                let (contract_type, status) = ORDER_EVENTS
+                    .iter()
+                    .find(|(contract_type, _)| *contract_type == failure.decoder)
+                    .ok_or_else(|| "Not an order event".to_string())?;
+                let timestamp = self.block_timestamp(failure.block).await?;
+
+                let mut pool = self.database.orders.get_pool();
+                pool.insert(
+                    self.decode_order_event(log, contract_type, *status, failure.block, timestamp)
+                        .map_err(|f| f.error)?,
+                );
+                store(pool).await?;
+            }
+            DecodeStage::Governance => {
+                let log = receipt
+                    .logs
+                    .iter()
+                    .find(|l| self.to_string(&l.log_index) == failure.log_index)
+                    .ok_or_else(|| "Log not found in transaction receipt".to_string())?;
+                let (contract_type, action) = GOVERNANCE_EVENTS
+                    .iter()
+                    .find(|(contract_type, _)| *contract_type == failure.decoder)
+                    .ok_or_else(|| "Not a governance event".to_string())?;
+                let timestamp = self.block_timestamp(failure.block).await?;
+
+                let mut pool = self.database.governance_events.get_pool();
+                pool.insert(
+                    self.decode_governance_event(
+                        log,
+                        contract_type,
+                        *action,
+                        failure.block,
+                        timestamp,
+                    )
+                    .map_err(|f| f.error)?,
+                );
+                store(pool).await?;
+            }
+            DecodeStage::Transfer => {
+                let log = receipt
+                    .logs
+                    .iter()
+                    .find(|l| self.to_string(&l.log_index) == failure.log_index)
+                    .ok_or_else(|| "Log not found in transaction receipt".to_string())?;
+
+                if failure.decoder == ERC1155 {
+                    let mut pool = self.database.erc1155_transfers.get_pool();
+                    pool.insert(
+                        self.decode_erc1155_transfer(log, failure.block)
+                            .map_err(|f| f.error)?,
+                    );
+                    store(pool).await?;
+                } else if failure.decoder == LegacyAuctionCreated {
+                    let timestamp = self.block_timestamp(failure.block).await?;
+
+                    let mut pool = self.database.auctions.get_pool();
+                    pool.insert(
+                        self.decode_auction(log, failure.block, timestamp)
+                            .map_err(|f| f.error)?,
+                    );
+                    store(pool).await?;
+                } else if failure.decoder == KatanaPair {
+                    let timestamp = self.block_timestamp(failure.block).await?;
+
+                    let mut pool = self.database.pair_reserves.get_pool();
+                    pool.insert(
+                        self.decode_pair_reserve(log, failure.block, timestamp)
+                            .map_err(|f| f.error)?,
+                    );
+                    store(pool).await?;
+                } else if let Some((contract_type, action)) = STAKING_EVENTS
+                    .iter()
+                    .find(|(contract_type, _)| *contract_type == failure.decoder)
+                {
+                    let timestamp = self.block_timestamp(failure.block).await?;
+
+                    let mut pool = self.database.staking_events.get_pool();
+                    pool.insert(
+                        self.decode_staking_event(
+                            log,
+                            contract_type,
+                            *action,
+                            failure.block,
+                            timestamp,
+                        )
+                        .map_err(|f| f.error)?,
+                    );
+                    store(pool).await?;
+                } else if let Some((contract_type, action)) = EQUIPMENT_EVENTS
+                    .iter()
+                    .find(|(contract_type, _)| *contract_type == failure.decoder)
+                {
+                    let timestamp = self.block_timestamp(failure.block).await?;
+
+                    let event = self
+                        .decode_equipment_event(
+                            &receipt,
+                            log,
+                            contract_type,
+                            *action,
+                            failure.block,
+                            timestamp,
+                        )
+                        .map_err(|f| f.error)?;
+
+                    let mut pool = self.database.equipment_events.get_pool();
+                    pool.insert(event.clone());
+                    store(pool).await?;
+                    self.database
+                        .axie_equipment
+                        .apply(&event)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                } else if failure.decoder == Erc1155Bulk {
+                    let mut pool = self.database.erc1155_transfers.get_pool();
+                    for transfer in self
+                        .decode_erc1155_batch_transfer(log, failure.block)
+                        .map_err(|f| f.error)?
+                    {
+                        pool.insert(transfer);
+                    }
+                    store(pool).await?;
+                } else {
+                    let contract = contracts
+                        .get(failure.address.as_str())
+                        .ok_or_else(|| "Contract is not tracked anymore".to_string())?;
+
+                    let mut pool = self.database.erc_transfers.get_pool();
+                    pool.insert(
+                        self.decode_erc_transfer(log, contract, failure.block)
+                            .map_err(|f| f.error)?,
+                    );
+                    store(pool).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn cache_timestamp(block: Block, timestamp: DateTime) {
+        let mut timestamps = BLOCK_TIMESTAMPS.lock().expect("Timestamp cache poisoned!");
+        timestamps.insert(block, timestamp);
+        if timestamps.len() > BLOCK_TIMESTAMP_CACHE {
+            timestamps.pop_first();
+        }
+    }
+
+    fn cached_timestamp(block: Block) -> Option<DateTime> {
+        BLOCK_TIMESTAMPS
+            .lock()
+            .expect("Timestamp cache poisoned!")
+            .get(&block)
+            .copied()
+    }
+
+    async fn block_timestamp(&self, number: Block) -> Result<DateTime, String> {
+        if let Some(timestamp) = Ronin::cached_timestamp(number) {
+            return Ok(timestamp);
+        }
+
+        let block = self
+            .provider
+            .eth()
+            .block(BlockId::Number(BlockNumber::from(number)))
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "Block not found".to_string())?;
+
+        let timestamp = DateTime::from_millis(block.timestamp.as_u64() as i64 * 1000);
+        Ronin::cache_timestamp(number, timestamp);
+
+        Ok(timestamp)
+    }
+
+    async fn call_contract(&self, address: H160, data: Vec<u8>, block: Block) -> Option<Vec<u8>> {
+        self.provider
+            .eth()
+            .call(
+                CallRequest::builder().to(address).data(Bytes(data)).build(),
+                Some(BlockId::Number(BlockNumber::from(block))),
+            )
+            .await
+            .ok()
+            .map(|result| result.0)
+            .filter(|result| !result.is_empty())
+    }
+
+    async fn call_decode(
+        &self,
+        address: H160,
+        data: Vec<u8>,
+        kind: ParamType,
+        block: Block,
+    ) -> Option<Token> {
+        let result = self.call_contract(address, data, block).await?;
+        web3::ethabi::decode(&[kind], &result).ok()?.pop()
+    }
+
+    async fn supports_interface(&self, address: H160, interface: [u8; 4], block: Block) -> bool {
+        let mut data = SUPPORTS_INTERFACE_SELECTOR.to_vec();
+        data.extend(web3::ethabi::encode(&[Token::FixedBytes(
+            interface.to_vec(),
+        )]));
+
+        self.call_decode(address, data, ParamType::Bool, block)
+            .await
+            .and_then(Token::into_bool)
+            .unwrap_or(false)
+    }
+
+    /// Metadata URI of an NFT from `tokenURI` (ERC721) or `uri` (ERC1155), with the `{id}`
+    /// placeholder of ERC1155 URIs replaced.
+    pub async fn token_uri(
+        &self,
+        token: H160,
+        token_id: U256,
+        erc: &ContractType,
+        block: Block,
+    ) -> Option<String> {
+        let selector = match erc {
+            ERC721 => ERC721_TOKEN_URI_SELECTOR,
+            ERC1155 => ERC1155_URI_SELECTOR,
+            _ => return None,
+        };
+
+        let mut data = selector.to_vec();
+        data.extend(web3::ethabi::encode(&[Token::Uint(token_id)]));
+
+        let uri = self
+            .call_decode(token, data, ParamType::String, block)
+            .await
+            .and_then(Token::into_string)?;
+
+        Some(uri.replace("{id}", &format!("{:064x}", token_id)))
+    }
+
+    /// Probes a newly deployed contract for ERC1155, ERC721 (ERC165) and ERC20 (decimals) support
+    /// and reads its name and symbol. Returns `None` for contracts that are no token.
+    async fn discover_token(
+        &self,
+        address: H160,
+        deployer: &str,
+        official: bool,
+        block: Block,
+    ) -> Option<DiscoveredToken> {
+        let decimals = self
+            .call_decode(
+                address,
+                ERC20_DECIMALS_SELECTOR.to_vec(),
+                ParamType::Uint(8),
+                block,
+            )
+            .await
+            .and_then(Token::into_uint);
+
+        let erc = if self
+            .supports_interface(address, ERC1155_INTERFACE_ID, block)
+            .await
+        {
+            ERC1155
+        } else if self
+            .supports_interface(address, ERC721_INTERFACE_ID, block)
+            .await
+        {
+            ERC721
+        } else if decimals.is_some() {
+            ERC20
+        } else {
+            return None;
+        };
+
+        let name = self
+            .call_decode(
+                address,
+                ERC20_NAME_SELECTOR.to_vec(),
+                ParamType::String,
+                block,
+            )
+            .await
+            .and_then(Token::into_string)
+            .unwrap_or_default();
+        let symbol = self
+            .call_decode(
+                address,
+                ERC20_SYMBOL_SELECTOR.to_vec(),
+                ParamType::String,
+                block,
+            )
+            .await
+            .and_then(Token::into_string)
+            .unwrap_or_default();
+
+        Some(DiscoveredToken {
+            address: self.to_string(&address),
+            name,
+            symbol,
+            decimals: decimals.map(|d| d.low_u64() as usize).unwrap_or(0),
+            erc,
+            deployer: deployer.to_string(),
+            official,
+            block,
+        })
+    }
+
+    /// All receipts of a block in a single `eth_getBlockReceipts` round trip. Returns `Ok(None)`
+    /// when the node does not support the method and an error for failures that may be transient,
+    /// including a null or malformed response.
+    async fn block_receipts(
+        &self,
+        block: Block,
+    ) -> Result<Option<HashMap<H256, TransactionReceipt>>, String> {
+        let result = self
+            .provider
+            .transport()
+            .execute(
+                "eth_getBlockReceipts",
+                vec![serde_json::Value::String(f!("0x{block:x}"))],
+            )
+            .await;
+
+        match result {
+            Ok(value) => match serde_json::from_value::<Option<Vec<TransactionReceipt>>>(value) {
+                Ok(Some(receipts)) => Ok(Some(
+                    receipts
+                        .into_iter()
+                        .map(|receipt| (receipt.transaction_hash, receipt))
+                        .collect(),
+                )),
+                Ok(None) => Err("Provider returned null".to_string()),
+                Err(err) => Err(f!("Failed to parse response: {err}")),
+            },
+            Err(err) if unsupported_method(&err) => {
+                warn!("[WARN] eth_getBlockReceipts is not available: {err}");
+                Ok(None)
+            }
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Loads the receipts of the transactions of `block`, over eth_getBlockReceipts while the node
+    /// supports it and one eth_getTransactionReceipt per missing receipt otherwise. Failing calls
+    /// are retried with `backoff`, the error of the last attempt is returned. Only a node that
+    /// rejects eth_getBlockReceipts as unsupported clears `block_receipts_supported`, a block whose
+    /// eth_getBlockReceipts calls keep failing falls back to eth_getTransactionReceipt on its own.
+    async fn load_receipts(
+        &self,
+        block: &web3::types::Block<Web3Transaction>,
+        backoff: &Backoff,
+        block_receipts_supported: &mut bool,
+    ) -> Result<HashMap<H256, TransactionReceipt>, String> {
+        let number = block.number.unwrap_or_default().as_u64();
+        let receipts = match block_receipts_supported {
+            true => match backoff
+                .retry(&f!("block receipts of {number}"), || {
+                    self.block_receipts(number)
+                })
+                .await
+            {
+                Ok(None) => {
+                    warn!(
+                        "[WARN] Falling back to one eth_getTransactionReceipt call per transaction"
+                    );
+                    *block_receipts_supported = false;
+                    None
+                }
+                Ok(receipts) => receipts,
+                Err(err) => {
+                    warn!(
+                        "[WARN] Failed to load block receipts of {number}: {err}, loading the \
+                         receipts of its transactions instead"
+                    );
+                    None
+                }
+            },
+            false => None,
+        };
+
+        let mut receipts = receipts.unwrap_or_default();
+        for tx in block.transactions.iter() {
+            if receipts.contains_key(&tx.hash) {
+                continue;
+            }
+
+            let receipt = backoff
+                .retry(&f!("receipt of {}", self.to_string(&tx.hash)), || async {
+                    match self.provider.eth().transaction_receipt(tx.hash).await {
+                        Ok(Some(receipt)) => Ok(receipt),
+                        Ok(None) => Err("Provider returned null".to_string()),
+                        Err(err) => Err(err.to_string()),
+                    }
+                })
+                .await?;
+            receipts.insert(tx.hash, receipt);
+        }
+
+        Ok(receipts)
+    }
+
+    /// Loads block `number` and the receipts of its transactions, as far as `needs` asks for.
+    async fn fetch_block(
+        &self,
+        number: Block,
+        args: &Args,
+        fallback: Option<&Provider>,
+        block_receipts_supported: &AtomicBool,
+        needs: &FetchNeeds,
+    ) -> FetchedBlock {
+        let (block, transactions) = match self.load_block(number, args, fallback, needs).await {
+            Some((block, transactions)) => (Some(block), transactions),
+            None => (None, 0),
+        };
+        let receipts_loaded = block
+            .as_ref()
+            .is_some_and(|block| needs.may_contain_logs(block));
+
+        let receipts = match &block {
+            Some(block) if receipts_loaded && !block.transactions.is_empty() => {
+                let mut supported = block_receipts_supported.load(Ordering::Relaxed);
+                let receipts = self
+                    .load_receipts(block, &Backoff::from(args), &mut supported)
+                    .await;
+                block_receipts_supported.store(supported, Ordering::Relaxed);
+                receipts
+            }
+            _ => Ok(HashMap::new()),
+        };
+
+        FetchedBlock {
+            block,
+            transactions,
+            receipts_loaded,
+            receipts,
+        }
+    }
+
+    /// Block `id` from `provider` and its number of transactions. Without `bodies` only the
+    /// transaction hashes are requested and the transactions of the block are left empty.
+    async fn request_block(
+        provider: &Provider,
+        id: BlockId,
+        bodies: bool,
+    ) -> web3::Result<Option<(web3::types::Block<Web3Transaction>, usize)>> {
+        if bodies {
+            let block = provider.eth().block_with_txs(id).await?;
+            return Ok(block.map(|block| {
+                let transactions = block.transactions.len();
+                (block, transactions)
+            }));
+        }
+
+        let Some(block) = provider.eth().block(id).await? else {
+            return Ok(None);
+        };
+        let transactions = block.transactions.len();
+        let header = web3::types::Block {
+            hash: block.hash,
+            parent_hash: block.parent_hash,
+            uncles_hash: block.uncles_hash,
+            author: block.author,
+            state_root: block.state_root,
+            transactions_root: block.transactions_root,
+            receipts_root: block.receipts_root,
+            number: block.number,
+            gas_used: block.gas_used,
+            gas_limit: block.gas_limit,
+            base_fee_per_gas: block.base_fee_per_gas,
+            extra_data: block.extra_data,
+            logs_bloom: block.logs_bloom,
+            timestamp: block.timestamp,
+            difficulty: block.difficulty,
+            total_difficulty: block.total_difficulty,
+            seal_fields: block.seal_fields,
+            uncles: block.uncles,
+            transactions: vec![],
+            size: block.size,
+            mix_hash: block.mix_hash,
+            nonce: block.nonce,
+        };
+
+        Ok(Some((header, transactions)))
+    }
+
+    /// Loads a block with its transactions, retrying null responses and provider errors
+    /// `--missing-block-retries` times with backoff. Blocks that are still missing are handled according to
+    /// `--missing-block-policy`; `None` means the block was recorded in `missing_blocks` and is
+    /// skipped.
+    async fn load_block(
+        &self,
+        number: Block,
+        args: &Args,
+        fallback: Option<&Provider>,
+        needs: &FetchNeeds,
+    ) -> Option<(web3::types::Block<Web3Transaction>, usize)> {
+        let id = BlockId::Number(BlockNumber::from(number));
+        let backoff = Backoff {
+            retries: args.missing_block_retries,
+            ..Backoff::from(args)
+        };
+
+        let mut error = match backoff
+            .retry(&f!("block {number}"), || async {
+                match Ronin::request_block(&self.provider, id, needs.bodies).await {
+                    Ok(Some(block)) => Ok(block),
+                    Ok(None) => Err("Provider returned null".to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .await
+        {
+            Ok(block) => return Some(block),
+            Err(error) => error,
+        };
+
+        match args.missing_block_policy {
+            MissingBlockPolicy::Fail => {
+                panic!("Failed to load block {} from provider: {}", number, error)
+            }
+            MissingBlockPolicy::Fallback => {
+                if let Some(provider) = fallback {
+                    match Ronin::request_block(provider, id, needs.bodies).await {
+                        Ok(Some(block)) => {
+                            info!("[INFO] Loaded block {} from the fallback provider", number);
+                            return Some(block);
+                        }
+                        Ok(None) => error = "Fallback provider returned null".to_string(),
+                        Err(err) => error = f!("Fallback provider: {err}"),
+                    }
+                }
+            }
+            MissingBlockPolicy::Skip => {}
+        }
+
+        warn!("[WARN] Skipping block {}: {}", number, error);
+        if !args.debug {
+            self.database
+                .missing_blocks
+                .record(&MissingBlock {
+                    block: number,
+                    error,
+                    attempts: args.missing_block_retries + 1,
+                    created_at: DateTime::now(),
+                })
+                .await
+                .expect("Failed to record missing block!");
+        }
+
+        None
+    }
+
+    /// Native RON balance changes of a transaction: the sender pays value and gas fee, the
+    /// recipient receives the value of successful transactions.
+    fn ron_balance_changes(
+        &self,
+        tx: &Web3Transaction,
+        receipt: &TransactionReceipt,
+        block: Block,
+    ) -> Vec<RonBalanceChange> {
+        let gas_price = receipt
+            .effective_gas_price
+            .or(tx.gas_price)
+            .unwrap_or_default();
+        let fee = receipt
+            .gas_used
+            .unwrap_or_default()
+            .saturating_mul(gas_price);
+        let succeeded = receipt.status.map(|s| s.as_u64() == 1).unwrap_or(true);
+        let value = if succeeded { tx.value } else { U256::zero() };
+
+        let mut changes: HashMap<H160, (U256, U256)> = HashMap::new();
+        if let Some(from) = tx.from {
+            changes.entry(from).or_default().1 = value.saturating_add(fee);
+        }
+        if let Some(to) = tx.to {
+            let received = &mut changes.entry(to).or_default().0;
+            *received = received.saturating_add(value);
+        }
+
+        let transaction_id = self.to_string(&tx.hash);
+        changes
+            .into_iter()
+            .filter(|(_, (received, spent))| !received.is_zero() || !spent.is_zero())
+            .map(|(address, (received, spent))| RonBalanceChange {
+                address: self.to_string(&address),
+                block,
+                transaction_id: transaction_id.to_owned(),
+                received: format!("{:x}", received),
+                spent: format!("{:x}", spent),
+            })
+            .collect()
+    }
+
+    fn transaction(
+        &self,
+        tx: &Web3Transaction,
+        receipt: &TransactionReceipt,
+        block: Block,
+        timestamp: DateTime,
+    ) -> Transaction {
+        Transaction {
+            from: f!("0x{}", self.to_string(&tx.from)),
+            to: f!("0x{}", self.to_string(&tx.to)),
+            hash: self.to_string(&tx.hash),
+            block,
+            timestamp,
+            history: vec![],
+            status: receipt.status.map(|status| status.as_u64()),
+            gas_used: receipt.gas_used.map(|gas| format!("{:x}", gas)),
+            effective_gas_price: receipt
+                .effective_gas_price
+                .or(tx.gas_price)
+                .map(|price| format!("{:x}", price)),
+            nonce: tx.nonce.low_u64(),
+            value: format!("{:x}", tx.value),
+            contract_created: receipt
+                .contract_address
+                .map(|address| self.to_string(&address)),
+            value_num: None,
+            gas_used_num: None,
+            effective_gas_price_num: None,
+        }
+        .with_numeric()
+    }
+
+    /// RON moved by the value of a successful transaction.
+    fn native_transfer(
+        &self,
+        tx: &Web3Transaction,
+        receipt: &TransactionReceipt,
+        timestamp: DateTime,
+    ) -> Option<NativeTransfer> {
+        let succeeded = receipt.status.map(|s| s.as_u64() == 1).unwrap_or(true);
+        if tx.value.is_zero() || !succeeded {
+            return None;
+        }
+
+        Some(NativeTransfer {
+            from: self.to_string(&tx.from?),
+            to: self.to_string(&tx.to?),
+            value: format!("{:x}", tx.value),
+            block: tx.block_number?.as_u64(),
+            timestamp,
+            transaction_id: self.to_string(&tx.hash),
+        })
+    }
+
+    /// Applies the balance changes committed up to `block` to `ron_balances`. Newly inserted
+    /// changes are always applied. Changes that were already indexed are only applied to wallets
+    /// whose balance was last updated before the block of the change, that is when an earlier run
+    /// stored the change but stopped before updating the balance. Re-running a range that
+    /// completed does not apply them twice.
+    async fn update_ron_balances(&self, changes: &Pool<RonBalanceChange>, block: Block) {
+        let duplicated = changes.duplicated();
+        let mut changes: Vec<&RonBalanceChange> = changes.inserted().iter().collect();
+        if !duplicated.is_empty() {
+            let addresses: Vec<&String> = duplicated.iter().map(|change| &change.address).collect();
+            let last_blocks = self.database.ron_balances.last_blocks(&addresses).await;
+            changes.extend(duplicated.iter().filter(|change| {
+                last_blocks
+                    .get(&change.address)
+                    .is_none_or(|last_block| *last_block < change.block)
+            }));
+        }
+
+        let mut deltas: HashMap<&str, i128> = HashMap::new();
+
+        for change in changes {
+            let received = U256::from_str_radix(&change.received, 16).unwrap_or_default();
+            let spent = U256::from_str_radix(&change.spent, 16).unwrap_or_default();
+
+            if received.bits() > 126 || spent.bits() > 126 {
+                warn!(
+                    "[WARN] Skipping RON balance change of {} in {}: value out of range",
+                    change.address, change.transaction_id
+                );
+                continue;
+            }
+
+            *deltas.entry(change.address.as_str()).or_default() +=
+                received.as_u128() as i128 - spent.as_u128() as i128;
+        }
+
+        if deltas.is_empty() {
+            return;
+        }
+
+        let mut balance_pool = self.database.ron_balances.get_pool();
+        for (address, delta) in deltas {
+            balance_pool.update(self.database.ron_balances.update(
+                &address.to_string(),
+                delta,
+                block,
+            ));
+        }
+
+        balance_pool
+            .commit(true)
+            .await
+            .expect("Failed to update RON balances");
+    }
+
+    /// Applies the ERC20 transfers committed up to `block` to `balances`. Mints and burns only
+    /// change the balance of the wallet side. Transfers that were already indexed are only applied
+    /// to balances last updated before their block, like in [`Ronin::update_ron_balances`].
+    async fn update_erc20_balances(&self, transfers: &Pool<ERCTransfer>, block: Block) {
+        let duplicated: Vec<&ERCTransfer> = transfers
+            .duplicated()
+            .iter()
+            .filter(|t| t.erc == ERC20)
+            .collect();
+        let last_blocks = match duplicated.is_empty() {
+            true => HashMap::new(),
+            false => {
+                let wallets: HashSet<&WalletAddress> =
+                    duplicated.iter().flat_map(|t| [&t.from, &t.to]).collect();
+                let tokens: HashSet<&WalletAddress> = duplicated.iter().map(|t| &t.token).collect();
+                self.database
+                    .balances
+                    .last_blocks(
+                        &wallets.into_iter().collect::<Vec<_>>(),
+                        &tokens.into_iter().collect::<Vec<_>>(),
+                    )
+                    .await
+            }
+        };
+        let applies = |transfer: &ERCTransfer, wallet: &WalletAddress, indexed: bool| {
+            !indexed
+                || last_blocks
+                    .get(&(wallet.to_owned(), transfer.token.to_owned()))
+                    .is_none_or(|last_block| *last_block < transfer.block)
+        };
+
+        let mut deltas: HashMap<(&str, &str), i128> = HashMap::new();
+        let inserted = transfers.inserted().iter().filter(|t| t.erc == ERC20);
+
+        for (transfer, indexed) in inserted
+            .map(|t| (t, false))
+            .chain(duplicated.into_iter().map(|t| (t, true)))
+        {
+            let value = U256::from_str_radix(&transfer.value_or_token_id, 16).unwrap_or_default();
+
+            if value.bits() > 126 {
+                warn!(
+                    "[WARN] Skipping balance change of {} in {}: value out of range",
+                    transfer.token, transfer.transaction_id
+                );
+                continue;
+            }
+
+            let value = value.as_u128() as i128;
+            if transfer.from != ZERO_ADDRESS && applies(transfer, &transfer.from, indexed) {
+                *deltas
+                    .entry((transfer.from.as_str(), transfer.token.as_str()))
+                    .or_default() -= value;
+            }
+            if transfer.to != ZERO_ADDRESS && applies(transfer, &transfer.to, indexed) {
+                *deltas
+                    .entry((transfer.to.as_str(), transfer.token.as_str()))
+                    .or_default() += value;
+            }
+        }
+
+        if deltas.is_empty() {
+            return;
+        }
+
+        let mut balance_pool = self.database.balances.get_pool();
+        for ((wallet, token), delta) in deltas {
+            balance_pool.update(self.database.balances.update(
+                &wallet.to_string(),
+                &token.to_string(),
+                delta,
+                block,
+            ));
+        }
+
+        balance_pool
+            .commit(true)
+            .await
+            .expect("Failed to update balances");
+    }
+
+    /// Compares the balances changed since the last reconciliation with `balanceOf` at `block` and
+    /// overwrites the ones that drifted, e.g. through rebasing tokens or transfers of untracked
+    /// contracts.
+    async fn reconcile_erc20_balances(&self, block: Block) {
+        let reconciled = self
+            .database
+            .settings
+            .load::<u64>(BALANCES_RECONCILED_KEY)
+            .await
+            .unwrap_or(0);
+
+        let balances = self.database.balances.changed_since(reconciled).await;
+        let mut balance_pool = self.database.balances.get_pool();
+
+        for balance in balances.iter() {
+            let (token, wallet) = match (
+                H160::from_str(&balance.token),
+                H160::from_str(&balance.wallet),
+            ) {
+                (Ok(token), Ok(wallet)) => (token, wallet),
+                _ => continue,
+            };
+
+            let mut data = ERC20_BALANCE_OF_SELECTOR.to_vec();
+            data.extend(web3::ethabi::encode(&[Token::Address(wallet)]));
+
+            let on_chain = match self
+                .call_decode(token, data, ParamType::Uint(256), block)
+                .await
+                .and_then(Token::into_uint)
+            {
+                Some(amount) if amount.bits() <= 126 => amount.as_u128() as i128,
+                _ => continue,
+            };
+
+            if on_chain != balance.amount() {
+                warn!(
+                    "[WARN] Balance of {} in {} drifted: indexed {}, on chain {}",
+                    balance.wallet,
+                    balance.token,
+                    balance.amount(),
+                    on_chain
+                );
+                balance_pool.update(self.database.balances.set(
+                    &balance.wallet,
+                    &balance.token,
+                    on_chain,
+                    block,
+                ));
+            }
+        }
+
+        let corrected = balance_pool.len();
+        balance_pool
+            .commit(true)
+            .await
+            .expect("Failed to reconcile balances");
+
+        self.database
+            .settings
+            .store(BALANCES_RECONCILED_KEY, &block)
+            .await
+            .expect("Failed to store balance reconciliation progress!");
+
+        info!(
+            "[INFO] Reconciled {} balances at block {}, corrected {}",
+            balances.len(),
+            block,
+            corrected
+        );
+    }
+
+    /// Adds the committed sales to `price_history`, those already indexed too in case an earlier
+    /// run stopped before adding them.
+    async fn update_price_history(&self, sales: &Pool<Sale>) {
+        // Written directly instead of through a pool, the file sink leaves MongoDB untouched.
+        if FileSink::get().is_some() {
+            return;
+        }
+
+        for sale in sales.inserted().iter().chain(sales.duplicated()) {
+            self.database
+                .price_history
+                .record(sale)
+                .await
+                .expect("Failed to update price history");
+        }
+    }
+
+    /// Applies the committed equipment events to `axie_equipment`, those already indexed too as a
+    /// slot never goes back to an older event.
+    async fn update_axie_equipment(&self, events: &Pool<EquipmentEvent>) {
+        if FileSink::get().is_some() {
+            return;
+        }
+
+        for event in events.inserted().iter().chain(events.duplicated()) {
+            self.database
+                .axie_equipment
+                .apply(event)
+                .await
+                .expect("Failed to update axie equipment");
+        }
+    }
+
+    /// Applies the committed ERC721 and ERC1155 transfers to `nft_owners`. ERC721 owners never go
+    /// back to an older transfer, so those already indexed are applied again. ERC1155 transfers
+    /// already indexed are only applied to holdings last updated before their block, like in
+    /// [`Ronin::update_ron_balances`].
+    async fn update_nft_owners(
+        &self,
+        erc: &Pool<ERCTransfer>,
+        erc1155: &Pool<ERC1155Transfer>,
+        block: Block,
+    ) {
+        for transfer in erc
+            .inserted()
+            .iter()
+            .chain(erc.duplicated())
+            .filter(|t| t.erc == ERC721)
+        {
+            self.database
+                .nft_owners
+                .transfer(
+                    &transfer.token,
+                    &transfer.value_or_token_id,
+                    &transfer.to,
+                    transfer.block,
+                )
+                .await
+                .expect("Failed to update nft owner");
+        }
+
+        let duplicated = erc1155.duplicated();
+        let last_blocks = match duplicated.is_empty() {
+            true => HashMap::new(),
+            false => {
+                let tokens: HashSet<&WalletAddress> = duplicated.iter().map(|t| &t.token).collect();
+                let token_ids: HashSet<&String> = duplicated.iter().map(|t| &t.token_id).collect();
+                let owners: HashSet<&WalletAddress> =
+                    duplicated.iter().flat_map(|t| [&t.from, &t.to]).collect();
+                self.database
+                    .nft_owners
+                    .last_blocks(
+                        &tokens.into_iter().collect::<Vec<_>>(),
+                        &token_ids.into_iter().collect::<Vec<_>>(),
+                        &owners.into_iter().collect::<Vec<_>>(),
+                    )
+                    .await
+            }
+        };
+        let applies = |transfer: &ERC1155Transfer, owner: &WalletAddress, indexed: bool| {
+            !indexed
+                || last_blocks
+                    .get(&(
+                        transfer.token.to_owned(),
+                        transfer.token_id.to_owned(),
+                        owner.to_owned(),
+                    ))
+                    .is_none_or(|last_block| *last_block < transfer.block)
+        };
+
+        let mut deltas: HashMap<(&str, &str, &str), i64> = HashMap::new();
+        for (transfer, indexed) in erc1155
+            .inserted()
+            .iter()
+            .map(|t| (t, false))
+            .chain(duplicated.iter().map(|t| (t, true)))
+        {
+            let value = match U256::from_str_radix(&transfer.value, 16) {
+                Ok(value) if value <= U256::from(i64::MAX) => value.as_u64() as i64,
+                _ => {
+                    warn!(
+                        "[WARN] Skipping owner change of {} for token {} id {}: value out of range",
+                        transfer.value, transfer.token, transfer.token_id
+                    );
+                    continue;
+                }
+            };
+
+            let token = transfer.token.as_str();
+            let token_id = transfer.token_id.as_str();
+            if transfer.from != ZERO_ADDRESS && applies(transfer, &transfer.from, indexed) {
+                *deltas.entry((token, token_id, &transfer.from)).or_default() -= value;
+            }
+            if transfer.to != ZERO_ADDRESS && applies(transfer, &transfer.to, indexed) {
+                *deltas.entry((token, token_id, &transfer.to)).or_default() += value;
+            }
+        }
+
+        if deltas.is_empty() {
+            return;
+        }
+
+        let mut owner_pool = self.database.nft_owners.get_pool();
+        for ((token, token_id, owner), delta) in deltas {
+            owner_pool.update(self.database.nft_owners.update(
+                &token.to_string(),
+                token_id,
+                &owner.to_string(),
+                delta,
+                block,
+            ));
+        }
+
+        owner_pool
+            .commit(true)
+            .await
+            .expect("Failed to update nft owners");
+    }
+
+    /// Applies the mints and burns among the committed ERC1155 transfers to the per token id
+    /// supply. Transfers that were already indexed are only applied to token ids whose supply was
+    /// last updated before their block, so re-running a range that completed does not count them
+    /// twice.
+    async fn update_erc1155_supply(&self, transfers: &Pool<ERC1155Transfer>, block: Block) {
+        let duplicated: Vec<&ERC1155Transfer> = transfers
+            .duplicated()
+            .iter()
+            .filter(|t| t.from == ZERO_ADDRESS || t.to == ZERO_ADDRESS)
+            .collect();
+        let last_blocks = match duplicated.is_empty() {
+            true => HashMap::new(),
+            false => {
+                let tokens: HashSet<&WalletAddress> = duplicated.iter().map(|t| &t.token).collect();
+                let token_ids: HashSet<&String> = duplicated.iter().map(|t| &t.token_id).collect();
+                self.database
+                    .erc1155_supply
+                    .last_blocks(
+                        &tokens.into_iter().collect::<Vec<_>>(),
+                        &token_ids.into_iter().collect::<Vec<_>>(),
+                    )
+                    .await
+            }
+        };
+        let mut deltas: HashMap<(WalletAddress, String), (i64, i64)> = HashMap::new();
+
+        for (transfer, indexed) in transfers
+            .inserted()
+            .iter()
+            .map(|t| (t, false))
+            .chain(duplicated.into_iter().map(|t| (t, true)))
+        {
+            let minted = transfer.from == ZERO_ADDRESS;
+            let burned = transfer.to == ZERO_ADDRESS;
+
+            if !minted && !burned {
+                continue;
+            }
+            if indexed
+                && last_blocks
+                    .get(&(transfer.token.to_owned(), transfer.token_id.to_owned()))
+                    .is_some_and(|last_block| *last_block >= transfer.block)
+            {
+                continue;
+            }
+
+            let value = match U256::from_str_radix(&transfer.value, 16) {
+                Ok(value) if value <= U256::from(i64::MAX) => value.as_u64() as i64,
+                _ => {
+                    warn!(
+                        "[WARN] Skipping supply change of {} for token {} id {}: value out of range",
+                        transfer.value, transfer.token, transfer.token_id
+                    );
+                    continue;
+                }
+            };
+
+            let delta = deltas
+                .entry((transfer.token.to_owned(), transfer.token_id.to_owned()))
+                .or_default();
+
+            if minted {
+                delta.0 += value;
+            }
+            if burned {
+                delta.1 += value;
+            }
+        }
+
+        if deltas.is_empty() {
+            return;
+        }
+
+        let mut supply_pool = self.database.erc1155_supply.get_pool();
+        for ((token, token_id), (minted, burned)) in deltas {
+            supply_pool.update(
+                self.database
+                    .erc1155_supply
+                    .update(&token, &token_id, minted, burned, block),
+            );
+        }
+
+        supply_pool
+            .commit(true)
+            .await
+            .expect("Failed to update erc 1155 supply");
+    }
+
+    /// Resolves an ERC20 token by address or contract name and converts `amount` whole tokens to
+    /// its raw amount. Returns the token address and the raw amount.
+    pub fn erc20_amount(token: &str, amount: u64) -> Option<(String, U256)> {
+        let token = token.to_lowercase();
+
+        Ronin::contract_list()
+            .values()
+            .filter(|c| c.erc == ERC20)
+            .find(|c| c.address == token || c.name.to_lowercase() == token)
+            .map(|contract| {
+                (
+                    contract.address.to_string(),
+                    U256::from(amount) * U256::exp10(contract.decimals),
+                )
+            })
+    }
+
+    /// Resolves the configured whale thresholds to raw token amounts keyed by token address.
+    fn whale_thresholds(thresholds: &[WhaleThreshold]) -> HashMap<String, U256> {
+        thresholds
+            .iter()
+            .map(|threshold| {
+                Ronin::erc20_amount(&threshold.token, threshold.amount).unwrap_or_else(|| {
+                    panic!(
+                        "Unknown ERC20 token for whale threshold: {}",
+                        threshold.token
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Copies the committed ERC20 transfers at or above their token threshold to
+    /// `whale_transfers`. Transfers that were already indexed are copied again, copies that exist
+    /// are kept by the unique index.
+    async fn write_whale_transfers(
+        &self,
+        transfers: &Pool<ERCTransfer>,
+        thresholds: &HashMap<String, U256>,
+    ) {
+        let mut pool = self.database.whale_transfers.get_pool();
+
+        for transfer in transfers
+            .inserted()
+            .iter()
+            .chain(transfers.duplicated())
+            .filter(|t| t.erc == ERC20)
+        {
+            let threshold = match thresholds.get(&transfer.token) {
+                None => continue,
+                Some(threshold) => threshold,
+            };
+
+            match U256::from_str_radix(&transfer.value_or_token_id, 16) {
+                Ok(value) if value >= *threshold => pool.insert(transfer.to_owned()),
+                _ => {}
+            }
+        }
+
+        pool.commit(false)
+            .await
+            .expect("Failed to insert whale transfers");
+    }
+
+    /// Writes both sides of the committed transfers to the per wallet `wallet_transfers` view.
+    /// Transfers that were already indexed are written again, sides that exist are kept by the
+    /// unique index.
+    async fn write_wallet_transfers(
+        &self,
+        erc: &Pool<ERCTransfer>,
+        erc1155: &Pool<ERC1155Transfer>,
+    ) {
+        let erc: Vec<&ERCTransfer> = erc.inserted().iter().chain(erc.duplicated()).collect();
+        let erc1155: Vec<&ERC1155Transfer> = erc1155
+            .inserted()
+            .iter()
+            .chain(erc1155.duplicated())
+            .collect();
+        if erc.is_empty() && erc1155.is_empty() {
+            return;
+        }
+
+        let mut pool = self.database.wallet_transfers.get_pool();
+        for transfer in erc {
+            for wallet_transfer in WalletTransfer::from_erc_transfer(transfer) {
+                pool.insert(wallet_transfer);
+            }
+        }
+        for transfer in erc1155 {
+            for wallet_transfer in WalletTransfer::from_erc1155_transfer(transfer) {
+                pool.insert(wallet_transfer);
+            }
+        }
+
+        pool.commit(false)
+            .await
+            .expect("Failed to insert wallet transfers");
+    }
+
+    /// Adds the duplicates suppressed while committing `pool` to the running total for its collection.
+    async fn record_duplicates<T>(&self, pool: &Pool<T>, block: Block)
+    where
+        T: Serialize + Clone + Eq + PartialEq,
+    {
+        if pool.duplicates() == 0 {
+            return;
+        }
+
+        info!(
+            "Block: {:>12}\t\tSuppressed duplicates: {:>5}\tCollection: {}",
+            block,
+            pool.duplicates(),
+            pool.name()
+        );
+
+        let collection = pool.name();
+        let key = f!("duplicates.{collection}");
+        let mut suppressed: SuppressedDuplicates =
+            self.database.settings.load(&key).await.unwrap_or_default();
+
+        suppressed.count += pool.duplicates() as u64;
+        suppressed.last_block = block;
+
+        self.database
+            .settings
+            .store(&key, &suppressed)
+            .await
+            .expect("Failed to store suppressed duplicates!");
+    }
+
+    /// Checks that the node has finished syncing and that its head has reached `stop_block`.
+    /// Returns `false` when the node is not ready and `wait` is disabled.
+    async fn node_ready(&self, stop_block: Block, wait: bool) -> bool {
+        loop {
+            let sync_state = self
+                .provider
+                .eth()
+                .syncing()
+                .await
+                .expect("Failed to retrieve sync state from node!");
+
+            let head = self
+                .provider
+                .eth()
+                .block_number()
+                .await
+                .expect("Failed to retrieve head block number from chain!")
+                .as_u64();
+
+            let reason = match sync_state {
+                SyncState::Syncing(info) => Some(f!(
+                    "node is still syncing (current block {}, highest block {})",
+                    info.current_block,
+                    info.highest_block
+                )),
+                SyncState::NotSyncing if head < stop_block => Some(f!(
+                    "node head {head} is below the requested stop block {stop_block}"
+                )),
+                SyncState::NotSyncing => None,
+            };
+
+            match reason {
+                None => return true,
+                Some(reason) if wait => {
+                    warn!(
+                        "[WARN] Waiting for node: {reason}. Checking again in {}s",
+                        NODE_SYNC_POLL_INTERVAL.as_secs()
+                    );
+                    tokio::time::sleep(NODE_SYNC_POLL_INTERVAL).await;
+                }
+                Some(reason) => {
+                    error!(
+                        "[ERROR] Refusing to start: {reason}. Use --wait-for-sync to wait instead."
+                    );
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Fetches the receipt of the first transaction at or after `start` to make sure the node can
+    /// serve historical receipts for the requested range.
+    async fn archive_ready(&self, start: Block, stop: Block) -> bool {
+        let requirement = "The node must be an archive node keeping the full transaction index (--gcmode archive --txlookuplimit 0)";
+        let probe_stop = stop.min(start + ARCHIVE_PROBE_BLOCKS);
+
+        for number in start..probe_stop {
+            let block = match self
+                .provider
+                .eth()
+                .block(BlockId::Number(BlockNumber::from(number)))
+                .await
+            {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    error!("[ERROR] Node does not know block {number}. {requirement}.");
+                    return false;
+                }
+                Err(err) => {
+                    error!(
+                        "[ERROR] Failed to load block {number} from node: {err}. {requirement}."
+                    );
+                    return false;
+                }
+            };
+
+            let tx = match block.transactions.first() {
+                None => continue,
+                Some(tx) => tx.to_owned(),
+            };
+
+            return match self.provider.eth().transaction_receipt(tx).await {
+                Ok(Some(_)) => true,
+                Ok(None) => {
+                    error!("[ERROR] Node has no receipt for transaction {tx:?} in block {number}. {requirement}.");
+                    false
+                }
+                Err(err) => {
+                    error!("[ERROR] Failed to load receipt for transaction {tx:?} in block {number}: {err}. {requirement}.");
+                    false
+                }
+            };
+        }
+
+        true
+    }
+
+    async fn write_metrics(&self, window: &MetricsWindow, block: Block) {
+        let head = match self.provider.eth().block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(err) => {
+                warn!("[WARN] Failed to retrieve head block number for metrics: {err}");
+                block
+            }
+        };
+        let elapsed = window.started.elapsed();
+
+        let metrics = Metrics {
+            timestamp: DateTime::now(),
+            interval_seconds: elapsed.as_secs(),
+            block,
+            head,
+            lag: head.saturating_sub(block),
+            blocks: window.blocks,
+            blocks_per_second: window.blocks as f64 / elapsed.as_secs_f64().max(1.0),
+            transactions: window.transactions,
+            erc_transfers: window.erc_transfers,
+            erc1155_transfers: window.erc1155_transfers,
+            erc_sales: window.erc_sales,
+            wallet_updates: window.wallet_updates,
+            duplicates: window.duplicates,
+            errors: window.errors,
+        };
+
+        if let Err(err) = self
+            .database
+            .metrics
+            .collection
+            .insert_one(metrics, None)
+            .await
+        {
+            warn!("[WARN] Failed to store metrics: {err}");
+        }
+    }
+
+    /// First block with a timestamp at or after `date`. Answered from the `blocks` collection when
+    /// the block before the match is stored too, otherwise by a binary search over the block
+    /// timestamps, read from the `blocks` collection where stored and from the node otherwise.
+    /// `None` if the chain head is older than `date`.
+    pub async fn block_at(&self, date: DateTime) -> Option<Block> {
+        if let Some(stored) = self.database.blocks.first_at(date).await {
+            if stored.block <= 1 || self.database.blocks.get(stored.block - 1).await.is_some() {
+                return Some(stored.block);
+            }
+        }
+
+        let timestamp = |block: Block| async move {
+            self.timestamp_of(block)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to load block {block}: {err}"))
+        };
+
+        let head = self.head_stop_block(0).await;
+        if timestamp(head).await < date {
+            return None;
+        }
+
+        let (mut low, mut high) = (0, head);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if timestamp(middle).await < date {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        Some(low)
+    }
+
+    /// Timestamp of `block` from the `blocks` collection, or the node if it is not stored.
+    pub async fn timestamp_of(&self, block: Block) -> Result<DateTime, String> {
+        if let Some(timestamp) = Ronin::cached_timestamp(block) {
+            return Ok(timestamp);
+        }
+
+        match self.database.blocks.get(block).await {
+            Some(stored) => Ok(stored.timestamp),
+            None => self.block_timestamp(block).await,
+        }
+    }
+
+    /// Current chain head minus the safety `offset`.
+    pub async fn head_stop_block(&self, offset: u64) -> Block {
+        self.provider
+            .eth()
+            .block_number()
+            .await
+            .expect("Failed to retrieve head block number from chain!")
+            .as_u64()
+            .saturating_sub(offset)
+    }
+
+    /// Connects the `--nats-url` sink, outside of debug mode.
+    async fn sink(&self, args: &Args) -> Option<NatsSink> {
+        match (&args.nats_url, args.debug) {
+            (Some(url), false) => {
+                Some(NatsSink::connect(url, &args.nats_subject_prefix, &args.nats_filters).await)
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits until this replica holds the leader lease of the stream.
+    async fn acquire_leadership(&self, args: &Args) -> Leadership {
+        let holder = args.instance_id.to_owned().unwrap_or_else(|| {
+            let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "fri".to_string());
+            f!("{hostname}-{}", std::process::id())
+        });
+        let ttl = Duration::from_secs(args.lease_ttl.max(3));
+        let mut waiting = false;
+
+        loop {
+            if self
+                .database
+                .leases
+                .acquire(LEADER_LEASE, &holder, ttl)
+                .await
+                .expect("Failed to acquire leader lease!")
+            {
+                info!("[INFO] {holder} is the leader");
+                return Leadership {
+                    holder,
+                    ttl,
+                    renewed: Instant::now(),
+                };
+            }
+
+            if !waiting {
+                info!("[INFO] {holder} is on standby, waiting for the leader lease");
+                waiting = true;
+            }
+            tokio::time::sleep(ttl / 3).await;
+        }
+    }
+
+    /// Renews the leader lease once a third of its ttl has passed. Returns false if another
+    /// replica took it over in the meantime.
+    async fn renew_leadership(&self, leadership: &mut Leadership) -> bool {
+        if leadership.renewed.elapsed() < leadership.ttl / 3 {
+            return true;
+        }
+
+        let renewed = self
+            .database
+            .leases
+            .acquire(LEADER_LEASE, &leadership.holder, leadership.ttl)
+            .await
+            .expect("Failed to renew leader lease!");
+        leadership.renewed = Instant::now();
+
+        renewed
+    }
+
+    async fn release_leadership(&self, leadership: Leadership) {
+        self.database
+            .leases
+            .release(LEADER_LEASE, &leadership.holder)
+            .await
+            .expect("Failed to release leader lease!");
+    }
+
+    /// Checks the parent hash of `block` against the stored previous block. On a mismatch the
+    /// stored blocks are walked back to the last one that is still canonical and everything after
+    /// it is rolled back to tombstones. Returns that block, the stream continues after it.
+    async fn rollback_reorg(
+        &self,
+        block: &web3::types::Block<Web3Transaction>,
+        depth: u64,
+    ) -> Option<Block> {
+        let number = block.number?.as_u64();
+        let parent = self.database.blocks.get(number.checked_sub(1)?).await?;
+        if parent.hash == self.to_string(&block.parent_hash) {
+            return None;
+        }
+
+        let mut fork = parent.block;
+        loop {
+            if number - fork > depth {
+                panic!("Reorg at block {number} is deeper than --reorg-depth {depth}");
+            }
+            fork -= 1;
+
+            let stored = match self.database.blocks.get(fork).await {
+                Some(stored) => stored,
+                None => break,
+            };
+            let canonical = self
+                .provider
+                .eth()
+                .block(BlockId::Number(BlockNumber::from(fork)))
+                .await
+                .unwrap_or_else(|err| panic!("Failed to load block {fork}: {err}"))
+                .and_then(|canonical| canonical.hash);
+            if canonical.map(|hash| self.to_string(&hash)) == Some(stored.hash) {
+                break;
+            }
+        }
+
+        let orphaned_at = OrphanedAt {
+            block: number,
+            hash: self.to_string(&block.hash),
+        };
+        BLOCK_TIMESTAMPS
+            .lock()
+            .expect("Timestamp cache poisoned!")
+            .split_off(&(fork + 1));
+        reorg::rollback(&self.database, fork, orphaned_at).await;
+
+        Some(fork)
+    }
+
+    /// Decodes block `number` into [`RoninEvent`]s, a `Block` event followed by each
+    /// transaction and the transfers and sales of its receipt.
+    async fn block_events(
+        &self,
+        number: Block,
+        contracts: &ContractList,
+        spam_tokens: &HashSet<String>,
+        block_receipts_supported: &mut bool,
+    ) -> Vec<RoninEvent> {
+        let backoff = Backoff::default();
+        let id = BlockId::Number(BlockNumber::from(number));
+        let block = backoff
+            .retry(&f!("block {number}"), || async {
+                match self.provider.eth().block_with_txs(id).await {
+                    Ok(Some(block)) => Ok(block),
+                    Ok(None) => Err("Provider returned null".to_string()),
+                    Err(err) => Err(err.to_string()),
+                }
+            })
+            .await
+            .unwrap_or_else(|err| panic!("Failed to load block {number}: {err}"));
+        let mut receipts = self
+            .load_receipts(&block, &backoff, block_receipts_supported)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to load receipts of block {number}: {err}"));
+
+        let timestamp = block.timestamp.as_u64() * 1000;
+        let timestamp = DateTime::from_millis(i64::try_from(timestamp).unwrap());
+        let mut events = vec![RoninEvent::Block {
+            block: number,
+            hash: self.to_string(&block.hash),
+            timestamp,
+            transactions: block.transactions.len(),
+        }];
+
+        for tx in block.transactions.iter() {
+            let receipt = receipts
+                .remove(&tx.hash)
+                .expect("Failed to unwrap transaction receipt!");
+            events.push(RoninEvent::Transaction(
+                self.transaction(tx, &receipt, number, timestamp),
+            ));
+
+            let decoded = self.decode_receipt(
+                &receipt,
+                number,
+                timestamp,
+                contracts,
+                DecodeFeatures::all(),
+            );
+            for mut transfer in decoded.erc_transfers {
+                transfer.spam = spam_tokens.contains(&transfer.token);
+                events.push(RoninEvent::ErcTransfer(transfer));
+            }
+            for mut transfer in decoded.erc1155_transfers {
+                transfer.spam = spam_tokens.contains(&transfer.token);
+                events.push(RoninEvent::Erc1155Transfer(transfer));
+            }
+            events.extend(decoded.sales.into_iter().map(RoninEvent::Sale));
+        }
+
+        events
+    }
+
+    /// Decodes the blocks of `range` in order without writing anything, for embedders and tests
+    /// consuming the decoder directly. RPC calls are retried with the default backoff, a block that
+    /// still fails to load panics like the indexer does.
+    pub fn stream_events(&self, range: Range<Block>) -> impl Stream<Item = RoninEvent> + '_ {
+        stream::unfold(
+            (range, None, true),
+            move |(mut range, tracked, mut block_receipts_supported)| async move {
+                let block = range.next()?;
+                let (contracts, spam_tokens) = match tracked {
+                    Some(tracked) => tracked,
+                    None => (
+                        self.tracked_contracts().await,
+                        self.database.spam_tokens.tokens().await,
+                    ),
+                };
+
+                let events = self
+                    .block_events(
+                        block,
+                        &contracts,
+                        &spam_tokens,
+                        &mut block_receipts_supported,
+                    )
+                    .await;
+
+                Some((
+                    stream::iter(events),
+                    (
+                        range,
+                        Some((contracts, spam_tokens)),
+                        block_receipts_supported,
+                    ),
+                ))
+            },
+        )
+        .flatten()
+    }
+
+    pub async fn stream(&self, offset: u64, args: Args) {
+        let _worker = status::worker();
+        if args.debug {
+            debug!("W A R N I N G");
+            debug!("DEBUG MODE ENABLED! NOT SAVING ANYTHING TO DATABASE!");
+            debug!("Start Block: {}", args.debug_start_block);
+            debug!("Stop Block: {}", args.debug_stop_block);
+            thread::sleep(Duration::new(1, 0));
+        }
+
+        if args.replay {
+            if args.debug {
+                debug!("Can't replay while in debug mode!!!");
+            }
+            debug!("W A R N I N G");
+            debug!("About to drop ANY data stored in the database for this app!");
+            debug!("Waiting 15 seconds...");
+            thread::sleep(Duration::new(15, 0));
+            self.database
+                .settings
+                .collection
+                .drop(None)
+                .await
+                .expect("Failed to drop settings collection");
+            self.database
+                .wallets
+                .collection
+                .drop(None)
+                .await
+                .expect("Failed to drop wallets collection");
+            self.database
+                .transactions
+                .collection
+                .drop(None)
+                .await
+                .expect("Failed to drop transactions collection");
+            self.database
+                .erc_transfers
+                .collection
+                .drop(None)
+                .await
+                .expect("Failed to drop erc_transfers collection");
+            self.database
+                .erc1155_transfers
+                .collection
+                .drop(None)
+                .await
+                .expect("Failed to drop erc1155_transfers collection");
+
+            self.database.create_indexes().await;
+        }
+
+        let mut contracts = self.tracked_contracts().await;
+        let official_deployers: Vec<String> = args
+            .official_deployers
+            .iter()
+            .map(|deployer| deployer.to_lowercase())
+            .collect();
+        let whale_thresholds = Ronin::whale_thresholds(&args.whale_thresholds);
+        let abis = AbiRegistry::load(&self.database).await;
+        let spam_tokens = self.database.spam_tokens.tokens().await;
+        let mut ignored_contracts: HashSet<H160> = HashSet::new();
+        let subscriptions: Vec<EventSubscription> = match &args.events {
+            Some(path) => subscriptions::load(&self.database, path).await,
+            None => vec![],
+        };
+
+        let mut stream_stop_block: Block = self.head_stop_block(offset).await;
+
+        if args.debug {
+            stream_stop_block = args.debug_stop_block;
+        }
+
+        if let Some(StopBlock::Number(stop_block)) = args.stop_block {
+            stream_stop_block = stop_block;
+        }
+
+        let follow_head = args.stop_block == Some(StopBlock::Latest);
+        let mut stop_block_refreshed = Instant::now();
+
+        if !self.node_ready(stream_stop_block, args.wait_for_sync).await {
+            return;
+        }
+
+        // The start block is read once the lease is held, a standby resumes where the leader stopped.
+        let mut leadership = match args.leader_election && !args.debug {
+            true => Some(self.acquire_leadership(&args).await),
+            false => None,
+        };
+
+        if !args.debug {
+            let features = StreamFeatures {
+                features: args
+                    .features()
+                    .into_iter()
+                    .map(|(name, enabled)| (name.to_string(), enabled))
+                    .collect(),
+            };
+            self.database
+                .settings
+                .store(STREAM_FEATURES_KEY, &features)
+                .await
+                .expect("Failed to store stream features!");
+        }
+
+        let opts = FindOneOptions::builder()
+            .sort(doc! {
+                "block": -1i64
+            })
+            .build();
+
+        let resume_block = self.database.settings.load::<u64>(STREAM_KEY).await;
+        let mut start: u64 = match resume_block {
+            Some(block) => block + 1,
+            // Databases of older releases only have their latest transaction to resume from.
+            None => match self
+                .database
+                .transactions
+                .collection
+                .find_one(None, opts)
+                .await
+                .unwrap()
+            {
+                None => 1,
+                Some(settings) => settings.block,
+            },
+        };
+
+        if let Some(first_block) = args.first_block() {
+            start = first_block;
+        } else if args.debug {
+            start = args.debug_start_block;
+        }
+
+        let mut largest_block_by_tx_num: LargestBlock =
+            match self.database.settings.load("largest_block_by_tx_num").await {
+                None => LargestBlock {
+                    number: 0,
+                    tx_num: 0,
+                },
+                Some(largest_block) => largest_block,
+            };
+
+        if start > stream_stop_block {
+            info!("[INFO] Offset not large enough. Exiting!");
+            return;
+        }
+
+        if !self.archive_ready(start, stream_stop_block).await {
+            return;
+        }
+
+        info!("[INFO] Streaming from {} to {}", &start, &stream_stop_block);
+        if !args.debug {
+            storage_profile::record(&self.database).await;
+        }
+        let sink = self.sink(&args).await;
+        status::head(self.head_stop_block(0).await);
+
+        let mut current_block: Block = start.to_owned();
+        let mut pools = StreamPools::new(&self.database, &subscriptions);
+        let atomic = args.atomic_commits
+            && !args.debug
+            && FileSink::get().is_none()
+            && self.database.supports_transactions().await;
+        if args.atomic_commits && !atomic && !args.debug {
+            info!("[INFO] MongoDB transactions are not available, blocks are committed without");
+        }
+        let backoff = Backoff::from(&args);
+        let block_receipts_supported = probe::profile(&self.database, &args.web3_hostname)
+            .await
+            .is_none_or(|profile| profile.block_receipts);
+
+        let fallback = match (args.missing_block_policy, &args.fallback_web3_hostname) {
+            (MissingBlockPolicy::Fallback, Some(hostname)) => Some(Ronin::connect(hostname).await),
+            (MissingBlockPolicy::Fallback, None) => {
+                panic!("--missing-block-policy fallback requires --fallback-web3-hostname")
+            }
+            _ => None,
+        };
+        let needs = FetchNeeds::new(
+            &args,
+            &abis,
+            self.log_filters(&contracts, DecodeFeatures::from(&args)),
+        );
+        if !needs.receipts {
+            info!("[INFO] No enabled feature reads receipts, they are not loaded");
+        } else if needs.screen.is_some() {
+            info!(
+                "[INFO] Loading receipts only for blocks whose logs bloom matches a decoded event"
+            );
+        }
+        let mut fetcher = BlockFetcher::new(
+            self.clone(),
+            args.clone(),
+            fallback,
+            block_receipts_supported,
+            needs.clone(),
+            stream_stop_block,
+        );
+        let mut writer = StreamWriter::start(
+            self.clone(),
+            StreamCommit {
+                args: args.clone(),
+                atomic,
+                whale_thresholds,
+                sink,
+                metrics: MetricsWindow::new(),
+                resume_block,
+            },
+        );
+
+        loop {
+            if let Some(leadership) = leadership.as_mut() {
+                if !self.renew_leadership(leadership).await {
+                    warn!("[WARN] Lost the leader lease to another replica, exiting");
+                    writer.finish().await;
+                    return;
+                }
+            }
+
+            let FetchedBlock {
+                block,
+                transactions: num_txs,
+                receipts_loaded,
+                receipts,
+            } = fetcher.next(current_block).await;
+            let block = match block {
+                Some(block) => block,
+                None => {
+                    current_block += 1;
+                    if current_block >= stream_stop_block {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let block_number: u64 = block.number.unwrap().as_u64();
+            let timestamp = block.timestamp.as_u64() * 1000;
+            let timestamp = DateTime::from_millis(i64::try_from(timestamp).unwrap());
+            Ronin::cache_timestamp(block_number, timestamp);
+
+            // Blocks within the reorg depth of the stop block are committed one by one, the reorg
+            // check compares against the stored parent.
+            let near_stop = block_number + args.reorg_depth.max(1) >= stream_stop_block;
+
+            if args.feature_blocks && !args.debug && args.reorg_depth > 0 {
+                if near_stop {
+                    // The parent may still be in the pools with --flush-blocks above 1.
+                    let committed = StreamPools::new(&self.database, &subscriptions);
+                    writer.write(std::mem::replace(&mut pools, committed)).await;
+                    writer.flush().await;
+                }
+                if let Some(fork) = self.rollback_reorg(&block, args.reorg_depth).await {
+                    current_block = fork + 1;
+                    continue;
+                }
+            }
+
+            if args.feature_blocks && !args.debug {
+                pools.blocks.insert(IndexedBlock {
+                    block: block_number,
+                    hash: self.to_string(&block.hash),
+                    parent_hash: self.to_string(&block.parent_hash),
+                    timestamp,
+                    miner: self.to_string(&block.author),
+                    gas_used: block.gas_used.low_u64(),
+                    transactions: num_txs as u64,
+                    size: block.size.map(|size| size.low_u64()),
+                });
+            }
+
+            if num_txs > 0 {
+                if !args.debug && num_txs as u64 > largest_block_by_tx_num.tx_num {
+                    largest_block_by_tx_num = LargestBlock {
+                        number: block_number,
+                        tx_num: num_txs as u64,
+                    };
+                    self.database
+                        .settings
+                        .store("largest_block_by_tx_num", &largest_block_by_tx_num)
+                        .await
+                        .expect("Failed to store largest_block_by_tx_num!");
+                }
+
+                let mut receipts = match receipts {
+                    Ok(receipts) => receipts,
+                    Err(error) => {
+                        warn!("[WARN] Skipping block {}: {}", block_number, error);
+                        if !args.debug {
+                            self.database
+                                .failed_blocks
+                                .record(&FailedBlock {
+                                    block: block_number,
+                                    to_block: block_number,
+                                    stage: FailedStage::Receipts,
+                                    error,
+                                    attempts: backoff.retries + 1,
+                                    created_at: DateTime::now(),
+                                })
+                                .await
+                                .expect("Failed to record failed block!");
+                        }
+
+                        current_block += 1;
+                        if current_block >= stream_stop_block {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
+                for tx in block.transactions {
+                    let tx_from = self.to_string(&tx.from);
+                    let tx_to = self.to_string(&tx.to);
+                    let tx_hash = self.to_string(&tx.hash);
 
                     if args.feature_wallet_updates {
-                        wallet_pool.update(self.database.wallets.update(
+                        pools.wallets.update(self.database.wallets.update(
                             &tx_from,
                             block_number,
                             &tx_hash,
                         ));
-                        wallet_pool.update(self.database.wallets.update(
+                        pools.wallets.update(self.database.wallets.update(
                             &tx_to,
                             block_number,
                             &tx_hash,
                         ));
                     }
 
-                    if args.debug && !args.debug_disable_wallet_updates {
-                        debug!(
-                            "[WALLET UPDATE] Address: {}\tBlock: {:>14}\tTransaction: {}",
-                            &tx_from, &block_number, &tx_hash
-                        );
-                        debug!(
-                            "[WALLET UPDATE] Address: {}\tBlock: {:>14}\tTransaction: {}",
-                            &tx_to, &block_number, &tx_hash
-                        );
-                    }
+                    if args.debug && !args.debug_disable_wallet_updates {
+                        debug!(
+                            "[WALLET UPDATE] Address: {}\tBlock: {:>14}\tTransaction: {}",
+                            &tx_from, &block_number, &tx_hash
+                        );
+                        debug!(
+                            "[WALLET UPDATE] Address: {}\tBlock: {:>14}\tTransaction: {}",
+                            &tx_to, &block_number, &tx_hash
+                        );
+                    }
+
+                    // Without receipts, transactions are stored without status, gas used and
+                    // created contract.
+                    let receipt: TransactionReceipt = match receipts_loaded {
+                        true => receipts
+                            .remove(&tx.hash)
+                            .expect("Failed to unwrap transaction receipt!"),
+                        false => TransactionReceipt::default(),
+                    };
+
+                    if args.feature_ron_balances {
+                        for change in self.ron_balance_changes(&tx, &receipt, block_number) {
+                            pools.ron_changes.insert(change);
+                        }
+                    }
+
+                    if args.feature_native_transfers {
+                        if let Some(transfer) = self.native_transfer(&tx, &receipt, timestamp) {
+                            pools.native_transfers.insert(transfer);
+                        }
+                    }
+
+                    if let Some(address) = receipt.contract_address {
+                        if official_deployers.contains(&tx_from) {
+                            if let Some(token) = self
+                                .discover_token(address, &tx_from, true, block_number)
+                                .await
+                            {
+                                info!(
+                                    "[INFO] Discovered official {:?} token {} ({}) at {}",
+                                    token.erc, token.name, token.symbol, token.address
+                                );
+                                Ronin::track_token(&mut contracts, &token);
+                                pools.tokens.insert(token);
+                            }
+                        }
+                    }
+
+                    if args.feature_contract_discovery {
+                        for token in self
+                            .discover_contracts(
+                                &receipt,
+                                block_number,
+                                &mut contracts,
+                                &mut ignored_contracts,
+                            )
+                            .await
+                        {
+                            pools.tokens.insert(token);
+                        }
+                    }
+
+                    let mut decoded = self.decode_receipt(
+                        &receipt,
+                        block_number,
+                        timestamp,
+                        &contracts,
+                        DecodeFeatures::from(&args),
+                    );
+
+                    if args.feature_receipt_checks {
+                        for anomaly in self.receipt_anomalies(&receipt, &decoded, true) {
+                            pools.anomalies.insert(anomaly);
+                        }
+                    }
+
+                    for transfer in decoded.erc_transfers.iter_mut() {
+                        transfer.spam = spam_tokens.contains(&transfer.token);
+                    }
+                    for transfer in decoded.erc1155_transfers.iter_mut() {
+                        transfer.spam = spam_tokens.contains(&transfer.token);
+                    }
+                    handler::decoded(&decoded);
+
+                    for transfer in decoded.erc_transfers {
+                        pools.erc_transfers.insert(transfer);
+                    }
+                    for transfer in decoded.erc1155_transfers {
+                        pools.erc1155_transfers.insert(transfer);
+                    }
+                    for sale in decoded.sales {
+                        pools.sales.insert(sale);
+                    }
+                    for auction in decoded.auctions {
+                        pools.auctions.insert(auction);
+                    }
+                    for order in decoded.orders {
+                        pools.orders.insert(order);
+                    }
+                    for reserve in decoded.pair_reserves {
+                        pools.pair_reserves.insert(reserve);
+                    }
+                    for event in decoded.governance_events {
+                        pools.governance_events.insert(event);
+                    }
+                    for event in decoded.staking_events {
+                        pools.staking_events.insert(event);
+                    }
+                    for event in decoded.equipment_events {
+                        pools.equipment_events.insert(event);
+                    }
+                    for failure in decoded.decode_failures {
+                        pools.decode_failures.insert(failure);
+                    }
+
+                    if !abis.is_empty() {
+                        for log in receipt.logs.iter() {
+                            if let Some(event) = abis.decode_log(log, block_number, timestamp) {
+                                handler::contract_event(&event);
+                                pools.contract_events.insert(event);
+                            }
+                        }
+                    }
+
+                    for (subscription, pool) in
+                        subscriptions.iter().zip(pools.subscriptions.iter_mut())
+                    {
+                        for log in receipt.logs.iter() {
+                            if let Some(event) = subscription.decode(log, block_number, timestamp) {
+                                handler::contract_event(&event);
+                                pool.insert(event);
+                            }
+                        }
+                    }
+
+                    if args.feature_transactions {
+                        let transaction = self.transaction(&tx, &receipt, current_block, timestamp);
+                        handler::transaction(&transaction);
+                        pools.transactions.insert(transaction);
+                    }
+                }
+            } else if args.empty_logs && !args.debug {
+                BlockSummary {
+                    block: current_block,
+                    ..Default::default()
+                }
+                .print(args.log_format);
+            }
+
+            handler::block(current_block, timestamp);
+            pools.add_block(current_block, num_txs);
+
+            let reconcile = !args.debug
+                && args.feature_erc20_balances
+                && args.balance_reconcile_interval > 0
+                && current_block.rem_euclid(args.balance_reconcile_interval) == 0;
+
+            if args.debug {
+                pools = StreamPools::new(&self.database, &subscriptions);
+            } else if reconcile
+                || pools.blocks_pending >= args.flush_blocks.max(1)
+                || pools.len() >= args.flush_documents
+                || near_stop
+            {
+                let committed = StreamPools::new(&self.database, &subscriptions);
+                writer.write(std::mem::replace(&mut pools, committed)).await;
+            }
+
+            if reconcile {
+                writer.flush().await;
+                self.reconcile_erc20_balances(current_block).await;
+            }
+            current_block += 1;
+
+            if follow_head
+                && (current_block >= stream_stop_block
+                    || stop_block_refreshed.elapsed().as_secs() >= args.stop_block_refresh)
+            {
+                let refreshed_stop_block = self.head_stop_block(offset).await;
+
+                if refreshed_stop_block > stream_stop_block {
+                    info!(
+                        "[INFO] Extending stop block from {} to {}",
+                        stream_stop_block, refreshed_stop_block
+                    );
+                    stream_stop_block = refreshed_stop_block;
+                    fetcher.stop_at(stream_stop_block);
+                }
+                status::head(refreshed_stop_block + offset);
+                stop_block_refreshed = Instant::now();
+            }
+
+            if current_block >= stream_stop_block {
+                break;
+            }
+        }
+
+        if !args.debug {
+            writer.write(pools).await;
+        }
+        writer.finish().await;
+
+        if let Some(leadership) = leadership {
+            self.release_leadership(leadership).await;
+        }
+    }
+
+    /// Writes the pools in one transaction, so a crash never leaves the documents of a block
+    /// partially inserted. Documents a resumed block already stored are left out of it. If the
+    /// transaction is aborted, the pools are left for [`Ronin::commit_stream`] to commit without
+    /// one.
+    async fn commit_atomically(&self, pools: &mut StreamPools, args: &Args) {
+        let mut session = self
+            .database
+            .start_session()
+            .await
+            .expect("Failed to start MongoDB session!");
+        session
+            .start_transaction(None)
+            .await
+            .expect("Failed to start MongoDB transaction!");
+
+        let written = match pools.write_in(args, &mut session).await {
+            Ok(()) => session.commit_transaction().await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = written {
+            // Fails if the commit already ended the transaction.
+            session.abort_transaction().await.ok();
+            pools.discard_write();
+            info!(
+                "[INFO] Committing blocks {:?}..={:?} without a transaction: {err}",
+                pools.first_block, pools.last_block
+            );
+        }
+    }
+
+    /// Commits the pools of the stream, in one transaction with `--atomic-commits`. Derived
+    /// collections, duplicates, the sink and the metrics are updated as of the last added block,
+    /// after the transaction.
+    async fn commit_stream(&self, pools: &mut StreamPools, commit: &mut StreamCommit) {
+        let args = &commit.args;
+        let whale_thresholds = &commit.whale_thresholds;
+        let sink = commit.sink.as_ref();
+        let (Some(first_block), Some(block)) = (pools.first_block, pools.last_block) else {
+            return;
+        };
+        // The file sink leaves MongoDB untouched.
+        let markers = FileSink::get().is_none();
+        if markers {
+            self.database
+                .block_status
+                .mark(first_block, block, BlockState::Pending)
+                .await
+                .expect("Failed to mark blocks pending!");
+        }
+        if commit.atomic {
+            self.commit_atomically(pools, args).await;
+        }
+        let transactions_pending = pools.transactions_pending;
+        let erc_insert_num = pools.erc_transfers.len();
+        let erc1155_insert_num = pools.erc1155_transfers.len();
+        let erc_sale_num = pools.sales.len();
+        let wallet_update_num = pools.wallets.len();
+
+        let StreamPools {
+            blocks: block_pool,
+            transactions: tx_pool,
+            erc_transfers: erc_pool,
+            erc1155_transfers: erc1155_pool,
+            sales: erc_sale_pool,
+            auctions: auction_pool,
+            orders: order_pool,
+            pair_reserves: pair_reserve_pool,
+            ron_changes: ron_change_pool,
+            native_transfers: native_transfer_pool,
+            tokens: token_pool,
+            governance_events: governance_pool,
+            staking_events: staking_pool,
+            equipment_events: equipment_pool,
+            decode_failures: decode_failure_pool,
+            anomalies: anomaly_pool,
+            contract_events: contract_event_pool,
+            subscriptions: subscription_pools,
+            wallets: wallet_pool,
+            ..
+        } = pools;
+
+        // The pools target different collections, so they are committed concurrently.
+        // Derived collections are written after the pool they are derived from.
+        tokio::join!(
+            async {
+                if args.feature_blocks {
+                    block_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert blocks");
+                }
+            },
+            async {
+                if args.feature_transactions {
+                    tx_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert transactions");
+
+                    let moved = self
+                        .database
+                        .transactions
+                        .reinclude(tx_pool.duplicated())
+                        .await
+                        .expect("Failed to move reorged transactions");
+                    if moved > 0 {
+                        warn!(
+                            "[WARN] Block: {:>12}\tMoved {} transactions included in an earlier block (reorg)",
+                            block, moved
+                        );
+                    }
+                }
+            },
+            async {
+                if args.feature_erc_transfers {
+                    erc_pool
+                        .commit(true)
+                        .await
+                        .expect("Failed to insert erc transfers");
+
+                    erc1155_pool
+                        .commit(true)
+                        .await
+                        .expect("Failed to insert erc 1155 transfers");
+
+                    if args.feature_erc1155_supply {
+                        self.update_erc1155_supply(erc1155_pool, block).await;
+                    }
+
+                    if args.feature_erc20_balances {
+                        self.update_erc20_balances(erc_pool, block).await;
+                    }
+
+                    if args.feature_nft_owners {
+                        self.update_nft_owners(erc_pool, erc1155_pool, block).await;
+                    }
+
+                    if !whale_thresholds.is_empty() {
+                        self.write_whale_transfers(erc_pool, whale_thresholds).await;
+                    }
+
+                    if args.feature_wallet_transfers {
+                        self.write_wallet_transfers(erc_pool, erc1155_pool).await;
+                    }
+                }
+            },
+            async {
+                if args.feature_erc_721_sales {
+                    erc_sale_pool
+                        .commit(true)
+                        .await
+                        .expect("Failed to insert erc sales");
+                    if args.feature_price_history {
+                        self.update_price_history(erc_sale_pool).await;
+                    }
+                    auction_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert auctions");
+                    order_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert orders");
+                }
+            },
+            async {
+                if args.feature_wallet_updates {
+                    wallet_pool
+                        .commit(true)
+                        .await
+                        .expect("Failed to update wallets");
+                }
+            },
+            async {
+                if args.feature_pair_reserves {
+                    pair_reserve_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert pair reserves");
+                }
+            },
+            async {
+                token_pool
+                    .commit(false)
+                    .await
+                    .expect("Failed to insert tokens");
+            },
+            async {
+                if args.feature_ron_balances {
+                    ron_change_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert RON balance changes");
+                    self.update_ron_balances(ron_change_pool, block).await;
+                }
+            },
+            async {
+                if args.feature_native_transfers {
+                    native_transfer_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert native transfers");
+                }
+            },
+            async {
+                if args.feature_governance {
+                    governance_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert governance events");
+                }
+            },
+            async {
+                if args.feature_staking {
+                    staking_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert staking events");
+                }
+            },
+            async {
+                if args.feature_axie_equipment {
+                    equipment_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to insert equipment events");
+                    self.update_axie_equipment(equipment_pool).await;
+                }
+            },
+            async {
+                decode_failure_pool
+                    .commit(false)
+                    .await
+                    .expect("Failed to store decode failures");
+            },
+            async {
+                anomaly_pool
+                    .commit(false)
+                    .await
+                    .expect("Failed to store receipt anomalies");
+            },
+            async {
+                contract_event_pool
+                    .commit(false)
+                    .await
+                    .expect("Failed to insert contract events");
+            },
+            async {
+                for pool in subscription_pools.iter_mut() {
+                    pool.commit(false)
+                        .await
+                        .expect("Failed to insert subscribed events");
+                }
+            },
+        );
+
+        self.record_duplicates(tx_pool, block).await;
+        self.record_duplicates(erc_pool, block).await;
+        self.record_duplicates(erc1155_pool, block).await;
+        self.record_duplicates(erc_sale_pool, block).await;
+        self.record_duplicates(wallet_pool, block).await;
+
+        if let Some(sink) = sink.as_ref() {
+            sink.publish(tx_pool).await;
+            sink.publish(erc_pool).await;
+            sink.publish(erc1155_pool).await;
+            sink.publish(erc_sale_pool).await;
+        }
+
+        let metrics = &mut commit.metrics;
+        metrics.blocks += pools.blocks_pending;
+        metrics.transactions += transactions_pending as u64;
+        metrics.erc_transfers += erc_insert_num as u64;
+        metrics.erc1155_transfers += erc1155_insert_num as u64;
+        metrics.erc_sales += erc_sale_num as u64;
+        metrics.wallet_updates += wallet_update_num as u64;
+        metrics.add_pool(tx_pool);
+        metrics.add_pool(erc_pool);
+        metrics.add_pool(erc1155_pool);
+        metrics.add_pool(erc_sale_pool);
+        metrics.add_pool(wallet_pool);
+
+        let summary = BlockSummary {
+            block,
+            transactions: transactions_pending,
+            erc_transfers: erc_insert_num,
+            erc1155_transfers: erc1155_insert_num,
+            wallet_updates: wallet_update_num,
+            erc_sales: erc_sale_num,
+        };
+
+        // Empty blocks are only printed with --empty-logs, the others at least once per 100 blocks.
+        if transactions_pending > 0
+            && (log_enabled!(Info) || block / 100 != first_block.saturating_sub(1) / 100)
+        {
+            summary.print(args.log_format);
+        }
+
+        if markers {
+            self.database
+                .block_status
+                .mark(first_block, block, BlockState::Complete)
+                .await
+                .expect("Failed to mark blocks complete!");
+
+            // Re-indexing older ranges leaves the resume point alone.
+            if commit.resume_block.is_none_or(|resume| block > resume) {
+                self.database
+                    .settings
+                    .store(STREAM_KEY, &block)
+                    .await
+                    .expect("Failed to store stream progress!");
+                commit.resume_block = Some(block);
+            }
+        }
+        status::indexed(block);
+    }
+
+    /// eth_getLogs filters covering the events decoded by [`Ronin::decode_receipt`]. Transfers are
+    /// restricted to the tracked contracts, the other events are matched by signature on any
+    /// address.
+    fn log_filters(&self, contracts: &ContractList, features: DecodeFeatures) -> LogFilters {
+        let topic = |topic: &str| H256::from_str(topic).unwrap();
+        let signature = |contract_type: &ContractType| self.events[contract_type].signature();
+        let mut filters = vec![];
+
+        if features.erc_transfers || features.erc_721_sales {
+            let addresses = contracts
+                .keys()
+                .map(|address| H160::from_str(address).unwrap())
+                .collect();
+
+            filters.push((
+                Some(addresses),
+                vec![
+                    topic(ERC_TRANSFER_TOPIC),
+                    topic(ERC1155_TRANSFER_SINGLE_TOPIC),
+                    topic(ERC1155_TRANSFER_BATCH_TOPIC),
+                ],
+            ));
+        }
+
+        let mut topics = vec![];
+        if features.erc_721_sales {
+            topics.push(topic(MARKETPLACE_V2_ORDER_MATCHED_TOPIC));
+            topics.push(topic(MARKETPLACE_AXIE_SALE_TOPIC));
+            topics.push(signature(&LegacyAuctionCreated));
+        }
+        if features.pair_reserves {
+            topics.push(topic(PAIR_SYNC_TOPIC));
+        }
+        if !topics.is_empty() {
+            filters.push((None, topics));
+        }
+
+        if let Some(contracts) = GOVERNANCE_CONTRACTS.get().filter(|_| features.governance) {
+            filters.push((
+                Some(
+                    contracts
+                        .iter()
+                        .map(|address| H160::from_str(address).unwrap())
+                        .collect(),
+                ),
+                GOVERNANCE_EVENTS
+                    .iter()
+                    .map(|(t, _)| signature(t))
+                    .collect(),
+            ));
+        }
+
+        if features.erc_721_sales {
+            filters.push((
+                Some(vec![H160::from_str(MARKETPLACE_V2_CONTRACT).unwrap()]),
+                vec![
+                    signature(&MarketplaceV2OrderCreated),
+                    signature(&MarketplaceV2OrderCancelled),
+                ],
+            ));
+        }
+
+        if features.staking {
+            filters.push((
+                Some(
+                    STAKING_CONTRACTS
+                        .iter()
+                        .map(|address| H160::from_str(address).unwrap())
+                        .collect(),
+                ),
+                STAKING_EVENTS.iter().map(|(t, _)| signature(t)).collect(),
+            ));
+        }
+
+        if let Some(contract) = EQUIPMENT_CONTRACT.get().filter(|_| features.axie_equipment) {
+            filters.push((
+                Some(vec![H160::from_str(contract).unwrap()]),
+                EQUIPMENT_EVENTS.iter().map(|(t, _)| signature(t)).collect(),
+            ));
+        }
+
+        filters
+    }
+
+    /// Loads the logs of `from..=to` and groups them into one receipt per transaction, ordered by
+    /// block and log index. The receipts only carry the hash, block number and matching logs.
+    /// Failing requests are retried with `backoff`, the error of the last attempt is returned.
+    async fn range_receipts(
+        &self,
+        from: Block,
+        to: Block,
+        filters: &[(Option<Vec<H160>>, Vec<H256>)],
+        backoff: &Backoff,
+    ) -> Result<Vec<TransactionReceipt>, String> {
+        let mut logs: Vec<Log> = vec![];
+
+        for (addresses, topics) in filters {
+            let mut filter = FilterBuilder::default()
+                .from_block(BlockNumber::from(from))
+                .to_block(BlockNumber::from(to))
+                .topics(Some(topics.to_owned()), None, None, None);
+            if let Some(addresses) = addresses {
+                filter = filter.address(addresses.to_owned());
+            }
+
+            let filter = filter.build();
+            logs.extend(
+                backoff
+                    .retry(&f!("logs of {from}..{to}"), || {
+                        self.provider.eth().logs(filter.to_owned())
+                    })
+                    .await?,
+            );
+        }
+
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        logs.dedup_by_key(|log| (log.block_number, log.log_index));
+
+        let mut receipts: Vec<TransactionReceipt> = vec![];
+        for log in logs {
+            match receipts.last_mut() {
+                Some(receipt) if Some(receipt.transaction_hash) == log.transaction_hash => {
+                    receipt.logs.push(log)
+                }
+                _ => receipts.push(TransactionReceipt {
+                    transaction_hash: log.transaction_hash.unwrap_or_default(),
+                    transaction_index: log.transaction_index.unwrap_or_default(),
+                    block_hash: log.block_hash,
+                    block_number: log.block_number,
+                    logs: vec![log],
+                    ..Default::default()
+                }),
+            }
+        }
+
+        Ok(receipts)
+    }
+
+    /// Archive backfill over eth_getLogs: scans `--logs-range` blocks per request for the events of
+    /// [`Ronin::log_filters`] and decodes them like [`Ronin::stream`] does, without loading the
+    /// transactions and receipts. The range is split into chunks of `--chunk-blocks` blocks that
+    /// `--logs-workers` workers index concurrently. The progress of every chunk is kept in
+    /// `backfill_chunks`, a restart resumes the unfinished chunks instead of the whole range. The
+    /// `logs_mode.last_block` setting holds the last block up to which every chunk is done.
+    pub async fn stream_logs(&self, offset: u64, args: Args) {
+        let contracts = self.tracked_contracts().await;
+
+        let stop: Block = match args.stop_block {
+            Some(StopBlock::Number(stop_block)) => stop_block,
+            _ => self.head_stop_block(offset).await,
+        };
+
+        if !self.node_ready(stop, args.wait_for_sync).await {
+            return;
+        }
+
+        let mut start: Block = self
+            .database
+            .settings
+            .load::<u64>(LOGS_MODE_KEY)
+            .await
+            .map(|last_block| last_block + 1)
+            .unwrap_or_else(|| {
+                contracts
+                    .values()
+                    .map(|contract| contract.deploy_block)
+                    .min()
+                    .unwrap_or(0)
+                    .max(1)
+            });
+
+        let mut chunks: Vec<BackfillChunk> = vec![];
+        if let Some(first_block) = args.first_block() {
+            start = first_block;
+        } else {
+            chunks = self.database.backfill_chunks.unfinished().await;
+            chunks.retain(|chunk| chunk.last_block < stop);
+            if let Some(planned_to) = self.database.backfill_chunks.planned_to().await {
+                start = start.max(planned_to + 1);
+            }
+        }
+
+        let chunk_blocks = args.chunk_blocks.max(1);
+        let mut from = start;
+        while from <= stop {
+            let chunk = BackfillChunk::new(from, (from + chunk_blocks - 1).min(stop));
+            if !args.debug && args.start_block.is_none() {
+                self.database
+                    .backfill_chunks
+                    .plan(&chunk)
+                    .await
+                    .expect("Failed to store backfill chunk!");
+            }
+            from = chunk.to + 1;
+            chunks.push(chunk);
+        }
+
+        let Some(first) = chunks.first().map(|chunk| chunk.last_block + 1) else {
+            info!("[INFO] Offset not large enough. Exiting!");
+            return;
+        };
+
+        info!(
+            "[INFO] Streaming logs from {} to {} in {} chunks",
+            &first,
+            &stop,
+            chunks.len()
+        );
+        if !args.debug {
+            storage_profile::record(&self.database).await;
+        }
+        status::head(self.head_stop_block(0).await);
 
-                    let receipt: TransactionReceipt = self
-                        .provider
-                        .eth()
-                        .transaction_receipt(tx.hash)
-                        .await
-                        .expect("Failed to retrieve transaction receipt!")
-                        .expect("Failed to unwrap transaction receipt!");
+        let backfill = self
+            .logs_backfill(&args, contracts, chunks, first, stop, !args.debug)
+            .await;
 
-                    if args.feature_erc_721_sales {
-                        if current_block > MARKETPLACE_V2_DEPLOY_BLOCK {
-                            match self.order_matched(&receipt).await {
-                                None => {}
-                                Some(sale) => {
-                                    if args.debug {
-                                        debug!("[MARKETPLACE V2 SALE] {:#?}", sale);
-                                    }
-                                    erc_sale_pool.insert(sale);
-                                }
-                            }
-                        } else {
-                            match self.legacy_erc_sale(&receipt).await {
-                                None => {}
-                                Some(sale) => {
-                                    if args.debug {
-                                        debug!("[MARKETPLACE SALE] {:#?}", sale);
-                                    }
-                                    erc_sale_pool.insert(sale);
-                                }
-                            }
-                        }
-                    }
+        join_all((0..args.logs_workers.max(1)).map(|_| {
+            rate_limit::worker(args.rpc_worker_qps, self.backfill_worker(&backfill, stop))
+        }))
+        .await;
 
-                    if !receipt.logs.is_empty() {
-                        for log in receipt.logs {
-                            if args.feature_erc_transfers {
-                                if current_block > ERC1155_DEPLOY_BLOCK {
-                                    match &log.topics.clone().into_iter().find(|t| {
-                                        self.to_string(t).as_str() == ERC1155_TRANSFER_SINGLE_TOPIC
-                                    }) {
-                                        None => {}
-                                        Some(_) => {
-                                            let raw_log = RawLog {
-                                                topics: log.topics.clone(),
-                                                data: log.data.0.clone(),
-                                            };
-
-                                            let contract_address = self.to_string(&log.address);
-                                            match contracts.get(&contract_address.as_str()) {
-                                                None => continue,
-                                                Some(_) => {
-                                                    let event_data = transfer_events
-                                                        .get(&ERC1155)
-                                                        .unwrap()
-                                                        .to_owned()
-                                                        .parse_log(raw_log)
-                                                        .expect(
-                                                            "Failed to parsed transaction log!",
-                                                        );
-
-                                                    let operator = self.to_string(
-                                                        &event_data.params[0].value.to_string(),
-                                                    );
-                                                    let operator = f!("0x{operator}");
-
-                                                    let from = self.to_string(
-                                                        &event_data.params[1].value.to_string(),
-                                                    );
-                                                    let from = f!("0x{from}");
-
-                                                    let to = self.to_string(
-                                                        &event_data.params[2].value.to_string(),
-                                                    );
-                                                    let to = f!("0x{to}");
-
-                                                    let token_id = self.to_string(
-                                                        &event_data.params[3].value.to_string(),
-                                                    );
-
-                                                    let value = self.to_string(
-                                                        &event_data.params[4].value.to_string(),
-                                                    );
-
-                                                    let signature =
-                                                        ERC1155Transfer::get_transfer_id(
-                                                            &self.to_string(&log.transaction_hash),
-                                                            &self.to_string(&log.log_index),
-                                                        );
-
-                                                    let transfer = ERC1155Transfer {
-                                                        token: contract_address,
-                                                        operator,
-                                                        from,
-                                                        to,
-                                                        token_id,
-                                                        value,
-                                                        block: block_number,
-                                                        transaction_id: self
-                                                            .to_string(&log.transaction_hash),
-                                                        log_index: self.to_string(&log.log_index),
-                                                        log_id: signature,
-                                                    };
-                                                    if args.debug {
-                                                        debug!(
-                                                            "[ERC1155 Transfer] {:#?}",
-                                                            transfer
-                                                        );
-                                                    }
-
-                                                    erc1155_pool.insert(transfer)
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                match log
-                                    .topics
-                                    .clone()
-                                    .into_iter()
-                                    .find(|t| self.to_string(t).as_str() == ERC_TRANSFER_TOPIC)
-                                {
-                                    None => {}
-                                    Some(_) => {
-                                        let raw_log = RawLog {
-                                            topics: log.topics.clone(),
-                                            data: log.data.0,
-                                        };
-
-                                        let contract_address = self.to_string(&log.address);
-
-                                        match contracts.get(&contract_address.as_str()) {
-                                            None => continue,
-                                            Some(contract) => {
-                                                let event_data = transfer_events
-                                                    .get(&contract.erc)
-                                                    .unwrap()
-                                                    .to_owned()
-                                                    .parse_log(raw_log)
-                                                    .expect("Failed to parsed transaction log!");
-
-                                                let from = self.to_string(
-                                                    &event_data.params[0].value.to_string(),
-                                                );
-                                                let from = f!("0x{from}");
-
-                                                let to = self.to_string(
-                                                    &event_data.params[1].value.to_string(),
-                                                );
-                                                let to = f!("0x{to}");
-
-                                                let signature = ERCTransfer::get_transfer_id(
-                                                    &self.to_string(&log.transaction_hash),
-                                                    &self.to_string(&log.log_index),
-                                                );
-
-                                                let transfer = ERCTransfer {
-                                                    from,
-                                                    to,
-                                                    token: contract_address.to_owned(),
-                                                    value_or_token_id: self.to_string(
-                                                        &event_data.params[2].value.to_string(),
-                                                    ),
-                                                    block: block_number,
-                                                    transaction_id: self
-                                                        .to_string(&log.transaction_hash),
-                                                    erc: contract.erc.to_owned(),
-                                                    log_index: self.to_string(&log.log_index),
-                                                    log_id: signature,
-                                                };
-
-                                                if args.debug {
-                                                    debug!("[ERC Transfer] {:#?}", transfer);
-                                                }
-
-                                                erc_pool.insert(transfer);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+        if !args.debug {
+            backfill
+                .report
+                .into_inner()
+                .expect("Backfill report poisoned!")
+                .finish(&self.database, args.report.as_deref())
+                .await;
+        }
+    }
 
-                    if args.feature_transactions {
-                        let from = f!("0x{tx_from}");
-                        let to = f!("0x{tx_to}");
-
-                        tx_pool.push(Transaction {
-                            from,
-                            to,
-                            hash: self.to_string(&tx.hash),
-                            block: current_block,
-                            timestamp,
-                        });
-                    }
+    /// Indexes `from..=to` over eth_getLogs without storing chunk progress or moving
+    /// `logs_mode.last_block`, used to retry failed blocks.
+    pub async fn reindex_logs(&self, from: Block, to: Block, args: &Args) {
+        let contracts = self.tracked_contracts().await;
+        let chunks = vec![BackfillChunk::new(from, to)];
+        let backfill = self
+            .logs_backfill(args, contracts, chunks, from, to, false)
+            .await;
+
+        self.backfill_worker(&backfill, to).await;
+    }
+
+    async fn logs_backfill<'a>(
+        &self,
+        args: &'a Args,
+        contracts: ContractList,
+        chunks: Vec<BackfillChunk>,
+        first: Block,
+        stop: Block,
+        persist: bool,
+    ) -> LogsBackfill<'a> {
+        let features = DecodeFeatures::from(args);
+
+        LogsBackfill {
+            progress: Mutex::new(
+                chunks
+                    .iter()
+                    .map(|chunk| (chunk.from, (chunk.last_block, chunk.to)))
+                    .collect(),
+            ),
+            queue: Mutex::new(chunks.into()),
+            report: Mutex::new(BackfillReport::new(first, stop)),
+            sink: self.sink(args).await,
+            max_logs_range: probe::profile(&self.database, &args.web3_hostname)
+                .await
+                .map(|profile| profile.max_logs_range),
+            backoff: Backoff::from(args),
+            persist,
+            args,
+            filters: self.log_filters(&contracts, features),
+            spam_tokens: self.database.spam_tokens.tokens().await,
+            whale_thresholds: Ronin::whale_thresholds(&args.whale_thresholds),
+            contracts,
+            features,
+        }
+    }
+
+    /// Indexes chunks of the queue until it is empty, then takes over the rest of the chunks of
+    /// other workers until no chunk has enough blocks left to split.
+    async fn backfill_worker(&self, backfill: &LogsBackfill<'_>, stop: Block) {
+        let _worker = status::worker();
+        let range = match backfill.max_logs_range {
+            Some(max_logs_range) => backfill.args.logs_range.clamp(1, max_logs_range.max(1)),
+            None => backfill.args.logs_range.max(1),
+        };
+
+        loop {
+            let queued = backfill
+                .queue
+                .lock()
+                .expect("Backfill queue poisoned!")
+                .pop_front();
+            let chunk = match queued {
+                Some(chunk) => chunk,
+                None => match self.steal_chunk(backfill, range, stop).await {
+                    Some(chunk) => chunk,
+                    None => return,
+                },
+            };
+
+            let mut from = chunk.last_block + 1;
+            loop {
+                // The end moves down when another worker takes over the rest of the chunk.
+                let end = backfill
+                    .progress
+                    .lock()
+                    .expect("Backfill progress poisoned!")
+                    .get(&chunk.from)
+                    .map_or(chunk.to, |(_, end)| *end)
+                    .min(stop);
+                if from > end {
+                    break;
                 }
 
-                if !args.debug && args.feature_transactions {
+                let to = (from + range - 1).min(end);
+                self.backfill_range(backfill, from, to).await;
+                self.checkpoint(backfill, &chunk, to).await;
+                from = to + 1;
+            }
+        }
+    }
+
+    /// Splits off the back half of the chunk with the most blocks left behind the range its
+    /// worker is indexing, so idle workers share the dense block ranges instead of waiting for
+    /// the last chunks to finish. `None` once no chunk has `range` blocks to spare.
+    async fn steal_chunk(
+        &self,
+        backfill: &LogsBackfill<'_>,
+        range: Block,
+        stop: Block,
+    ) -> Option<BackfillChunk> {
+        let (victim, end, stolen_to) = {
+            let mut progress = backfill
+                .progress
+                .lock()
+                .expect("Backfill progress poisoned!");
+            let (victim, last_block, in_flight, to) = progress
+                .iter()
+                .map(|(from, (last_block, to))| {
+                    (*from, *last_block, last_block + range, (*to).min(stop))
+                })
+                .filter(|(_, _, in_flight, to)| in_flight + range <= *to)
+                .max_by_key(|(_, _, in_flight, to)| to - in_flight)?;
+
+            let end = in_flight + (to - in_flight) / 2;
+            progress.insert(victim, (last_block, end));
+            progress.insert(end + 1, (end, to));
+            (victim, end, to)
+        };
+
+        let chunk = BackfillChunk::new(end + 1, stolen_to);
+        if backfill.persist {
+            // The new chunk is stored first, a crash in between indexes the range twice instead
+            // of not at all.
+            self.database
+                .backfill_chunks
+                .plan(&chunk)
+                .await
+                .expect("Failed to store backfill chunk!");
+            self.database
+                .backfill_chunks
+                .shrink(victim, end)
+                .await
+                .expect("Failed to store backfill chunk!");
+        }
+
+        info!(
+            "[INFO] Took over blocks {}..={} of the chunk at {}",
+            chunk.from, chunk.to, victim
+        );
+
+        Some(chunk)
+    }
+
+    /// Records the progress of a chunk. Unless the run is not persisted, stores it and advances
+    /// `logs_mode.last_block` to the last block up to which all chunks are done.
+    async fn checkpoint(&self, backfill: &LogsBackfill<'_>, chunk: &BackfillChunk, to: Block) {
+        let (end, frontier) = {
+            let mut progress = backfill
+                .progress
+                .lock()
+                .expect("Backfill progress poisoned!");
+            let end = progress.get(&chunk.from).map_or(chunk.to, |(_, end)| *end);
+            progress.insert(chunk.from, (to, end));
+            let frontier = progress
+                .values()
+                .find(|(last_block, end)| last_block < end)
+                .or_else(|| progress.values().last())
+                .map(|(last_block, _)| *last_block);
+            (end, frontier)
+        };
+
+        if !backfill.persist {
+            return;
+        }
+
+        let chunk = BackfillChunk {
+            to: end,
+            ..chunk.to_owned()
+        };
+        self.database
+            .backfill_chunks
+            .progress(&chunk, to)
+            .await
+            .expect("Failed to store backfill chunk progress!");
+
+        if let Some(frontier) = frontier {
+            self.database
+                .settings
+                .store(LOGS_MODE_KEY, &frontier)
+                .await
+                .expect("Failed to store logs mode progress!");
+            status::indexed(frontier);
+        }
+    }
+
+    async fn backfill_range(&self, backfill: &LogsBackfill<'_>, from: Block, to: Block) {
+        let LogsBackfill {
+            args,
+            contracts,
+            features,
+            filters,
+            spam_tokens,
+            whale_thresholds,
+            sink,
+            backoff,
+            ..
+        } = backfill;
+
+        let chunk_started = Instant::now();
+        let receipts = match self.range_receipts(from, to, filters, backoff).await {
+            Ok(receipts) => receipts,
+            Err(error) => {
+                warn!("[WARN] Skipping blocks {}..={}: {}", from, to, error);
+                if !args.debug {
                     self.database
-                        .transactions
-                        .collection
-                        .insert_many(&tx_pool, None)
+                        .failed_blocks
+                        .record(&FailedBlock {
+                            block: from,
+                            to_block: to,
+                            stage: FailedStage::Logs,
+                            error,
+                            attempts: backoff.retries + 1,
+                            created_at: DateTime::now(),
+                        })
                         .await
-                        .ok();
+                        .expect("Failed to record failed block!");
                 }
+                return;
+            }
+        };
 
-                let erc_insert_num = erc_pool.len();
-                let erc1155_insert_num = erc1155_pool.len();
-                let erc_sale_num = erc_sale_pool.len();
-                let wallet_update_num = wallet_pool.len();
+        let mut erc_pool: Pool<ERCTransfer> = self.database.erc_transfers.get_pool();
+        let mut erc1155_pool: Pool<ERC1155Transfer> = self.database.erc1155_transfers.get_pool();
+        let mut erc_sale_pool: Pool<Sale> = self.database.erc_sales.get_pool();
+        let mut auction_pool: Pool<Auction> = self.database.auctions.get_pool();
+        let mut order_pool: Pool<OrderEvent> = self.database.orders.get_pool();
+        let mut pair_reserve_pool: Pool<PairReserve> = self.database.pair_reserves.get_pool();
+        let mut governance_pool: Pool<GovernanceEvent> = self.database.governance_events.get_pool();
+        let mut staking_pool: Pool<StakingEvent> = self.database.staking_events.get_pool();
+        let mut equipment_pool: Pool<EquipmentEvent> = self.database.equipment_events.get_pool();
+        let mut decode_failure_pool: Pool<DecodeFailure> = self.database.decode_failures.get_pool();
+        let mut anomaly_pool: Pool<ReceiptAnomaly> = self.database.receipt_anomalies.get_pool();
 
-                if !args.debug {
+        let mut timestamps: HashMap<Block, DateTime> = HashMap::new();
+        let mut block_times: HashMap<Block, Duration> = HashMap::new();
+
+        for receipt in receipts.iter() {
+            let receipt_started = Instant::now();
+            let block_number = receipt.block_number.unwrap_or_default().as_u64();
+            let timestamp = match timestamps.get(&block_number) {
+                Some(timestamp) => *timestamp,
+                None => {
+                    let timestamp = self
+                        .timestamp_of(block_number)
+                        .await
+                        .unwrap_or_else(|err| panic!("Failed to load block {block_number}: {err}"));
+                    timestamps.insert(block_number, timestamp);
+                    timestamp
+                }
+            };
+
+            let mut decoded =
+                self.decode_receipt(receipt, block_number, timestamp, contracts, *features);
+
+            if args.feature_receipt_checks {
+                for anomaly in self.receipt_anomalies(receipt, &decoded, false) {
+                    anomaly_pool.insert(anomaly);
+                }
+            }
+
+            for transfer in decoded.erc_transfers.iter_mut() {
+                transfer.spam = spam_tokens.contains(&transfer.token);
+            }
+            for transfer in decoded.erc1155_transfers.iter_mut() {
+                transfer.spam = spam_tokens.contains(&transfer.token);
+            }
+            handler::decoded(&decoded);
+
+            for transfer in decoded.erc_transfers {
+                erc_pool.insert(transfer);
+            }
+            for transfer in decoded.erc1155_transfers {
+                erc1155_pool.insert(transfer);
+            }
+            for sale in decoded.sales {
+                erc_sale_pool.insert(sale);
+            }
+            for auction in decoded.auctions {
+                auction_pool.insert(auction);
+            }
+            for order in decoded.orders {
+                order_pool.insert(order);
+            }
+            for reserve in decoded.pair_reserves {
+                pair_reserve_pool.insert(reserve);
+            }
+            for event in decoded.governance_events {
+                governance_pool.insert(event);
+            }
+            for event in decoded.staking_events {
+                staking_pool.insert(event);
+            }
+            for event in decoded.equipment_events {
+                equipment_pool.insert(event);
+            }
+            for failure in decoded.decode_failures {
+                decode_failure_pool.insert(failure);
+            }
+
+            *block_times.entry(block_number).or_default() += receipt_started.elapsed();
+        }
+
+        {
+            let mut report = backfill.report.lock().expect("Backfill report poisoned!");
+            for (block, elapsed) in block_times {
+                report.block(block, elapsed);
+            }
+        }
+        for (block, timestamp) in timestamps.into_iter().collect::<BTreeMap<_, _>>() {
+            handler::block(block, timestamp);
+        }
+
+        let erc_insert_num = erc_pool.len();
+        let erc1155_insert_num = erc1155_pool.len();
+        let erc_sale_num = erc_sale_pool.len();
+
+        if !args.debug {
+            tokio::join!(
+                async {
                     if args.feature_erc_transfers {
                         erc_pool
                             .commit(true)
@@ -1065,75 +5833,508 @@ impl Ronin {
                             .commit(true)
                             .await
                             .expect("Failed to insert erc 1155 transfers");
-                    }
 
+                        if args.feature_erc1155_supply {
+                            self.update_erc1155_supply(&erc1155_pool, to).await;
+                        }
+
+                        if args.feature_erc20_balances {
+                            self.update_erc20_balances(&erc_pool, to).await;
+                        }
+
+                        if args.feature_nft_owners {
+                            self.update_nft_owners(&erc_pool, &erc1155_pool, to).await;
+                        }
+
+                        if !whale_thresholds.is_empty() {
+                            self.write_whale_transfers(&erc_pool, whale_thresholds)
+                                .await;
+                        }
+
+                        if args.feature_wallet_transfers {
+                            self.write_wallet_transfers(&erc_pool, &erc1155_pool).await;
+                        }
+                    }
+                },
+                async {
                     if args.feature_erc_721_sales {
                         erc_sale_pool
                             .commit(true)
                             .await
                             .expect("Failed to insert erc sales");
+                        if args.feature_price_history {
+                            self.update_price_history(&erc_sale_pool).await;
+                        }
+                        auction_pool
+                            .commit(false)
+                            .await
+                            .expect("Failed to insert auctions");
+                        order_pool
+                            .commit(false)
+                            .await
+                            .expect("Failed to insert orders");
                     }
-
-                    if args.feature_wallet_updates {
-                        wallet_pool
-                            .commit(true)
+                },
+                async {
+                    if args.feature_pair_reserves {
+                        pair_reserve_pool
+                            .commit(false)
                             .await
-                            .expect("Failed to update wallets");
+                            .expect("Failed to insert pair reserves");
                     }
-
-                    if log_enabled!(Info) {
-                        info!(
-                        "Block: {:>12}\t\tTransactions: {:>4}\tERC Transfers: {:>5}\tERC 1155 Transfers: {:>5}\tWallet Updates: {:>5}\tERC721 Sales: {:>5}",
-                        &current_block,
-                        num_txs,
-                        erc_insert_num,
-                        erc1155_insert_num,
-                        wallet_update_num,
-                        erc_sale_num
-                    );
-                    } else {
-                        if current_block.rem_euclid(100) == 0 {
-                            println!(
-                                "Block: {:>12}\t\tTransactions: {:>4}\tERC Transfers: {:>5}\tERC 1155 Transfers: {:>5}\tWallet Updates: {:>5}\tERC721 Sales: {:>5}",
-                                &current_block,
-                                num_txs,
-                                erc_insert_num,
-                                erc1155_insert_num,
-                                wallet_update_num,
-                                erc_sale_num
-                            );
-                        }
+                },
+                async {
+                    if args.feature_governance {
+                        governance_pool
+                            .commit(false)
+                            .await
+                            .expect("Failed to insert governance events");
+                    }
+                },
+                async {
+                    if args.feature_staking {
+                        staking_pool
+                            .commit(false)
+                            .await
+                            .expect("Failed to insert staking events");
                     }
+                },
+                async {
+                    if args.feature_axie_equipment {
+                        equipment_pool
+                            .commit(false)
+                            .await
+                            .expect("Failed to insert equipment events");
+                        self.update_axie_equipment(&equipment_pool).await;
+                    }
+                },
+                async {
+                    decode_failure_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to store decode failures");
+                },
+                async {
+                    anomaly_pool
+                        .commit(false)
+                        .await
+                        .expect("Failed to store receipt anomalies");
+                },
+            );
+
+            self.record_duplicates(&erc_pool, to).await;
+            self.record_duplicates(&erc1155_pool, to).await;
+            self.record_duplicates(&erc_sale_pool, to).await;
+
+            if let Some(sink) = sink.as_ref() {
+                sink.publish(&erc_pool).await;
+                sink.publish(&erc1155_pool).await;
+                sink.publish(&erc_sale_pool).await;
+            }
+
+            let mut report = backfill.report.lock().expect("Backfill report poisoned!");
+            report.pool(&erc_pool);
+            report.pool(&erc1155_pool);
+            report.pool(&erc_sale_pool);
+            report.pool(&auction_pool);
+            report.pool(&order_pool);
+            report.pool(&pair_reserve_pool);
+            report.pool(&governance_pool);
+            report.pool(&staking_pool);
+            report.pool(&equipment_pool);
+            report.pool(&decode_failure_pool);
+            report.pool(&anomaly_pool);
+            report.decode_failures += decode_failure_pool.inserted().len() as u64;
+
+            BlockSummary {
+                block: to,
+                transactions: receipts.len(),
+                erc_transfers: erc_insert_num,
+                erc1155_transfers: erc1155_insert_num,
+                wallet_updates: 0,
+                erc_sales: erc_sale_num,
+            }
+            .print(args.log_format);
+        }
+
+        if !args.debug
+            && args.feature_erc20_balances
+            && args.logs_workers <= 1
+            && args.balance_reconcile_interval > 0
+            && to / args.balance_reconcile_interval != (from - 1) / args.balance_reconcile_interval
+        {
+            self.reconcile_erc20_balances(to).await;
+        }
+
+        backfill
+            .report
+            .lock()
+            .expect("Backfill report poisoned!")
+            .chunk(from, to, receipts.len(), chunk_started.elapsed());
+    }
+}
+
+/// State shared by the workers of [`Ronin::stream_logs`].
+struct LogsBackfill<'a> {
+    args: &'a Args,
+    contracts: ContractList,
+    features: DecodeFeatures,
+    filters: LogFilters,
+    spam_tokens: HashSet<WalletAddress>,
+    whale_thresholds: HashMap<String, U256>,
+    sink: Option<NatsSink>,
+    /// eth_getLogs range limit of the node measured by `probe`.
+    max_logs_range: Option<u64>,
+    backoff: Backoff,
+    /// Whether chunk progress and `logs_mode.last_block` are stored.
+    persist: bool,
+    report: Mutex<BackfillReport>,
+    /// Chunks no worker has started yet.
+    queue: Mutex<VecDeque<BackfillChunk>>,
+    /// Last completed and last block of the chunks of this run by first block. The last block
+    /// moves down when another worker takes over the rest of a chunk.
+    progress: Mutex<BTreeMap<Block, (Block, Block)>>,
+}
+
+/// Block loaded by [`BlockFetcher`] with the receipts of its transactions. `block` is `None` for a
+/// block skipped by `--missing-block-policy`.
+struct FetchedBlock {
+    block: Option<web3::types::Block<Web3Transaction>>,
+    /// Transactions of the block, also when only their hashes were loaded.
+    transactions: usize,
+    /// Whether the receipts were loaded. They are skipped when no enabled feature reads them or
+    /// the logs bloom of the block rules out every decoded event.
+    receipts_loaded: bool,
+    receipts: Result<HashMap<H256, TransactionReceipt>, String>,
+}
+
+/// Block data the enabled features need from the node.
+#[derive(Debug, Clone)]
+struct FetchNeeds {
+    /// Transaction bodies, otherwise only the block header and transaction hashes are loaded.
+    bodies: bool,
+    /// Receipts, needed for logs, gas and created contracts.
+    receipts: bool,
+    /// Filters of the decoded events when they are all the receipts are read for. Blocks whose
+    /// logs bloom matches none of them are indexed without loading the receipts.
+    screen: Option<Arc<LogFilters>>,
+}
+
+impl FetchNeeds {
+    fn new(args: &Args, abis: &AbiRegistry, filters: LogFilters) -> FetchNeeds {
+        let logs = args.feature_erc_transfers
+            || args.feature_erc_721_sales
+            || args.feature_pair_reserves
+            || args.feature_governance
+            || args.feature_staking
+            || args.feature_axie_equipment;
+        // Features reading the receipt of every transaction, or logs beyond the decoded events.
+        let every_receipt = args.feature_transactions
+            || args.feature_ron_balances
+            || args.feature_native_transfers
+            || args.feature_contract_discovery
+            || args.feature_receipt_checks
+            || !args.official_deployers.is_empty()
+            || args.events.is_some()
+            || !abis.is_empty();
+        let receipts = logs || every_receipt;
+
+        FetchNeeds {
+            bodies: receipts || args.feature_transactions || args.feature_wallet_updates,
+            receipts,
+            screen: (logs && !every_receipt).then(|| Arc::new(filters)),
+        }
+    }
+
+    /// Whether `block` may hold a log the receipts are loaded for.
+    fn may_contain_logs(&self, block: &web3::types::Block<Web3Transaction>) -> bool {
+        let (Some(filters), Some(bloom)) = (&self.screen, &block.logs_bloom) else {
+            return self.receipts;
+        };
+
+        filters.iter().any(|(addresses, topics)| {
+            let address = match addresses {
+                Some(addresses) => addresses
+                    .iter()
+                    .any(|address| bloom_contains(bloom, address.as_bytes())),
+                None => true,
+            };
+
+            address
+                && topics
+                    .iter()
+                    .any(|topic| bloom_contains(bloom, topic.as_bytes()))
+        })
+    }
+}
+
+/// Whether the logs bloom `bloom` may contain the address or topic `input`. Each input sets the
+/// three bits addressed by the first three byte pairs of its hash.
+fn bloom_contains(bloom: &H2048, input: &[u8]) -> bool {
+    let hash = keccak256(input);
+    let bytes = bloom.as_bytes();
+
+    (0..3).all(|pair| {
+        let bit = (usize::from(hash[2 * pair]) << 8 | usize::from(hash[2 * pair + 1])) & 2047;
+        bytes[bytes.len() - 1 - bit / 8] & (1 << (bit % 8)) != 0
+    })
+}
+
+/// Returns `value` borrowed for the lifetime of the process, as the entries of a [`ContractList`]
+/// need it. Each distinct value is leaked once, however often the contract list is rebuilt.
+fn intern(value: &str) -> &'static str {
+    let mut interned = INTERNED.lock().expect("Interned strings poisoned!");
+    match interned.get(value) {
+        Some(value) => value,
+        None => {
+            let value: &'static str = Box::leak(value.to_owned().into_boxed_str());
+            interned.insert(value);
+            value
+        }
+    }
+}
+
+/// Whether `err` rejects the called method as unsupported: JSON-RPC error -32601 or a node that
+/// reports the method as not found or not supported in its message.
+fn unsupported_method(err: &web3::Error) -> bool {
+    let web3::Error::Rpc(err) = err else {
+        return false;
+    };
+    let message = err.message.to_lowercase();
+
+    err.code.code() == -32601
+        || [
+            "method not found",
+            "not supported",
+            "unsupported",
+            "does not exist",
+            "is not available",
+        ]
+        .iter()
+        .any(|phrase| message.contains(phrase))
+}
+
+/// Fetch stage of [`Ronin::stream`]. Loads blocks and their receipts up to `--prefetch-blocks`
+/// ahead on a task of its own, so the node serves the next blocks while the current one is
+/// decoded and written. Blocks at or beyond the stop block are never fetched.
+struct BlockFetcher {
+    ronin: Ronin,
+    args: Args,
+    fallback: Option<Provider>,
+    block_receipts_supported: Arc<AtomicBool>,
+    needs: FetchNeeds,
+    stop: watch::Sender<Block>,
+    /// Block the prefetched blocks continue with.
+    next: Block,
+    blocks: Option<mpsc::Receiver<FetchedBlock>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl BlockFetcher {
+    fn new(
+        ronin: Ronin,
+        args: Args,
+        fallback: Option<Provider>,
+        block_receipts_supported: bool,
+        needs: FetchNeeds,
+        stop: Block,
+    ) -> BlockFetcher {
+        BlockFetcher {
+            ronin,
+            args,
+            fallback,
+            block_receipts_supported: Arc::new(AtomicBool::new(block_receipts_supported)),
+            needs,
+            stop: watch::channel(stop).0,
+            next: 0,
+            blocks: None,
+            task: None,
+        }
+    }
+
+    fn stop_at(&self, stop: Block) {
+        self.stop.send_replace(stop);
+    }
+
+    /// Restarts the fetch task at `from`, dropping the blocks prefetched for another position.
+    fn start(&mut self, from: Block) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+
+        let (sender, receiver) = mpsc::channel(self.args.prefetch_blocks);
+        let ronin = self.ronin.clone();
+        let args = self.args.clone();
+        let fallback = self.fallback.clone();
+        let block_receipts_supported = self.block_receipts_supported.clone();
+        let needs = self.needs.clone();
+        let mut stop = self.stop.subscribe();
+
+        self.task = Some(tokio::spawn(async move {
+            for number in from.. {
+                if stop.wait_for(|stop| number < *stop).await.is_err() {
+                    return;
                 }
-            } else if args.empty_logs && !args.debug {
-                if log_enabled!(Info) {
-                    info!(
-                    "Block: {:>12}\t\tTransactions: {:>4}\tERC Transfers: {:>5}\tERC 1155 Transfers: {:>5}\tWallet Updates: {:>5}\tERC721 Sales: {:>5}",
-                    &current_block,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0
-                );
-                } else {
-                    println!(
-                    "Block: {:>12}\t\tTransactions: {:>4}\tERC Transfers: {:>5}\tERC 1155 Transfers: {:>5}\tWallet Updates: {:>5}\tERC721 Sales: {:>5}",
-                    &current_block,
-                    0,
-                    0,
-                    0,
-                    0,
-                    0
-                );
+
+                let fetched = ronin
+                    .fetch_block(
+                        number,
+                        &args,
+                        fallback.as_ref(),
+                        &block_receipts_supported,
+                        &needs,
+                    )
+                    .await;
+                if sender.send(fetched).await.is_err() {
+                    return;
                 }
             }
+        }));
+        self.blocks = Some(receiver);
+        self.next = from;
+    }
 
-            current_block += 1;
+    /// Block `number` with its receipts. Fetching restarts when `number` does not continue the
+    /// previous block, like after a reorg.
+    async fn next(&mut self, number: Block) -> FetchedBlock {
+        if self.args.prefetch_blocks == 0 {
+            return self
+                .ronin
+                .fetch_block(
+                    number,
+                    &self.args,
+                    self.fallback.as_ref(),
+                    &self.block_receipts_supported,
+                    &self.needs,
+                )
+                .await;
+        }
 
-            if current_block >= stream_stop_block {
-                break;
+        if self.blocks.is_none() || self.next != number {
+            self.start(number);
+        }
+        self.next = number + 1;
+
+        self.blocks
+            .as_mut()
+            .expect("Block fetcher not started!")
+            .recv()
+            .await
+            .expect("Block fetcher stopped!")
+    }
+}
+
+impl Drop for BlockFetcher {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Batch of the stream for [`StreamWriter`].
+enum WriterMessage {
+    Pools(Box<StreamPools>),
+    /// Answered once the batches sent before it are committed.
+    Flush(oneshot::Sender<()>),
+}
+
+/// What [`Ronin::commit_stream`] commits the pools of the stream with.
+struct StreamCommit {
+    args: Args,
+    atomic: bool,
+    whale_thresholds: HashMap<String, U256>,
+    sink: Option<NatsSink>,
+    metrics: MetricsWindow,
+    /// Block stored under [`STREAM_KEY`], which only moves forward.
+    resume_block: Option<Block>,
+}
+
+/// Write stage of [`Ronin::stream`]. Commits the pools of the stream on a task of its own, so the
+/// next blocks are fetched and decoded while the previous ones are written. One batch waits while
+/// another is written, the stream waits for the writer before handing over a third.
+struct StreamWriter {
+    batches: mpsc::Sender<WriterMessage>,
+    task: JoinHandle<()>,
+}
+
+impl StreamWriter {
+    fn start(ronin: Ronin, mut commit: StreamCommit) -> StreamWriter {
+        let (batches, mut receiver) = mpsc::channel(1);
+
+        let task = tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                let mut pools = match message {
+                    WriterMessage::Pools(pools) => pools,
+                    WriterMessage::Flush(done) => {
+                        done.send(()).ok();
+                        continue;
+                    }
+                };
+                let Some(block) = pools.last_block else {
+                    continue;
+                };
+
+                ronin.commit_stream(&mut pools, &mut commit).await;
+
+                if commit.args.metrics_interval > 0
+                    && commit.metrics.started.elapsed().as_secs() >= commit.args.metrics_interval
+                {
+                    ronin.write_metrics(&commit.metrics, block).await;
+                    commit.metrics = MetricsWindow::new();
+                }
             }
+        });
+
+        StreamWriter { batches, task }
+    }
+
+    /// Hands the pools of the blocks added since the last write to the writer task.
+    async fn write(&mut self, pools: StreamPools) {
+        self.send(WriterMessage::Pools(Box::new(pools))).await;
+    }
+
+    /// Waits until every batch handed over so far is committed.
+    async fn flush(&mut self) {
+        let (done, flushed) = oneshot::channel();
+        self.send(WriterMessage::Flush(done)).await;
+        if flushed.await.is_err() {
+            self.stopped().await;
+        }
+    }
+
+    /// Commits the remaining batches and stops the writer task.
+    async fn finish(self) {
+        drop(self.batches);
+        if let Err(err) = self.task.await {
+            std::panic::resume_unwind(err.into_panic());
+        }
+    }
+
+    async fn send(&mut self, message: WriterMessage) {
+        if self.batches.send(message).await.is_err() {
+            self.stopped().await;
+        }
+    }
+
+    /// Raises the panic the writer task stopped with.
+    async fn stopped(&mut self) -> ! {
+        match (&mut self.task).await {
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            _ => panic!("Stream writer stopped!"),
         }
     }
 }
+
+/// Commits a pool of retried documents, treating documents that already exist as stored.
+async fn store<T>(mut pool: Pool<T>) -> Result<(), String>
+where
+    T: Serialize + Clone + Eq + PartialEq,
+{
+    pool.commit(false).await.map_err(|err| err.to_string())?;
+
+    match pool.errors() {
+        0 => Ok(()),
+        _ => Err(f!("Failed to store decoded document in {}", pool.name())),
+    }
+}
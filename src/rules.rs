@@ -0,0 +1,546 @@
+use std::cmp::Ordering;
+
+use serde_json::Value;
+use web3::types::U256;
+
+use crate::ronin::{ContractType, Ronin};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Text(String),
+    Number(String),
+    Op(Op),
+    Open,
+    Close,
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Text(String),
+    Amount(U256),
+    Decimal(f64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, Operand),
+    In(String, Vec<Operand>),
+}
+
+/// Filter rule of the config files and flags, e.g.
+/// `token == WETH and value_or_token_id >= 10 WETH and not to in (0xabc.., 0xdef..)`.
+///
+/// * Conditions compare a document field, dotted for nested fields like `params.value`, with a
+///   value: `==`, `!=`, `<`, `<=`, `>`, `>=` or `in (a, b, ..)`. A missing field never matches,
+///   an array field matches if one of its elements does.
+/// * Conditions combine with `and`, `or`, `not` and parentheses.
+/// * Values are quoted strings, `0x` addresses, numbers, `true`/`false` and contract names like
+///   `WETH`, which resolve to the contract address. Quote a name to compare the plain text.
+/// * A number followed by an ERC20 contract name is an amount in whole tokens, `1.5 WETH` is
+///   converted to the raw amount with the decimals of the token.
+///
+/// Strings compared with a number are read as hex, the way amounts and token ids are stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    source: String,
+    expr: Expr,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, quote)) if quote == c => break,
+                        Some((_, next)) => text.push(next),
+                        None => return Err(f!("Unclosed string at {start}")),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.next_if(|(_, next)| *next == '=').is_some();
+                let op = match (c, equals) {
+                    ('=', true) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => return Err(f!("Invalid operator at {start}")),
+                };
+                tokens.push(Token::Op(op));
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '.' => {
+                let mut end = start + c.len_utf8();
+                // Contract names like AXS-WETH-LP contain dashes.
+                while let Some((index, next)) = chars.next_if(|(_, next)| {
+                    next.is_ascii_alphanumeric() || matches!(next, '_' | '.' | '-')
+                }) {
+                    end = index + next.len_utf8();
+                }
+
+                let word = &source[start..end];
+                match c.is_ascii_digit() && !word.starts_with("0x") {
+                    true => tokens.push(Token::Number(word.to_string())),
+                    false => tokens.push(Token::Ident(word.to_string())),
+                }
+            }
+            c => return Err(f!("Unexpected {c} at {start}")),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+}
+
+/// Raw amount of `amount` whole tokens of an ERC20 contract given by name or address.
+fn token_amount(amount: &str, token: &str) -> Result<U256, String> {
+    let contract = Ronin::contract_list()
+        .into_values()
+        .filter(|contract| contract.erc == ContractType::ERC20)
+        .find(|contract| {
+            contract.name.eq_ignore_ascii_case(token)
+                || contract.address.eq_ignore_ascii_case(token)
+        })
+        .ok_or_else(|| f!("Unknown ERC20 token {token}"))?;
+
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > contract.decimals {
+        return Err(f!(
+            "{amount} {token} has more than {} decimals",
+            contract.decimals
+        ));
+    }
+    let digits = f!("{whole}{fraction:0<width$}", width = contract.decimals);
+
+    U256::from_dec_str(&digits).map_err(|_| f!("Invalid amount {amount} {token}"))
+}
+
+/// Address of a contract given by name, or `None` for an unknown name.
+fn contract_address(name: &str) -> Option<String> {
+    Ronin::contract_list()
+        .into_values()
+        .find(|contract| contract.name.eq_ignore_ascii_case(name))
+        .map(|contract| contract.address.to_string())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(f!("Expected {expected:?}, found {token:?}")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while keyword(self.peek(), "or") {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while keyword(self.peek(), "and") {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if keyword(self.peek(), "not") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.next();
+            let expr = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(expr);
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Expr, String> {
+        let field = match self.next() {
+            Some(Token::Ident(field)) => field,
+            token => return Err(f!("Expected a field, found {token:?}")),
+        };
+
+        if keyword(self.peek(), "in") {
+            self.next();
+            self.expect(Token::Open)?;
+            let mut values = vec![self.operand()?];
+            while self.peek() == Some(&Token::Comma) {
+                self.next();
+                values.push(self.operand()?);
+            }
+            self.expect(Token::Close)?;
+            return Ok(Expr::In(field, values));
+        }
+
+        match self.next() {
+            Some(Token::Op(op)) => Ok(Expr::Compare(field, op, self.operand()?)),
+            token => Err(f!("Expected an operator after {field}, found {token:?}")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::Text(text)) => Ok(Operand::Text(text)),
+            Some(Token::Number(number)) => match self.peek() {
+                Some(Token::Ident(unit))
+                    if !unit.eq_ignore_ascii_case("and") && !unit.eq_ignore_ascii_case("or") =>
+                {
+                    let amount = token_amount(&number, unit)?;
+                    self.next();
+                    Ok(Operand::Amount(amount))
+                }
+                _ if number.contains('.') => number
+                    .parse::<f64>()
+                    .map(Operand::Decimal)
+                    .map_err(|_| f!("Invalid number {number}")),
+                _ => U256::from_dec_str(&number)
+                    .map(Operand::Amount)
+                    .map_err(|_| f!("Invalid number {number}")),
+            },
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("true") => {
+                Ok(Operand::Bool(true))
+            }
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case("false") => {
+                Ok(Operand::Bool(false))
+            }
+            Some(Token::Ident(word)) if word.starts_with("0x") => {
+                Ok(Operand::Text(word.to_lowercase()))
+            }
+            Some(Token::Ident(word)) => Ok(Operand::Text(contract_address(&word).unwrap_or(word))),
+            token => Err(f!("Expected a value, found {token:?}")),
+        }
+    }
+}
+
+/// Number of a document value, strings are read as hex.
+fn amount(value: &Value) -> Option<U256> {
+    match value {
+        Value::Number(number) => number.as_u64().map(U256::from),
+        Value::String(text) => U256::from_str_radix(text.trim_start_matches("0x"), 16).ok(),
+        _ => None,
+    }
+}
+
+fn ordering(value: &Value, operand: &Operand) -> Option<Ordering> {
+    match operand {
+        Operand::Text(text) => value.as_str().map(|value| value.cmp(text.as_str())),
+        Operand::Amount(expected) => amount(value).map(|value| value.cmp(expected)),
+        Operand::Decimal(expected) => value.as_f64().and_then(|value| value.partial_cmp(expected)),
+        Operand::Bool(expected) => value.as_bool().map(|value| value.cmp(expected)),
+    }
+}
+
+fn compare(value: &Value, op: Op, operand: &Operand) -> bool {
+    if let Value::Array(values) = value {
+        return values.iter().any(|value| compare(value, op, operand));
+    }
+
+    match (ordering(value, operand), op) {
+        (None, _) => false,
+        (Some(ordering), Op::Eq) => ordering == Ordering::Equal,
+        (Some(ordering), Op::Ne) => ordering != Ordering::Equal,
+        (Some(ordering), Op::Lt) => ordering == Ordering::Less,
+        (Some(ordering), Op::Le) => ordering != Ordering::Greater,
+        (Some(ordering), Op::Gt) => ordering == Ordering::Greater,
+        (Some(ordering), Op::Ge) => ordering != Ordering::Less,
+    }
+}
+
+fn field<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(document, |value, key| value.get(key))
+}
+
+impl Expr {
+    fn matches(&self, document: &Value) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(document) && right.matches(document),
+            Expr::Or(left, right) => left.matches(document) || right.matches(document),
+            Expr::Not(expr) => !expr.matches(document),
+            Expr::Compare(path, op, operand) => field(document, path)
+                .map(|value| compare(value, *op, operand))
+                .unwrap_or(false),
+            Expr::In(path, operands) => field(document, path)
+                .map(|value| {
+                    operands
+                        .iter()
+                        .any(|operand| compare(value, Op::Eq, operand))
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl Rule {
+    pub fn parse(source: &str) -> Result<Rule, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expr = parser
+            .or()
+            .map_err(|err| f!("Invalid rule {source}: {err}"))?;
+        if let Some(token) = parser.peek() {
+            return Err(f!("Invalid rule {source}: unexpected {token:?}"));
+        }
+
+        Ok(Rule {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Evaluates the rule against a document serialized to JSON.
+    pub fn matches<T: serde::Serialize>(&self, document: &T) -> bool {
+        match serde_json::to_value(document) {
+            Ok(value) => self.expr.matches(&value),
+            Err(_) => false,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const WETH: &str = "0xc99a6a985ed2cac1ef41640596c5a5f9f4e19ef5";
+
+    fn condition(field: &str, op: Op, operand: Operand) -> Expr {
+        Expr::Compare(field.to_string(), op, operand)
+    }
+
+    fn number(value: u64) -> Operand {
+        Operand::Amount(U256::from(value))
+    }
+
+    fn expr(source: &str) -> Expr {
+        Rule::parse(source).unwrap().expr
+    }
+
+    fn error(source: &str) -> String {
+        Rule::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            expr("a == 1 or b == 2 and c == 3"),
+            Expr::Or(
+                Box::new(condition("a", Op::Eq, number(1))),
+                Box::new(Expr::And(
+                    Box::new(condition("b", Op::Eq, number(2))),
+                    Box::new(condition("c", Op::Eq, number(3))),
+                )),
+            )
+        );
+
+        let rule = Rule::parse("a == 1 or b == 2 and c == 3").unwrap();
+        assert!(rule.matches(&json!({"a": 1, "b": 0, "c": 0})));
+        assert!(!rule.matches(&json!({"a": 0, "b": 2, "c": 0})));
+        assert!(rule.matches(&json!({"a": 0, "b": 2, "c": 3})));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(
+            expr("not a == 1 and b == 2"),
+            Expr::And(
+                Box::new(Expr::Not(Box::new(condition("a", Op::Eq, number(1))))),
+                Box::new(condition("b", Op::Eq, number(2))),
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_group() {
+        let rule = Rule::parse("(a == 1 or b == 2) and c == 3").unwrap();
+        assert!(!rule.matches(&json!({"a": 1, "b": 0, "c": 0})));
+        assert!(rule.matches(&json!({"a": 1, "b": 0, "c": 3})));
+
+        let rule = Rule::parse("not (a == 1 or b == 2)").unwrap();
+        assert!(rule.matches(&json!({"a": 0, "b": 0})));
+        assert!(!rule.matches(&json!({"a": 0, "b": 2})));
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        assert_eq!(expr("a == 1 AND b == 2"), expr("a == 1 and b == 2"));
+        assert_eq!(expr("NOT a IN (1)"), expr("not a in (1)"));
+    }
+
+    #[test]
+    fn in_matches_any_value() {
+        assert_eq!(
+            expr("to in (0xABC, \"WETH\", WETH)"),
+            Expr::In(
+                "to".to_string(),
+                vec![
+                    Operand::Text("0xabc".to_string()),
+                    Operand::Text("WETH".to_string()),
+                    Operand::Text(WETH.to_string()),
+                ],
+            )
+        );
+
+        let rule = Rule::parse("to in (0xabc, 0xdef)").unwrap();
+        assert!(rule.matches(&json!({"to": "0xdef"})));
+        assert!(!rule.matches(&json!({"to": "0x123"})));
+    }
+
+    #[test]
+    fn whole_token_amounts() {
+        assert_eq!(
+            expr("value >= 1.5 WETH"),
+            condition(
+                "value",
+                Op::Ge,
+                Operand::Amount(U256::from(1_500_000_000_000_000_000u64)),
+            )
+        );
+
+        // Amounts are stored hex encoded.
+        let rule = Rule::parse("value >= 1.5 WETH").unwrap();
+        assert!(rule.matches(&json!({"value": "14d1120d7b160000"})));
+        assert!(!rule.matches(&json!({"value": "14d1120d7b15ffff"})));
+
+        assert!(error("value > 1.0000000000000000001 WETH").contains("more than 18 decimals"));
+        assert!(error("value > 1 AXIE").contains("Unknown ERC20 token AXIE"));
+    }
+
+    #[test]
+    fn plain_numbers_and_booleans() {
+        assert_eq!(
+            expr("price < 2.5"),
+            condition("price", Op::Lt, Operand::Decimal(2.5))
+        );
+        assert_eq!(
+            expr("spam != true"),
+            condition("spam", Op::Ne, Operand::Bool(true))
+        );
+
+        let rule = Rule::parse("value > 254").unwrap();
+        assert!(rule.matches(&json!({"value": "ff"})));
+        assert!(!rule.matches(&json!({"value": "fe"})));
+    }
+
+    #[test]
+    fn contract_names_resolve_to_addresses() {
+        assert_eq!(
+            expr("token == AXS-WETH-LP"),
+            condition(
+                "token",
+                Op::Eq,
+                Operand::Text("0xc6344bc1604fcab1a5aad712d766796e2b7a70b9".to_string()),
+            )
+        );
+        assert_eq!(
+            expr("name == 'WETH'"),
+            condition("name", Op::Eq, Operand::Text("WETH".to_string()))
+        );
+        assert_eq!(
+            expr("name == UNKNOWN"),
+            condition("name", Op::Eq, Operand::Text("UNKNOWN".to_string()))
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let rule = Rule::parse("params.value == 5").unwrap();
+        assert!(rule.matches(&json!({"params": {"value": 5}})));
+        assert!(!rule.matches(&json!({"value": 5})));
+
+        let rule = Rule::parse("ids == 3").unwrap();
+        assert!(rule.matches(&json!({"ids": [1, 2, 3]})));
+
+        // A missing field never matches, so its negation does.
+        assert!(!Rule::parse("to != 0xabc").unwrap().matches(&json!({})));
+        assert!(Rule::parse("not to == 0xabc").unwrap().matches(&json!({})));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert!(error("a == \"x").contains("Unclosed string at 5"));
+        assert!(error("a = 1").contains("Invalid operator at 2"));
+        assert!(error("a == 1 $").contains("Unexpected $ at 7"));
+    }
+
+    #[test]
+    fn malformed_rules() {
+        for source in [
+            "",
+            "a",
+            "a ==",
+            "== 1",
+            "a == 1 b == 2",
+            "(a == 1",
+            "a == 1)",
+            "a in 1",
+            "a in (1,)",
+            "a in (1",
+            "a == 1 and",
+            "not",
+        ] {
+            assert!(Rule::parse(source).is_err(), "{source} parsed");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::runtime::{Builder, Runtime};
+
+/// Builds the multi threaded runtime the indexer runs on. `worker_threads` of 0 keeps the tokio
+/// default of one worker per core.
+pub fn build(worker_threads: usize, pin_workers: bool) -> Runtime {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+
+    if worker_threads > 0 {
+        builder.worker_threads(worker_threads);
+    }
+
+    if pin_workers {
+        let cores = allowed_cores();
+        let next = AtomicUsize::new(0);
+
+        builder.on_thread_start(move || {
+            if cores.is_empty() {
+                return;
+            }
+            let core = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+            pin_current_thread(core);
+        });
+    }
+
+    builder.build().expect("Failed to build runtime!")
+}
+
+/// Cores the process may run on, in order, so pinning respects taskset/cgroup restrictions.
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> Vec<usize> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return vec![];
+        }
+
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|core| libc::CPU_ISSET(*core, &set))
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> Vec<usize> {
+    eprintln!("--pin-workers is only supported on Linux");
+    vec![]
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) {}
@@ -0,0 +1,91 @@
+use log::{info, warn};
+use mongodb::bson::doc;
+
+use crate::cache::indexed_head;
+use crate::mongo::{self, Database, VERSIONED_COLLECTIONS};
+use crate::ronin::LOGS_MODE_KEY;
+
+/// Renames `from` to `to` within the database, failing if `to` already exists.
+async fn rename(database: &Database, from: &str, to: &str) {
+    let name = database._database.name();
+
+    database
+        ._client
+        .database("admin")
+        .run_command(
+            doc! {
+                "renameCollection": f!("{name}.{from}"),
+                "to": f!("{name}.{to}"),
+                "dropTarget": false
+            },
+            None,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("Failed to rename {from} to {to}: {err}"));
+}
+
+/// Promotes the collections of shadow decoder `version` to the live collections. The live
+/// collections are kept as `<name>_pre_v<version>` so a promotion can be undone by renaming them
+/// back.
+///
+/// Refuses to promote unless the shadow, which runs in logs mode, has caught up with the live
+/// pipeline, i.e. its `logs_mode.last_block` reached the live indexed head, or `force` is set.
+/// Each rename is atomic on its own but the promotion as a whole is not: readers may briefly see
+/// promoted and old collections side by side. Stop the shadow before promoting and restart the
+/// live pipeline with the new decoder afterwards.
+pub async fn promote(database: &Database, version: u32, force: bool) {
+    let shadow = mongo::build(
+        database._client.clone(),
+        database._database.name(),
+        Some(version),
+    );
+
+    let shadow_block = shadow.settings.load::<u64>(LOGS_MODE_KEY).await;
+    let live_block = indexed_head(database).await.max(
+        database
+            .settings
+            .load::<u64>(LOGS_MODE_KEY)
+            .await
+            .unwrap_or(0),
+    );
+
+    match shadow_block {
+        Some(block) if block >= live_block => {
+            info!("[INFO] Shadow v{version} is at block {block}, live at {live_block}")
+        }
+        _ if force => warn!(
+            "[WARN] Promoting shadow v{version} at block {:?} behind live block {live_block}",
+            shadow_block
+        ),
+        _ => panic!(
+            "Shadow v{version} is at block {:?} behind live block {live_block}, pass --force to promote anyway",
+            shadow_block
+        ),
+    }
+
+    let existing = database
+        ._database
+        .list_collection_names(None)
+        .await
+        .expect("Failed to list collections!");
+
+    for collection in VERSIONED_COLLECTIONS {
+        let versioned = mongo::versioned_name(collection, Some(version));
+
+        if !existing.contains(&versioned) {
+            warn!("[WARN] Shadow v{version} has no {versioned}, keeping {collection}");
+            continue;
+        }
+
+        if existing.iter().any(|name| name == collection) {
+            rename(database, collection, &f!("{collection}_pre_v{version}")).await;
+        }
+        rename(database, &versioned, collection).await;
+
+        info!("[INFO] Promoted {versioned} to {collection}");
+    }
+
+    println!(
+        "Promoted shadow v{version}, the previous collections are kept as <name>_pre_v{version}"
+    );
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use log::{info, warn};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::mongo::collections::transaction_pool::Pool;
+use crate::rules::Rule;
+
+/// File sink from `--sink file:<dir>`, replaces the MongoDB writes of the pools.
+static FILE_SINK: OnceLock<FileSink> = OnceLock::new();
+
+/// Appends committed documents as newline delimited JSON to `<dir>/<collection>.ndjson`. Updates
+/// go to `<dir>/<collection>.updates.ndjson` as `{"filter", "update", "upsert"}` lines.
+pub struct FileSink {
+    dir: PathBuf,
+    files: std::sync::Mutex<HashMap<String, File>>,
+}
+
+impl FileSink {
+    pub fn init(dir: &Path) {
+        fs::create_dir_all(dir)
+            .unwrap_or_else(|err| panic!("Failed to create {}: {err}", dir.display()));
+
+        let sink = FileSink {
+            dir: dir.to_path_buf(),
+            files: std::sync::Mutex::new(HashMap::new()),
+        };
+        if FILE_SINK.set(sink).is_err() {
+            panic!("File sink set twice!");
+        }
+
+        info!("[INFO] Writing indexed documents to {}", dir.display());
+    }
+
+    pub fn get() -> Option<&'static FileSink> {
+        FILE_SINK.get()
+    }
+
+    /// Appends one line per document to the file of `name`.
+    pub fn append<T: Serialize>(&self, name: &str, documents: &[T]) {
+        if documents.is_empty() {
+            return;
+        }
+
+        let path = self.dir.join(f!("{name}.ndjson"));
+        let mut buffer = vec![];
+        for document in documents {
+            serde_json::to_writer(&mut buffer, document).expect("Failed to serialize document");
+            buffer.push(b'\n');
+        }
+
+        let mut files = self.files.lock().expect("File sink poisoned");
+        let file = files.entry(name.to_owned()).or_insert_with(|| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .unwrap_or_else(|err| panic!("Failed to open {}: {err}", path.display()))
+        });
+        file.write_all(&buffer)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+    }
+}
+
+/// Publishes committed documents as JSON messages to NATS, one subject per collection
+/// (`<prefix>.<collection>`). Speaks the plain text NATS client protocol without TLS or auth.
+pub struct NatsSink {
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    prefix: String,
+    /// Rules the documents of a collection have to match to be published.
+    filters: HashMap<String, Rule>,
+}
+
+impl NatsSink {
+    /// Connects to `url`. Each filter is given as `<collection>=<rule>`.
+    pub async fn connect(url: &str, prefix: &str, filters: &[String]) -> NatsSink {
+        let filters: HashMap<String, Rule> = filters
+            .iter()
+            .map(|filter| {
+                let (collection, rule) = filter.split_once('=').unwrap_or_else(|| {
+                    panic!("Invalid NATS filter {filter}: expected <collection>=<rule>")
+                });
+                let rule = Rule::parse(rule).unwrap_or_else(|err| panic!("{err}"));
+                info!("[INFO] Publishing {collection} matching {}", rule.source());
+
+                (collection.trim().to_string(), rule)
+            })
+            .collect();
+
+        let address = url.strip_prefix("nats://").unwrap_or(url);
+        let stream = TcpStream::connect(address)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to connect to NATS {url}: {err}"));
+        let (reader, writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let mut info = String::new();
+        reader
+            .read_line(&mut info)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to read NATS server info: {err}"));
+        if !info.starts_with("INFO") {
+            panic!("Unexpected NATS greeting: {}", info.trim());
+        }
+
+        let writer = Arc::new(Mutex::new(writer));
+        writer
+            .lock()
+            .await
+            .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"fri\"}\r\n")
+            .await
+            .expect("Failed to send NATS connect");
+
+        // The server pings idle clients and drops those that do not answer.
+        let ponger = writer.clone();
+        tokio::spawn(async move {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match reader.read_line(&mut line).await {
+                    Ok(0) => {
+                        warn!("[WARN] NATS server closed the connection");
+                        return;
+                    }
+                    Ok(_) if line.starts_with("PING") => {
+                        if let Err(err) = ponger.lock().await.write_all(b"PONG\r\n").await {
+                            warn!("[WARN] Failed to answer NATS ping: {err}");
+                        }
+                    }
+                    Ok(_) if line.starts_with("-ERR") => {
+                        warn!("[WARN] NATS error: {}", line.trim())
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("[WARN] Failed to read from NATS: {err}");
+                        return;
+                    }
+                }
+            }
+        });
+
+        info!("[INFO] Publishing indexed documents to NATS {url}");
+
+        NatsSink {
+            writer,
+            prefix: prefix.to_string(),
+            filters,
+        }
+    }
+
+    /// Publishes the documents inserted by the last commit of `pool`.
+    pub async fn publish<T>(&self, pool: &Pool<T>)
+    where
+        T: Serialize + Clone + Eq + PartialEq,
+    {
+        let filter = self.filters.get(pool.name());
+        let documents: Vec<&T> = pool
+            .inserted()
+            .iter()
+            .filter(|document| filter.map(|rule| rule.matches(document)).unwrap_or(true))
+            .collect();
+        if documents.is_empty() {
+            return;
+        }
+
+        let subject = f!("{}.{}", self.prefix, pool.name());
+        let mut buffer = vec![];
+        for document in documents {
+            let payload = serde_json::to_vec(document).expect("Failed to serialize document");
+            buffer.extend_from_slice(f!("PUB {subject} {}\r\n", payload.len()).as_bytes());
+            buffer.extend_from_slice(&payload);
+            buffer.extend_from_slice(b"\r\n");
+        }
+
+        let mut writer = self.writer.lock().await;
+        writer
+            .write_all(&buffer)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to publish {subject} to NATS: {err}"));
+        writer
+            .flush()
+            .await
+            .unwrap_or_else(|err| panic!("Failed to publish {subject} to NATS: {err}"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use log::info;
+use mongodb::bson::{doc, DateTime, Document};
+
+use crate::analysis::{block_range_filter, ZERO_ADDRESS};
+use crate::mongo::collections::erc1155_transfer::ERC1155Transfer;
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::spam_token::SpamToken;
+use crate::mongo::collections::{Address, Block};
+use crate::mongo::Database;
+use crate::ronin::{ContractType, Ronin};
+use crate::tiering;
+
+/// `--include-spam`, keeps the transfers of flagged tokens in queries and exports.
+static INCLUDE_SPAM: OnceLock<bool> = OnceLock::new();
+
+pub fn include_spam(include: bool) {
+    if INCLUDE_SPAM.set(include).is_err() {
+        panic!("Spam filter set twice!");
+    }
+}
+
+/// Restricts a transfer filter to transfers of tokens not flagged as spam, unless
+/// `--include-spam` is given.
+pub fn exclude(filter: &mut Document) {
+    if !INCLUDE_SPAM.get().copied().unwrap_or(false) {
+        filter.insert("spam", doc! {"$ne": true});
+    }
+}
+
+/// Transfers of one token, with the recipients of every identical transfer per sender and amount.
+#[derive(Default)]
+struct Token {
+    erc: Option<ContractType>,
+    airdrops: HashMap<(Address, String), HashSet<Address>>,
+    senders: HashSet<Address>,
+    liquid: bool,
+}
+
+impl Token {
+    fn transfer(
+        &mut self,
+        erc: &ContractType,
+        from: &str,
+        to: &str,
+        amount: String,
+        pairs: &HashSet<Address>,
+    ) {
+        self.erc.get_or_insert_with(|| erc.to_owned());
+        self.airdrops
+            .entry((from.to_owned(), amount))
+            .or_default()
+            .insert(to.to_owned());
+        self.senders.insert(from.to_owned());
+        self.liquid |= pairs.contains(from) || pairs.contains(to);
+    }
+}
+
+/// Flags tokens as spam airdrops and marks their transfers with `spam`. A token is flagged when
+/// one sender sent the identical amount to at least `min_recipients` wallets in the range, it was
+/// never moved through a Katana pair and fewer than `max_active_ratio` of the recipients moved it
+/// on. Tracked contracts are never flagged. The transfers stay stored, queries and exports only
+/// skip them unless `--include-spam` is given.
+pub async fn classify(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    min_recipients: usize,
+    max_active_ratio: f64,
+) {
+    let tracked: HashSet<String> = Ronin::contract_list()
+        .keys()
+        .map(|address| address.to_string())
+        .collect();
+    let mut pairs: HashSet<Address> = HashSet::new();
+    for tier in tiering::tiers(database, "pair_reserves", 0, 0).await {
+        pairs.extend(
+            tier.database
+                .pair_reserves
+                .collection
+                .distinct("pair", tier.filter(doc! {}), None)
+                .await
+                .expect("Failed to query pair reserves!")
+                .into_iter()
+                .filter_map(|pair| pair.as_str().map(str::to_string)),
+        );
+    }
+
+    let mut tokens: HashMap<Address, Token> = HashMap::new();
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc_transfers.collection.clone(),
+        block_range_filter(from_block, to_block),
+        None,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query erc transfers!");
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc transfers!")
+    {
+        let transfer: ERCTransfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc transfer!");
+        // NFT airdrops send a distinct token id to every recipient.
+        let amount = match transfer.erc {
+            ContractType::ERC20 => transfer.value_or_token_id,
+            _ => String::new(),
+        };
+
+        tokens.entry(transfer.token).or_default().transfer(
+            &transfer.erc,
+            &transfer.from,
+            &transfer.to,
+            amount,
+            &pairs,
+        );
+    }
+
+    let mut cursor = tiering::find(
+        database,
+        |db| db.erc1155_transfers.collection.clone(),
+        block_range_filter(from_block, to_block),
+        None,
+        (from_block, to_block),
+    )
+    .await
+    .expect("Failed to query erc1155 transfers!");
+    while cursor
+        .advance()
+        .await
+        .expect("Failed to read erc1155 transfers!")
+    {
+        let transfer: ERC1155Transfer = cursor
+            .deserialize_current()
+            .expect("Failed to deserialize erc1155 transfer!");
+
+        tokens.entry(transfer.token).or_default().transfer(
+            &ContractType::ERC1155,
+            &transfer.from,
+            &transfer.to,
+            f!("{}:{}", transfer.token_id, transfer.value),
+            &pairs,
+        );
+    }
+
+    let mut flagged = 0;
+    for (address, token) in tokens {
+        if token.liquid || tracked.contains(&address) {
+            continue;
+        }
+
+        let ((sender, _), recipients) = match token
+            .airdrops
+            .iter()
+            .max_by_key(|(_, recipients)| recipients.len())
+        {
+            Some(airdrop) if airdrop.1.len() >= min_recipients => airdrop,
+            _ => continue,
+        };
+
+        let active = token
+            .senders
+            .iter()
+            .filter(|from| *from != sender && *from != ZERO_ADDRESS && recipients.contains(*from))
+            .count();
+        let active_ratio = active as f64 / recipients.len() as f64;
+        if active_ratio >= max_active_ratio {
+            continue;
+        }
+
+        database
+            .spam_tokens
+            .store(&SpamToken {
+                token: address.to_owned(),
+                erc: token.erc.unwrap_or(ContractType::ERC20),
+                sender: sender.to_owned(),
+                recipients: recipients.len() as u64,
+                active_ratio,
+                from_block,
+                to_block,
+                flagged_at: DateTime::now(),
+            })
+            .await
+            .expect("Failed to store spam token!");
+
+        let filter = doc! {"token": &address};
+        let update = doc! {"$set": {"spam": true}};
+        database
+            .erc_transfers
+            .collection
+            .update_many(filter.to_owned(), update.to_owned(), None)
+            .await
+            .expect("Failed to flag erc transfers!");
+        database
+            .erc1155_transfers
+            .collection
+            .update_many(filter.to_owned(), update.to_owned(), None)
+            .await
+            .expect("Failed to flag erc1155 transfers!");
+        database
+            .whale_transfers
+            .collection
+            .update_many(filter.to_owned(), update.to_owned(), None)
+            .await
+            .expect("Failed to flag whale transfers!");
+        database
+            .wallet_transfers
+            .collection
+            .update_many(filter, update, None)
+            .await
+            .expect("Failed to flag wallet transfers!");
+
+        info!(
+            "[INFO] Flagged {address} as spam: {} recipients from {sender}",
+            recipients.len()
+        );
+        flagged += 1;
+    }
+
+    println!("Flagged {flagged} spam tokens");
+}
@@ -0,0 +1,503 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::AggregateOptions;
+use serde::Serialize;
+
+use crate::analysis::block_range_filter;
+use crate::mongo::collections::{Address, Block};
+use crate::mongo::Database;
+use crate::ronin::{Ronin, SuppressedDuplicates};
+use crate::{spam, tiering};
+
+/// Transfers of one token within the range. The volume is in whole tokens for ERC20 and empty
+/// for ERC721.
+#[derive(Serialize)]
+pub struct TokenTransfers {
+    pub token: Address,
+    pub name: Option<String>,
+    pub transfers: u64,
+    pub volume: String,
+}
+
+/// Sales of one collection in one payment token, legacy auction sales have none. The volume is
+/// the raw amount of the payment token.
+#[derive(Serialize)]
+pub struct TokenSales {
+    pub token: Address,
+    pub name: Option<String>,
+    pub payment_token: Option<Address>,
+    pub sales: u64,
+    pub volume: String,
+}
+
+/// Distinct transaction senders of one UTC day.
+#[derive(Serialize)]
+pub struct DailyWallets {
+    pub day: String,
+    pub wallets: u64,
+}
+
+#[derive(Serialize)]
+pub struct LargestBlock {
+    pub block: Block,
+    pub transactions: u64,
+    pub gas_used: u64,
+}
+
+/// Inserts into one collection suppressed as duplicates since the collection was created.
+#[derive(Serialize)]
+pub struct Duplicates {
+    pub collection: String,
+    pub count: u64,
+    pub last_block: Block,
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    pub transfers_per_token: Vec<TokenTransfers>,
+    pub sales_per_token: Vec<TokenSales>,
+    pub active_wallets_per_day: Vec<DailyWallets>,
+    pub largest_blocks: Vec<LargestBlock>,
+    pub duplicates: Vec<Duplicates>,
+}
+
+/// Runs `pipeline` after matching `filter` on every tier of collection `name` holding blocks
+/// `from_block..=to_block`, the results of the tiers are returned one after the other and need to
+/// be merged by the caller.
+async fn aggregate(
+    database: &Database,
+    name: &str,
+    from_block: Block,
+    to_block: Block,
+    filter: Document,
+    pipeline: Vec<Document>,
+) -> Vec<Document> {
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let mut documents = vec![];
+
+    for tier in tiering::tiers(database, name, from_block, to_block).await {
+        let mut stages = vec![doc! {"$match": tier.filter(filter.to_owned())}];
+        stages.extend(pipeline.iter().cloned());
+
+        let mut cursor = tier
+            .database
+            ._database
+            .collection::<Document>(name)
+            .aggregate(stages, options.to_owned())
+            .await
+            .unwrap_or_else(|err| panic!("Failed to aggregate {name}: {err}"));
+
+        while cursor
+            .advance()
+            .await
+            .unwrap_or_else(|err| panic!("Failed to read {name}: {err}"))
+        {
+            documents.push(
+                cursor
+                    .deserialize_current()
+                    .unwrap_or_else(|err| panic!("Failed to read {name}: {err}")),
+            );
+        }
+    }
+
+    documents
+}
+
+fn string(document: &Document, key: &str) -> Option<String> {
+    document.get_str(key).ok().map(str::to_string)
+}
+
+fn count(document: &Document, key: &str) -> u64 {
+    match document.get(key) {
+        Some(Bson::Int32(count)) => *count as u64,
+        Some(Bson::Int64(count)) => *count as u64,
+        _ => 0,
+    }
+}
+
+fn decimal(document: &Document, key: &str) -> String {
+    match document.get(key) {
+        Some(Bson::Decimal128(amount)) => amount.to_string(),
+        Some(Bson::Int32(amount)) => amount.to_string(),
+        Some(Bson::Int64(amount)) => amount.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Sum of two volumes of [`decimal`]. A volume of a single tier is kept as it is, volumes split
+/// between the hot and cold database are added as floats.
+fn add_volume(volume: &str, other: &str) -> String {
+    match (volume, other) {
+        ("", other) => other.to_string(),
+        (volume, "") => volume.to_string(),
+        (volume, other) => {
+            let parse = |volume: &str| volume.parse::<f64>().unwrap_or_default();
+            (parse(volume) + parse(other)).to_string()
+        }
+    }
+}
+
+fn contract_name(address: &str) -> Option<String> {
+    Ronin::contract_list()
+        .get(address)
+        .map(|contract| contract.name.to_string())
+}
+
+/// Transfers and volume per token over the hot and cold `erc_transfers` collection, the busiest
+/// `limit` first.
+pub async fn transfers_per_token(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    limit: i64,
+) -> Vec<TokenTransfers> {
+    let mut filter = block_range_filter(from_block, to_block);
+    spam::exclude(&mut filter);
+
+    let pipeline = vec![doc! {"$group": {
+        "_id": "$token",
+        "transfers": {"$sum": 1},
+        "volume": {"$sum": "$value_decimal"}
+    }}];
+
+    let mut tokens: HashMap<Address, TokenTransfers> = HashMap::new();
+    for document in aggregate(
+        database,
+        "erc_transfers",
+        from_block,
+        to_block,
+        filter,
+        pipeline,
+    )
+    .await
+    {
+        let token = string(&document, "_id").unwrap_or_default();
+        let transfers = count(&document, "transfers");
+        let volume = decimal(&document, "volume");
+        tokens
+            .entry(token.to_owned())
+            .and_modify(|row| {
+                row.transfers += transfers;
+                row.volume = add_volume(&row.volume, &volume);
+            })
+            .or_insert_with(|| TokenTransfers {
+                name: contract_name(&token),
+                token,
+                transfers,
+                volume: volume.to_owned(),
+            });
+    }
+
+    let mut rows: Vec<TokenTransfers> = tokens.into_values().collect();
+    rows.sort_by(|a, b| b.transfers.cmp(&a.transfers).then(a.token.cmp(&b.token)));
+    rows.truncate(limit as usize);
+    rows
+}
+
+/// Sales and volume per collection and payment token over the hot and cold `erc_sales` collection,
+/// the busiest `limit` first.
+pub async fn sales_per_token(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    limit: i64,
+) -> Vec<TokenSales> {
+    let pipeline = vec![doc! {"$group": {
+        "_id": {"token": "$token", "payment_token": "$payment_token"},
+        "sales": {"$sum": 1},
+        "volume": {"$sum": "$price_num"}
+    }}];
+    let documents = aggregate(
+        database,
+        "erc721_sales",
+        from_block,
+        to_block,
+        block_range_filter(from_block, to_block),
+        pipeline,
+    )
+    .await;
+
+    let mut tokens: HashMap<(Address, Option<Address>), TokenSales> = HashMap::new();
+    for document in documents {
+        let id = document.get_document("_id").cloned().unwrap_or_default();
+        let token = string(&id, "token").unwrap_or_default();
+        let payment_token = string(&id, "payment_token");
+        let sales = count(&document, "sales");
+        let volume = decimal(&document, "volume");
+        tokens
+            .entry((token.to_owned(), payment_token.to_owned()))
+            .and_modify(|row| {
+                row.sales += sales;
+                row.volume = add_volume(&row.volume, &volume);
+            })
+            .or_insert_with(|| TokenSales {
+                name: contract_name(&token),
+                token,
+                payment_token,
+                sales,
+                volume: volume.to_owned(),
+            });
+    }
+
+    let mut rows: Vec<TokenSales> = tokens.into_values().collect();
+    rows.sort_by(|a, b| b.sales.cmp(&a.sales).then(a.token.cmp(&b.token)));
+    rows.truncate(limit as usize);
+    rows
+}
+
+/// Distinct transaction senders per UTC day over the hot and cold `transactions` collection, the
+/// latest `days` days first.
+pub async fn active_wallets_per_day(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    days: i64,
+) -> Vec<DailyWallets> {
+    let pipeline = vec![
+        // Grouping by day and sender first keeps the distinct senders out of a single document.
+        doc! {"$group": {
+            "_id": {
+                "day": {"$dateToString": {"format": "%Y-%m-%d", "date": "$timestamp"}},
+                "wallet": "$from"
+            }
+        }},
+        doc! {"$group": {"_id": "$_id.day", "wallets": {"$sum": 1}}},
+        doc! {"$sort": {"_id": -1}},
+        doc! {"$limit": days},
+    ];
+    let filter = block_range_filter(from_block, to_block);
+    let documents = aggregate(
+        database,
+        "transactions",
+        from_block,
+        to_block,
+        filter.to_owned(),
+        pipeline,
+    )
+    .await;
+
+    let mut wallets: BTreeMap<String, Option<u64>> = BTreeMap::new();
+    for document in documents {
+        let day = string(&document, "_id").unwrap_or_default();
+        let count = count(&document, "wallets");
+        wallets
+            .entry(day)
+            .and_modify(|wallets| *wallets = None)
+            .or_insert(Some(count));
+    }
+
+    let mut rows = vec![];
+    for (day, count) in wallets.into_iter().rev().take(days as usize) {
+        let wallets = match count {
+            Some(count) => count,
+            // The day the cold cutoff falls on is split between the tiers, the same sender can be
+            // counted in both.
+            None => split_day_wallets(database, &day, from_block, to_block, &filter).await,
+        };
+        rows.push(DailyWallets { day, wallets });
+    }
+    rows
+}
+
+/// Distinct transaction senders of `day` over the tiers of the `transactions` collection.
+async fn split_day_wallets(
+    database: &Database,
+    day: &str,
+    from_block: Block,
+    to_block: Block,
+    filter: &Document,
+) -> u64 {
+    let start = DateTime::parse_rfc3339_str(f!("{day}T00:00:00Z"))
+        .unwrap_or_else(|err| panic!("Failed to parse day {day}: {err}"));
+    let end = DateTime::from_millis(start.timestamp_millis() + 24 * 60 * 60 * 1000);
+    let filter = doc! {"$and": [
+        filter,
+        {"timestamp": {"$gte": start, "$lt": end}}
+    ]};
+
+    aggregate(
+        database,
+        "transactions",
+        from_block,
+        to_block,
+        filter,
+        vec![doc! {"$group": {"_id": "$from"}}],
+    )
+    .await
+    .iter()
+    .filter_map(|document| string(document, "_id"))
+    .collect::<HashSet<String>>()
+    .len() as u64
+}
+
+/// Blocks with the most transactions over the `blocks` collection, written with the blocks
+/// feature.
+pub async fn largest_blocks(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    limit: i64,
+) -> Vec<LargestBlock> {
+    let pipeline = vec![
+        doc! {"$sort": {"transactions": -1, "block": 1}},
+        doc! {"$limit": limit},
+    ];
+    let documents = aggregate(
+        database,
+        "blocks",
+        from_block,
+        to_block,
+        block_range_filter(from_block, to_block),
+        pipeline,
+    )
+    .await;
+
+    let mut rows: Vec<LargestBlock> = documents
+        .iter()
+        .map(|document| LargestBlock {
+            block: count(document, "block"),
+            transactions: count(document, "transactions"),
+            gas_used: count(document, "gas_used"),
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.transactions
+            .cmp(&a.transactions)
+            .then(a.block.cmp(&b.block))
+    });
+    rows.truncate(limit as usize);
+    rows
+}
+
+/// Suppressed duplicates per collection, as recorded by the stream under `duplicates.<collection>`.
+pub async fn duplicates(database: &Database) -> Vec<Duplicates> {
+    database
+        .settings
+        .load_prefixed::<SuppressedDuplicates>("duplicates.")
+        .await
+        .into_iter()
+        .map(|(key, suppressed)| Duplicates {
+            collection: key.trim_start_matches("duplicates.").to_string(),
+            count: suppressed.count,
+            last_block: suppressed.last_block,
+        })
+        .collect()
+}
+
+/// Runs the aggregates over the block range, `limit` rows each and `days` days of active wallets,
+/// and reads the suppressed duplicates of the whole database.
+pub async fn stats(
+    database: &Database,
+    from_block: Block,
+    to_block: Block,
+    limit: i64,
+    days: i64,
+) -> Stats {
+    Stats {
+        transfers_per_token: transfers_per_token(database, from_block, to_block, limit).await,
+        sales_per_token: sales_per_token(database, from_block, to_block, limit).await,
+        active_wallets_per_day: active_wallets_per_day(database, from_block, to_block, days).await,
+        largest_blocks: largest_blocks(database, from_block, to_block, limit).await,
+        duplicates: duplicates(database).await,
+    }
+}
+
+/// Prints `rows` below `header` as a table with columns padded to their widest cell.
+fn print_table(title: &str, header: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| f!("{cell:<width$}"))
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+
+    println!("{title}");
+    println!("{}", line(header.to_vec()));
+    for row in &rows {
+        println!("{}", line(row.iter().map(String::as_str).collect()));
+    }
+    println!();
+}
+
+impl Stats {
+    pub fn print(&self) {
+        let name = |name: &Option<String>| name.to_owned().unwrap_or_default();
+
+        print_table(
+            "Transfers per token",
+            &["token", "name", "transfers", "volume"],
+            self.transfers_per_token
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.token.to_owned(),
+                        name(&row.name),
+                        row.transfers.to_string(),
+                        row.volume.to_owned(),
+                    ]
+                })
+                .collect(),
+        );
+        print_table(
+            "Sales per token",
+            &["token", "name", "payment token", "sales", "volume"],
+            self.sales_per_token
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.token.to_owned(),
+                        name(&row.name),
+                        row.payment_token.to_owned().unwrap_or_default(),
+                        row.sales.to_string(),
+                        row.volume.to_owned(),
+                    ]
+                })
+                .collect(),
+        );
+        print_table(
+            "Active wallets per day",
+            &["day", "wallets"],
+            self.active_wallets_per_day
+                .iter()
+                .map(|row| vec![row.day.to_owned(), row.wallets.to_string()])
+                .collect(),
+        );
+        print_table(
+            "Largest blocks",
+            &["block", "transactions", "gas used"],
+            self.largest_blocks
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.block.to_string(),
+                        row.transactions.to_string(),
+                        row.gas_used.to_string(),
+                    ]
+                })
+                .collect(),
+        );
+        print_table(
+            "Suppressed duplicates",
+            &["collection", "duplicates", "last block"],
+            self.duplicates
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.collection.to_owned(),
+                        row.count.to_string(),
+                        row.last_block.to_string(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::cache::indexed_head;
+use crate::cli_args::Args;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+use crate::ronin::{Ronin, StreamFeatures, LOGS_MODE_KEY, STREAM_FEATURES_KEY, STREAM_KEY};
+use crate::tiering::{self, TIERED_COLLECTIONS};
+
+/// Progress of the running indexer as reported by the status server.
+struct Status {
+    started: Instant,
+    last_block: AtomicU64,
+    head: AtomicU64,
+    features: Vec<(&'static str, bool)>,
+}
+
+static STATUS: OnceLock<Status> = OnceLock::new();
+
+/// Stream and logs mode backfill workers currently indexing.
+static WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts an indexing worker in `/status` until it is dropped.
+pub struct Worker;
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        WORKERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Registers a running stream or backfill worker, see [`Worker`].
+pub fn worker() -> Worker {
+    WORKERS.fetch_add(1, Ordering::Relaxed);
+    Worker
+}
+
+/// Records the last block written by the stream.
+pub fn indexed(block: Block) {
+    if let Some(status) = STATUS.get() {
+        status.last_block.store(block, Ordering::Relaxed);
+    }
+}
+
+/// Records the latest known block of the chain.
+pub fn head(block: Block) {
+    if let Some(status) = STATUS.get() {
+        status.head.store(block, Ordering::Relaxed);
+    }
+}
+
+fn status_json(status: &Status) -> Value {
+    let last_block = status.last_block.load(Ordering::Relaxed);
+    let head = status.head.load(Ordering::Relaxed);
+
+    json!({
+        "last_indexed_block": last_block,
+        "chain_head": head,
+        "lag": head.saturating_sub(last_block),
+        "uptime_seconds": status.started.elapsed().as_secs(),
+        "workers": WORKERS.load(Ordering::Relaxed),
+        "features": status
+            .features
+            .iter()
+            .map(|(name, enabled)| (name.to_string(), Value::from(*enabled)))
+            .collect::<serde_json::Map<String, Value>>(),
+    })
+}
+
+async fn respond(mut stream: TcpStream, status: &Status) -> std::io::Result<()> {
+    let mut request = [0u8; 1024];
+    let read = stream.read(&mut request).await?;
+    let request = String::from_utf8_lossy(&request[..read]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (code, body) = match path {
+        "/healthz" => ("200 OK", json!({"status": "ok"})),
+        "/status" => ("200 OK", status_json(status)),
+        _ => ("404 Not Found", json!({"error": "not found"})),
+    };
+    let body = body.to_string();
+
+    stream
+        .write_all(
+            f!("HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+                .as_bytes(),
+        )
+        .await?;
+    stream.shutdown().await
+}
+
+/// Serves `/healthz` and `/status` on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr, args: &Args) {
+    let status = STATUS.get_or_init(|| Status {
+        started: Instant::now(),
+        last_block: AtomicU64::new(0),
+        head: AtomicU64::new(0),
+        features: args.features(),
+    });
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to bind status server to {addr}: {err}"));
+    info!("[INFO] Status server listening on {addr}");
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tokio::spawn(async move {
+                        if let Err(err) = respond(stream, status).await {
+                            debug!("Status request of {peer} failed: {err}");
+                        }
+                    });
+                }
+                Err(err) => warn!("[WARN] Failed to accept status connection: {err}"),
+            }
+        }
+    });
+}
+
+/// Last indexed block, chain head, lag, document counts per collection of the hot and, once `tier`
+/// ran, the cold database and the features the stream last ran with, as JSON with `json` and as text
+/// otherwise. The last indexed block is the furthest of the stream and logs mode progress and the
+/// latest indexed transaction.
+pub async fn report(database: &Database, args: &Args, json: bool) {
+    let mut last_block = indexed_head(database).await;
+    for key in [STREAM_KEY, LOGS_MODE_KEY] {
+        last_block = last_block.max(database.settings.load::<u64>(key).await.unwrap_or(0));
+    }
+    let head = Ronin::connect(&args.web3_hostname)
+        .await
+        .eth()
+        .block_number()
+        .await
+        .expect("Failed to retrieve head block number from chain!")
+        .as_u64();
+
+    let mut names = database
+        ._database
+        .list_collection_names(None)
+        .await
+        .expect("Failed to list collections!");
+    names.sort();
+    let mut counts = vec![];
+    for name in names {
+        let count = database
+            ._database
+            .collection::<mongodb::bson::Document>(&name)
+            .estimated_document_count(None)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to count {name}: {err}"));
+        counts.push((name, count));
+    }
+
+    let mut cold_counts = vec![];
+    if let Some(cold) = tiering::cold(database).await {
+        for name in TIERED_COLLECTIONS {
+            let count = cold
+                ._database
+                .collection::<mongodb::bson::Document>(name)
+                .estimated_document_count(None)
+                .await
+                .unwrap_or_else(|err| panic!("Failed to count cold {name}: {err}"));
+            cold_counts.push((name.to_string(), count));
+        }
+    }
+
+    let features = database
+        .settings
+        .load::<StreamFeatures>(STREAM_FEATURES_KEY)
+        .await
+        .map(|stream| stream.features);
+
+    if json {
+        let report = json!({
+            "last_indexed_block": last_block,
+            "chain_head": head,
+            "lag": head.saturating_sub(last_block),
+            "collections": counts
+                .iter()
+                .map(|(name, count)| (name.to_owned(), Value::from(*count)))
+                .collect::<serde_json::Map<String, Value>>(),
+            "cold_collections": cold_counts
+                .iter()
+                .map(|(name, count)| (name.to_owned(), Value::from(*count)))
+                .collect::<serde_json::Map<String, Value>>(),
+            "features": features.as_ref().map(|features| {
+                features
+                    .iter()
+                    .map(|(name, enabled)| (name.to_owned(), Value::from(*enabled)))
+                    .collect::<serde_json::Map<String, Value>>()
+            }),
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return;
+    }
+
+    println!("Last indexed block: {last_block}");
+    println!("Chain head:         {head}");
+    println!(
+        "Lag:                {} blocks",
+        head.saturating_sub(last_block)
+    );
+    println!();
+    println!("Collections:");
+    let width = counts.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, count) in &counts {
+        println!("  {name:<width$}  {count:>12}");
+    }
+    println!();
+    if !cold_counts.is_empty() {
+        println!("Cold collections:");
+        let width = cold_counts
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, count) in &cold_counts {
+            println!("  {name:<width$}  {count:>12}");
+        }
+        println!();
+    }
+    match features {
+        Some(features) => {
+            let enabled: Vec<&str> = features
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.as_str())
+                .collect();
+            println!("Enabled features: {}", enabled.join(", "));
+        }
+        None => println!("Enabled features: unknown, the stream has not run yet"),
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{info, warn};
+use mongodb::bson::DateTime;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use web3::ethabi::param_type::Reader;
+use web3::ethabi::{Event, EventParam, RawLog};
+use web3::types::{Log, H160};
+
+use crate::abi::params_document;
+use crate::mongo::collections::contract_event::{ContractEvent, SubscriptionIndexes};
+use crate::mongo::collections::erc_transfer::ERCTransfer;
+use crate::mongo::collections::transaction_pool::Pool;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+use crate::rules::Rule;
+
+/// Event subscription entry of an `--events` file.
+#[derive(Deserialize)]
+struct SubscriptionConfig {
+    /// Emitting contract, events of any address are decoded when omitted.
+    #[serde(default)]
+    address: Option<String>,
+    /// Solidity event declaration, e.g. `Sync(uint112 reserve0, uint112 reserve1)`.
+    event: String,
+    /// Target collection of the decoded events.
+    collection: String,
+    /// Name stored as `contract` on the documents, defaults to the address.
+    #[serde(default)]
+    name: Option<String>,
+    /// Rule the decoded events have to match to be stored, see [`Rule`].
+    #[serde(default)]
+    filter: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionFile {
+    events: Vec<SubscriptionConfig>,
+}
+
+/// A configured event decoded into its own collection as [`ContractEvent`] documents.
+#[derive(Clone)]
+pub struct EventSubscription {
+    pub address: Option<H160>,
+    pub event: Event,
+    pub name: String,
+    pub collection: Collection<ContractEvent>,
+    pub filter: Option<Rule>,
+}
+
+fn to_string<T: Serialize>(value: &T) -> String {
+    web3::helpers::to_string(value).replace('\"', "")
+}
+
+/// Splits `value` at the commas outside of parentheses.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                params.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+
+    params
+        .into_iter()
+        .map(|param| param.trim())
+        .filter(|param| !param.is_empty())
+        .collect()
+}
+
+/// Parses a Solidity event declaration like
+/// `event Transfer(address indexed from, address indexed to, uint256 value)`.
+pub fn parse_event(declaration: &str) -> Result<Event, String> {
+    let declaration = declaration.trim();
+    let declaration = declaration.strip_prefix("event ").unwrap_or(declaration);
+
+    let (name, params) = declaration
+        .split_once('(')
+        .ok_or_else(|| f!("Invalid event {declaration}: missing parameter list"))?;
+    let params = params
+        .trim_end_matches(';')
+        .trim_end()
+        .strip_suffix(')')
+        .ok_or_else(|| f!("Invalid event {declaration}: unclosed parameter list"))?;
+
+    let inputs = split_params(params)
+        .into_iter()
+        .map(|param| {
+            let mut words = param.split_whitespace();
+            let kind = words.next().unwrap_or_default();
+            let kind = Reader::read(kind).map_err(|err| f!("Invalid type {kind}: {err}"))?;
+
+            let mut indexed = false;
+            let mut name = String::new();
+            for word in words {
+                match word {
+                    "indexed" => indexed = true,
+                    word => name = word.to_owned(),
+                }
+            }
+
+            Ok(EventParam {
+                name,
+                kind,
+                indexed,
+            })
+        })
+        .collect::<Result<Vec<EventParam>, String>>()?;
+
+    Ok(Event {
+        name: name.trim().to_owned(),
+        inputs,
+        anonymous: false,
+    })
+}
+
+/// Loads the event subscriptions of a TOML or JSON file (by extension) with an `events` array and
+/// sets up the indexes of their collections.
+pub async fn load(database: &Database, path: &Path) -> Vec<EventSubscription> {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read events {}: {err}", path.display()));
+
+    let file: SubscriptionFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml_edit::de::from_str(&content)
+            .unwrap_or_else(|err| panic!("Invalid events {}: {err}", path.display())),
+        _ => serde_json::from_str(&content)
+            .unwrap_or_else(|err| panic!("Invalid events {}: {err}", path.display())),
+    };
+
+    let mut subscriptions = vec![];
+    for config in file.events {
+        let event = parse_event(&config.event).unwrap_or_else(|err| panic!("{err}"));
+        let filter = config
+            .filter
+            .as_deref()
+            .map(|filter| Rule::parse(filter).unwrap_or_else(|err| panic!("{err}")));
+        let address = config.address.as_ref().map(|address| {
+            H160::from_str(address).unwrap_or_else(|_| panic!("Invalid event address {address}"))
+        });
+
+        let collection = database
+            ._database
+            .collection::<ContractEvent>(&config.collection);
+        let setup_key: &'static str = Box::leak(f!("setup.{}", config.collection).into_boxed_str());
+        database
+            .setup_indexes(&SubscriptionIndexes { setup_key }, &collection)
+            .await;
+
+        info!(
+            "[INFO] Subscribed to {} into {}",
+            event.signature(),
+            config.collection
+        );
+
+        subscriptions.push(EventSubscription {
+            name: config
+                .name
+                .or(config.address)
+                .unwrap_or_else(|| event.name.to_owned()),
+            address,
+            event,
+            collection,
+            filter,
+        });
+    }
+
+    subscriptions
+}
+
+impl EventSubscription {
+    pub fn get_pool(&self) -> Pool<ContractEvent> {
+        Pool::new(self.collection.to_owned())
+    }
+
+    pub fn decode(&self, log: &Log, block: Block, timestamp: DateTime) -> Option<ContractEvent> {
+        if self.address.map(|a| a != log.address).unwrap_or(false)
+            || log.topics.first() != Some(&self.event.signature())
+        {
+            return None;
+        }
+
+        let parsed = match self.event.parse_log(RawLog {
+            topics: log.topics.to_owned(),
+            data: log.data.0.to_owned(),
+        }) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                warn!(
+                    "[WARN] Failed to decode {} of {:?}: {err}",
+                    self.event.name, log.transaction_hash
+                );
+                return None;
+            }
+        };
+
+        let transaction_id = to_string(&log.transaction_hash);
+        let log_index = to_string(&log.log_index);
+
+        let event = ContractEvent {
+            address: format!("{:?}", log.address),
+            contract: self.name.to_owned(),
+            event: self.event.name.to_owned(),
+            params: params_document(parsed.params.iter().map(|p| (&p.name, &p.value))),
+            block,
+            timestamp,
+            log_id: ERCTransfer::get_transfer_id(&transaction_id, &log_index),
+            transaction_id,
+            log_index,
+        };
+
+        match &self.filter {
+            Some(rule) if !rule.matches(&event) => None,
+            _ => Some(event),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use log::info;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::{FindOneOptions, FindOptions, InsertManyOptions};
+use mongodb::{Collection, Cursor};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::analysis::block_range_filter;
+use crate::mongo;
+use crate::mongo::collections::settings::SettingsValue;
+use crate::mongo::collections::transaction_pool::DUPLICATE_KEY_ERROR;
+use crate::mongo::collections::Block;
+use crate::mongo::Database;
+
+/// Collections keyed by `block` that can be moved to the cold database. Aggregates (wallets,
+/// erc1155_supply) and bookkeeping (settings, metrics, decode_failures) always stay hot.
+pub const TIERED_COLLECTIONS: [&str; 12] = [
+    "transactions",
+    "erc_transfers",
+    "erc1155_transfers",
+    "wallet_transfers",
+    "erc721_sales",
+    "auctions",
+    "pair_reserves",
+    "governance_events",
+    "staking_events",
+    "native_transfers",
+    "orders",
+    "equipment_events",
+];
+
+/// Settings key holding the first block that is still stored in the hot database. Readers query
+/// the cold database for blocks below it and the hot database for everything else.
+pub const COLD_CUTOFF_KEY: &str = "tier.cold_cutoff";
+
+/// Settings key holding the cold database the last `tier` run moved documents to.
+pub const COLD_DATABASE_KEY: &str = "tier.cold_database";
+
+/// `--cold-db-uri`, the hot client is used without it.
+static COLD_DB_URI: OnceLock<String> = OnceLock::new();
+
+/// Cold database opened by the readers on first use.
+static COLD: OnceCell<Database> = OnceCell::const_new();
+
+#[derive(Serialize, Deserialize)]
+pub struct ColdDatabase {
+    pub name: String,
+}
+
+impl SettingsValue for ColdDatabase {}
+
+/// Sets the connection URL of the cold database for the readers.
+pub fn set_cold_db_uri(uri: &str) {
+    if COLD_DB_URI.set(uri.to_string()).is_err() {
+        panic!("Cold database URL set twice!");
+    }
+}
+
+/// Part of a tiered collection: the hot or the cold database with the blocks it holds of a
+/// queried range. `range` is `None` while nothing was moved to cold storage.
+pub struct Tier {
+    pub database: Database,
+    range: Option<(Block, Block)>,
+}
+
+impl Tier {
+    /// Restricts `filter` to the blocks of this tier.
+    pub fn filter(&self, mut filter: Document) -> Document {
+        let Some((from_block, to_block)) = self.range else {
+            return filter;
+        };
+        let range = block_range_filter(from_block, to_block);
+
+        match filter.contains_key("block") {
+            true => doc! {"$and": [filter, range]},
+            false => {
+                filter.extend(range);
+                filter
+            }
+        }
+    }
+}
+
+async fn cold_database(hot: &Database, name: &str) -> Database {
+    COLD.get_or_init(|| async {
+        match COLD_DB_URI.get() {
+            Some(uri) => mongo::connect(uri, name).await,
+            None => mongo::build(hot._client.clone(), name, None),
+        }
+    })
+    .await
+    .to_owned()
+}
+
+/// Cold database the last `tier` run moved documents to, `None` before the first run.
+pub async fn cold(hot: &Database) -> Option<Database> {
+    let cold = hot.settings.load::<ColdDatabase>(COLD_DATABASE_KEY).await?;
+    Some(cold_database(hot, &cold.name).await)
+}
+
+/// Tiers holding blocks `from_block..=to_block` of `collection`, oldest first. A `to_block` of 0
+/// leaves the range open ended. Blocks below [`COLD_CUTOFF_KEY`] are read from the cold database
+/// once `tier` moved them, everything else and the collections that are never tiered from the hot
+/// one.
+pub async fn tiers(
+    hot: &Database,
+    collection: &str,
+    from_block: Block,
+    to_block: Block,
+) -> Vec<Tier> {
+    let unsplit = || {
+        vec![Tier {
+            database: hot.to_owned(),
+            range: None,
+        }]
+    };
+    if !TIERED_COLLECTIONS.contains(&collection) {
+        return unsplit();
+    }
+    let (Some(cutoff), Some(cold)) = (
+        hot.settings.load::<Block>(COLD_CUTOFF_KEY).await,
+        hot.settings.load::<ColdDatabase>(COLD_DATABASE_KEY).await,
+    ) else {
+        return unsplit();
+    };
+
+    let mut tiers = vec![];
+    if from_block < cutoff {
+        let to_block = match to_block {
+            0 => cutoff - 1,
+            to_block => to_block.min(cutoff - 1),
+        };
+        tiers.push(Tier {
+            database: cold_database(hot, &cold.name).await,
+            range: Some((from_block, to_block)),
+        });
+    }
+    if to_block == 0 || to_block >= cutoff {
+        tiers.push(Tier {
+            database: hot.to_owned(),
+            range: Some((from_block.max(cutoff), to_block)),
+        });
+    }
+
+    tiers
+}
+
+/// Cursor over the documents of several tiers, one after the other.
+pub struct TieredCursor<T> {
+    cursors: VecDeque<Cursor<T>>,
+}
+
+impl<T> TieredCursor<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    pub async fn advance(&mut self) -> mongodb::error::Result<bool> {
+        while let Some(cursor) = self.cursors.front_mut() {
+            if cursor.advance().await? {
+                return Ok(true);
+            }
+            self.cursors.pop_front();
+        }
+
+        Ok(false)
+    }
+
+    pub fn deserialize_current(&self) -> mongodb::error::Result<T> {
+        self.cursors
+            .front()
+            .expect("Cursor read before advancing!")
+            .deserialize_current()
+    }
+}
+
+/// Finds the documents matching `filter` within blocks `from_block..=to_block` in the tiers of
+/// `collection`, which picks the collection of a database. Older tiers come first, so a sort by
+/// block stays ordered while other sorts and limits apply per tier.
+pub async fn find<T>(
+    database: &Database,
+    collection: impl Fn(&Database) -> Collection<T>,
+    filter: Document,
+    options: impl Into<Option<FindOptions>>,
+    (from_block, to_block): (Block, Block),
+) -> mongodb::error::Result<TieredCursor<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let options = options.into();
+    let name = collection(database).name().to_string();
+    let mut cursors = VecDeque::new();
+
+    for tier in tiers(database, &name, from_block, to_block).await {
+        cursors.push_back(
+            collection(&tier.database)
+                .find(tier.filter(filter.to_owned()), options.to_owned())
+                .await?,
+        );
+    }
+
+    Ok(TieredCursor { cursors })
+}
+
+/// First document matching `filter` in the tiers of `collection`. The newest tier is searched
+/// first when `options` sort by descending block, the oldest otherwise.
+pub async fn find_one<T>(
+    database: &Database,
+    collection: impl Fn(&Database) -> Collection<T>,
+    filter: Document,
+    options: impl Into<Option<FindOneOptions>>,
+) -> mongodb::error::Result<Option<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let options = options.into();
+    let newest_first = options
+        .as_ref()
+        .and_then(|options| options.sort.as_ref())
+        .and_then(|sort| sort.get("block"))
+        .is_some_and(|direction| match direction {
+            Bson::Int32(direction) => *direction < 0,
+            Bson::Int64(direction) => *direction < 0,
+            _ => false,
+        });
+    let name = collection(database).name().to_string();
+
+    let mut tiers = tiers(database, &name, 0, 0).await;
+    if newest_first {
+        tiers.reverse();
+    }
+    for tier in tiers {
+        if let Some(document) = collection(&tier.database)
+            .find_one(tier.filter(filter.to_owned()), options.to_owned())
+            .await?
+        {
+            return Ok(Some(document));
+        }
+    }
+
+    Ok(None)
+}
+
+pub(crate) async fn latest_block(collection: &Collection<Document>) -> Block {
+    let options = FindOneOptions::builder()
+        .sort(doc! {"block": -1i64})
+        .build();
+
+    collection
+        .find_one(None, options)
+        .await
+        .expect("Failed to query latest block")
+        .and_then(|document| document.get("block").and_then(Bson::as_i64))
+        .unwrap_or_default() as Block
+}
+
+/// Copies one batch of documents below `cutoff` with an `_id` after `after` to the cold
+/// collection. Documents that already exist in cold storage, e.g. after an interrupted run, count
+/// as copied. Returns the number of documents copied and the `_id` of the last one.
+async fn copy_batch(
+    hot: &Collection<Document>,
+    cold: &Collection<Document>,
+    cutoff: Block,
+    after: Option<Bson>,
+    batch_size: i64,
+) -> (usize, Option<Bson>) {
+    let options = FindOptions::builder()
+        .sort(doc! {"_id": 1i64})
+        .limit(batch_size)
+        .build();
+    let mut filter = doc! {"block": {"$lt": cutoff as i64}};
+    if let Some(after) = after {
+        filter.insert("_id", doc! {"$gt": after});
+    }
+    let mut cursor = hot
+        .find(filter, options)
+        .await
+        .unwrap_or_else(|_| panic!("Failed to query {}", hot.name()));
+
+    let mut documents: Vec<Document> = vec![];
+    while cursor
+        .advance()
+        .await
+        .unwrap_or_else(|_| panic!("Failed to read {}", hot.name()))
+    {
+        documents.push(
+            cursor
+                .deserialize_current()
+                .unwrap_or_else(|_| panic!("Failed to deserialize {}", hot.name())),
+        );
+    }
+
+    let Some(last) = documents
+        .last()
+        .and_then(|document| document.get("_id").cloned())
+    else {
+        return (0, None);
+    };
+
+    if let Err(error) = cold
+        .insert_many(
+            &documents,
+            InsertManyOptions::builder().ordered(false).build(),
+        )
+        .await
+    {
+        let only_duplicates = match error.kind.as_ref() {
+            ErrorKind::BulkWrite(failure) => {
+                failure.write_concern_error.is_none()
+                    && failure
+                        .write_errors
+                        .iter()
+                        .flatten()
+                        .all(|e| e.code == DUPLICATE_KEY_ERROR)
+            }
+            _ => false,
+        };
+
+        if !only_duplicates {
+            panic!("Failed to insert into cold {}: {:?}", cold.name(), error);
+        }
+    }
+
+    (documents.len(), Some(last))
+}
+
+/// Moves all documents older than the newest `keep_blocks` indexed blocks from the hot to the
+/// cold database. Every tiered collection is copied first, then the new boundary is recorded under
+/// [`COLD_CUTOFF_KEY`] in both databases, and the cold database under [`COLD_DATABASE_KEY`] in the
+/// hot one for the readers, and only then are the copies deleted from the hot database. The blocks
+/// stay readable throughout, and a run interrupted before the boundary moved is simply repeated.
+/// Copies a previous run left in the hot database are deleted as well.
+pub async fn move_to_cold(hot: &Database, cold: &Database, keep_blocks: u64, batch_size: i64) {
+    let latest = latest_block(&hot._database.collection::<Document>("transactions")).await;
+    let cutoff = latest.saturating_sub(keep_blocks);
+
+    let previous: Block = hot
+        .settings
+        .load::<Block>(COLD_CUTOFF_KEY)
+        .await
+        .unwrap_or_default();
+
+    if cutoff <= previous {
+        println!("Blocks below {} are already in cold storage", previous);
+    } else {
+        for name in TIERED_COLLECTIONS {
+            let hot_collection = hot._database.collection::<Document>(name);
+            let cold_collection = cold._database.collection::<Document>(name);
+
+            let mut copied = 0;
+            let mut after = None;
+            loop {
+                let (batch, last) =
+                    copy_batch(&hot_collection, &cold_collection, cutoff, after, batch_size).await;
+                if batch == 0 {
+                    break;
+                }
+                copied += batch;
+                after = last;
+                info!("[INFO] Tiering: copied {} documents of {}", copied, name);
+            }
+
+            println!(
+                "Copied {} documents of {} below block {}",
+                copied, name, cutoff
+            );
+        }
+
+        hot.settings
+            .store(
+                COLD_DATABASE_KEY,
+                &ColdDatabase {
+                    name: cold._database.name().to_string(),
+                },
+            )
+            .await
+            .expect("Failed to store cold database");
+        for database in [cold, hot] {
+            database
+                .settings
+                .store(COLD_CUTOFF_KEY, &cutoff)
+                .await
+                .expect("Failed to store cold cutoff");
+        }
+    }
+
+    let cutoff = cutoff.max(previous);
+    for name in TIERED_COLLECTIONS {
+        let deleted = hot
+            ._database
+            .collection::<Document>(name)
+            .delete_many(doc! {"block": {"$lt": cutoff as i64}}, None)
+            .await
+            .unwrap_or_else(|_| panic!("Failed to delete moved documents from {}", name))
+            .deleted_count;
+
+        println!(
+            "Moved {} documents of {} below block {}",
+            deleted, name, cutoff
+        );
+    }
+}